@@ -1,6 +1,7 @@
 use near_primitives::block::BlockValidityError;
 use near_primitives::challenge::{ChunkProofs, ChunkState, MaybeEncodedShardChunk};
 use near_primitives::errors::{ChunkAccessError, EpochError, StorageError};
+use near_primitives::genesis::GenesisError;
 use near_primitives::shard_layout::ShardLayoutError;
 use near_primitives::sharding::{BadHeaderForProtocolVersionError, ChunkHash, ShardChunkHeader};
 use near_primitives::types::{BlockHeight, EpochId, ShardId, ShardIndex};
@@ -245,6 +246,9 @@ pub enum Error {
     /// Resharding error.
     #[error("Resharding Error: {0}")]
     ReshardingError(String),
+    /// The genesis block can't be built from the genesis config.
+    #[error("Invalid Genesis: {0}")]
+    InvalidGenesis(#[from] GenesisError),
     /// EpochSyncProof validation error.
     #[error("EpochSyncProof Validation Error: {0}")]
     InvalidEpochSyncProof(String),
@@ -289,6 +293,7 @@ impl Error {
             | Error::StorageError(_)
             | Error::GCError(_)
             | Error::ReshardingError(_)
+            | Error::InvalidGenesis(_)
             | Error::DBNotFoundErr(_) => false,
             Error::InvalidBlockPastTime(_, _)
             | Error::InvalidBlockFutureTime(_)
@@ -424,6 +429,7 @@ impl Error {
             Error::NotAChunkValidator => "not_a_chunk_validator",
             Error::InvalidChallengeRoot => "invalid_challenge_root",
             Error::ReshardingError(_) => "resharding_error",
+            Error::InvalidGenesis(_) => "invalid_genesis",
 
             Error::BadHeaderForProtocolVersion(_) => "bad_header_for_protocol_version",
         }
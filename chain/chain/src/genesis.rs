@@ -32,25 +32,28 @@ impl Chain {
     ) -> Result<(Block, Vec<ShardChunk>), Error> {
         let congestion_infos =
             get_genesis_congestion_infos(epoch_manager, runtime_adapter, &state_roots)?;
+        let shard_ids = epoch_manager.shard_ids(&EpochId::default())?;
         let genesis_chunks = genesis_chunks(
             state_roots,
             congestion_infos,
-            &epoch_manager.shard_ids(&EpochId::default())?,
+            &shard_ids,
             chain_genesis.gas_limit,
             chain_genesis.height,
             chain_genesis.protocol_version,
         );
         let validator_stakes =
             epoch_manager.get_epoch_block_producers_ordered(&EpochId::default())?;
-        let genesis_block = Block::genesis(
+        let genesis_block = Block::try_genesis(
             chain_genesis.protocol_version,
             genesis_chunks.iter().map(|chunk| chunk.cloned_header()).collect(),
+            &shard_ids,
             chain_genesis.time,
             chain_genesis.height,
             chain_genesis.min_gas_price,
             chain_genesis.total_supply,
             &validator_stakes,
-        );
+        )?;
+        tracing::debug!(target: "chain", summary = %genesis_block.genesis_summary(), "built genesis block");
 
         // verify that the genesis block hash matches either mainnet or testnet
         let hash = genesis_block.hash().to_string();
@@ -90,6 +90,12 @@ impl ShardedTransactionPool {
         })
     }
 
+    /// Returns the number of transactions in the pool for the given shard and
+    /// their total size in bytes.
+    pub fn pool_len_and_size(&self, shard_uid: ShardUId) -> (usize, u64) {
+        self.tx_pools.get(&shard_uid).map_or((0, 0), |pool| (pool.len(), pool.transaction_size()))
+    }
+
     pub fn debug_status(&self) -> String {
         self.tx_pools
             .iter()
@@ -3,18 +3,19 @@
 #![allow(clippy::arc_with_non_send_sync)]
 
 use std::mem::swap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use crate::Client;
 use crate::chunk_producer::ProduceChunkResult;
 use crate::client::CatchupState;
 use actix_rt::System;
 use itertools::Itertools;
-use near_async::messaging::Sender;
+use near_async::messaging::{IntoMultiSender, Sender, noop};
 use near_chain::chain::{BlockCatchUpRequest, do_apply_chunks};
 use near_chain::test_utils::{wait_for_all_blocks_in_processing, wait_for_block_in_processing};
 use near_chain::{Chain, ChainStoreAccess, Provenance};
 use near_client_primitives::types::Error;
+use near_network::client::ClientSenderForNetwork;
 use near_network::types::HighestHeightPeerInfo;
 use near_primitives::block::Block;
 use near_primitives::hash::CryptoHash;
@@ -320,3 +321,130 @@ pub fn run_catchup(
     }
     Ok(())
 }
+
+/// Builds a `ClientSenderForNetwork` for tests which only care about some of
+/// the messages the network sends to the client. All senders are no-ops
+/// unless overridden with `with()`, e.g.
+/// `ClientSenderForNetworkBuilder::new().with(|s| s.state_response = sender)`.
+pub struct ClientSenderForNetworkBuilder {
+    senders: ClientSenderForNetwork,
+}
+
+impl Default for ClientSenderForNetworkBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientSenderForNetworkBuilder {
+    pub fn new() -> Self {
+        Self { senders: noop().into_multi_sender() }
+    }
+
+    /// Overrides some of the senders.
+    pub fn with(mut self, f: impl FnOnce(&mut ClientSenderForNetwork)) -> Self {
+        f(&mut self.senders);
+        self
+    }
+
+    pub fn build(self) -> ClientSenderForNetwork {
+        self.senders
+    }
+
+    /// Like `build()`, but also records the name of the sender every message
+    /// goes through, so that the test can check which messages were sent.
+    pub fn build_recording(self) -> (ClientSenderForNetwork, SentClientMessages) {
+        let sent = SentClientMessages::default();
+        let s = self.senders;
+        let senders = ClientSenderForNetwork {
+            tx_status_request: sent.record("tx_status_request", s.tx_status_request),
+            tx_status_response: sent.record("tx_status_response", s.tx_status_response),
+            transaction: sent.record("transaction", s.transaction),
+            state_request_header: sent.record("state_request_header", s.state_request_header),
+            state_request_part: sent.record("state_request_part", s.state_request_part),
+            state_response: sent.record("state_response", s.state_response),
+            block_approval: sent.record("block_approval", s.block_approval),
+            block_request: sent.record("block_request", s.block_request),
+            block_headers_request: sent.record("block_headers_request", s.block_headers_request),
+            block: sent.record("block", s.block),
+            block_headers: sent.record("block_headers", s.block_headers),
+            challenge: sent.record("challenge", s.challenge),
+            network_info: sent.record("network_info", s.network_info),
+            announce_account: sent.record("announce_account", s.announce_account),
+            chunk_endorsement: sent.record("chunk_endorsement", s.chunk_endorsement),
+            epoch_sync_request: sent.record("epoch_sync_request", s.epoch_sync_request),
+            epoch_sync_response: sent.record("epoch_sync_response", s.epoch_sync_response),
+            optimistic_block_receiver: sent
+                .record("optimistic_block_receiver", s.optimistic_block_receiver),
+        };
+        (senders, sent)
+    }
+}
+
+/// Names of the `ClientSenderForNetwork` senders used, in the order in which
+/// the messages were sent. See `ClientSenderForNetworkBuilder::build_recording()`.
+#[derive(Clone, Default)]
+pub struct SentClientMessages(Arc<Mutex<Vec<&'static str>>>);
+
+impl SentClientMessages {
+    fn record<M: Send + 'static>(&self, name: &'static str, sender: Sender<M>) -> Sender<M> {
+        let sent = self.0.clone();
+        Sender::from_fn(move |msg: M| {
+            sent.lock().unwrap().push(name);
+            sender.send(msg);
+        })
+    }
+
+    pub fn all(&self) -> Vec<&'static str> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn count(&self, name: &str) -> usize {
+        self.0.lock().unwrap().iter().filter(|sent| **sent == name).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientSenderForNetworkBuilder;
+    use near_async::messaging::Sender;
+    use near_network::client::{SetNetworkInfo, StateResponseReceived};
+    use near_network::types::NetworkInfo;
+    use near_primitives::network::PeerId;
+    use near_primitives::state_sync::{
+        ShardStateSyncResponse, ShardStateSyncResponseV2, StateResponseInfo, StateResponseInfoV2,
+    };
+    use near_primitives::types::ShardId;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_client_sender_for_network_builder() {
+        let state_responses = Arc::new(Mutex::new(0));
+        let (senders, sent) = ClientSenderForNetworkBuilder::new()
+            .with(|s| {
+                let state_responses = state_responses.clone();
+                s.state_response = Sender::from_fn(move |_| {
+                    *state_responses.lock().unwrap() += 1;
+                });
+            })
+            .build_recording();
+
+        let state_response = StateResponseInfo::V2(Box::new(StateResponseInfoV2 {
+            shard_id: ShardId::new(0),
+            sync_hash: Default::default(),
+            state_response: ShardStateSyncResponse::V2(ShardStateSyncResponseV2 {
+                header: None,
+                part: None,
+            }),
+        }));
+        drop(senders.state_response.send_async(StateResponseReceived {
+            peer_id: PeerId::random(),
+            state_response_info: Box::new(state_response),
+        }));
+        senders.network_info.send(SetNetworkInfo(NetworkInfo::default()));
+
+        assert_eq!(*state_responses.lock().unwrap(), 1);
+        assert_eq!(sent.all(), vec!["state_response", "network_info"]);
+        assert_eq!(sent.count("block"), 0);
+    }
+}
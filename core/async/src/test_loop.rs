@@ -103,6 +103,10 @@ pub struct TestLoopV2 {
     /// If present, a function to call to print something every time an event is
     /// handled. Intended only for debugging.
     every_event_callback: Option<Box<dyn FnMut(&TestLoopData)>>,
+    /// If present, a function to call after every handled event, with the
+    /// identifier of the event. Used to attribute side effects of the event,
+    /// like metric updates, to the node that handled it.
+    after_event_callback: Option<Box<dyn FnMut(&str, &TestLoopData)>>,
     /// All events with this identifier are ignored in testloop execution environment.
     denylisted_identifiers: HashSet<String>,
 }
@@ -212,6 +216,7 @@ impl TestLoopV2 {
             clock: FakeClock::default(),
             shutting_down,
             every_event_callback: None,
+            after_event_callback: None,
             denylisted_identifiers: HashSet::new(),
         }
     }
@@ -273,6 +278,13 @@ impl TestLoopV2 {
         self.every_event_callback = Some(Box::new(callback));
     }
 
+    pub fn set_after_event_callback(
+        &mut self,
+        callback: impl FnMut(&str, &TestLoopData) + 'static,
+    ) {
+        self.after_event_callback = Some(Box::new(callback));
+    }
+
     /// Helper to push events we have just received into the heap.
     fn queue_received_events(&mut self) {
         for event in self.pending_events.lock().unwrap().events.drain(..) {
@@ -366,6 +378,10 @@ impl TestLoopV2 {
 
             let callback = event.event.callback;
             callback(&mut self.data);
+
+            if let Some(callback) = &mut self.after_event_callback {
+                callback(&event.event.identifier, &self.data);
+            }
         }
 
         // Push any new events into the queue. Do this before emitting the end log line,
@@ -401,12 +417,28 @@ impl TestLoopV2 {
     /// advance. If it returns true, execution stops before advancing the clock.
     pub fn run_until(
         &mut self,
-        mut condition: impl FnMut(&mut TestLoopData) -> bool,
+        condition: impl FnMut(&mut TestLoopData) -> bool,
         maximum_duration: Duration,
     ) {
+        assert!(
+            self.try_run_until(condition, maximum_duration),
+            "run_until did not fulfill the condition within the given deadline"
+        );
+    }
+
+    /// Same as `run_until`, but returns false instead of panicking if the
+    /// condition is not fulfilled within the maximum duration. In that case,
+    /// the clock is advanced to the deadline.
+    pub fn try_run_until(
+        &mut self,
+        mut condition: impl FnMut(&mut TestLoopData) -> bool,
+        maximum_duration: Duration,
+    ) -> bool {
         let deadline = self.current_time + maximum_duration;
-        let mut decider = move |next_time, data: &mut TestLoopData| {
+        let mut fulfilled = false;
+        let mut decider = |next_time, data: &mut TestLoopData| {
             if condition(data) {
+                fulfilled = true;
                 return AdvanceDecision::Stop;
             }
             if let Some(next_time) = next_time {
@@ -414,11 +446,12 @@ impl TestLoopV2 {
                     return AdvanceDecision::AdvanceToNextEvent;
                 }
             }
-            panic!("run_until did not fulfill the condition within the given deadline");
+            AdvanceDecision::AdvanceToAndStop(deadline)
         };
         while let Some(event) = self.advance_till_next_event(&mut decider) {
             self.process_event(event);
         }
+        fulfilled
     }
 
     pub fn shutdown_and_drain_remaining_events(mut self, maximum_duration: Duration) {
@@ -8,7 +8,7 @@ use tracing_opentelemetry::OpenTelemetryLayer;
 use tracing_subscriber::filter::{Filtered, Targets};
 use tracing_subscriber::layer::Layered;
 use tracing_subscriber::reload::Handle;
-use tracing_subscriber::{EnvFilter, Registry, fmt, reload};
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, reload};
 
 static LOG_LAYER_RELOAD_HANDLE: OnceLock<
     Handle<EnvFilter, log_counter::LogCountingLayer<Registry>>,
@@ -29,14 +29,8 @@ pub(crate) type LogLayer<Inner> = Layered<
     Inner,
 >;
 
-pub(crate) type SimpleLogLayer<Inner, W> = Layered<
-    Filtered<
-        fmt::Layer<Inner, fmt::format::DefaultFields, fmt::format::Format, W>,
-        EnvFilter,
-        Inner,
-    >,
-    Inner,
->;
+pub(crate) type SimpleLogLayer<Inner> =
+    Layered<Filtered<Box<dyn Layer<Inner> + Send + Sync>, EnvFilter, Inner>, Inner>;
 
 pub(crate) type TracingLayer<Inner> = Layered<
     Filtered<OpenTelemetryLayer<Inner, Tracer>, reload::Layer<Targets, Inner>, Inner>,
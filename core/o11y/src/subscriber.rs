@@ -52,6 +52,18 @@ pub struct Options {
     /// Enable JSON output of IO events, written to a file.
     #[clap(long)]
     record_io_trace: Option<PathBuf>,
+
+    /// Format of the log lines. Only honoured by `default_subscriber`.
+    #[clap(skip)]
+    log_format: LogFormat,
+}
+
+impl Options {
+    /// Sets the format of the log lines written by `default_subscriber`.
+    pub fn with_log_format(mut self, log_format: LogFormat) -> Self {
+        self.log_format = log_format;
+        self
+    }
 }
 
 impl<S: tracing::Subscriber + Send + Sync> DefaultSubscriberGuard<S> {
@@ -92,6 +104,16 @@ pub enum ColorOutput {
     Auto,
 }
 
+/// Format of the log lines.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum LogFormat {
+    /// Human-readable lines, as logged by neard.
+    #[default]
+    Pretty,
+    /// One JSON object per line, for ingestion into log pipelines.
+    Json,
+}
+
 fn is_terminal() -> bool {
     use std::io::IsTerminal;
     std::io::stderr().is_terminal()
@@ -102,19 +124,23 @@ fn add_simple_log_layer<S, W>(
     writer: W,
     ansi: bool,
     with_span_events: bool,
+    log_format: LogFormat,
     subscriber: S,
-) -> SimpleLogLayer<S, W>
+) -> SimpleLogLayer<S>
 where
-    S: tracing::Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
-    W: for<'writer> fmt::MakeWriter<'writer> + 'static,
+    S: tracing::Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+    W: for<'writer> fmt::MakeWriter<'writer> + Send + Sync + 'static,
 {
     let layer = fmt::layer()
         .with_ansi(ansi)
         .with_span_events(get_fmt_span(with_span_events))
-        .with_writer(writer)
-        .with_filter(filter);
+        .with_writer(writer);
+    let layer = match log_format {
+        LogFormat::Pretty => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
 
-    subscriber.with(layer)
+    subscriber.with(layer.with_filter(filter))
 }
 
 fn get_fmt_span(with_span_events: bool) -> fmt::format::FmtSpan {
@@ -209,6 +235,7 @@ pub fn default_subscriber(
         make_writer,
         color_output,
         options.log_span_events,
+        options.log_format,
         subscriber,
     );
 
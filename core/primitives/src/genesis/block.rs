@@ -1,9 +1,15 @@
+use std::collections::BTreeSet;
+use std::fmt;
 use std::sync::Arc;
 
 use near_crypto::{KeyType, Signature};
 use near_primitives_core::hash::CryptoHash;
-use near_primitives_core::types::{Balance, BlockHeight, MerkleHash, ProtocolVersion};
-use near_primitives_core::version::{PROD_GENESIS_PROTOCOL_VERSION, ProtocolFeature};
+use near_primitives_core::types::{
+    Balance, BlockHeight, MerkleHash, ProtocolVersion, ShardId, StateRoot,
+};
+use near_primitives_core::version::{
+    PROD_GENESIS_PROTOCOL_VERSION, PROTOCOL_VERSION, ProtocolFeature,
+};
 use near_time::Utc;
 
 use crate::block::{
@@ -11,12 +17,172 @@ use crate::block::{
     compute_bp_hash_from_validator_stakes,
 };
 use crate::block_body::{BlockBody, BlockBodyV1};
+use crate::challenge::Challenges;
 use crate::sharding::ShardChunkHeader;
 use crate::types::EpochId;
 use crate::types::validator_stake::ValidatorStake;
 
+/// Error returned when the genesis block can't be built from the given
+/// protocol version and chunks.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum GenesisError {
+    #[error("genesis chunk for shard {shard_id} is provided more than once")]
+    DuplicateShard { shard_id: ShardId },
+    #[error("genesis chunk for shard {shard_id} is not in the genesis shard layout")]
+    UnexpectedShard { shard_id: ShardId },
+    #[error("genesis chunk for shard {shard_id} of the genesis shard layout is missing")]
+    MissingShard { shard_id: ShardId },
+    #[error(
+        "genesis protocol version {0} is not supported, expected a version in {min}..={max}",
+        min = PROD_GENESIS_PROTOCOL_VERSION,
+        max = PROTOCOL_VERSION
+    )]
+    UnsupportedProtocolVersion(ProtocolVersion),
+}
+
+/// Merkle roots over the genesis chunks that the genesis block header commits
+/// to. Tools that receive a genesis block can use this to check that a set of
+/// chunks matches its header without rebuilding the whole block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesisRoots {
+    pub state_root: MerkleHash,
+    pub prev_chunk_outgoing_receipts_root: MerkleHash,
+    pub chunk_headers_root: MerkleHash,
+    pub chunk_tx_root: MerkleHash,
+    pub challenges_root: MerkleHash,
+}
+
+impl GenesisRoots {
+    /// Computes the roots from the genesis chunks, given in shard index order.
+    /// The genesis block never has challenges.
+    pub fn compute(chunks: &[ShardChunkHeader]) -> Self {
+        Self {
+            state_root: Block::compute_state_root(chunks),
+            prev_chunk_outgoing_receipts_root: Block::compute_chunk_prev_outgoing_receipts_root(
+                chunks,
+            ),
+            chunk_headers_root: Block::compute_chunk_headers_root(chunks).0,
+            chunk_tx_root: Block::compute_chunk_tx_root(chunks),
+            challenges_root: Block::compute_challenges_root(&Challenges::new()),
+        }
+    }
+
+    /// Returns the roots stored in the given genesis block header.
+    pub fn from_header(header: &BlockHeader) -> Self {
+        Self {
+            state_root: *header.prev_state_root(),
+            prev_chunk_outgoing_receipts_root: *header.prev_chunk_outgoing_receipts_root(),
+            chunk_headers_root: *header.chunk_headers_root(),
+            chunk_tx_root: *header.chunk_tx_root(),
+            challenges_root: *header.challenges_root(),
+        }
+    }
+}
+
+/// The arguments of `Block::genesis`. Two equal `GenesisInputs` build the
+/// same block, so tools can compare them to skip rebuilding and hashing a
+/// genesis block they already have. Inputs that only differ in chunk order
+/// are not equal, since the order changes the chunk roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesisInputs {
+    pub genesis_protocol_version: ProtocolVersion,
+    /// Genesis chunks, in the order they are passed to `Block::genesis`.
+    pub chunks: Vec<ShardChunkHeader>,
+    pub timestamp: Utc,
+    pub height: BlockHeight,
+    pub initial_gas_price: Balance,
+    pub initial_total_supply: Balance,
+    pub validator_stakes: Vec<ValidatorStake>,
+}
+
+/// The fields of a genesis block that tools building one usually want to check,
+/// returned by `Block::genesis_summary`. Its `Display` output is a short
+/// multi-line description meant to be printed or logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesisSummary {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    pub protocol_version: ProtocolVersion,
+    /// State root of each shard, in shard index order.
+    pub state_roots: Vec<(ShardId, StateRoot)>,
+    pub total_supply: Balance,
+    pub next_bp_hash: CryptoHash,
+}
+
+impl GenesisSummary {
+    pub fn num_shards(&self) -> usize {
+        self.state_roots.len()
+    }
+}
+
+impl fmt::Display for GenesisSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "genesis block {}", self.hash)?;
+        writeln!(f, "  height: {}", self.height)?;
+        writeln!(f, "  protocol version: {}", self.protocol_version)?;
+        writeln!(f, "  total supply: {}", self.total_supply)?;
+        writeln!(f, "  next bp hash: {}", self.next_bp_hash)?;
+        write!(f, "  shards: {}", self.num_shards())?;
+        for (shard_id, state_root) in &self.state_roots {
+            write!(f, "\n    shard {} state root: {}", shard_id, state_root)?;
+        }
+        Ok(())
+    }
+}
+
+/// Unix timestamp in seconds of the mainnet genesis block, which the timestamps
+/// returned by `genesis_timestamp_for_seed` are offset from.
+const SEEDED_GENESIS_TIMESTAMP_BASE: i64 = 1_595_350_551;
+
+/// Timestamps returned by `genesis_timestamp_for_seed` are at most this many
+/// seconds after `SEEDED_GENESIS_TIMESTAMP_BASE`, about 100 years.
+const SEEDED_GENESIS_TIMESTAMP_RANGE: u64 = 100 * 365 * 24 * 60 * 60;
+
+/// Returns a genesis timestamp that only depends on `seed`, to pass to
+/// `Block::genesis` instead of the current time where the genesis block must be
+/// reproducible, e.g. in test fixtures or when replaying a chain. The same seed
+/// always gives the same timestamp, and so the same genesis block hash.
+///
+/// The timestamp is a whole number of seconds after the mainnet genesis.
+pub fn genesis_timestamp_for_seed(seed: u64) -> Utc {
+    let offset = (seed % SEEDED_GENESIS_TIMESTAMP_RANGE) as i64;
+    Utc::from_unix_timestamp(SEEDED_GENESIS_TIMESTAMP_BASE + offset)
+        .expect("seeded genesis timestamp is in range")
+}
+
+/// Checks that there is exactly one genesis chunk for each of `shard_ids`, the
+/// shard ids of the genesis shard layout. Shard ids are not necessarily
+/// contiguous, and the order of the chunks is not checked because it follows
+/// the shard layout's shard index order rather than the shard ids.
+fn validate_genesis_chunks(
+    chunks: &[ShardChunkHeader],
+    shard_ids: &[ShardId],
+) -> Result<(), GenesisError> {
+    let expected: BTreeSet<ShardId> = shard_ids.iter().copied().collect();
+    let mut seen = BTreeSet::new();
+    for chunk in chunks {
+        let shard_id = chunk.shard_id();
+        if !seen.insert(shard_id) {
+            return Err(GenesisError::DuplicateShard { shard_id });
+        }
+        if !expected.contains(&shard_id) {
+            return Err(GenesisError::UnexpectedShard { shard_id });
+        }
+    }
+    if let Some(&shard_id) = expected.difference(&seen).next() {
+        return Err(GenesisError::MissingShard { shard_id });
+    }
+    Ok(())
+}
+
 impl Block {
     /// Returns genesis block for given genesis date and state root.
+    ///
+    /// Use `genesis_timestamp_for_seed` for the timestamp if the block hash
+    /// needs to be the same across runs.
+    ///
+    /// The protocol version and chunks are not validated, use `try_genesis`
+    /// to check them against the genesis shard layout.
     pub fn genesis(
         genesis_protocol_version: ProtocolVersion,
         chunks: Vec<ShardChunkHeader>,
@@ -26,6 +192,95 @@ impl Block {
         initial_total_supply: Balance,
         validator_stakes: &Vec<ValidatorStake>,
     ) -> Self {
+        Self::build_genesis(
+            genesis_protocol_version,
+            chunks,
+            timestamp,
+            height,
+            initial_gas_price,
+            initial_total_supply,
+            validator_stakes,
+        )
+    }
+
+    /// Same as `genesis`, but also returns the state root of each shard, in
+    /// the order of the block's chunks, for callers that need them to set up
+    /// their shard stores.
+    pub fn genesis_with_outputs(
+        genesis_protocol_version: ProtocolVersion,
+        chunks: Vec<ShardChunkHeader>,
+        timestamp: Utc,
+        height: BlockHeight,
+        initial_gas_price: Balance,
+        initial_total_supply: Balance,
+        validator_stakes: &Vec<ValidatorStake>,
+    ) -> (Self, Vec<(ShardId, StateRoot)>) {
+        let block = Self::genesis(
+            genesis_protocol_version,
+            chunks,
+            timestamp,
+            height,
+            initial_gas_price,
+            initial_total_supply,
+            validator_stakes,
+        );
+        let state_roots = block.chunk_state_roots();
+        (block, state_roots)
+    }
+
+    /// Fallible version of `genesis`. Returns an error if the chunks do not
+    /// cover each of `shard_ids`, the shard ids of the genesis shard layout,
+    /// exactly once, or if the protocol
+    /// version is outside of `PROD_GENESIS_PROTOCOL_VERSION..=PROTOCOL_VERSION`.
+    ///
+    /// The genesis block is built with the block, header and body versions
+    /// that were in use at `genesis_protocol_version`, so that historical
+    /// chains can be replayed from their original genesis:
+    ///
+    /// | Protocol version                             | Block   | Header | Body |
+    /// |----------------------------------------------|---------|--------|------|
+    /// | `PROD_GENESIS_PROTOCOL_VERSION` (29)         | BlockV1 | V1     | -    |
+    /// | 30 until `BlockHeaderV3` (49)                | BlockV2 | V2     | -    |
+    /// | `BlockHeaderV3` (49) until `BlockHeaderV4`   | BlockV2 | V3     | -    |
+    /// | `BlockHeaderV4` (63) until `StatelessValidation` | BlockV3 | V4 | V1   |
+    /// | `StatelessValidation` (69) until `ChunkEndorsementsInBlockHeader` | BlockV4 | V4 | V2 |
+    /// | `ChunkEndorsementsInBlockHeader` (72) onwards | BlockV4 | V5    | V2   |
+    pub fn try_genesis(
+        genesis_protocol_version: ProtocolVersion,
+        chunks: Vec<ShardChunkHeader>,
+        shard_ids: &[ShardId],
+        timestamp: Utc,
+        height: BlockHeight,
+        initial_gas_price: Balance,
+        initial_total_supply: Balance,
+        validator_stakes: &Vec<ValidatorStake>,
+    ) -> Result<Self, GenesisError> {
+        if !(PROD_GENESIS_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&genesis_protocol_version) {
+            return Err(GenesisError::UnsupportedProtocolVersion(genesis_protocol_version));
+        }
+        validate_genesis_chunks(&chunks, shard_ids)?;
+        Ok(Self::build_genesis(
+            genesis_protocol_version,
+            chunks,
+            timestamp,
+            height,
+            initial_gas_price,
+            initial_total_supply,
+            validator_stakes,
+        ))
+    }
+
+    fn build_genesis(
+        genesis_protocol_version: ProtocolVersion,
+        chunks: Vec<ShardChunkHeader>,
+        timestamp: Utc,
+        height: BlockHeight,
+        initial_gas_price: Balance,
+        initial_total_supply: Balance,
+        validator_stakes: &Vec<ValidatorStake>,
+    ) -> Self {
+        let next_bp_hash =
+            Self::compute_genesis_next_bp_hash(genesis_protocol_version, validator_stakes);
         if genesis_protocol_version == PROD_GENESIS_PROTOCOL_VERSION {
             Self::prod_genesis(
                 chunks,
@@ -33,25 +288,71 @@ impl Block {
                 height,
                 initial_gas_price,
                 initial_total_supply,
-                compute_bp_hash_from_validator_stakes(validator_stakes, false),
+                next_bp_hash,
             )
         } else {
-            Self::latest_genesis(
+            Self::versioned_genesis(
                 genesis_protocol_version,
                 chunks,
                 timestamp,
                 height,
                 initial_gas_price,
                 initial_total_supply,
-                compute_bp_hash_from_validator_stakes(
-                    validator_stakes,
-                    ProtocolFeature::BlockHeaderV3.enabled(genesis_protocol_version),
-                ),
+                next_bp_hash,
             )
         }
     }
 
-    fn latest_genesis(
+    /// Returns a genesis block without any chunks, for tests that only need a
+    /// valid head to build on. The block has no shards, so `chunks_included`
+    /// is 0, the chunk mask is empty and all the chunk roots are the default
+    /// hash. The gas price and total supply are 0 and there are no validators.
+    pub fn empty_genesis(
+        genesis_protocol_version: ProtocolVersion,
+        timestamp: Utc,
+        height: BlockHeight,
+    ) -> Self {
+        Self::genesis(genesis_protocol_version, vec![], timestamp, height, 0, 0, &vec![])
+    }
+
+    /// Returns the `next_bp_hash` that `genesis` puts in the header for the
+    /// given genesis validators, so that tools can check that a genesis block
+    /// they received was built for the validator set they expect. Before
+    /// `BlockHeaderV3` the hash only covers the V1 form of the stakes.
+    pub fn compute_genesis_next_bp_hash(
+        genesis_protocol_version: ProtocolVersion,
+        validator_stakes: &Vec<ValidatorStake>,
+    ) -> CryptoHash {
+        compute_bp_hash_from_validator_stakes(
+            validator_stakes,
+            ProtocolFeature::BlockHeaderV3.enabled(genesis_protocol_version),
+        )
+    }
+
+    /// Returns the height, shard state roots, total supply and other fields
+    /// worth checking after building a genesis block. The protocol version is
+    /// the one the genesis block was built with.
+    pub fn genesis_summary(&self) -> GenesisSummary {
+        let header = self.header();
+        GenesisSummary {
+            hash: *header.hash(),
+            height: header.height(),
+            protocol_version: header.latest_protocol_version(),
+            state_roots: self.chunk_state_roots(),
+            total_supply: header.total_supply(),
+            next_bp_hash: *header.next_bp_hash(),
+        }
+    }
+
+    fn chunk_state_roots(&self) -> Vec<(ShardId, StateRoot)> {
+        self.chunks().iter_raw().map(|chunk| (chunk.shard_id(), chunk.prev_state_root())).collect()
+    }
+
+    /// Builds the genesis block for protocol versions after
+    /// `PROD_GENESIS_PROTOCOL_VERSION`. The block, header and body versions
+    /// are picked by the regular versioned constructors based on
+    /// `genesis_protocol_version`.
+    fn versioned_genesis(
         genesis_protocol_version: ProtocolVersion,
         chunks: Vec<ShardChunkHeader>,
         timestamp: Utc,
@@ -75,16 +376,17 @@ impl Block {
             vrf_proof,
             chunk_endorsements,
         );
+        let roots = GenesisRoots::compute(body.chunks());
         let header = BlockHeader::genesis(
             genesis_protocol_version,
             height,
-            Block::compute_state_root(body.chunks()),
+            roots.state_root,
             body.compute_hash(),
-            Block::compute_chunk_prev_outgoing_receipts_root(body.chunks()),
-            Block::compute_chunk_headers_root(body.chunks()).0,
-            Block::compute_chunk_tx_root(body.chunks()),
+            roots.prev_chunk_outgoing_receipts_root,
+            roots.chunk_headers_root,
+            roots.chunk_tx_root,
             body.chunks().len() as u64,
-            Block::compute_challenges_root(body.challenges()),
+            roots.challenges_root,
             timestamp,
             initial_gas_price,
             initial_total_supply,
@@ -109,13 +411,14 @@ impl Block {
             vrf_proof: near_crypto::vrf::Proof([0; 64]),
         });
 
+        let roots = GenesisRoots::compute(body.chunks());
         let header = BlockHeader::prod_genesis(
             height,
-            Block::compute_state_root(body.chunks()),
-            Block::compute_chunk_prev_outgoing_receipts_root(body.chunks()),
-            Block::compute_chunk_headers_root(body.chunks()).0,
-            Block::compute_chunk_tx_root(body.chunks()),
-            Block::compute_challenges_root(body.challenges()),
+            roots.state_root,
+            roots.prev_chunk_outgoing_receipts_root,
+            roots.chunk_headers_root,
+            roots.chunk_tx_root,
+            roots.challenges_root,
             timestamp,
             initial_gas_price,
             initial_total_supply,
@@ -195,3 +498,429 @@ impl BlockHeader {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshDeserialize;
+    use near_primitives_core::hash::CryptoHash;
+    use near_primitives_core::types::{BlockHeight, ProtocolVersion, ShardId};
+    use near_primitives_core::version::{
+        PROD_GENESIS_PROTOCOL_VERSION, PROTOCOL_VERSION, ProtocolFeature,
+    };
+    use near_time::Utc;
+
+    use super::{
+        GenesisError, GenesisInputs, GenesisRoots, GenesisSummary, genesis_timestamp_for_seed,
+        validate_genesis_chunks,
+    };
+    use near_crypto::{KeyType, PublicKey};
+
+    use crate::bandwidth_scheduler::BandwidthRequests;
+    use crate::block::{Block, BlockHeader};
+    use crate::sharding::{ShardChunkHeader, ShardChunkHeaderV3};
+    use crate::types::validator_stake::ValidatorStake;
+    use crate::validator_signer::EmptyValidatorSigner;
+
+    fn chunks(shard_ids: &[u64]) -> Vec<ShardChunkHeader> {
+        shard_ids
+            .iter()
+            .map(|&id| ShardChunkHeader::new_dummy(0, ShardId::new(id), CryptoHash::default()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_genesis_chunks() {
+        let shard_ids = |ids: &[u64]| ids.iter().copied().map(ShardId::new).collect::<Vec<_>>();
+        assert_eq!(validate_genesis_chunks(&chunks(&[0]), &shard_ids(&[0])), Ok(()));
+        assert_eq!(validate_genesis_chunks(&chunks(&[2, 0, 1]), &shard_ids(&[0, 1, 2])), Ok(()));
+        // Shard ids of later shard layouts don't have to be contiguous.
+        assert_eq!(validate_genesis_chunks(&chunks(&[5, 3, 6]), &shard_ids(&[5, 3, 6])), Ok(()));
+        assert_eq!(
+            validate_genesis_chunks(&chunks(&[0, 2]), &shard_ids(&[0, 1])),
+            Err(GenesisError::UnexpectedShard { shard_id: ShardId::new(2) })
+        );
+        assert_eq!(
+            validate_genesis_chunks(&chunks(&[5, 3]), &shard_ids(&[5, 3, 6])),
+            Err(GenesisError::MissingShard { shard_id: ShardId::new(6) })
+        );
+        assert_eq!(
+            validate_genesis_chunks(&chunks(&[1, 0, 1]), &shard_ids(&[0, 1])),
+            Err(GenesisError::DuplicateShard { shard_id: ShardId::new(1) })
+        );
+    }
+
+    fn try_genesis(protocol_version: ProtocolVersion) -> Result<Block, GenesisError> {
+        Block::try_genesis(
+            protocol_version,
+            chunks(&[0]),
+            &[ShardId::new(0)],
+            genesis_timestamp_for_seed(0),
+            0,
+            1_000,
+            1_000,
+            &vec![],
+        )
+    }
+
+    #[test]
+    fn test_genesis_versions() {
+        let check = |feature: ProtocolFeature, is_expected: fn(&Block) -> bool| {
+            let protocol_version = feature.protocol_version();
+            assert!(!is_expected(&try_genesis(protocol_version - 1).unwrap()));
+            assert!(is_expected(&try_genesis(protocol_version).unwrap()));
+        };
+        check(ProtocolFeature::BlockHeaderV3, |block| {
+            matches!(block, Block::BlockV2(_))
+                && matches!(block.header(), BlockHeader::BlockHeaderV3(_))
+        });
+        check(ProtocolFeature::BlockHeaderV4, |block| {
+            matches!(block, Block::BlockV3(_))
+                && matches!(block.header(), BlockHeader::BlockHeaderV4(_))
+        });
+        check(ProtocolFeature::StatelessValidation, |block| {
+            matches!(block, Block::BlockV4(_))
+                && matches!(block.header(), BlockHeader::BlockHeaderV4(_))
+        });
+        check(ProtocolFeature::ChunkEndorsementsInBlockHeader, |block| {
+            matches!(block, Block::BlockV4(_))
+                && matches!(block.header(), BlockHeader::BlockHeaderV5(_))
+        });
+        let block = try_genesis(PROD_GENESIS_PROTOCOL_VERSION + 1).unwrap();
+        assert!(matches!(block, Block::BlockV2(_)));
+        assert!(matches!(block.header(), BlockHeader::BlockHeaderV2(_)));
+    }
+
+    /// Builds genesis blocks with several chunks and checks that recomputing
+    /// each root from the chunks gives the value stored in the header.
+    #[test]
+    fn test_genesis_roots_round_trip() {
+        let chunks: Vec<_> = (0..4)
+            .map(|id| {
+                let prev_block_hash = CryptoHash::hash_bytes(&[id as u8]);
+                ShardChunkHeader::new_dummy(0, ShardId::new(id), prev_block_hash)
+            })
+            .collect();
+        for protocol_version in [
+            PROD_GENESIS_PROTOCOL_VERSION + 1,
+            ProtocolFeature::BlockHeaderV4.protocol_version(),
+            ProtocolFeature::StatelessValidation.protocol_version(),
+            PROTOCOL_VERSION,
+        ] {
+            let block = Block::try_genesis(
+                protocol_version,
+                chunks.clone(),
+                &chunks.iter().map(|chunk| chunk.shard_id()).collect::<Vec<_>>(),
+                genesis_timestamp_for_seed(0),
+                0,
+                1_000,
+                1_000,
+                &vec![],
+            )
+            .unwrap();
+            let expected = GenesisRoots::from_header(block.header());
+            assert_eq!(GenesisRoots::compute(&chunks), expected);
+            // The chunks stored in the block give the same roots.
+            let block_chunks: Vec<_> = block.chunks().iter_raw().cloned().collect();
+            assert_eq!(GenesisRoots::compute(&block_chunks), expected);
+        }
+    }
+
+    /// Genesis blocks built with the same seed are identical, and different
+    /// seeds give different blocks.
+    #[test]
+    fn test_genesis_timestamp_for_seed() {
+        let genesis = |seed: u64| {
+            Block::genesis(
+                PROTOCOL_VERSION,
+                chunks(&[0, 1]),
+                genesis_timestamp_for_seed(seed),
+                0,
+                1_000,
+                1_000,
+                &vec![],
+            )
+        };
+        assert_eq!(genesis(7).hash(), genesis(7).hash());
+        assert_ne!(genesis(7).hash(), genesis(8).hash());
+
+        assert_eq!(genesis_timestamp_for_seed(0).unix_timestamp(), 1_595_350_551);
+        assert_eq!(genesis_timestamp_for_seed(u64::MAX), genesis_timestamp_for_seed(u64::MAX));
+        assert!(genesis_timestamp_for_seed(u64::MAX) > genesis_timestamp_for_seed(0));
+    }
+
+    /// A genesis block without chunks is consistent with its own header.
+    #[test]
+    fn test_empty_genesis() {
+        for protocol_version in
+            [PROD_GENESIS_PROTOCOL_VERSION, PROD_GENESIS_PROTOCOL_VERSION + 1, PROTOCOL_VERSION]
+        {
+            for height in [0, 7] {
+                let block =
+                    Block::empty_genesis(protocol_version, genesis_timestamp_for_seed(0), height);
+                assert_eq!(block.check_validity(), Ok(()));
+                let header = block.header();
+                assert_eq!(header.height(), height);
+                assert_eq!(header.latest_protocol_version(), protocol_version);
+                assert_eq!(header.chunks_included(), 0);
+                assert!(header.chunk_mask().is_empty());
+                assert!(header.verify_chunks_included());
+                assert_eq!(block.chunks().len(), 0);
+                assert_eq!(GenesisRoots::from_header(header), GenesisRoots::compute(&[]));
+                assert_eq!(*header.prev_state_root(), CryptoHash::default());
+                assert_eq!(block.genesis_summary().num_shards(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_genesis_inputs_eq() {
+        let inputs = GenesisInputs {
+            genesis_protocol_version: PROTOCOL_VERSION,
+            chunks: chunks(&[0, 1]),
+            timestamp: genesis_timestamp_for_seed(7),
+            height: 0,
+            initial_gas_price: 1_000,
+            initial_total_supply: 1_000,
+            validator_stakes: vec![],
+        };
+        assert_eq!(inputs, inputs.clone());
+
+        let other_timestamp =
+            GenesisInputs { timestamp: genesis_timestamp_for_seed(8), ..inputs.clone() };
+        assert_ne!(inputs, other_timestamp);
+
+        let other_chunks = GenesisInputs { chunks: chunks(&[1, 0]), ..inputs.clone() };
+        assert_ne!(inputs, other_chunks);
+    }
+
+    /// Chunks at `height` for shards `0..state_roots.len()`, with the given
+    /// state roots.
+    fn chunks_with_state_roots(
+        state_roots: &[CryptoHash],
+        height: BlockHeight,
+    ) -> Vec<ShardChunkHeader> {
+        state_roots
+            .iter()
+            .enumerate()
+            .map(|(id, state_root)| {
+                ShardChunkHeader::V3(ShardChunkHeaderV3::new(
+                    PROTOCOL_VERSION,
+                    CryptoHash::default(),
+                    *state_root,
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    height,
+                    ShardId::new(id as u64),
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                    Some(Default::default()),
+                    BandwidthRequests::default_for_protocol_version(PROTOCOL_VERSION),
+                    &EmptyValidatorSigner::default().into(),
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_genesis_summary() {
+        let state_roots: Vec<_> = (0..3).map(|id| CryptoHash::hash_bytes(&[id as u8])).collect();
+        let chunks = chunks_with_state_roots(&state_roots, 7);
+        let validator_stakes = vec![ValidatorStake::new(
+            "test0".parse().unwrap(),
+            PublicKey::empty(KeyType::ED25519),
+            1_000,
+        )];
+        for protocol_version in [PROD_GENESIS_PROTOCOL_VERSION + 1, PROTOCOL_VERSION] {
+            let block = Block::genesis(
+                protocol_version,
+                chunks.clone(),
+                genesis_timestamp_for_seed(0),
+                7,
+                1_000,
+                2_000,
+                &validator_stakes,
+            );
+            let summary = block.genesis_summary();
+            assert_eq!(
+                summary,
+                GenesisSummary {
+                    hash: *block.hash(),
+                    height: 7,
+                    protocol_version,
+                    state_roots: state_roots
+                        .iter()
+                        .enumerate()
+                        .map(|(id, state_root)| (ShardId::new(id as u64), *state_root))
+                        .collect(),
+                    total_supply: 2_000,
+                    next_bp_hash: Block::compute_genesis_next_bp_hash(
+                        protocol_version,
+                        &validator_stakes,
+                    ),
+                }
+            );
+            assert_eq!(summary.num_shards(), 3);
+            let printed = summary.to_string();
+            assert!(printed.starts_with(&format!("genesis block {}\n", block.hash())));
+            assert!(printed.contains(&format!("shard 2 state root: {}", state_roots[2])));
+        }
+    }
+
+    /// The state roots returned along with the genesis block are the ones in
+    /// its chunk headers.
+    #[test]
+    fn test_genesis_with_outputs() {
+        let state_roots: Vec<_> = (0..4).map(|id| CryptoHash::hash_bytes(&[id as u8])).collect();
+        let chunks = chunks_with_state_roots(&state_roots, 0);
+        let (block, returned_roots) = Block::genesis_with_outputs(
+            PROTOCOL_VERSION,
+            chunks.clone(),
+            genesis_timestamp_for_seed(0),
+            0,
+            1_000,
+            1_000,
+            &vec![],
+        );
+        let chunk_header_roots: Vec<_> = block
+            .chunks()
+            .iter_raw()
+            .map(|chunk| (chunk.shard_id(), chunk.prev_state_root()))
+            .collect();
+        assert_eq!(returned_roots, chunk_header_roots);
+        assert_eq!(
+            returned_roots,
+            state_roots
+                .iter()
+                .enumerate()
+                .map(|(id, state_root)| (ShardId::new(id as u64), *state_root))
+                .collect::<Vec<_>>()
+        );
+        // The block is the same as the one built by `genesis`.
+        let expected = Block::genesis(
+            PROTOCOL_VERSION,
+            chunks,
+            genesis_timestamp_for_seed(0),
+            0,
+            1_000,
+            1_000,
+            &vec![],
+        );
+        assert_eq!(block.hash(), expected.hash());
+    }
+
+    /// The `next_bp_hash` of a genesis block is the one computed for its
+    /// validators, and doesn't match a different validator set.
+    #[test]
+    fn test_genesis_next_bp_hash() {
+        let validator = |account_id: &str, stake| {
+            ValidatorStake::new(
+                account_id.parse().unwrap(),
+                PublicKey::empty(KeyType::ED25519),
+                stake,
+            )
+        };
+        let validator_stakes = vec![validator("test0", 1_000), validator("test1", 2_000)];
+        let other_stakes = vec![validator("test0", 1_000), validator("test1", 3_000)];
+        for protocol_version in [
+            PROD_GENESIS_PROTOCOL_VERSION + 1,
+            ProtocolFeature::BlockHeaderV3.protocol_version(),
+            PROTOCOL_VERSION,
+        ] {
+            let block = Block::genesis(
+                protocol_version,
+                chunks(&[0, 1]),
+                genesis_timestamp_for_seed(0),
+                0,
+                1_000,
+                1_000,
+                &validator_stakes,
+            );
+            let expected = Block::compute_genesis_next_bp_hash(protocol_version, &validator_stakes);
+            assert_eq!(block.header().next_bp_hash(), &expected);
+            assert_ne!(
+                block.header().next_bp_hash(),
+                &Block::compute_genesis_next_bp_hash(protocol_version, &other_stakes)
+            );
+        }
+    }
+
+    #[test]
+    fn test_genesis_unsupported_version() {
+        for protocol_version in [PROD_GENESIS_PROTOCOL_VERSION - 1, PROTOCOL_VERSION + 1] {
+            assert_eq!(
+                try_genesis(protocol_version).unwrap_err(),
+                GenesisError::UnsupportedProtocolVersion(protocol_version)
+            );
+        }
+    }
+
+    /// The prod genesis hash must stay fixed forever, so any change to the
+    /// serialized inner parts of `BlockHeaderV1` has to be caught here.
+    #[test]
+    fn test_prod_genesis_header_borsh_round_trip() {
+        let header = BlockHeader::prod_genesis(
+            9820210,
+            CryptoHash::hash_bytes(b"state_root"),
+            CryptoHash::hash_bytes(b"outgoing_receipts_root"),
+            CryptoHash::hash_bytes(b"chunk_headers_root"),
+            CryptoHash::hash_bytes(b"chunk_tx_root"),
+            CryptoHash::hash_bytes(b"challenges_root"),
+            Utc::from_unix_timestamp_nanos(1_595_350_551_591_948_000).unwrap(),
+            1_000_000_000,
+            1_000_000_000 * 10u128.pow(24),
+            CryptoHash::hash_bytes(b"next_bp_hash"),
+        );
+        let BlockHeader::BlockHeaderV1(header_v1) = &header else {
+            panic!("prod genesis header should be BlockHeaderV1");
+        };
+
+        let bytes = borsh::to_vec(&header).unwrap();
+        let decoded = BlockHeader::try_from_slice(&bytes).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.hash(), header.hash());
+
+        let recomputed = BlockHeader::compute_hash(
+            *header.prev_hash(),
+            &borsh::to_vec(&header_v1.inner_lite).unwrap(),
+            &borsh::to_vec(&header_v1.inner_rest).unwrap(),
+        );
+        assert_eq!(&recomputed, header.hash());
+        assert_eq!(
+            header.hash(),
+            &"J7tCYSPEPo1xfSnRaN2FBuhNLfUgj5FEzXn6efueXbcD".parse::<CryptoHash>().unwrap()
+        );
+    }
+
+    /// Pins the hash of a genesis block built at the latest protocol version
+    /// from fixed inputs. A new expected value means that the genesis format
+    /// changed: the block or header layout, the way it is hashed, or what
+    /// `Block::genesis` puts in it. Since the header stores the protocol
+    /// version, bumping `PROTOCOL_VERSION` changes the hash as well.
+    #[test]
+    #[cfg(not(feature = "nightly"))]
+    fn test_latest_genesis_hash() {
+        let validator_stakes = vec![ValidatorStake::new(
+            "test0".parse().unwrap(),
+            PublicKey::empty(KeyType::ED25519),
+            5 * 10u128.pow(30),
+        )];
+        let block = Block::genesis(
+            PROTOCOL_VERSION,
+            vec![],
+            genesis_timestamp_for_seed(0),
+            0,
+            1_000_000_000,
+            10u128.pow(33),
+            &validator_stakes,
+        );
+        assert_eq!(
+            block.hash(),
+            &"AjVrZ1aSDpRSYWEYJprGrCj1Mr5pZ6fzutMei9sW1AEE".parse::<CryptoHash>().unwrap()
+        );
+    }
+}
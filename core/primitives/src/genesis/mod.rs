@@ -5,6 +5,9 @@ mod block;
 #[cfg(feature = "solomon")]
 mod chunk;
 
+pub use block::{
+    GenesisError, GenesisInputs, GenesisRoots, GenesisSummary, genesis_timestamp_for_seed,
+};
 #[cfg(feature = "solomon")]
 pub use chunk::genesis_chunks;
 
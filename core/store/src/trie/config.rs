@@ -37,6 +37,11 @@ pub struct TrieConfig {
     pub load_memtries_for_shards: Vec<ShardUId>,
     /// Whether mem-trie should be loaded for each tracked shard.
     pub load_memtries_for_tracked_shards: bool,
+    /// Maximum memory a single mem-trie loaded because of the options above may
+    /// take. Loading is aborted once the trie grows past it, and the shard is read
+    /// from disk instead. Only available for tests, to exercise that fallback.
+    #[cfg(feature = "test_features")]
+    pub memtrie_memory_limit: Option<bytesize::ByteSize>,
 }
 
 impl TrieConfig {
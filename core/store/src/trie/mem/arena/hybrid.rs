@@ -128,12 +128,11 @@ impl HybridArena {
     }
 
     /// Number of active allocations (alloc calls minus dealloc calls).
-    #[cfg(test)]
     pub fn num_active_allocs(&self) -> usize {
         self.allocator.num_active_allocs()
     }
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test_features"))]
     pub fn active_allocs_bytes(&self) -> usize {
         self.allocator.active_allocs_bytes()
     }
@@ -69,7 +69,7 @@ impl STArena {
         self.allocator.num_active_allocs()
     }
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test_features"))]
     pub fn active_allocs_bytes(&self) -> usize {
         self.allocator.active_allocs_bytes()
     }
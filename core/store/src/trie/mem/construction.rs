@@ -200,6 +200,12 @@ impl<'a, A: ArenaMut> TrieConstructor<'a, A> {
         }
     }
 
+    /// The arena the trie is being constructed in.
+    #[cfg(any(test, feature = "test_features"))]
+    pub fn arena(&self) -> &A {
+        self.arena
+    }
+
     fn recycle_segment(&mut self, segment: TrieConstructionSegment) {
         self.trail_freelist.free(segment.trail);
     }
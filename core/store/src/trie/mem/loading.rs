@@ -2,13 +2,15 @@ use super::arena::single_thread::STArena;
 use super::memtries::MemTries;
 use super::node::MemTrieNodeId;
 use crate::adapter::StoreAdapter;
-use crate::flat::FlatStorageStatus;
+use crate::flat::{BlockInfo, FlatStorageStatus};
 use crate::trie::mem::arena::Arena;
 use crate::trie::mem::construction::TrieConstructor;
 use crate::trie::mem::memtrie_update::TrackingMode;
 use crate::trie::mem::parallel_loader::load_memtrie_in_parallel;
 use crate::trie::ops::insert_delete::GenericTrieUpdateInsertDelete;
 use crate::{DBCol, NibbleSlice, Store};
+#[cfg(any(test, feature = "test_features"))]
+use bytesize::ByteSize;
 use near_primitives::errors::StorageError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::{ShardUId, get_block_shard_uid};
@@ -50,6 +52,56 @@ fn load_trie_from_flat_state(
     };
 
     info!(target: "memtrie", shard_uid=%shard_uid, "Done loading trie from flat state, took {:?}", load_start.elapsed());
+    Ok(memtries_from_arena(shard_uid, state_root, block_height, arena, root_id))
+}
+
+/// Same as `load_trie_from_flat_state` without `parallelize`, but stops
+/// loading and returns `None` as soon as the trie takes more memory than
+/// `memory_limit`.
+#[cfg(any(test, feature = "test_features"))]
+fn load_trie_from_flat_state_with_limit(
+    store: &Store,
+    shard_uid: ShardUId,
+    state_root: StateRoot,
+    block_height: BlockHeight,
+    memory_limit: ByteSize,
+) -> Result<Option<MemTries>, StorageError> {
+    if state_root == StateRoot::default() {
+        return Ok(Some(MemTries::new(shard_uid)));
+    }
+
+    info!(target: "memtrie", shard_uid=%shard_uid, %memory_limit, "Loading trie from flat state with a memory limit...");
+    let mut arena = STArena::new(shard_uid.to_string());
+    let mut recon = TrieConstructor::new(&mut arena);
+    let mut num_keys_loaded = 0;
+    for item in store.flat_store().iter(shard_uid) {
+        let (key, value) = item?;
+        recon.add_leaf(NibbleSlice::new(&key), value);
+        num_keys_loaded += 1;
+        let memory_usage = recon.arena().active_allocs_bytes() as u64;
+        if memory_usage > memory_limit.as_u64() {
+            info!(
+                target: "memtrie",
+                %shard_uid,
+                num_keys_loaded,
+                memory_usage,
+                %memory_limit,
+                "Memtrie exceeds memory limit, stopped loading"
+            );
+            return Ok(None);
+        }
+    }
+    let root_id = recon.finalize().expect("state root cannot be empty");
+    Ok(Some(memtries_from_arena(shard_uid, state_root, block_height, arena, root_id)))
+}
+
+fn memtries_from_arena(
+    shard_uid: ShardUId,
+    state_root: StateRoot,
+    block_height: BlockHeight,
+    arena: STArena,
+    root_id: MemTrieNodeId,
+) -> MemTries {
     let root = root_id.as_ptr(arena.memory());
     assert_eq!(
         root.view().node_hash(),
@@ -57,7 +109,7 @@ fn load_trie_from_flat_state(
         "In-memory trie for shard {} has incorrect state root",
         shard_uid
     );
-    Ok(MemTries::new_from_arena_and_root(shard_uid, block_height, arena, root_id))
+    MemTries::new_from_arena_and_root(shard_uid, block_height, arena, root_id)
 }
 
 fn load_memtrie_single_thread(
@@ -121,8 +173,51 @@ pub fn load_trie_from_flat_state_and_delta(
     parallelize: bool,
 ) -> Result<MemTries, StorageError> {
     debug!(target: "memtrie", %shard_uid, "Loading base trie from flat state...");
-    let flat_store = store.flat_store();
-    let flat_head = match flat_store.get_flat_storage_status(shard_uid)? {
+    let (flat_head, state_root) = get_flat_head_and_state_root(store, shard_uid, state_root)?;
+    let mut memtries =
+        load_trie_from_flat_state(&store, shard_uid, state_root, flat_head.height, parallelize)?;
+    apply_flat_state_deltas(store, shard_uid, &mut memtries)?;
+    Ok(memtries)
+}
+
+/// Same as `load_trie_from_flat_state_and_delta`, but stops loading and returns
+/// `None` as soon as the tries take more memory than `memory_limit`. The base
+/// trie is always loaded in a single thread, because the parallel loader can't
+/// be stopped half way. Only used by tests, see
+/// `TrieConfig::memtrie_memory_limit`.
+#[cfg(any(test, feature = "test_features"))]
+pub fn load_trie_from_flat_state_and_delta_with_limit(
+    store: &Store,
+    shard_uid: ShardUId,
+    state_root: Option<StateRoot>,
+    memory_limit: ByteSize,
+) -> Result<Option<MemTries>, StorageError> {
+    debug!(target: "memtrie", %shard_uid, "Loading base trie from flat state...");
+    let (flat_head, state_root) = get_flat_head_and_state_root(store, shard_uid, state_root)?;
+    let Some(mut memtries) = load_trie_from_flat_state_with_limit(
+        &store,
+        shard_uid,
+        state_root,
+        flat_head.height,
+        memory_limit,
+    )?
+    else {
+        return Ok(None);
+    };
+    apply_flat_state_deltas(store, shard_uid, &mut memtries)?;
+    if memtries.arena().active_allocs_bytes() as u64 > memory_limit.as_u64() {
+        info!(target: "memtrie", %shard_uid, %memory_limit, "Memtrie exceeds memory limit after applying deltas");
+        return Ok(None);
+    }
+    Ok(Some(memtries))
+}
+
+fn get_flat_head_and_state_root(
+    store: &Store,
+    shard_uid: ShardUId,
+    state_root: Option<StateRoot>,
+) -> Result<(BlockInfo, StateRoot), StorageError> {
+    let flat_head = match store.flat_store().get_flat_storage_status(shard_uid)? {
         FlatStorageStatus::Ready(status) => status.flat_head,
         other => {
             return Err(StorageError::MemTrieLoadingError(format!(
@@ -136,11 +231,15 @@ pub fn load_trie_from_flat_state_and_delta(
         Some(state_root) => state_root,
         None => get_state_root(store, flat_head.hash, shard_uid)?,
     };
+    Ok((flat_head, state_root))
+}
 
-    let mut memtries =
-        load_trie_from_flat_state(&store, shard_uid, state_root, flat_head.height, parallelize)
-            .unwrap();
-
+fn apply_flat_state_deltas(
+    store: &Store,
+    shard_uid: ShardUId,
+    memtries: &mut MemTries,
+) -> Result<(), StorageError> {
+    let flat_store = store.flat_store();
     debug!(target: "memtrie", %shard_uid, "Loading flat state deltas...");
     // We load the deltas in order of height, so that we always have the previous state root
     // already loaded.
@@ -174,12 +273,13 @@ pub fn load_trie_from_flat_state_and_delta(
     }
 
     debug!(target: "memtrie", %shard_uid, "Done loading memtries for shard");
-    Ok(memtries)
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::load_trie_from_flat_state_and_delta;
+    use crate::adapter::flat_store::encode_flat_state_db_key;
     use crate::adapter::{StoreAdapter, StoreUpdateAdapter};
     use crate::flat::test_utils::MockChain;
     use crate::flat::{BlockInfo, FlatStorageReadyStatus, FlatStorageStatus};
@@ -187,11 +287,14 @@ mod tests {
         TestTriesBuilder, create_test_store, simplify_changes, test_populate_flat_storage,
         test_populate_trie,
     };
-    use crate::trie::mem::loading::load_trie_from_flat_state;
+    use crate::trie::mem::loading::{
+        load_trie_from_flat_state, load_trie_from_flat_state_with_limit,
+    };
     use crate::trie::mem::lookup::memtrie_lookup;
     use crate::trie::mem::nibbles_utils::{all_two_nibble_nibbles, multi_hex_to_nibbles};
     use crate::trie::update::TrieUpdateResult;
     use crate::{DBCol, KeyLookupMode, NibbleSlice, ShardTries, Store, Trie, TrieUpdate};
+    use bytesize::ByteSize;
     use near_primitives::bandwidth_scheduler::BandwidthRequests;
     use near_primitives::congestion_info::CongestionInfo;
     use near_primitives::hash::CryptoHash;
@@ -368,6 +471,47 @@ mod tests {
         check_random(32, 100000, 1);
     }
 
+    /// Checks that loading stops as soon as the memory limit is exceeded,
+    /// without reading the rest of the flat storage.
+    #[test]
+    fn test_memtrie_load_stops_at_memory_limit() {
+        let (shard_tries, shard_layout) = TestTriesBuilder::new().with_flat_storage(true).build2();
+        let shard_uid = shard_layout.shard_uids().next().unwrap();
+
+        let changes = (0..1000u32)
+            .map(|i| {
+                let key = [&[0u8][..], &i.to_be_bytes()].concat();
+                (key.clone(), Some(key))
+            })
+            .collect::<Vec<_>>();
+        test_populate_flat_storage(
+            &shard_tries,
+            shard_uid,
+            &CryptoHash::default(),
+            &CryptoHash::default(),
+            &changes,
+        );
+        let state_root = test_populate_trie(&shard_tries, &Trie::EMPTY_ROOT, shard_uid, changes);
+
+        // A corrupted entry sorted after all the others: a full load fails on it.
+        let store = shard_tries.store().store();
+        let mut store_update = store.store_update();
+        store_update.set(DBCol::FlatState, &encode_flat_state_db_key(shard_uid, &[0xff]), &[0xff]);
+        store_update.commit().unwrap();
+        assert!(load_trie_from_flat_state(&store, shard_uid, state_root, 123, false).is_err());
+
+        // With a limit much smaller than the trie, loading gives up before reaching it.
+        let memtries = load_trie_from_flat_state_with_limit(
+            &store,
+            shard_uid,
+            state_root,
+            123,
+            ByteSize::kb(1),
+        )
+        .unwrap();
+        assert!(memtries.is_none());
+    }
+
     #[test]
     fn test_memtrie_load_with_delta() {
         let test_key = TrieKey::ContractData {
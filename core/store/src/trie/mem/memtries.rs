@@ -220,6 +220,13 @@ impl MemTries {
     pub fn num_roots(&self) -> usize {
         self.heights.iter().map(|(_, v)| v.len()).sum()
     }
+
+    /// Number of trie nodes currently held in memory, across all roots.
+    /// Every node occupies exactly one arena allocation.
+    /// Used for unit testing and integration testing.
+    pub fn num_nodes(&self) -> usize {
+        self.arena.num_active_allocs()
+    }
 }
 
 #[cfg(test)]
@@ -478,6 +478,51 @@ impl ShardTries {
         Ok(())
     }
 
+    /// Returns `TrieConfig::memtrie_memory_limit`, unless the shard is pending
+    /// resharding, in which case its memtrie is needed whatever its size.
+    #[cfg(feature = "test_features")]
+    fn memtrie_memory_limit(
+        &self,
+        shard_uid: &ShardUId,
+        shard_uids_pending_resharding: &HashSet<ShardUId>,
+    ) -> Option<bytesize::ByteSize> {
+        if shard_uids_pending_resharding.contains(shard_uid) {
+            return None;
+        }
+        self.0.trie_config.memtrie_memory_limit
+    }
+
+    /// Same as `load_memtrie`, but if the trie takes more than `memory_limit`,
+    /// loading is aborted and the shard is read from disk instead.
+    #[cfg(feature = "test_features")]
+    fn load_memtrie_with_limit(
+        &self,
+        shard_uid: &ShardUId,
+        state_root: Option<StateRoot>,
+        memory_limit: bytesize::ByteSize,
+    ) -> Result<(), StorageError> {
+        tracing::info!(target: "memtrie", "Loading trie to memory for shard {:?}...", shard_uid);
+        let Some(memtries) =
+            crate::trie::mem::loading::load_trie_from_flat_state_and_delta_with_limit(
+                &self.0.store.store(),
+                *shard_uid,
+                state_root,
+                memory_limit,
+            )?
+        else {
+            tracing::warn!(
+                target: "memtrie",
+                ?shard_uid,
+                %memory_limit,
+                "Memtrie exceeds the memory limit, not loading it. The shard will be read from disk instead."
+            );
+            return Ok(());
+        };
+        self.0.memtries.write().unwrap().insert(*shard_uid, Arc::new(RwLock::new(memtries)));
+        tracing::info!(target: "memtrie", "Memtrie loading complete for shard {:?}", shard_uid);
+        Ok(())
+    }
+
     /// Loads in-memory trie upon catchup, if it is enabled.
     /// Requires state root because `ChunkExtra` is not available at the time mem-trie is being loaded.
     /// Mem-tries of shards that are pending resharding must be loaded in any case.
@@ -495,6 +540,12 @@ impl ShardTries {
         // It should not happen that memtrie is already loaded for a shard
         // for which we just did state sync.
         debug_assert!(!self.0.memtries.read().unwrap().contains_key(shard_uid));
+        #[cfg(feature = "test_features")]
+        if let Some(memory_limit) =
+            self.memtrie_memory_limit(shard_uid, shard_uids_pending_resharding)
+        {
+            return self.load_memtrie_with_limit(shard_uid, Some(*state_root), memory_limit);
+        }
         self.load_memtrie(shard_uid, Some(*state_root), false)
     }
 
@@ -505,7 +556,8 @@ impl ShardTries {
     /// The `shard_uids_pending_resharding` parameter is used to load memtries
     /// for shards that are not configured to be loaded but should be loaded
     /// anyway. This is used when a shard is about to be resharded and we need
-    /// to have the memtries loaded for it.
+    /// to have the memtries loaded for it. The `memtrie_memory_limit` from the
+    /// test config doesn't apply to these.
     pub fn load_memtries_for_enabled_shards(
         &self,
         tracked_shards: &[ShardUId],
@@ -533,7 +585,15 @@ impl ShardTries {
         tracing::info!(target: "memtrie", "Loading tries to memory for shards {:?}...", shard_uids_to_load);
         shard_uids_to_load
             .par_iter()
-            .map(|shard_uid| self.load_memtrie(shard_uid, None, parallelize))
+            .map(|shard_uid| {
+                #[cfg(feature = "test_features")]
+                if let Some(memory_limit) =
+                    self.memtrie_memory_limit(shard_uid, shard_uids_pending_resharding)
+                {
+                    return self.load_memtrie_with_limit(shard_uid, None, memory_limit);
+                }
+                self.load_memtrie(shard_uid, None, parallelize)
+            })
             .collect::<Result<(), StorageError>>()?;
 
         tracing::info!(target: "memtrie", "Memtries loading complete for shards {:?}", shard_uids_to_load);
@@ -5,6 +5,9 @@
 //! the executable is built from a release branch or `trunk` if it’s built from
 //! master.  Build is a `git describe` of the commit the binary was built at
 //! (for official releases it should be the same as version).
+//!
+//! If `NEARD_PROTOCOL_SCHEMA` is set, the protocol schema file it points to is
+//! embedded into the binary, see `neard print-protocol-schema --embedded`.
 
 use anyhow::{Result, anyhow};
 
@@ -116,6 +119,24 @@ fn get_enabled_features() -> String {
     features.join(", ")
 }
 
+/// Copies the protocol schema written by `protocol-schema-check
+/// --write-embedded` to `OUT_DIR`, from where it's included into the binary.
+/// An empty file is written if `NEARD_PROTOCOL_SCHEMA` is not set.
+fn embed_protocol_schema() -> Result<()> {
+    let out_path = std::path::PathBuf::from(env("OUT_DIR")?).join("protocol_schema.toml");
+    println!("cargo:rerun-if-env-changed=NEARD_PROTOCOL_SCHEMA");
+    let schema = match std::env::var_os("NEARD_PROTOCOL_SCHEMA") {
+        Some(path) => {
+            let path = std::path::PathBuf::from(path);
+            println!("cargo:rerun-if-changed={}", path.display());
+            std::fs::read(&path).map_err(|err| anyhow!("{}: {}", path.display(), err))?
+        }
+        None => Vec::new(),
+    };
+    std::fs::write(out_path, schema)?;
+    Ok(())
+}
+
 fn main() {
     if let Err(err) = try_main() {
         eprintln!("{}", err);
@@ -142,5 +163,7 @@ fn try_main() -> Result<()> {
 
     println!("cargo:rustc-env=NEARD_FEATURES={}", get_enabled_features());
 
+    embed_protocol_schema()?;
+
     Ok(())
 }
@@ -152,6 +152,9 @@ impl NeardCmd {
             NeardSubCommand::DumpTestContracts(cmd) => {
                 cmd.run()?;
             }
+            NeardSubCommand::PrintProtocolSchema(cmd) => {
+                cmd.run()?;
+            }
         };
         Ok(())
     }
@@ -261,6 +264,9 @@ pub(super) enum NeardSubCommand {
 
     /// Placeholder for test contracts subcommand
     DumpTestContracts(DumpTestContractCommand),
+
+    /// Print the hashes of the protocol structs this binary was built with.
+    PrintProtocolSchema(PrintProtocolSchemaCommand),
 }
 
 #[allow(unused)]
@@ -815,6 +821,36 @@ impl ValidateConfigCommand {
     }
 }
 
+/// Protocol schema computed by `protocol-schema-check --write-embedded`, or
+/// empty if `NEARD_PROTOCOL_SCHEMA` wasn't set at build time.
+static EMBEDDED_PROTOCOL_SCHEMA: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/protocol_schema.toml"));
+
+#[derive(clap::Parser)]
+pub(super) struct PrintProtocolSchemaCommand {
+    /// Print the schema embedded into the binary at build time. This is
+    /// currently the only supported source.
+    #[clap(long)]
+    embedded: bool,
+}
+
+impl PrintProtocolSchemaCommand {
+    pub(super) fn run(&self) -> anyhow::Result<()> {
+        if !self.embedded {
+            anyhow::bail!("please pass --embedded to print the schema embedded at build time");
+        }
+        if EMBEDDED_PROTOCOL_SCHEMA.is_empty() {
+            anyhow::bail!(
+                "this binary was built without a protocol schema. Build it with \
+                 NEARD_PROTOCOL_SCHEMA set to a file written by \
+                 `protocol-schema-check --write-embedded` to embed one"
+            );
+        }
+        print!("{}", EMBEDDED_PROTOCOL_SCHEMA);
+        Ok(())
+    }
+}
+
 #[cfg(target_os = "linux")]
 fn normalize_whitespace(s: &str) -> String {
     s.split_whitespace().collect::<Vec<_>>().join(" ")
@@ -0,0 +1,61 @@
+//! Checks that the version information generated by the build script is
+//! compiled into the neard binary.
+
+static NEARD_VERSION: &str = env!("NEARD_VERSION");
+static NEARD_BUILD: &str = env!("NEARD_BUILD");
+static NEARD_COMMIT: &str = env!("NEARD_COMMIT");
+static RUSTC_VERSION: &str = env!("NEARD_RUSTC_VERSION");
+static NEARD_FEATURES: &str = env!("NEARD_FEATURES");
+
+/// Returns the commit hash part of a `git describe --always --dirty=-modified`
+/// output, or None if it's just a tag.
+fn describe_commit_prefix(build: &str) -> Option<&str> {
+    let build = build.strip_suffix("-modified").unwrap_or(build);
+    // `<tag>-<distance>-g<hash>` if there are commits after the tag.
+    let hash = match build.rsplit_once("-g") {
+        Some((_, hash)) => hash,
+        None => build,
+    };
+    // Otherwise it's the abbreviated hash if there is no tag at all.
+    (hash.len() >= 7 && hash.bytes().all(|b| b.is_ascii_hexdigit())).then_some(hash)
+}
+
+#[test]
+fn test_version_env_vars() {
+    assert!(!NEARD_VERSION.is_empty());
+    assert!(!NEARD_BUILD.is_empty());
+    assert!(!NEARD_COMMIT.is_empty());
+    assert!(!RUSTC_VERSION.is_empty());
+
+    if NEARD_COMMIT == "unknown" {
+        // Not built from a git repository.
+        assert_eq!(NEARD_BUILD, "unknown");
+        return;
+    }
+    assert_eq!(NEARD_COMMIT.len(), 40, "not a commit hash: {NEARD_COMMIT}");
+    if let Some(prefix) = describe_commit_prefix(NEARD_BUILD) {
+        assert!(
+            NEARD_COMMIT.starts_with(prefix),
+            "build {NEARD_BUILD} doesn't match commit {NEARD_COMMIT}"
+        );
+    }
+}
+
+#[test]
+fn test_version_output() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_neard"))
+        .arg("--version")
+        .output()
+        .expect("failed to run neard");
+    assert!(output.status.success(), "neard --version failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for expected in [
+        format!("(release {NEARD_VERSION})"),
+        format!("(build {NEARD_BUILD})"),
+        format!("(commit {NEARD_COMMIT})"),
+        format!("(rustc {RUSTC_VERSION})"),
+        format!("features: [{NEARD_FEATURES}]"),
+    ] {
+        assert!(stdout.contains(&expected), "{expected:?} missing from {stdout:?}");
+    }
+}
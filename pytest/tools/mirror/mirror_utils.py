@@ -218,8 +218,9 @@ def mirror_cleanup(process):
 # helper class so we can pass restart_once() as a callback to send_traffic()
 class MirrorProcess:
 
-    def __init__(self, near_root, source_home, online_source):
+    def __init__(self, near_root, source_home, online_source, extra_args=None):
         self.online_source = online_source
+        self.extra_args = extra_args or []
         self.source_home = source_home
         self.neard = os.path.join(near_root, 'neard')
         self.start()
@@ -255,6 +256,7 @@ class MirrorProcess:
             ]
             if self.online_source:
                 args.append('--online-source')
+            args.extend(self.extra_args)
             self.process = subprocess.Popen(args,
                                             stdin=subprocess.DEVNULL,
                                             stdout=stdout,
@@ -0,0 +1,71 @@
+#!/usr/bin/env python3
+
+import sys
+import pathlib
+
+sys.path.append(str(pathlib.Path(__file__).resolve().parents[2] / 'lib'))
+
+from cluster import spin_up_node, load_config
+from configured_logger import logger
+import transaction
+import utils
+
+import mirror_utils
+
+# Checks that with --mirror-stakes, a Stake transaction sent on the source chain is
+# mirrored to the target chain. It sets up the source and target chains like online_test.py,
+# then sends a Stake transaction from test1 on the source chain, and waits for test1's
+# locked balance on the target chain to be updated to the staked amount.
+
+
+def locked_balance(node, account_id):
+    return int(node.get_account(account_id)['result']['locked'])
+
+
+def main():
+    config = load_config()
+
+    near_root, source_nodes, target_node_dirs, traffic_data = mirror_utils.start_source_chain(
+        config)
+
+    target_nodes = [
+        spin_up_node(config, near_root, target_node_dirs[i],
+                     len(source_nodes) + 1 + i)
+        for i in range(len(target_node_dirs))
+    ]
+
+    mirror = mirror_utils.MirrorProcess(near_root,
+                                        mirror_utils.dot_near() /
+                                        f'{mirror_utils.MIRROR_DIR}/source',
+                                        online_source=True,
+                                        extra_args=['--mirror-stakes'])
+
+    staker_key = source_nodes[1].signer_key
+    account_id = staker_key.account_id
+    # Stake more than what's locked on either chain, so the stake takes effect right away
+    # instead of at the end of the epoch.
+    stake = max(locked_balance(source_nodes[1], account_id),
+                locked_balance(target_nodes[0], account_id)) + 10**24
+
+    block_hash = source_nodes[1].get_latest_block().hash_bytes
+    tx = transaction.sign_staking_tx(staker_key, staker_key, stake,
+                                     traffic_data.nonces[1], block_hash)
+    res = source_nodes[1].send_tx_and_wait(tx, timeout=20)
+    assert 'error' not in res, res
+    assert 'Failure' not in res['result']['status'], res
+    logger.info(f'staked {stake} for {account_id} on the source chain')
+
+    for height, _ in utils.poll_blocks(target_nodes[0],
+                                       timeout=mirror_utils.TIMEOUT):
+        locked = locked_balance(target_nodes[0], account_id)
+        if locked == stake:
+            logger.info(
+                f'{account_id} has {locked} locked on the target chain at #{height}'
+            )
+            break
+        code = mirror.process.poll()
+        assert code is None, f'mirror process exited with code {code}'
+
+
+if __name__ == '__main__':
+    main()
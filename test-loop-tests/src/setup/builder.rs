@@ -1,6 +1,7 @@
+use bytesize::ByteSize;
 use itertools::Itertools;
 use near_chain_configs::test_genesis::TestGenesisBuilder;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -53,8 +54,12 @@ pub(crate) struct TestLoopBuilder {
     track_all_shards: bool,
     /// Whether to load mem tries for the tracked shards.
     load_memtries_for_tracked_shards: bool,
+    /// Maximum size of a loaded mem trie, see `TrieConfig::memtrie_memory_limit`.
+    memtrie_memory_limit: Option<ByteSize>,
     /// Upgrade schedule which determines when the clients start voting for new protocol versions.
     upgrade_schedule: ProtocolUpgradeVotingSchedule,
+    /// Per account overrides of `upgrade_schedule`.
+    upgrade_schedule_overrides: HashMap<AccountId, ProtocolUpgradeVotingSchedule>,
 }
 
 impl TestLoopBuilder {
@@ -72,7 +77,9 @@ impl TestLoopBuilder {
             warmup_pending: Arc::new(AtomicBool::new(true)),
             track_all_shards: false,
             load_memtries_for_tracked_shards: true,
+            memtrie_memory_limit: None,
             upgrade_schedule: PROTOCOL_UPGRADE_SCHEDULE.clone(),
+            upgrade_schedule_overrides: HashMap::new(),
         }
     }
 
@@ -150,11 +157,28 @@ impl TestLoopBuilder {
         self
     }
 
+    #[cfg(feature = "test_features")]
+    pub fn memtrie_memory_limit(mut self, memory_limit: ByteSize) -> Self {
+        self.memtrie_memory_limit = Some(memory_limit);
+        self
+    }
+
     pub fn protocol_upgrade_schedule(mut self, schedule: ProtocolUpgradeVotingSchedule) -> Self {
         self.upgrade_schedule = schedule;
         self
     }
 
+    /// Use a different upgrade schedule for the client of `account_id`, e.g. to model a validator
+    /// that hasn't upgraded its binary yet and keeps voting for the old protocol version.
+    pub fn protocol_upgrade_schedule_for_account(
+        mut self,
+        account_id: AccountId,
+        schedule: ProtocolUpgradeVotingSchedule,
+    ) -> Self {
+        self.upgrade_schedule_overrides.insert(account_id, schedule);
+        self
+    }
+
     /// Build the test loop environment.
     pub(crate) fn build(self) -> TestLoopEnv {
         self.ensure_genesis().ensure_epoch_config_store().ensure_clients().build_impl()
@@ -210,9 +234,11 @@ impl TestLoopBuilder {
             runtime_config_store: self.runtime_config_store,
             network_shared_state: TestLoopNetworkSharedState::new(),
             upgrade_schedule: self.upgrade_schedule,
+            upgrade_schedule_overrides: self.upgrade_schedule_overrides,
             chunks_storage: Default::default(),
             drop_conditions: Default::default(),
             load_memtries_for_tracked_shards: self.load_memtries_for_tracked_shards,
+            memtrie_memory_limit: self.memtrie_memory_limit,
             warmup_pending: self.warmup_pending,
         };
         (self.test_loop, shared_state)
@@ -256,12 +282,20 @@ pub struct NodeStateBuilder<'a> {
 
     account_id: Option<AccountId>,
     archive: bool,
+    store: Option<Store>,
     config_modifier: Option<Box<dyn Fn(&mut ClientConfig) + 'a>>,
 }
 
 impl<'a> NodeStateBuilder<'a> {
     pub fn new(genesis: Genesis, tempdir_path: PathBuf) -> Self {
-        Self { genesis, tempdir_path, account_id: None, archive: false, config_modifier: None }
+        Self {
+            genesis,
+            tempdir_path,
+            account_id: None,
+            archive: false,
+            store: None,
+            config_modifier: None,
+        }
     }
 
     pub fn account_id(mut self, account_id: AccountId) -> Self {
@@ -274,6 +308,14 @@ impl<'a> NodeStateBuilder<'a> {
         self
     }
 
+    /// Start the node from an existing store, for example a snapshot of another node
+    /// taken with `TestLoopEnv::snapshot_node_store`, instead of one with only the
+    /// genesis state. Not supported for archival nodes.
+    pub fn store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
     pub fn config_modifier(mut self, modifier: impl Fn(&mut ClientConfig) + 'a) -> Self {
         self.config_modifier = Some(Box::new(modifier));
         self
@@ -321,6 +363,10 @@ impl<'a> NodeStateBuilder<'a> {
     }
 
     fn setup_store(&self) -> (Store, Option<Store>) {
+        if let Some(store) = &self.store {
+            assert!(!self.archive, "archival nodes can't be started from an existing store");
+            return (store.clone(), None);
+        }
         let (store, split_store) = if self.archive {
             let (hot_store, split_store) = create_test_split_store();
             (hot_store, Some(split_store))
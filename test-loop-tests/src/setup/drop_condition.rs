@@ -5,6 +5,7 @@ use std::sync::{Arc, Mutex};
 use near_async::messaging::{CanSend, LateBoundSender};
 use near_async::test_loop::data::TestLoopData;
 use near_async::test_loop::sender::TestLoopSender;
+use near_async::time::Duration;
 use near_chunks::adapter::ShardsManagerRequestFromClient;
 use near_chunks::shards_manager_actor::ShardsManagerActor;
 use near_epoch_manager::EpochManagerAdapter;
@@ -13,7 +14,8 @@ use near_primitives::types::{AccountId, BlockHeight, ShardId, ShardIndex};
 use near_vm_runner::logic::ProtocolVersion;
 
 use crate::utils::network::{
-    block_dropper_by_height, chunk_endorsement_dropper, chunk_endorsement_dropper_by_hash,
+    block_dropper_by_height, chunk_endorsement_delayer, chunk_endorsement_dropper,
+    chunk_endorsement_dropper_by_hash, chunk_withholding_dropper, contract_distribution_delayer,
 };
 
 use super::state::NodeExecutionData;
@@ -35,6 +37,26 @@ pub enum DropCondition {
     ChunksProducedByHeight(HashMap<ShardId, Vec<bool>>),
     // Drops Block broadcast messages with height in `self.0`
     BlocksByHeight(HashSet<BlockHeight>),
+    /// Whether test loop should make the given account withhold all chunks
+    /// it produces. The producer never distributes its chunk parts and
+    /// ignores requests for them, so other nodes see these chunks as missing.
+    ChunksWithheldBy(AccountId),
+    /// Whether test loop should delay endorsements from the given account by
+    /// `delay` on top of the regular network delay. Only endorsements for
+    /// chunks with `height_created % every_nth_height == 0` are delayed, so
+    /// `every_nth_height == 1` makes the validator persistently late.
+    EndorsementsDelayedFrom {
+        account_id: AccountId,
+        delay: Duration,
+        every_nth_height: u64,
+    },
+    /// Whether test loop should delay all contract distribution messages
+    /// (contract accesses, deploys, code requests and responses) sent to or
+    /// from the given account by `delay` on top of the regular network delay.
+    ContractDistributionDelayedFor {
+        account_id: AccountId,
+        delay: Duration,
+    },
 }
 
 /// Stores all chunks ever observed on chain. Determines if a chunk can be
@@ -127,6 +149,20 @@ impl NodeExecutionData {
             DropCondition::BlocksByHeight(heights) => {
                 self.register_drop_blocks_by_height(test_loop_data, heights);
             }
+            DropCondition::ChunksWithheldBy(account_id) => {
+                self.register_withhold_chunks_by(test_loop_data, chunks_storage, account_id);
+            }
+            DropCondition::EndorsementsDelayedFrom { account_id, delay, every_nth_height } => {
+                self.register_delay_endorsements_from(
+                    test_loop_data,
+                    account_id,
+                    *delay,
+                    *every_nth_height,
+                );
+            }
+            DropCondition::ContractDistributionDelayedFor { account_id, delay } => {
+                self.register_delay_contract_distribution_for(test_loop_data, account_id, *delay);
+            }
         }
     }
 
@@ -224,6 +260,68 @@ impl NodeExecutionData {
         let peer_actor = test_loop_data.get_mut(&self.peer_manager_sender.actor_handle());
         peer_actor.register_override_handler(block_dropper_by_height(heights.clone()));
     }
+
+    fn register_withhold_chunks_by(
+        &self,
+        test_loop_data: &mut TestLoopData,
+        chunks_storage: Arc<Mutex<TestLoopChunksStorage>>,
+        account_id: &AccountId,
+    ) {
+        // Only the malicious producer itself withholds its chunks, the rest of
+        // the network behaves honestly.
+        if &self.account_id != account_id {
+            return;
+        }
+
+        let client_actor = test_loop_data.get(&self.client_sender.actor_handle());
+        let epoch_manager = client_actor.client.chain.epoch_manager.clone();
+
+        let peer_actor = test_loop_data.get_mut(&self.peer_manager_sender.actor_handle());
+        peer_actor.register_override_handler(chunk_withholding_dropper(
+            chunks_storage,
+            epoch_manager,
+            account_id.clone(),
+        ));
+    }
+
+    fn register_delay_endorsements_from(
+        &self,
+        test_loop_data: &mut TestLoopData,
+        account_id: &AccountId,
+        delay: Duration,
+        every_nth_height: u64,
+    ) {
+        // The delayed endorsements are sent back to the validator's own peer
+        // manager, so the handler is only needed there.
+        if &self.account_id != account_id {
+            return;
+        }
+
+        let peer_manager_sender = self.peer_manager_sender.clone();
+        let peer_actor = test_loop_data.get_mut(&self.peer_manager_sender.actor_handle());
+        peer_actor.register_override_handler(chunk_endorsement_delayer(
+            account_id.clone(),
+            delay,
+            every_nth_height,
+            peer_manager_sender,
+        ));
+    }
+
+    fn register_delay_contract_distribution_for(
+        &self,
+        test_loop_data: &mut TestLoopData,
+        account_id: &AccountId,
+        delay: Duration,
+    ) {
+        let peer_manager_sender = self.peer_manager_sender.clone();
+        let peer_actor = test_loop_data.get_mut(&self.peer_manager_sender.actor_handle());
+        peer_actor.register_override_handler(contract_distribution_delayer(
+            self.account_id.clone(),
+            account_id.clone(),
+            delay,
+            peer_manager_sender,
+        ));
+    }
 }
 
 /// Checks whether chunk is validated by the given account.
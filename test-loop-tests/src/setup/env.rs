@@ -7,12 +7,25 @@ use near_client::SetNetworkInfo;
 use near_network::types::NetworkInfo;
 use near_primitives::types::AccountId;
 use near_store::adapter::StoreAdapter;
+use near_store::test_utils::create_test_store;
+use near_store::{DBCol, Store};
 use std::sync::atomic::Ordering;
+use strum::IntoEnumIterator;
 
 use super::drop_condition::DropCondition;
-use super::setup::setup_client;
+use super::setup::try_setup_client;
 use super::state::{NodeExecutionData, NodeSetupState, SharedState};
 
+/// How a column of a node's store is damaged by `TestLoopEnv::restart_node_with_corrupted_column`.
+#[derive(Clone, Copy, Debug)]
+pub enum ColumnCorruption {
+    /// Delete all the entries of the column.
+    Truncate,
+    /// Flip all bits of the values of the column. For reference counted columns,
+    /// the reference counts are left intact.
+    Corrupt,
+}
+
 pub struct TestLoopEnv {
     pub test_loop: TestLoopV2,
     pub node_datas: Vec<NodeExecutionData>,
@@ -115,6 +128,16 @@ impl TestLoopEnv {
     ///
     /// Additionally, we set the NetworkInfo for this node which is required for state sync to work.
     pub fn restart_node(&mut self, new_identifier: &str, node_state: NodeSetupState) {
+        self.try_restart_node(new_identifier, node_state).unwrap();
+    }
+
+    /// Same as `restart_node`, but returns the error if the node fails to start
+    /// from its store instead of panicking.
+    pub fn try_restart_node(
+        &mut self,
+        new_identifier: &str,
+        node_state: NodeSetupState,
+    ) -> Result<(), near_client::Error> {
         // get the HeightHeightPeerInfo from all nodes
         let highest_height_peers = self
             .node_datas
@@ -123,9 +146,9 @@ impl TestLoopEnv {
             .map(|data| data.get_highest_height_peer_info(&self.test_loop.data))
             .collect_vec();
 
-        // setup_client handles adding the account_id and peer_id details to network_shared_state
+        // try_setup_client handles adding the account_id and peer_id details to network_shared_state
         let node_data =
-            setup_client(new_identifier, &mut self.test_loop, node_state, &self.shared_state);
+            try_setup_client(new_identifier, &mut self.test_loop, node_state, &self.shared_state)?;
 
         // Note: TestLoopEnv does not currently propagate the network info to other peers. This is because
         // the networking layer is completely mocked out. So in order to allow the new node to sync, we
@@ -136,6 +159,41 @@ impl TestLoopEnv {
 
         // Finally push node_data into node_datas
         self.node_datas.push(node_data);
+        Ok(())
+    }
+
+    /// Function to restart a stopped node after damaging the given column of its store, to
+    /// simulate a node that crashed and left its database in a bad state.
+    ///
+    /// Returns the error if the node refuses to start from the damaged store.
+    pub fn restart_node_with_corrupted_column(
+        &mut self,
+        new_identifier: &str,
+        node_state: NodeSetupState,
+        col: DBCol,
+        corruption: ColumnCorruption,
+    ) -> Result<(), near_client::Error> {
+        corrupt_column(&node_state.store, col, corruption);
+        if let Some(split_store) = &node_state.split_store {
+            corrupt_column(split_store, col, corruption);
+        }
+        self.try_restart_node(new_identifier, node_state)
+    }
+
+    /// Copies the store of a running node into a new store, the way an operator would
+    /// take a database snapshot to bootstrap other nodes from a trusted checkpoint
+    /// instead of genesis. The copy can be passed to `NodeStateBuilder::store`.
+    ///
+    /// Only the hot store is copied, so this isn't supported for archival nodes.
+    pub fn snapshot_node_store(&self, identifier: &str) -> Store {
+        let node_data = self
+            .node_datas
+            .iter()
+            .find(|data| data.identifier == identifier)
+            .expect("Node with identifier not found");
+        let client_actor = self.test_loop.data.get(&node_data.client_sender.actor_handle());
+        assert!(!client_actor.client.config.archive, "archival nodes can't be snapshotted");
+        copy_store(&client_actor.client.chain.chain_store.store())
     }
 
     /// Function to add a new node in test loop environment. This function takes in the identifier
@@ -167,3 +225,35 @@ impl TestLoopEnv {
         self.node_datas.iter().find(|data| &data.account_id == account_id)
     }
 }
+
+fn copy_store(store: &Store) -> Store {
+    let snapshot = create_test_store();
+    let mut store_update = snapshot.store_update();
+    for col in DBCol::iter() {
+        for item in store.iter_raw_bytes(col) {
+            let (key, value) = item.unwrap();
+            store_update.set_raw_bytes(col, &key, &value);
+        }
+    }
+    store_update.commit().unwrap();
+    snapshot
+}
+
+fn corrupt_column(store: &Store, col: DBCol, corruption: ColumnCorruption) {
+    let mut store_update = store.store_update();
+    match corruption {
+        ColumnCorruption::Truncate => store_update.delete_all(col),
+        ColumnCorruption::Corrupt => {
+            for item in store.iter_raw_bytes(col) {
+                let (key, value) = item.unwrap();
+                // The reference count is stored as the last 8 bytes of the value.
+                let payload_len =
+                    if col.is_rc() { value.len().saturating_sub(8) } else { value.len() };
+                let mut corrupted = value.to_vec();
+                corrupted[..payload_len].iter_mut().for_each(|byte| *byte = !*byte);
+                store_update.set_raw_bytes(col, &key, &corrupted);
+            }
+        }
+    }
+    store_update.commit().unwrap();
+}
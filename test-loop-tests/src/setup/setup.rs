@@ -38,6 +38,17 @@ pub fn setup_client(
     node_state: NodeSetupState,
     shared_state: &SharedState,
 ) -> NodeExecutionData {
+    try_setup_client(identifier, test_loop, node_state, shared_state).unwrap()
+}
+
+/// Same as `setup_client`, but returns the error if the client or view client
+/// can't be created from the node's store, e.g. because the store is corrupted.
+pub fn try_setup_client(
+    identifier: &str,
+    test_loop: &mut TestLoopV2,
+    node_state: NodeSetupState,
+    shared_state: &SharedState,
+) -> Result<NodeExecutionData, near_client::Error> {
     let NodeSetupState { account_id, client_config, store, split_store } = node_state;
     let SharedState {
         genesis,
@@ -46,6 +57,7 @@ pub fn setup_client(
         runtime_config_store,
         network_shared_state,
         upgrade_schedule,
+        upgrade_schedule_overrides,
         chunks_storage,
         drop_conditions,
         load_memtries_for_tracked_shards,
@@ -69,6 +81,14 @@ pub fn setup_client(
         ..Default::default()
     };
 
+    #[cfg(not(feature = "test_features"))]
+    let trie_config = TrieConfig::from_store_config(&store_config);
+    #[cfg(feature = "test_features")]
+    let trie_config = TrieConfig {
+        memtrie_memory_limit: shared_state.memtrie_memory_limit,
+        ..TrieConfig::from_store_config(&store_config)
+    };
+
     let sync_jobs_actor = SyncJobsActor::new(client_adapter.as_multi_sender());
     let chain_genesis = ChainGenesis::new(&genesis.config);
     let epoch_manager = EpochManager::new_arc_handle_from_epoch_config_store(
@@ -87,7 +107,7 @@ pub fn setup_client(
         &genesis.config,
         epoch_manager.clone(),
         runtime_config_store.clone(),
-        TrieConfig::from_store_config(&store_config),
+        trie_config.clone(),
         client_config.gc.gc_num_epochs_to_keep,
     );
 
@@ -139,9 +159,8 @@ pub fn setup_client(
         Arc::new(test_loop.future_spawner(identifier)),
         client_adapter.as_multi_sender(),
         client_adapter.as_multi_sender(),
-        upgrade_schedule.clone(),
-    )
-    .unwrap();
+        upgrade_schedule_overrides.get(&account_id).unwrap_or(upgrade_schedule).clone(),
+    )?;
 
     // If this is an archival node and split storage is initialized, then create view-specific
     // versions of EpochManager, ShardTracker and RuntimeAdapter and use them to initialize the
@@ -163,7 +182,7 @@ pub fn setup_client(
             &genesis.config,
             view_epoch_manager.clone(),
             runtime_config_store.clone(),
-            TrieConfig::from_store_config(&store_config),
+            trie_config,
             client_config.gc.gc_num_epochs_to_keep,
         );
         (view_epoch_manager, view_shard_tracker, view_runtime_adapter)
@@ -180,8 +199,7 @@ pub fn setup_client(
         network_adapter.as_multi_sender(),
         client_config.clone(),
         near_client::adversarial::Controls::default(),
-    )
-    .unwrap();
+    )?;
 
     let shards_manager = ShardsManagerActor::new(
         test_loop.clock(),
@@ -321,5 +339,5 @@ pub fn setup_client(
         node_data.register_drop_condition(&mut test_loop.data, chunks_storage.clone(), condition);
     }
 
-    node_data
+    Ok(node_data)
 }
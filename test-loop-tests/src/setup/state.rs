@@ -1,3 +1,5 @@
+use bytesize::ByteSize;
+use std::collections::HashMap;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 
@@ -44,11 +46,16 @@ pub struct SharedState {
     /// PeerId, and the route back CryptoHash, so that individual network actors can do routing.
     pub network_shared_state: TestLoopNetworkSharedState,
     pub upgrade_schedule: ProtocolUpgradeVotingSchedule,
+    /// Upgrade schedules for the accounts that vote differently from `upgrade_schedule`. Looked up
+    /// by account id whenever a node is set up, so removing an entry before restarting the node
+    /// makes it switch to the shared schedule.
+    pub upgrade_schedule_overrides: HashMap<AccountId, ProtocolUpgradeVotingSchedule>,
     /// Stores all chunks ever observed on chain. Used by drop conditions to simulate network drops.
     pub chunks_storage: Arc<Mutex<TestLoopChunksStorage>>,
     /// List of drop conditions that apply to all nodes in the network.
     pub drop_conditions: Vec<DropCondition>,
     pub load_memtries_for_tracked_shards: bool,
+    pub memtrie_memory_limit: Option<ByteSize>,
     /// Flag to indicate if warmup is pending. This is used to ensure that warmup is only done once.
     pub warmup_pending: Arc<AtomicBool>,
 }
@@ -59,6 +59,7 @@ use crate::setup::builder::TestLoopBuilder;
 use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
 use crate::setup::state::NodeExecutionData;
+use crate::utils::sharding::run_with_shard_layouts;
 use crate::utils::transactions::{TransactionRunner, run_txs_parallel};
 use crate::utils::{ONE_NEAR, TGAS};
 
@@ -69,7 +70,7 @@ fn ultra_slow_test_bandwidth_scheduler_three_shards_random_receipts() {
         .num_shards(3)
         .default_link_generator(|| Box::new(RandomReceiptSizeGenerator))
         .build();
-    let summary = run_bandwidth_scheduler_test(scenario, 2000);
+    let summary = run_bandwidth_scheduler_test(scenario, bandwidth_scheduler_shard_layout(3), 2000);
     assert!(summary.bandwidth_utilization > 0.55); // 55% utilization
     assert!(summary.link_imbalance_ratio < 1.8); // < 80% difference on links
     assert!(summary.worst_link_estimation_ratio > 0.4); // 40% of estimated link throughput
@@ -86,7 +87,7 @@ fn ultra_slow_test_bandwidth_scheduler_four_shards_random_receipts_missing_chunk
         .default_link_generator(|| Box::new(RandomReceiptSizeGenerator))
         .missing_chunk_probability(0.1)
         .build();
-    let summary = run_bandwidth_scheduler_test(scenario, 2000);
+    let summary = run_bandwidth_scheduler_test(scenario, bandwidth_scheduler_shard_layout(5), 2000);
     assert!(summary.bandwidth_utilization > 0.35); // 35% utilization
     assert!(summary.link_imbalance_ratio < 6.0); // < 500% difference on links
     assert!(summary.worst_link_estimation_ratio > 0.1); // 10% of estimated link throughput
@@ -101,8 +102,38 @@ fn ultra_slow_test_bandwidth_scheduler_four_shards_random_receipts_missing_chunk
     assert!(summary.max_outgoing <= summary.max_shard_bandwidth);
 }
 
-fn run_bandwidth_scheduler_test(scenario: TestScenario, tx_concurrency: usize) -> TestSummary {
+/// Random receipt sizes on all links, checked across several shard layouts.
+/// Utilization depends a lot on the number of shards, so only the hard limits
+/// of the scheduler are checked here.
+#[test]
+fn ultra_slow_test_bandwidth_scheduler_shard_layouts_random_receipts() {
+    let shard_layouts = [2, 4, 6].map(bandwidth_scheduler_shard_layout).to_vec();
+    run_with_shard_layouts(shard_layouts, |shard_layout| {
+        let scenario = TestScenarioBuilder::new()
+            .num_shards(shard_layout.num_shards())
+            .default_link_generator(|| Box::new(RandomReceiptSizeGenerator))
+            .build();
+        let summary = run_bandwidth_scheduler_test(scenario, shard_layout, 2000);
+        assert!(summary.max_incoming <= summary.max_shard_bandwidth);
+        assert!(summary.max_outgoing <= summary.max_shard_bandwidth);
+    });
+}
+
+/// Shard layout with boundary accounts `shard1`, `shard2`, ... expected by
+/// `run_bandwidth_scheduler_test`.
+fn bandwidth_scheduler_shard_layout(num_shards: u64) -> ShardLayout {
+    let boundary_accounts =
+        (1..num_shards).map(|i| format!("shard{}", i).parse().unwrap()).collect();
+    ShardLayout::multi_shard_custom(boundary_accounts, 0)
+}
+
+fn run_bandwidth_scheduler_test(
+    scenario: TestScenario,
+    shard_layout: ShardLayout,
+    tx_concurrency: usize,
+) -> TestSummary {
     init_test_logger();
+    assert_eq!(scenario.num_shards, shard_layout.num_shards());
     let active_links = scenario.get_active_links();
     let mut rng = ChaCha20Rng::seed_from_u64(0);
 
@@ -110,9 +141,7 @@ fn run_bandwidth_scheduler_test(scenario: TestScenario, tx_concurrency: usize) -
     let workload_blocks = 50;
 
     // Boundary accounts between shards
-    let boundary_accounts: Vec<AccountId> =
-        (1..scenario.num_shards).map(|i| format!("shard{}", i).parse().unwrap()).collect();
-    let shard_layout = ShardLayout::multi_shard_custom(boundary_accounts.clone(), 0);
+    let boundary_accounts: Vec<AccountId> = shard_layout.boundary_accounts().clone();
 
     // Accounts that will be sending receipts to each other. One per shard.
     let workload_accounts: Vec<AccountId> = (0..scenario.num_shards)
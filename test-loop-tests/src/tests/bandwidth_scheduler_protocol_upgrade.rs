@@ -1,8 +1,16 @@
 use std::collections::HashMap;
 
+use itertools::Itertools;
+use near_async::test_loop::data::TestLoopData;
+use near_async::time::Duration;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, BlockHeight};
 use near_primitives::version::ProtocolFeature;
+use near_store::ShardUId;
 
-use super::protocol_upgrade::test_protocol_upgrade;
+use crate::setup::state::NodeExecutionData;
+
+use super::protocol_upgrade::{EPOCH_LENGTH, setup_env, test_protocol_upgrade};
 
 #[test]
 fn test_bandwidth_scheduler_protocol_upgrade_no_missing_chunks() {
@@ -21,3 +29,158 @@ fn test_bandwidth_scheduler_protocol_upgrade_with_missing_chunks_two() {
         HashMap::from_iter([(0, 0..0), (1, -2..0), (2, 0..2), (3, -2..2)].into_iter()),
     );
 }
+
+/// Checks the blocks in `heights` on the `reference` node, which must track all shards. Before the
+/// bandwidth scheduler is enabled no chunk may carry bandwidth requests, as those would lead to
+/// bandwidth grants that nodes on the old version don't know about. Independently of the version
+/// all the nodes that applied a chunk must agree on its chunk extra.
+fn assert_bandwidth_state_consistent(
+    test_loop_data: &TestLoopData,
+    node_datas: &[NodeExecutionData],
+    reference: &NodeExecutionData,
+    heights: impl Iterator<Item = BlockHeight>,
+) {
+    let client = &test_loop_data.get(&reference.client_sender.actor_handle()).client;
+    for height in heights {
+        // Skipped heights don't have a block.
+        let Ok(block_hash) = client.chain.get_block_hash_by_height(height) else {
+            continue;
+        };
+        let block = client.chain.get_block(&block_hash).unwrap();
+        let epoch_id = block.header().epoch_id();
+        let protocol_version = client.epoch_manager.get_epoch_protocol_version(epoch_id).unwrap();
+        let enabled = ProtocolFeature::BandwidthScheduler.enabled(protocol_version);
+
+        if !enabled {
+            for chunk_header in block.chunks().iter_raw() {
+                assert!(
+                    chunk_header.bandwidth_requests().is_none(),
+                    "chunk for shard {} in block at height {} has bandwidth requests before the upgrade",
+                    chunk_header.shard_id(),
+                    height
+                );
+            }
+        }
+
+        let shard_layout = client.epoch_manager.get_shard_layout(epoch_id).unwrap();
+        for shard_id in shard_layout.shard_ids() {
+            let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+            let chunk_extra = client.chain.get_chunk_extra(&block_hash, &shard_uid).unwrap();
+            if !enabled {
+                assert!(
+                    chunk_extra.bandwidth_requests().is_none(),
+                    "chunk extra for shard {} at height {} has bandwidth requests before the upgrade",
+                    shard_id,
+                    height
+                );
+            }
+            let other_chunk_extras = node_datas
+                .iter()
+                .filter_map(|data| {
+                    let client = &test_loop_data.get(&data.client_sender.actor_handle()).client;
+                    client.chain.get_chunk_extra(&block_hash, &shard_uid).ok()
+                })
+                .collect_vec();
+            for other_chunk_extra in other_chunk_extras {
+                assert_eq!(
+                    chunk_extra, other_chunk_extra,
+                    "nodes disagree on the chunk extra for shard {} at height {}",
+                    shard_id, height
+                );
+            }
+        }
+    }
+}
+
+/// One of the two block producers doesn't upgrade in time and keeps voting for the old protocol
+/// version, so the upgrade doesn't get the required stake. The network has to stay on the old
+/// version, without any bandwidth requests, and in agreement on the chunk extras until the lagging
+/// producer upgrades, after which the bandwidth scheduler gets enabled as usual.
+#[test]
+fn slow_test_bandwidth_scheduler_protocol_upgrade_without_supermajority() {
+    init_test_logger();
+
+    let old_protocol = ProtocolFeature::BandwidthScheduler.protocol_version() - 1;
+    let new_protocol = ProtocolFeature::BandwidthScheduler.protocol_version();
+    let lagging_producer: AccountId = "account1".parse().unwrap();
+    let rpc_id: AccountId = "account4".parse().unwrap();
+    let (mut env, _) =
+        setup_env(old_protocol, new_protocol, &HashMap::new(), &[lagging_producer.clone()]);
+    let rpc_handle = env.get_node_data_by_account_id(&rpc_id).unwrap().client_sender.actor_handle();
+
+    // Had both producers voted for the new version, the network would have upgraded by now.
+    let rpc_client = &env.test_loop.data.get(&rpc_handle).client;
+    let start_height = rpc_client.chain.genesis().height() + 1;
+    let start_epoch_height = rpc_client
+        .epoch_manager
+        .get_epoch_info(&rpc_client.chain.head().unwrap().epoch_id)
+        .unwrap()
+        .epoch_height();
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let client = &test_loop_data.get(&rpc_handle).client;
+            let tip = client.chain.head().unwrap();
+            let epoch_height =
+                client.epoch_manager.get_epoch_info(&tip.epoch_id).unwrap().epoch_height();
+            epoch_height >= start_epoch_height + 4
+        },
+        Duration::seconds((6 * EPOCH_LENGTH) as i64),
+    );
+
+    let rpc_client = &env.test_loop.data.get(&rpc_handle).client;
+    let tip = rpc_client.chain.head().unwrap();
+    for epoch_id in [tip.epoch_id, tip.next_epoch_id] {
+        assert_eq!(
+            rpc_client.epoch_manager.get_epoch_protocol_version(&epoch_id).unwrap(),
+            old_protocol
+        );
+    }
+    let rpc_data = env.get_node_data_by_account_id(&rpc_id).unwrap();
+    assert_bandwidth_state_consistent(
+        &env.test_loop.data,
+        &env.node_datas,
+        rpc_data,
+        start_height..=tip.height,
+    );
+    let last_checked_height = tip.height;
+
+    // Restart the lagging producer with an upgraded binary. It's restarted right away, so that the
+    // network doesn't miss its blocks.
+    let node_state = env.kill_node(lagging_producer.as_str());
+    env.shared_state.upgrade_schedule_overrides.remove(&lagging_producer);
+    env.restart_node(&format!("{}-upgraded", lagging_producer), node_state);
+
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let client = &test_loop_data.get(&rpc_handle).client;
+            let tip = client.chain.head().unwrap();
+            client.epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap() == new_protocol
+        },
+        Duration::seconds((4 * EPOCH_LENGTH) as i64),
+    );
+    let upgrade_height = env.test_loop.data.get(&rpc_handle).client.chain.head().unwrap().height;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let head = test_loop_data.get(&rpc_handle).client.chain.head().unwrap();
+            head.height > upgrade_height + EPOCH_LENGTH
+        },
+        Duration::seconds((2 * EPOCH_LENGTH) as i64),
+    );
+
+    let rpc_client = &env.test_loop.data.get(&rpc_handle).client;
+    let tip = rpc_client.chain.head().unwrap();
+    let rpc_data = env.get_node_data_by_account_id(&rpc_id).unwrap();
+    assert_bandwidth_state_consistent(
+        &env.test_loop.data,
+        &env.node_datas,
+        rpc_data,
+        last_checked_height + 1..=tip.height,
+    );
+    // Once the upgrade is active, the chunks carry bandwidth requests.
+    let block = rpc_client.chain.get_block(&tip.last_block_hash).unwrap();
+    for chunk_header in block.chunks().iter_raw() {
+        assert!(chunk_header.bandwidth_requests().is_some());
+    }
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,144 @@
+use itertools::Itertools;
+use near_async::test_loop::data::TestLoopData;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_client::Client;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, ShardId};
+
+use crate::setup::builder::{NodeStateBuilder, TestLoopBuilder};
+use crate::utils::ONE_NEAR;
+use crate::utils::transactions::{do_create_account, make_accounts};
+
+const NUM_VALIDATORS: usize = 2;
+const EPOCH_LENGTH: u64 = 10;
+const GC_NUM_EPOCHS_TO_KEEP: u64 = 3;
+
+/// Starts a node from a snapshot of another node's store, taken once the early
+/// blocks are garbage collected, the way operators bootstrap nodes from a trusted
+/// checkpoint rather than from genesis. The node has to sync forward to the head
+/// of the chain and serve transactions, and everything it keeps track of relative
+/// to genesis has to agree with the rest of the network even though it never
+/// processed the blocks right after genesis.
+#[test]
+fn test_sync_from_trusted_checkpoint() {
+    init_test_logger();
+
+    let accounts = make_accounts(NUM_VALIDATORS + 2);
+    let validators = accounts.iter().take(NUM_VALIDATORS).map(|a| a.as_str()).collect_vec();
+    // A node that tracks all shards, whose store is used as the checkpoint.
+    let source_id: AccountId = accounts[NUM_VALIDATORS].clone();
+    let new_node_id: AccountId = accounts[NUM_VALIDATORS + 1].clone();
+
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .validators_spec(ValidatorsSpec::desired_roles(&validators, &[]))
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let genesis_height = genesis.config.genesis_height;
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(accounts[..=NUM_VALIDATORS].to_vec())
+        .gc_num_epochs_to_keep(GC_NUM_EPOCHS_TO_KEEP)
+        .build()
+        .warmup();
+
+    let source_handle =
+        env.get_node_data_by_account_id(&source_id).unwrap().client_sender.actor_handle();
+    env.test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            test_loop_data.get(&source_handle).client.chain.tail().unwrap() > genesis_height
+        },
+        Duration::seconds(((GC_NUM_EPOCHS_TO_KEEP + 3) * EPOCH_LENGTH) as i64),
+    );
+    let checkpoint = env.snapshot_node_store(source_id.as_str());
+    let (checkpoint_height, checkpoint_tail) = {
+        let client = &env.test_loop.data.get(&source_handle).client;
+        (client.chain.head().unwrap().height, client.chain.tail().unwrap())
+    };
+    tracing::info!(target: "test", checkpoint_height, checkpoint_tail, "took checkpoint");
+
+    // Let the network move a few epochs past the checkpoint before starting the
+    // new node, so that it has to catch up across epoch boundaries.
+    env.test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            test_loop_data.get(&source_handle).client.chain.head().unwrap().height
+                > checkpoint_height + 2 * EPOCH_LENGTH
+        },
+        Duration::seconds((3 * EPOCH_LENGTH) as i64),
+    );
+
+    let new_node_state = NodeStateBuilder::new(
+        env.shared_state.genesis.clone(),
+        env.shared_state.tempdir.path().to_path_buf(),
+    )
+    .account_id(new_node_id.clone())
+    .store(checkpoint)
+    .config_modifier(|config| {
+        config.gc.gc_num_epochs_to_keep = GC_NUM_EPOCHS_TO_KEEP;
+        config.tracked_shards = vec![ShardId::new(666)];
+    })
+    .build();
+    env.add_node(new_node_id.as_str(), new_node_state);
+    let new_node_handle = env.node_datas.last().unwrap().client_sender.actor_handle();
+
+    // The node starts where the checkpoint was taken, without the early blocks.
+    {
+        let client = &env.test_loop.data.get(&new_node_handle).client;
+        assert_eq!(client.chain.head().unwrap().height, checkpoint_height);
+        assert_eq!(client.chain.tail().unwrap(), checkpoint_tail);
+        assert!(client.chain.get_block_by_height(genesis_height + 1).is_err());
+    }
+
+    env.test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            let source_head = test_loop_data.get(&source_handle).client.chain.head().unwrap();
+            let new_node_head = test_loop_data.get(&new_node_handle).client.chain.head().unwrap();
+            new_node_head.height >= source_head.height
+        },
+        Duration::seconds((3 * EPOCH_LENGTH) as i64),
+    );
+
+    {
+        let source = &env.test_loop.data.get(&source_handle).client;
+        let new_node = &env.test_loop.data.get(&new_node_handle).client;
+        assert_genesis_bookkeeping(source, new_node);
+    }
+
+    // The node participates in the network: a transaction submitted through it
+    // gets included and executed, and the node sees its outcome.
+    let new_account: AccountId = format!("checkpoint.{}", accounts[0]).parse().unwrap();
+    do_create_account(&mut env, &new_node_id, &accounts[0], &new_account, 100 * ONE_NEAR);
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Checks that the node started from the checkpoint agrees with `source` on the
+/// genesis block, and on the block ordinal, block merkle tree and epoch height of
+/// its head, which are all counted from genesis.
+fn assert_genesis_bookkeeping(source: &Client, new_node: &Client) {
+    assert_eq!(new_node.chain.genesis().hash(), source.chain.genesis().hash());
+    assert_eq!(new_node.chain.genesis().height(), source.chain.genesis().height());
+    let genesis_ordinal = new_node.chain.genesis().block_ordinal();
+    assert_eq!(
+        new_node.chain.chain_store().get_block_hash_from_ordinal(genesis_ordinal).unwrap(),
+        *new_node.chain.genesis().hash()
+    );
+
+    let head = new_node.chain.head().unwrap();
+    let header = new_node.chain.get_block_header(&head.last_block_hash).unwrap();
+    assert_eq!(
+        new_node.chain.chain_store().get_block_hash_from_ordinal(header.block_ordinal()).unwrap(),
+        head.last_block_hash
+    );
+    assert_eq!(
+        new_node.chain.chain_store().get_block_merkle_tree(&head.last_block_hash).unwrap(),
+        source.chain.chain_store().get_block_merkle_tree(&head.last_block_hash).unwrap()
+    );
+    assert_eq!(
+        new_node.epoch_manager.get_epoch_info(&head.epoch_id).unwrap().epoch_height(),
+        source.epoch_manager.get_epoch_info(&head.epoch_id).unwrap().epoch_height()
+    );
+}
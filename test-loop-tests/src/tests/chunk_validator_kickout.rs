@@ -2,17 +2,31 @@ use crate::setup::builder::TestLoopBuilder;
 use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::ONE_NEAR;
+use crate::utils::network::chunk_endorsement_dropper;
+use crate::utils::transactions::get_next_nonce;
 use crate::utils::validators::get_epoch_all_validators;
 use itertools::Itertools;
+use near_async::messaging::SendAsync;
 use near_async::test_loop::data::TestLoopData;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_network::client::ProcessTxRequest;
+use near_network::types::NetworkRequests;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::shard_layout::ShardLayout;
-use near_primitives::types::AccountId;
+use near_primitives::test_utils::{create_test_signer, create_user_test_signer};
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Balance, BlockHeight, EpochHeight};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 const NUM_ACCOUNTS: usize = 8;
 const NUM_PRODUCER_ACCOUNTS: usize = 6;
+/// Delay of the endorsements on top of the regular network delay. Large enough
+/// for the endorsement to miss the block which includes the chunk.
+const ENDORSEMENT_DELAY: Duration = Duration::seconds(2);
 
 fn create_accounts() -> Vec<AccountId> {
     (0..NUM_ACCOUNTS).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>()
@@ -23,6 +37,9 @@ enum TestCase {
     DropChunksValidatedBy(AccountId),
     /// Drop endorsements from the account.
     DropEndorsementsFrom(AccountId),
+    /// Delay endorsements from the account for chunks at every
+    /// `every_nth_height`-th height.
+    DelayEndorsementsFrom { account_id: AccountId, every_nth_height: BlockHeight },
 }
 
 impl TestCase {
@@ -30,6 +47,16 @@ impl TestCase {
         match self {
             TestCase::DropChunksValidatedBy(account_id) => account_id,
             TestCase::DropEndorsementsFrom(account_id) => account_id,
+            TestCase::DelayEndorsementsFrom { account_id, .. } => account_id,
+        }
+    }
+
+    /// Whether the account misses enough endorsements to fall below the 50%
+    /// kickout threshold for chunk validators.
+    fn misses_most_endorsements(&self) -> bool {
+        match self {
+            TestCase::DropChunksValidatedBy(_) | TestCase::DropEndorsementsFrom(_) => true,
+            TestCase::DelayEndorsementsFrom { every_nth_height, .. } => *every_nth_height == 1,
         }
     }
 }
@@ -50,16 +77,18 @@ fn run_test_chunk_validator_kickout(accounts: Vec<AccountId>, test_case: TestCas
         TestCase::DropChunksValidatedBy(_) => 1,
         // Target giving a large number of mandates to each chunk validator, so that if we drop all the
         // endorsements from one of the validators, this will not result in missing any chunks.
-        TestCase::DropEndorsementsFrom(_) => 16,
+        TestCase::DropEndorsementsFrom(_) | TestCase::DelayEndorsementsFrom { .. } => 16,
     };
 
     // Only chunk validator-only node can be kicked out for low endorsement stats.
-    let account_to_kickout =
-        if chunk_validators_only.contains(&test_case.selected_account().as_str()) {
-            Some(test_case.selected_account())
-        } else {
-            None
-        };
+    let account_to_kickout = if chunk_validators_only
+        .contains(&test_case.selected_account().as_str())
+        && test_case.misses_most_endorsements()
+    {
+        Some(test_case.selected_account())
+    } else {
+        None
+    };
 
     let shard_layout = ShardLayout::simple_v1(&["account2", "account4", "account6"]);
     let validators_spec =
@@ -94,6 +123,15 @@ fn run_test_chunk_validator_kickout(accounts: Vec<AccountId>, test_case: TestCas
         TestCase::DropEndorsementsFrom(account_id) => {
             env.drop(DropCondition::EndorsementsFrom(account_id.clone()))
         }
+        // Delay endorsements from `account_id` so that they arrive after the
+        // chunk was already included.
+        TestCase::DelayEndorsementsFrom { account_id, every_nth_height } => {
+            env.drop(DropCondition::EndorsementsDelayedFrom {
+                account_id: account_id.clone(),
+                delay: ENDORSEMENT_DELAY,
+                every_nth_height: *every_nth_height,
+            })
+        }
     };
     let TestLoopEnv { mut test_loop, node_datas, shared_state } = env.warmup();
 
@@ -118,6 +156,10 @@ fn run_test_chunk_validator_kickout(accounts: Vec<AccountId>, test_case: TestCas
                 num_missed_chunks, 0,
                 "No chunk must be missed when dropping endorsements from the selected account"
             ),
+            TestCase::DelayEndorsementsFrom { .. } => assert_eq!(
+                num_missed_chunks, 0,
+                "No chunk must be missed when delaying endorsements from the selected account"
+            ),
         }
 
         let validators = get_epoch_all_validators(client);
@@ -178,3 +220,169 @@ fn slow_test_block_producer_not_kicked_out_when_endorsements_dropped() {
     let test_case = TestCase::DropEndorsementsFrom(accounts[NUM_PRODUCER_ACCOUNTS - 1].clone());
     run_test_chunk_validator_kickout(accounts, test_case);
 }
+
+/// Checks that chunk validator is kicked out when all the endorsements it generates arrive late.
+#[test]
+fn slow_test_chunk_validator_kicked_out_when_endorsements_persistently_late() {
+    let accounts = create_accounts();
+    let test_case = TestCase::DelayEndorsementsFrom {
+        account_id: accounts[NUM_PRODUCER_ACCOUNTS + 1].clone(),
+        every_nth_height: 1,
+    };
+    run_test_chunk_validator_kickout(accounts, test_case);
+}
+
+/// Checks that chunk validator is not kicked out when only some of the endorsements it generates arrive late.
+#[test]
+fn slow_test_chunk_validator_not_kicked_out_when_endorsements_occasionally_late() {
+    let accounts = create_accounts();
+    let test_case = TestCase::DelayEndorsementsFrom {
+        account_id: accounts[NUM_PRODUCER_ACCOUNTS + 1].clone(),
+        every_nth_height: 4,
+    };
+    run_test_chunk_validator_kickout(accounts, test_case);
+}
+
+/// Handler dropping all endorsements from `validator` for as long as
+/// `misbehaving` is set.
+fn switchable_endorsement_dropper(
+    validator: AccountId,
+    misbehaving: Arc<AtomicBool>,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    let dropper = chunk_endorsement_dropper(validator);
+    Box::new(
+        move |request| {
+            if misbehaving.load(Ordering::Relaxed) { dropper(request) } else { Some(request) }
+        },
+    )
+}
+
+/// Checks that a chunk validator kicked out for dropping all its endorsements
+/// is admitted back to the validator set once it behaves correctly again and
+/// stakes anew, and that it then stays in the validator set.
+#[test]
+fn slow_test_chunk_validator_reentry_after_kickout() {
+    init_test_logger();
+    let epoch_length = 10;
+    let accounts = create_accounts();
+    let accounts_str = accounts.iter().map(|a| a.as_str()).collect_vec();
+    let (block_and_chunk_producers, chunk_validators_only) =
+        accounts_str.split_at(NUM_PRODUCER_ACCOUNTS);
+    let validator = accounts[NUM_PRODUCER_ACCOUNTS + 1].clone();
+
+    let shard_layout = ShardLayout::simple_v1(&["account2", "account4", "account6"]);
+    let validators_spec =
+        ValidatorsSpec::desired_roles(block_and_chunk_producers, chunk_validators_only);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(epoch_length)
+        .shard_layout(shard_layout)
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let genesis_stake: Balance =
+        genesis.config.validators.iter().find(|info| info.account_id == validator).unwrap().amount;
+    let epoch_config_store = TestEpochConfigBuilder::from_genesis(&genesis)
+        .kickouts_for_chunk_validators_only()
+        // Many mandates per chunk validator, so that dropping all the endorsements
+        // of one of them doesn't make any chunks miss.
+        .target_validator_mandates_per_shard(16)
+        .build_store_for_genesis_protocol_version();
+
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(accounts.clone())
+        .build();
+    let misbehaving = Arc::new(AtomicBool::new(true));
+    for data in &env.node_datas {
+        let peer_actor = env.test_loop.data.get_mut(&data.peer_manager_sender.actor_handle());
+        peer_actor.register_override_handler(switchable_endorsement_dropper(
+            validator.clone(),
+            misbehaving.clone(),
+        ));
+    }
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = env.warmup();
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let tx_processor_sender = node_datas[0].tx_processor_sender.clone();
+    // Whether the validator is in the validator set, for each epoch seen.
+    let validator_in_epoch = RefCell::new(BTreeMap::<EpochHeight, bool>::new());
+    let record_epoch = |test_loop_data: &TestLoopData| -> (EpochHeight, bool) {
+        let client = &test_loop_data.get(&client_handle).client;
+        let tip = client.chain.head().unwrap();
+        let epoch_height =
+            client.epoch_manager.get_epoch_height_from_prev_block(&tip.prev_block_hash).unwrap();
+        let is_validator = get_epoch_all_validators(client).contains(&validator.to_string());
+        validator_in_epoch.borrow_mut().insert(epoch_height, is_validator);
+        (epoch_height, is_validator)
+    };
+
+    // Run until the validator is kicked out for missing all its endorsements.
+    test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            let (epoch_height, is_validator) = record_epoch(test_loop_data);
+            assert!(epoch_height < 4);
+            !is_validator
+        },
+        Duration::seconds((5 * epoch_length) as i64),
+    );
+    let kickout_epoch_height = *validator_in_epoch.borrow().keys().last().unwrap();
+    tracing::info!(target: "test", kickout_epoch_height, "validator kicked out");
+
+    // Behave correctly again and stake the same amount as in genesis at the start
+    // of every epoch the validator is out, since the kickout cancels its stake.
+    misbehaving.store(false, Ordering::Relaxed);
+    let latest_stake_epoch_height = RefCell::new(0);
+    test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            let (epoch_height, is_validator) = record_epoch(test_loop_data);
+            assert!(epoch_height < kickout_epoch_height + 4);
+            if is_validator {
+                return true;
+            }
+            if epoch_height > *latest_stake_epoch_height.borrow() {
+                *latest_stake_epoch_height.borrow_mut() = epoch_height;
+                let tip = test_loop_data.get(&client_handle).client.chain.head().unwrap();
+                let tx = SignedTransaction::stake(
+                    get_next_nonce(test_loop_data, &node_datas, &validator),
+                    validator.clone(),
+                    &create_user_test_signer(&validator),
+                    genesis_stake,
+                    create_test_signer(validator.as_str()).public_key(),
+                    tip.last_block_hash,
+                );
+                drop(tx_processor_sender.send_async(ProcessTxRequest {
+                    transaction: tx,
+                    is_forwarded: false,
+                    check_only: false,
+                }));
+            }
+            false
+        },
+        Duration::seconds((5 * epoch_length) as i64),
+    );
+    let reentry_epoch_height = *validator_in_epoch.borrow().keys().last().unwrap();
+    tracing::info!(target: "test", reentry_epoch_height, "validator admitted back");
+
+    // Now that it endorses chunks again, the validator isn't kicked out again.
+    test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            let (epoch_height, is_validator) = record_epoch(test_loop_data);
+            assert!(is_validator, "validator kicked out again at epoch {}", epoch_height);
+            epoch_height >= reentry_epoch_height + 2
+        },
+        Duration::seconds((3 * epoch_length) as i64),
+    );
+
+    // The validator set changes epoch by epoch from including the validator, to
+    // not including it, to including it again.
+    let validator_in_epoch = validator_in_epoch.into_inner();
+    tracing::info!(target: "test", ?validator_in_epoch, "validator set membership by epoch");
+    for (&epoch_height, &is_validator) in &validator_in_epoch {
+        let expected = epoch_height < kickout_epoch_height || epoch_height >= reentry_epoch_height;
+        assert_eq!(is_validator, expected, "unexpected membership at epoch {}", epoch_height);
+    }
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
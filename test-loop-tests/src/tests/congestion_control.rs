@@ -13,6 +13,8 @@ use near_primitives::types::{AccountId, BlockHeight};
 use crate::setup::builder::TestLoopBuilder;
 use crate::setup::env::TestLoopEnv;
 use crate::setup::state::NodeExecutionData;
+use crate::utils::metrics::{NodeCounter, NodeMetrics};
+use crate::utils::sharding::run_with_shard_layouts;
 use crate::utils::transactions::{call_contract, check_txs, deploy_contract, make_accounts};
 use crate::utils::{ONE_NEAR, TGAS};
 
@@ -24,9 +26,19 @@ const NUM_CLIENTS: usize = NUM_PRODUCERS + NUM_VALIDATORS + NUM_RPC;
 
 /// A very simple test that exercises congestion control in the typical setup
 /// with producers, validators, rpc nodes, single shard tracking and state sync.
+/// It is run with a single shard and with a few multi-shard layouts.
 #[cfg_attr(not(feature = "test_features"), ignore)]
 #[test]
 fn slow_test_congestion_control_simple() {
+    let shard_layouts = vec![
+        ShardLayout::single_shard(),
+        ShardLayout::simple_v1(&["account3", "account5", "account7"]),
+        ShardLayout::simple_v1(&["account1", "account3", "account5", "account7", "account9"]),
+    ];
+    run_with_shard_layouts(shard_layouts, run_congestion_control_simple);
+}
+
+fn run_congestion_control_simple(shard_layout: ShardLayout) {
     init_test_logger();
 
     // Test setup
@@ -35,8 +47,9 @@ fn slow_test_congestion_control_simple() {
     let mut accounts = make_accounts(NUM_ACCOUNTS);
     accounts.push(contract_id.clone());
 
-    let (env, rpc_id) = setup(&accounts);
+    let (env, rpc_id) = setup(&accounts, shard_layout);
     let TestLoopEnv { mut test_loop, node_datas, shared_state } = env;
+    let node_metrics = NodeMetrics::capture(&mut test_loop);
 
     // Test
 
@@ -53,13 +66,24 @@ fn slow_test_congestion_control_simple() {
         Duration::seconds(100),
     );
 
+    // The rpc node tracks all shards, so it must have processed all the
+    // transactions. The contract calls burn much more gas than fits in a chunk,
+    // so some of the receipts must have been delayed.
+    let rpc_data = node_datas.iter().find(|data| data.account_id == rpc_id).unwrap();
+    let num_txs_processed =
+        node_metrics.get(&rpc_data.identifier, NodeCounter::TransactionsProcessed);
+    assert!(num_txs_processed >= accounts.len() as u64);
+    let num_delayed_receipts =
+        node_metrics.get(&rpc_data.identifier, NodeCounter::DelayedReceiptsProcessed);
+    assert!(num_delayed_receipts > 0);
+
     // Give the test a chance to finish off remaining events in the event loop, which can
     // be important for properly shutting down the nodes.
     TestLoopEnv { test_loop, node_datas, shared_state }
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
-fn setup(accounts: &Vec<AccountId>) -> (TestLoopEnv, AccountId) {
+fn setup(accounts: &Vec<AccountId>, shard_layout: ShardLayout) -> (TestLoopEnv, AccountId) {
     let clients = accounts.iter().take(NUM_CLIENTS).cloned().collect_vec();
 
     // split the clients into producers, validators, and rpc nodes
@@ -74,7 +98,6 @@ fn setup(accounts: &Vec<AccountId>) -> (TestLoopEnv, AccountId) {
     let [rpc_id] = rpcs else { panic!("Expected exactly one rpc node") };
 
     let epoch_length = 10;
-    let shard_layout = ShardLayout::simple_v1(&["account3", "account5", "account7"]);
     let validators_spec = ValidatorsSpec::desired_roles(&producers, &validators);
 
     let genesis = TestLoopBuilder::new_genesis_builder()
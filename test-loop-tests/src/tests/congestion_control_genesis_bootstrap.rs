@@ -3,6 +3,7 @@ use near_chain::ChainStoreAccess;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
 use near_client::Client;
 use near_o11y::testonly::init_test_logger;
+use near_primitives::congestion_info::{CongestionInfo, CongestionInfoV1};
 use near_primitives::shard_layout::ShardLayout;
 use near_primitives::types::AccountId;
 use near_primitives::version::{PROTOCOL_VERSION, ProtocolFeature};
@@ -13,7 +14,8 @@ use crate::utils::ONE_NEAR;
 
 const NUM_SHARDS: usize = 4;
 
-/// This test checks that the genesis congestion control info is saved into DB and not cleaned during GC,
+/// This test checks that the genesis block carries the bootstrapped congestion control info,
+/// and that it is saved into DB and not cleaned during GC,
 /// so that client can use it to bootstrap the genesis congestion control info after restarting.
 /// Restarting is the node is not checked here but in python/nayduck tests.
 #[test]
@@ -53,6 +55,9 @@ fn test_congestion_control_genesis_bootstrap() {
     test_loop.run_for(Duration::seconds(5));
 
     for i in 0..clients.len() {
+        check_genesis_block_congestion_info(
+            &test_loop.data.get(&node_datas[i].client_sender.actor_handle()).client,
+        );
         check_genesis_congestion_info_in_store(
             &mut test_loop.data.get_mut(&node_datas[i].client_sender.actor_handle()).client,
         );
@@ -62,6 +67,31 @@ fn test_congestion_control_genesis_bootstrap() {
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
+/// Checks that the congestion info in the genesis block is the one bootstrapped
+/// from a genesis state with no delayed or buffered receipts.
+fn check_genesis_block_congestion_info(client: &Client) {
+    let genesis_block = client.chain.genesis_block();
+    let block_congestion_info = genesis_block.block_congestion_info();
+    assert_eq!(block_congestion_info.all_shards().len(), NUM_SHARDS);
+
+    for chunk_header in genesis_block.chunks().iter_raw() {
+        let shard_id = chunk_header.shard_id();
+        // Bootstrapping sets the allowed shard to the shard itself, so that it
+        // doesn't depend on the other shards.
+        let expected = CongestionInfo::V1(CongestionInfoV1 {
+            delayed_receipts_gas: 0,
+            buffered_receipts_gas: 0,
+            receipt_bytes: 0,
+            allowed_shard: shard_id.into(),
+        });
+        assert_eq!(chunk_header.congestion_info(), Some(expected), "shard {}", shard_id);
+
+        let extended_info = block_congestion_info.get(&shard_id).unwrap();
+        assert_eq!(extended_info.congestion_info, expected, "shard {}", shard_id);
+        assert_eq!(extended_info.missed_chunks_count, 0, "shard {}", shard_id);
+    }
+}
+
 fn check_genesis_congestion_info_in_store(client: &mut Client) {
     let gc_config = client.config.gc.clone();
     let signer = client.validator_signer.get();
@@ -7,13 +7,14 @@ use near_primitives::types::AccountId;
 use near_vm_runner::ContractCode;
 
 use crate::setup::builder::TestLoopBuilder;
+use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::contract_distribution::{
     assert_all_chunk_endorsements_received, clear_compiled_contract_caches,
     run_until_caches_contain_contract,
 };
 use crate::utils::transactions::{call_contract, check_txs, deploy_contract, make_accounts};
-use crate::utils::{ONE_NEAR, get_node_head_height};
+use crate::utils::{ONE_NEAR, get_node_client, get_node_head_height};
 
 const EPOCH_LENGTH: u64 = 10;
 const GENESIS_HEIGHT: u64 = 1000;
@@ -35,7 +36,7 @@ fn test_contract_distribution_cross_shard() {
     init_test_logger();
     let accounts = make_accounts(NUM_ACCOUNTS);
 
-    let (mut env, rpc_id) = setup(&accounts);
+    let (mut env, rpc_id) = setup(&accounts, vec![]);
 
     let mut nonce = 1;
     let rpc_index = 8;
@@ -69,7 +70,72 @@ fn test_contract_distribution_cross_shard() {
     env.shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
-fn setup(accounts: &Vec<AccountId>) -> (TestLoopEnv, AccountId) {
+/// Same scenario as above, but all contract distribution messages sent to or from one
+/// of the chunk validators are delayed. Checks that the code still reaches every
+/// validator, that every validator can execute the contracts and that the chain keeps
+/// finalizing blocks in the meantime.
+#[cfg_attr(not(feature = "test_features"), ignore)]
+#[test]
+fn test_contract_distribution_with_slow_validator() {
+    init_test_logger();
+    let accounts = make_accounts(NUM_ACCOUNTS);
+
+    // account5 is a chunk validator only, so the delayed messages are code requests
+    // and responses and the contract accesses and deploys it receives.
+    let slow_validator = accounts[5].clone();
+    let drop_conditions = vec![DropCondition::ContractDistributionDelayedFor {
+        account_id: slow_validator.clone(),
+        delay: Duration::milliseconds(500),
+    }];
+    let (mut env, rpc_id) = setup(&accounts, drop_conditions);
+
+    let mut nonce = 1;
+    let contract_ids = [&accounts[0], &accounts[4]];
+    let sender_ids = [&accounts[0], &accounts[1], &accounts[4], &accounts[5]];
+
+    let start_final_height = get_final_head_height(&env, &slow_validator);
+
+    let contracts = deploy_contracts(&mut env, &rpc_id, &contract_ids, &mut nonce);
+    for contract in contracts.iter() {
+        run_until_caches_contain_contract(&mut env, contract.hash());
+    }
+
+    call_contracts(&mut env, &rpc_id, &contract_ids, &sender_ids, &mut nonce);
+
+    // Without the compiled contracts, the validators have to fetch the code again to
+    // validate the calls, and the slow validator has to wait for it. Once the contracts
+    // are back in every cache, every validator has executed them.
+    clear_compiled_contract_caches(&mut env);
+    call_contracts(&mut env, &rpc_id, &contract_ids, &sender_ids, &mut nonce);
+    for contract in contracts.iter() {
+        run_until_caches_contain_contract(&mut env, contract.hash());
+    }
+
+    // The slow validator may miss some endorsements, but that must not block finality.
+    for account_id in &accounts {
+        let head_height = get_node_head_height(&env, account_id);
+        let final_height = get_final_head_height(&env, account_id);
+        assert!(
+            final_height > start_final_height,
+            "{account_id} didn't finalize any block: final head stuck at {final_height}"
+        );
+        assert!(
+            head_height - final_height <= 3,
+            "{account_id} final head {final_height} lags too far behind head {head_height}"
+        );
+    }
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+fn get_final_head_height(env: &TestLoopEnv, account_id: &AccountId) -> u64 {
+    get_node_client(env, account_id).chain.final_head().unwrap().height
+}
+
+fn setup(
+    accounts: &Vec<AccountId>,
+    drop_conditions: Vec<DropCondition>,
+) -> (TestLoopEnv, AccountId) {
     let builder = TestLoopBuilder::new();
 
     // All block_and_chunk_producers will be both block and chunk validators.
@@ -100,13 +166,12 @@ fn setup(accounts: &Vec<AccountId>) -> (TestLoopEnv, AccountId) {
         .minimum_validators_per_shard(2)
         .build_store_for_genesis_protocol_version();
 
-    let env = builder
-        .genesis(genesis)
-        .epoch_config_store(epoch_config_store)
-        .clients(clients)
-        .build()
-        .warmup();
-    (env, rpc_id)
+    let mut env =
+        builder.genesis(genesis).epoch_config_store(epoch_config_store).clients(clients).build();
+    for drop_condition in drop_conditions {
+        env = env.drop(drop_condition);
+    }
+    (env.warmup(), rpc_id)
 }
 
 /// Deploys a contract for the given accounts (`contract_ids`) and waits until the transactions are executed.
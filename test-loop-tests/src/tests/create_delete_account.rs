@@ -3,15 +3,22 @@ use near_async::futures::{DelayedActionRunner, DelayedActionRunnerExt};
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
 use near_client::client_actor::ClientActorInner;
+use near_crypto::{InMemorySigner, KeyType, PublicKey, Signer};
 use near_o11y::testonly::init_test_logger;
-use near_primitives::types::AccountId;
+use near_primitives::account::AccessKey;
+use near_primitives::errors::{InvalidAccessKeyError, InvalidTxError};
+use near_primitives::test_utils::create_user_test_signer;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Nonce};
+use near_primitives::views::{QueryRequest, QueryResponseKind};
 
 use crate::setup::builder::TestLoopBuilder;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::ONE_NEAR;
+use crate::utils::client_queries::ClientQueries;
 use crate::utils::transactions::{
-    call_contract, check_txs, do_create_account, do_delete_account, do_deploy_contract,
-    get_next_nonce,
+    call_contract, check_txs, deterministic_signer, do_create_account, do_delete_account,
+    do_deploy_contract, execute_tx, get_next_nonce, get_shared_block_hash, make_accounts, run_tx,
 };
 
 /// Write block height to contract storage.
@@ -53,15 +60,14 @@ fn check_chunks(
     });
 }
 
-/// Tests account existence flow, from creation to deletion.
-#[test]
-fn test_create_delete_account() {
-    init_test_logger();
+/// Sets up an environment with 2 producers, 2 validators and an rpc node,
+/// and launches a task checking that all chunks are produced. Returns the
+/// environment, the accounts and the id of the rpc node.
+fn setup_env() -> (TestLoopEnv, Vec<AccountId>, AccountId) {
     let builder = TestLoopBuilder::new();
 
     let epoch_length = 5;
-    let accounts =
-        (0..5).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let accounts = make_accounts(5);
     let clients = accounts.clone();
 
     // Split the clients into producers, validators, and rpc nodes.
@@ -69,7 +75,7 @@ fn test_create_delete_account() {
     let (producers, tmp) = tmp.split_at(2);
     let (validators, tmp) = tmp.split_at(2);
     let (rpcs, tmp) = tmp.split_at(1);
-    let rpc_id = rpcs[0].parse().unwrap();
+    let rpc_id: AccountId = rpcs[0].parse().unwrap();
     assert!(tmp.is_empty());
 
     // Build test environment.
@@ -79,7 +85,7 @@ fn test_create_delete_account() {
         .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
         .build();
     let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
-    let mut env = builder
+    let env = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
         .clients(clients)
@@ -96,6 +102,50 @@ fn test_create_delete_account() {
     client_sender.run_later("check_chunks", Duration::seconds(0), move |actor, runner| {
         check_chunks(actor, runner, std::cell::Cell::new(0));
     });
+    (env, accounts, rpc_id)
+}
+
+/// Returns the access keys of the account together with their nonces.
+fn access_keys(env: &TestLoopEnv, account_id: &AccountId) -> Vec<(PublicKey, Nonce)> {
+    let clients = env
+        .node_datas
+        .iter()
+        .map(|data| &env.test_loop.data.get(&data.client_sender.actor_handle()).client)
+        .collect_vec();
+    let response = clients.runtime_query(
+        account_id,
+        QueryRequest::ViewAccessKeyList { account_id: account_id.clone() },
+    );
+    let QueryResponseKind::AccessKeyList(access_keys) = response.kind else {
+        panic!("Expected AccessKeyList response");
+    };
+    access_keys.keys.into_iter().map(|key| (key.public_key, key.access_key.nonce)).collect()
+}
+
+/// Signs a transfer of 1 NEAR from `signer_id` to `receiver_id`.
+fn transfer_tx(
+    env: &TestLoopEnv,
+    signer_id: &AccountId,
+    signer: &Signer,
+    receiver_id: &AccountId,
+    nonce: Nonce,
+) -> SignedTransaction {
+    let block_hash = get_shared_block_hash(&env.node_datas, &env.test_loop.data);
+    SignedTransaction::send_money(
+        nonce,
+        signer_id.clone(),
+        receiver_id.clone(),
+        signer,
+        ONE_NEAR,
+        block_hash,
+    )
+}
+
+/// Tests account existence flow, from creation to deletion.
+#[test]
+fn test_create_delete_account() {
+    init_test_logger();
+    let (mut env, accounts, rpc_id) = setup_env();
 
     let new_account: AccountId = format!("alice.{}", accounts[0]).parse().unwrap();
     let contract_code = near_test_contracts::rs_contract().to_vec();
@@ -112,3 +162,73 @@ fn test_create_delete_account() {
 
     env.shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
+
+/// Tests that an account recreated after deletion only has the access keys it
+/// is recreated with, and that nonces of the deleted keys are not carried over.
+#[test]
+fn test_create_delete_recreate_account() {
+    init_test_logger();
+    let (mut env, accounts, rpc_id) = setup_env();
+
+    let new_account: AccountId = format!("alice.{}", accounts[0]).parse().unwrap();
+    let old_signer = create_user_test_signer(&new_account);
+
+    // Create account and use its key, so that the key's nonce goes up.
+    do_create_account(&mut env, &rpc_id, &accounts[0], &new_account, 100 * ONE_NEAR);
+    let nonce = get_next_nonce(&env.test_loop.data, &env.node_datas, &new_account);
+    let tx = transfer_tx(&env, &new_account, &old_signer, &accounts[1], nonce);
+    run_tx(&mut env.test_loop, &rpc_id, tx, &env.node_datas, Duration::seconds(5));
+    let old_key_nonce = get_next_nonce(&env.test_loop.data, &env.node_datas, &new_account) - 1;
+
+    do_delete_account(&mut env, &rpc_id, &new_account, &accounts[1]);
+
+    // Recreate the account with a new key.
+    let new_signer = InMemorySigner::from_seed(new_account.clone(), KeyType::ED25519, "new");
+    let originator_signer = deterministic_signer(0);
+    let nonce = get_next_nonce(&env.test_loop.data, &env.node_datas, &accounts[0]);
+    let block_hash = get_shared_block_hash(&env.node_datas, &env.test_loop.data);
+    let tx = SignedTransaction::create_account(
+        nonce,
+        accounts[0].clone(),
+        new_account.clone(),
+        100 * ONE_NEAR,
+        new_signer.public_key(),
+        &originator_signer,
+        block_hash,
+    );
+    run_tx(&mut env.test_loop, &rpc_id, tx, &env.node_datas, Duration::seconds(5));
+
+    // Only the new key exists, and its nonce is derived from the height at
+    // which it was added rather than from the nonce of the deleted key.
+    let keys = access_keys(&env, &new_account);
+    assert_eq!(keys.len(), 1);
+    let (public_key, new_key_nonce) = keys[0].clone();
+    assert_eq!(public_key, new_signer.public_key());
+    assert_eq!(new_key_nonce % AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER, 0);
+    assert!(new_key_nonce > old_key_nonce);
+
+    // The deleted key can't sign transactions anymore.
+    let tx = transfer_tx(&env, &new_account, &old_signer, &accounts[1], old_key_nonce + 1);
+    let res = execute_tx(&mut env.test_loop, &rpc_id, tx, &env.node_datas, Duration::seconds(5));
+    assert_eq!(
+        res.unwrap_err(),
+        InvalidTxError::InvalidAccessKeyError(InvalidAccessKeyError::AccessKeyNotFound {
+            account_id: new_account.clone(),
+            public_key: old_signer.public_key().into(),
+        })
+    );
+
+    // The new key starts from its own nonce, so nonces valid for the deleted
+    // key are rejected.
+    let tx = transfer_tx(&env, &new_account, &new_signer, &accounts[1], old_key_nonce + 1);
+    let res = execute_tx(&mut env.test_loop, &rpc_id, tx, &env.node_datas, Duration::seconds(5));
+    assert_eq!(
+        res.unwrap_err(),
+        InvalidTxError::InvalidNonce { tx_nonce: old_key_nonce + 1, ak_nonce: new_key_nonce }
+    );
+
+    let tx = transfer_tx(&env, &new_account, &new_signer, &accounts[1], new_key_nonce + 1);
+    run_tx(&mut env.test_loop, &rpc_id, tx, &env.node_datas, Duration::seconds(5));
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
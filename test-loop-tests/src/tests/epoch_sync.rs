@@ -3,34 +3,69 @@ use std::rc::Rc;
 
 use itertools::Itertools;
 use near_async::messaging::CanSend;
+use near_async::test_loop::TestLoopV2;
+use near_async::test_loop::data::TestLoopDataHandle;
 use near_async::time::Duration;
 use near_chain::ChainStoreAccess;
 use near_chain_configs::GenesisConfig;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
-use near_client::SetNetworkInfo;
+use near_client::client_actor::ClientActorInner;
 use near_client::sync::epoch::EpochSync;
+use near_client::{Client, SetNetworkInfo};
 use near_network::types::NetworkInfo;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::epoch_sync::EpochSyncProof;
 use near_primitives::shard_layout::ShardLayout;
-use near_primitives::types::{AccountId, BlockHeightDelta};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta};
 use near_primitives::utils::compression::CompressedData;
 use near_store::adapter::StoreAdapter;
+use near_store::flat::FlatStorageStatus;
 
 use crate::setup::builder::{NodeStateBuilder, TestLoopBuilder};
 use crate::setup::env::TestLoopEnv;
+use crate::setup::state::NodeSetupState;
 use crate::utils::ONE_NEAR;
 use crate::utils::client_queries::ClientQueries;
+use crate::utils::sharding::{get_client_tracking_shard, get_tracked_shards};
 use crate::utils::transactions::{BalanceMismatchError, execute_money_transfers};
 
 const NUM_CLIENTS: usize = 4;
 
-fn setup_initial_blockchain(transaction_validity_period: BlockHeightDelta) -> TestLoopEnv {
+/// Epoch length used by the scenarios that don't test a specific one.
+const DEFAULT_EPOCH_LENGTH: BlockHeightDelta = 10;
+
+/// Sync statuses a node that only has genesis goes through to catch up with
+/// the chain.
+const EPOCH_SYNC_STATUS_HISTORY: &[&str] = &[
+    // Initial state.
+    "AwaitingPeers",
+    // State after having enough peers.
+    "NoSync",
+    // EpochSync should be entered first.
+    "EpochSync",
+    // EpochSync should succeed.
+    "EpochSyncDone",
+    // Header sync happens next to bring forward HEADER_HEAD.
+    "HeaderSync",
+    // State sync downloads the state from state dumps.
+    "StateSync",
+    // State sync is done.
+    "StateSyncDone",
+    // Block sync picks up from where StateSync left off, and finishes the sync.
+    "BlockSync",
+    // NoSync means we're up to date.
+    "NoSync",
+];
+
+fn setup_initial_blockchain(
+    epoch_length: BlockHeightDelta,
+    transaction_validity_period: BlockHeightDelta,
+) -> TestLoopEnv {
     let accounts =
         (0..100).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
     let clients = accounts.iter().take(NUM_CLIENTS).cloned().collect_vec();
 
-    let epoch_length = 10;
     let shard_layout = ShardLayout::simple_v1(&["account3", "account5", "account7"]);
     let validators_spec =
         ValidatorsSpec::desired_roles(&clients.iter().map(|t| t.as_str()).collect_vec(), &[]);
@@ -75,49 +110,47 @@ fn setup_initial_blockchain(transaction_validity_period: BlockHeightDelta) -> Te
     }
 
     // Make sure the chain progressed for several epochs.
-    let client_actor = test_loop.data.get(&node_datas[0].client_sender.actor_handle());
-    assert!(client_actor.client.chain.head().unwrap().height > 10050);
+    let node0 = node_datas[0].client_sender.actor_handle();
+    let min_height = shared_state.genesis.config.genesis_height + 5 * epoch_length;
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&node0).client.chain.head().unwrap().height > min_height
+        },
+        Duration::seconds(10 * epoch_length as i64),
+    );
 
     TestLoopEnv { test_loop, node_datas, shared_state }
 }
 
-fn bootstrap_node_via_epoch_sync(mut env: TestLoopEnv, source_node: usize) -> TestLoopEnv {
+/// Builds the state of a node that only has genesis, configured so that it
+/// catches up with the chain via epoch sync.
+fn fresh_node_state(env: &TestLoopEnv, account_id: AccountId) -> NodeSetupState {
     let genesis = env.shared_state.genesis.clone();
+    let epoch_length = genesis.config.epoch_length;
     let tempdir_path = env.shared_state.tempdir.path().to_path_buf();
-    let identifier = format!("account{}", env.node_datas.len());
-    let account_id = identifier.parse().unwrap();
-    let node_state = NodeStateBuilder::new(genesis, tempdir_path)
+    NodeStateBuilder::new(genesis, tempdir_path)
         .account_id(account_id)
-        .config_modifier(|config| {
+        .config_modifier(move |config| {
             // Enable epoch sync, and make the horizon small enough to trigger it.
-            config.epoch_sync.epoch_sync_horizon = 30;
+            config.epoch_sync.epoch_sync_horizon = 3 * epoch_length;
             // Make header sync horizon small enough to trigger it.
             config.block_header_fetch_horizon = 8;
             // Make block sync horizon small enough to trigger it.
             config.block_fetch_horizon = 3;
         })
-        .build();
-    env.add_node(&identifier, node_state);
-
-    let TestLoopEnv { mut test_loop, node_datas, shared_state } = env;
-
-    // Normally env.add_node() sets the network_info for the new node, but we want to override this
-    // with the highest height peer info from the source node.
-    let highest_height_peer_info =
-        node_datas[source_node].get_highest_height_peer_info(&test_loop.data);
-    let client_sender = &node_datas.last().unwrap().client_sender;
-    client_sender.send(SetNetworkInfo(NetworkInfo {
-        highest_height_peers: vec![highest_height_peer_info],
-        ..NetworkInfo::default()
-    }));
+        .build()
+}
 
-    // Check that the new node will reach a high height as well.
-    let new_node = client_sender.actor_handle();
+/// Records every distinct sync status the given node goes through, in order.
+fn record_sync_status_history(
+    test_loop: &mut TestLoopV2,
+    node: TestLoopDataHandle<ClientActorInner>,
+) -> Rc<RefCell<Vec<String>>> {
     let sync_status_history = Rc::new(RefCell::new(Vec::new()));
     {
         let sync_status_history = sync_status_history.clone();
         test_loop.set_every_event_callback(move |test_loop_data| {
-            let client = &test_loop_data.get(&new_node).client;
+            let client = &test_loop_data.get(&node).client;
             let header_head_height = client.chain.header_head().unwrap().height;
             let head_height = client.chain.head().unwrap().height;
             tracing::info!(
@@ -133,7 +166,30 @@ fn bootstrap_node_via_epoch_sync(mut env: TestLoopEnv, source_node: usize) -> Te
             }
         });
     }
+    sync_status_history
+}
+
+fn bootstrap_node_via_epoch_sync(mut env: TestLoopEnv, source_node: usize) -> TestLoopEnv {
+    let identifier = format!("account{}", env.node_datas.len());
+    let node_state = fresh_node_state(&env, identifier.parse().unwrap());
+    env.add_node(&identifier, node_state);
+
+    let epoch_length = env.shared_state.genesis.config.epoch_length;
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = env;
+
+    // Normally env.add_node() sets the network_info for the new node, but we want to override this
+    // with the highest height peer info from the source node.
+    let highest_height_peer_info =
+        node_datas[source_node].get_highest_height_peer_info(&test_loop.data);
+    let client_sender = &node_datas.last().unwrap().client_sender;
+    client_sender.send(SetNetworkInfo(NetworkInfo {
+        highest_height_peers: vec![highest_height_peer_info],
+        ..NetworkInfo::default()
+    }));
+
+    // Check that the new node will reach a high height as well.
     let new_node = client_sender.actor_handle();
+    let sync_status_history = record_sync_status_history(&mut test_loop, new_node.clone());
     let node0 = node_datas[0].client_sender.actor_handle();
     test_loop.run_until(
         |test_loop_data| {
@@ -141,7 +197,7 @@ fn bootstrap_node_via_epoch_sync(mut env: TestLoopEnv, source_node: usize) -> Te
             let node0_height = test_loop_data.get(&node0).client.chain.head().unwrap().height;
             new_node_height == node0_height
         },
-        Duration::seconds(20),
+        Duration::seconds(2 * epoch_length as i64),
     );
 
     let current_height = test_loop.data.get(&node0).client.chain.head().unwrap().height;
@@ -149,36 +205,11 @@ fn bootstrap_node_via_epoch_sync(mut env: TestLoopEnv, source_node: usize) -> Te
     test_loop.run_until(
         |test_loop_data| {
             let new_node_height = test_loop_data.get(&new_node).client.chain.head().unwrap().height;
-            new_node_height >= current_height + 30
+            new_node_height >= current_height + 3 * epoch_length
         },
-        Duration::seconds(30),
-    );
-    assert_eq!(
-        sync_status_history.borrow().as_slice(),
-        &[
-            // Initial state.
-            "AwaitingPeers",
-            // State after having enough peers.
-            "NoSync",
-            // EpochSync should be entered first.
-            "EpochSync",
-            // EpochSync should succeed.
-            "EpochSyncDone",
-            // Header sync happens next to bring forward HEADER_HEAD.
-            "HeaderSync",
-            // State sync downloads the state from state dumps.
-            "StateSync",
-            // State sync is done.
-            "StateSyncDone",
-            // Block sync picks up from where StateSync left off, and finishes the sync.
-            "BlockSync",
-            // NoSync means we're up to date.
-            "NoSync"
-        ]
-        .into_iter()
-        .map(|s| s.to_string())
-        .collect::<Vec<_>>()
+        Duration::seconds(3 * epoch_length as i64),
     );
+    assert_eq!(sync_status_history.borrow().as_slice(), EPOCH_SYNC_STATUS_HISTORY);
 
     TestLoopEnv { test_loop, node_datas, shared_state }
 }
@@ -188,7 +219,7 @@ fn bootstrap_node_via_epoch_sync(mut env: TestLoopEnv, source_node: usize) -> Te
 #[test]
 fn slow_test_epoch_sync_from_genesis() {
     init_test_logger();
-    let env = setup_initial_blockchain(20);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 20);
     let env = bootstrap_node_via_epoch_sync(env, 0);
     env.shutdown_and_drain_remaining_events(Duration::seconds(5));
 }
@@ -198,16 +229,197 @@ fn slow_test_epoch_sync_from_genesis() {
 #[test]
 fn slow_test_epoch_sync_from_another_epoch_synced_node() {
     init_test_logger();
-    let env = setup_initial_blockchain(20);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 20);
     let env = bootstrap_node_via_epoch_sync(env, 0);
     let env = bootstrap_node_via_epoch_sync(env, 4);
     env.shutdown_and_drain_remaining_events(Duration::seconds(5));
 }
 
+/// Bootstraps a node via epoch sync on a chain with the given epoch length,
+/// and checks the epoch sync proof the new node then serves. The transaction
+/// validity period is two epochs long, so the proof starts two epochs back.
+fn run_epoch_sync_with_epoch_length(epoch_length: BlockHeightDelta) {
+    init_test_logger();
+    let env = setup_initial_blockchain(epoch_length, 2 * epoch_length);
+    let env = bootstrap_node_via_epoch_sync(env, 0);
+    let proof = env.derive_epoch_sync_proof(NUM_CLIENTS);
+    let final_head_height = env.chain_final_head_height(NUM_CLIENTS);
+    sanity_check_epoch_sync_proof(&proof, final_head_height, &env.shared_state.genesis.config, 2);
+    assert_eq!(proof, env.derive_epoch_sync_proof(0));
+    env.shutdown_and_drain_remaining_events(Duration::seconds(5));
+}
+
+// A short epoch length puts many epoch boundaries, and so many epochs in the
+// proof, into a short chain.
+#[test]
+fn slow_test_epoch_sync_short_epoch_length() {
+    run_epoch_sync_with_epoch_length(5);
+}
+
+#[test]
+fn slow_test_epoch_sync_long_epoch_length() {
+    run_epoch_sync_with_epoch_length(50);
+}
+
+/// Number of blocks and chunks produced, and chunks endorsed, by a validator.
+#[derive(Debug, Default)]
+struct ValidatorWork {
+    blocks: usize,
+    chunks: usize,
+    endorsements: usize,
+}
+
+/// Counts the work of `account_id` included in the blocks of `client` at the
+/// given heights.
+fn count_validator_work(
+    client: &Client,
+    account_id: &AccountId,
+    heights: std::ops::RangeInclusive<BlockHeight>,
+) -> ValidatorWork {
+    let epoch_manager = client.epoch_manager.as_ref();
+    let mut work = ValidatorWork::default();
+    for height in heights {
+        let Ok(block) = client.chain.get_block_by_height(height) else {
+            continue;
+        };
+        if &epoch_manager.get_block_producer(block.header().epoch_id(), height).unwrap()
+            == account_id
+        {
+            work.blocks += 1;
+        }
+        for (shard_index, chunk) in block.chunks().iter_raw().enumerate() {
+            if !chunk.is_new_chunk(height) {
+                continue;
+            }
+            let epoch_id =
+                epoch_manager.get_epoch_id_from_prev_block(chunk.prev_block_hash()).unwrap();
+            let key = ChunkProductionKey {
+                epoch_id,
+                height_created: chunk.height_created(),
+                shard_id: chunk.shard_id(),
+            };
+            if epoch_manager.get_chunk_producer_info(&key).unwrap().account_id() == account_id {
+                work.chunks += 1;
+            }
+            let assignments = epoch_manager
+                .get_chunk_validator_assignments(
+                    &epoch_id,
+                    chunk.shard_id(),
+                    chunk.height_created(),
+                )
+                .unwrap();
+            let signatures = &block.chunk_endorsements()[shard_index];
+            for ((validator, _), signature) in assignments.assignments().iter().zip(signatures) {
+                if validator == account_id && signature.is_some() {
+                    work.endorsements += 1;
+                }
+            }
+        }
+    }
+    work
+}
+
+/// Checks that `client` has the complete state of every shard it tracks at its
+/// head: flat storage is ready, the state root matches the one computed by a
+/// node which never synced, and every trie node is present in the store.
+fn assert_complete_state(client: &Client, reference_clients: &[&Client]) {
+    let tip = client.chain.head().unwrap();
+    let tracked_shards = get_tracked_shards(client, &tip.last_block_hash);
+    assert!(!tracked_shards.is_empty());
+    for shard_uid in tracked_shards {
+        let flat_storage_status =
+            client.runtime_adapter.get_flat_storage_manager().get_flat_storage_status(shard_uid);
+        assert!(
+            matches!(flat_storage_status, FlatStorageStatus::Ready(_)),
+            "flat storage for shard {shard_uid} is not ready: {flat_storage_status:?}"
+        );
+
+        let state_root =
+            *client.chain.get_chunk_extra(&tip.last_block_hash, &shard_uid).unwrap().state_root();
+        let reference_client =
+            get_client_tracking_shard(reference_clients, &tip, shard_uid.shard_id());
+        let reference_state_root = *reference_client
+            .chain
+            .get_chunk_extra(&tip.last_block_hash, &shard_uid)
+            .unwrap()
+            .state_root();
+        assert_eq!(state_root, reference_state_root, "state root mismatch for shard {shard_uid}");
+
+        let trie = client
+            .runtime_adapter
+            .get_trie_for_shard(shard_uid.shard_id(), &tip.last_block_hash, state_root, false)
+            .unwrap();
+        let mut num_items = 0;
+        for item in trie.disk_iter().unwrap() {
+            item.unwrap_or_else(|err| panic!("incomplete state for shard {shard_uid}: {err}"));
+            num_items += 1;
+        }
+        assert!(num_items > 0, "empty state for shard {shard_uid}");
+    }
+}
+
+// Test that a validator which lost all of its data can rejoin the way a new
+// node does: epoch sync to near the head, state sync the shards of the current
+// epoch right after, and then go back to producing and validating blocks and
+// chunks.
+#[test]
+fn slow_test_epoch_sync_then_state_sync_validator() {
+    init_test_logger();
+    let mut env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 20);
+
+    // Replace the node of the last validator with one that only has genesis.
+    let validator = env.node_datas[NUM_CLIENTS - 1].account_id.clone();
+    env.kill_node(validator.as_str());
+    let node_state = fresh_node_state(&env, validator.clone());
+    env.restart_node(&format!("{validator}-fresh"), node_state);
+
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = env;
+    let new_node = node_datas.last().unwrap().client_sender.actor_handle();
+    let node0 = node_datas[0].client_sender.actor_handle();
+    let sync_status_history = record_sync_status_history(&mut test_loop, new_node.clone());
+    test_loop.run_until(
+        |test_loop_data| {
+            let new_node_height = test_loop_data.get(&new_node).client.chain.head().unwrap().height;
+            let node0_height = test_loop_data.get(&node0).client.chain.head().unwrap().height;
+            new_node_height == node0_height
+        },
+        Duration::seconds(20),
+    );
+    assert_eq!(sync_status_history.borrow().as_slice(), EPOCH_SYNC_STATUS_HISTORY);
+
+    // Run for a few more epochs, so that the validator does its work for at
+    // least one full epoch after syncing.
+    let synced_height = test_loop.data.get(&new_node).client.chain.head().unwrap().height;
+    test_loop.run_until(
+        |test_loop_data| {
+            let new_node_height = test_loop_data.get(&new_node).client.chain.head().unwrap().height;
+            new_node_height >= synced_height + 30
+        },
+        Duration::seconds(30),
+    );
+
+    let client = &test_loop.data.get(&new_node).client;
+    let reference_clients = node_datas[..NUM_CLIENTS - 1]
+        .iter()
+        .map(|data| &test_loop.data.get(&data.client_sender.actor_handle()).client)
+        .collect_vec();
+    assert_complete_state(client, &reference_clients);
+
+    let head_height = client.chain.head().unwrap().height;
+    let work = count_validator_work(client, &validator, synced_height + 10..=head_height);
+    tracing::info!("Work of {validator} after syncing: {work:?}");
+    assert!(work.blocks > 0, "{validator} did not produce blocks after syncing");
+    assert!(work.chunks > 0, "{validator} did not produce chunks after syncing");
+    assert!(work.endorsements > 0, "{validator} did not endorse chunks after syncing");
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(5));
+}
+
 #[test]
 fn slow_test_epoch_sync_transaction_validity_period_one_epoch() {
     init_test_logger();
-    let env = setup_initial_blockchain(10);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 10);
     let env = bootstrap_node_via_epoch_sync(env, 0);
     let env = bootstrap_node_via_epoch_sync(env, 4);
     env.shutdown_and_drain_remaining_events(Duration::seconds(5));
@@ -216,7 +428,7 @@ fn slow_test_epoch_sync_transaction_validity_period_one_epoch() {
 #[test]
 fn slow_test_epoch_sync_with_expired_transactions() {
     init_test_logger();
-    let env = setup_initial_blockchain(1);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 1);
     let env = bootstrap_node_via_epoch_sync(env, 0);
     let env = bootstrap_node_via_epoch_sync(env, 4);
     env.shutdown_and_drain_remaining_events(Duration::seconds(5));
@@ -300,7 +512,7 @@ fn sanity_check_epoch_sync_proof(
 #[test]
 fn slow_test_initial_epoch_sync_proof_sanity() {
     init_test_logger();
-    let env = setup_initial_blockchain(20);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 20);
     let proof = env.derive_epoch_sync_proof(0);
     let final_head_height = env.chain_final_head_height(0);
     sanity_check_epoch_sync_proof(&proof, final_head_height, &env.shared_state.genesis.config, 2);
@@ -314,7 +526,7 @@ fn slow_test_initial_epoch_sync_proof_sanity() {
 #[test]
 fn slow_test_epoch_sync_proof_sanity_from_epoch_synced_node() {
     init_test_logger();
-    let env = setup_initial_blockchain(20);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 20);
     let env = bootstrap_node_via_epoch_sync(env, 0);
     let old_proof = env.derive_epoch_sync_proof(0);
     let new_proof = env.derive_epoch_sync_proof(4);
@@ -344,7 +556,7 @@ fn slow_test_epoch_sync_proof_sanity_from_epoch_synced_node() {
 #[test]
 fn slow_test_epoch_sync_proof_sanity_shorter_transaction_validity_period() {
     init_test_logger();
-    let env = setup_initial_blockchain(10);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 10);
     let proof = env.derive_epoch_sync_proof(0);
     let final_head_height = env.chain_final_head_height(0);
     sanity_check_epoch_sync_proof(&proof, final_head_height, &env.shared_state.genesis.config, 1);
@@ -354,7 +566,7 @@ fn slow_test_epoch_sync_proof_sanity_shorter_transaction_validity_period() {
 #[test]
 fn slow_test_epoch_sync_proof_sanity_zero_transaction_validity_period() {
     init_test_logger();
-    let env = setup_initial_blockchain(0);
+    let env = setup_initial_blockchain(DEFAULT_EPOCH_LENGTH, 0);
     let proof = env.derive_epoch_sync_proof(0);
     let final_head_height = env.chain_final_head_height(0);
     // The proof should still be for the previous epoch, for state sync purposes.
@@ -2,6 +2,7 @@ use crate::setup::builder::TestLoopBuilder;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::ONE_NEAR;
 use crate::utils::validators::get_epoch_all_validators;
+use itertools::Itertools;
 use near_async::test_loop::data::TestLoopData;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::TestEpochConfigBuilder;
@@ -11,18 +12,25 @@ use near_primitives::shard_layout::ShardLayout;
 use near_primitives::test_utils::create_test_signer;
 use near_primitives::types::AccountId;
 use near_primitives::types::AccountInfo;
+use near_primitives::upgrade_schedule::ProtocolUpgradeVotingSchedule;
 use near_primitives::version::ProtocolFeature;
 
-#[test]
-fn slow_test_fix_cp_stake_threshold() {
-    init_test_logger();
+const EPOCH_LENGTH: u64 = 10;
+const NUM_SHARDS: u64 = 6;
 
-    let protocol_version = ProtocolFeature::FixChunkProducerStakingThreshold.protocol_version() - 1;
-    let epoch_length = 10;
+/// Sets up a chain at the protocol version right before `FixChunkProducerStakingThreshold`,
+/// voting for the version enabling it right away. test0 and test1 have most of the stake,
+/// test2's stake is right at the chunk producer threshold before the fix, and test3's is
+/// just above it.
+fn setup_env() -> TestLoopEnv {
+    let target_protocol_version =
+        ProtocolFeature::FixChunkProducerStakingThreshold.protocol_version();
+    let protocol_version = target_protocol_version - 1;
+    let epoch_length = EPOCH_LENGTH;
     let accounts =
         (0..6).map(|i| format!("test{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
     let clients = accounts.iter().cloned().collect::<Vec<_>>();
-    let num_shards = 6;
+    let num_shards = NUM_SHARDS;
     let shard_layout = ShardLayout::multi_shard(num_shards, 1);
     let validators = vec![
         AccountInfo {
@@ -58,12 +66,24 @@ fn slow_test_fix_cp_stake_threshold() {
         .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
         .build();
     let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
-    let TestLoopEnv { mut test_loop, node_datas, shared_state } = TestLoopBuilder::new()
+    TestLoopBuilder::new()
         .genesis(genesis)
-        .epoch_config_store(epoch_config_store.clone())
+        .epoch_config_store(epoch_config_store)
+        .protocol_upgrade_schedule(ProtocolUpgradeVotingSchedule::new_immediate(
+            target_protocol_version,
+        ))
         .clients(clients)
         .build()
-        .warmup();
+        .warmup()
+}
+
+#[test]
+fn slow_test_fix_cp_stake_threshold() {
+    init_test_logger();
+
+    let epoch_length = EPOCH_LENGTH;
+    let num_shards = NUM_SHARDS;
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = setup_env();
 
     let sender = node_datas[0].client_sender.clone();
     let handle = sender.actor_handle();
@@ -80,7 +100,7 @@ fn slow_test_fix_cp_stake_threshold() {
         protocol_version < ProtocolFeature::FixChunkProducerStakingThreshold.protocol_version()
     );
     assert_eq!(
-        epoch_config_store.get_config(protocol_version).shard_layout.num_shards(),
+        client.epoch_manager.get_epoch_config(&epoch_id).unwrap().shard_layout.num_shards(),
         num_shards
     );
     assert_eq!(
@@ -138,3 +158,78 @@ fn slow_test_fix_cp_stake_threshold() {
     TestLoopEnv { test_loop, node_datas, shared_state }
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
+
+/// Checks that the chunk producers are selected with the old threshold in every epoch
+/// before `FixChunkProducerStakingThreshold`, and with the new one starting exactly from
+/// the first epoch with the protocol version enabling it.
+#[test]
+fn slow_test_fix_cp_stake_threshold_upgrade_boundary() {
+    init_test_logger();
+
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = setup_env();
+    let target_protocol_version =
+        ProtocolFeature::FixChunkProducerStakingThreshold.protocol_version();
+    let handle = node_datas[0].client_sender.actor_handle();
+
+    let mut last_checked_epoch_id = None;
+    let mut num_epochs_before_upgrade = 0;
+    let upgrade_boundary_checked = |test_loop_data: &mut TestLoopData| -> bool {
+        let client = &test_loop_data.get(&handle).client;
+        let tip = client.chain.head().unwrap();
+        let epoch_height =
+            client.epoch_manager.get_epoch_height_from_prev_block(&tip.prev_block_hash).unwrap();
+        assert!(epoch_height < 5);
+
+        if last_checked_epoch_id == Some(tip.epoch_id) {
+            return false;
+        }
+        last_checked_epoch_id = Some(tip.epoch_id);
+
+        let protocol_version =
+            client.epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap();
+        let chunk_producers = client
+            .epoch_manager
+            .get_epoch_chunk_producers(&tip.epoch_id)
+            .unwrap()
+            .iter()
+            .map(|validator| validator.account_id().to_string())
+            .sorted()
+            .collect_vec();
+        if protocol_version < target_protocol_version {
+            // The threshold is `(1 / 62500) / num_shards` of the total stake, so test2 is
+            // right at it and test3 is just above it.
+            assert_eq!(
+                chunk_producers,
+                ["test0", "test1", "test3"],
+                "unexpected chunk producers in epoch {} with protocol version {}, before the fix",
+                epoch_height,
+                protocol_version
+            );
+            num_epochs_before_upgrade += 1;
+            return false;
+        }
+
+        // The threshold is no longer divided by the number of shards, so test3 is below it.
+        assert_eq!(protocol_version, target_protocol_version);
+        assert_eq!(
+            chunk_producers,
+            ["test0", "test1"],
+            "unexpected chunk producers in epoch {}, the first one with protocol version {}",
+            epoch_height,
+            protocol_version
+        );
+        true
+    };
+
+    test_loop.run_until(
+        upgrade_boundary_checked,
+        // Timeout at producing 5 epochs, approximately.
+        Duration::seconds((5 * EPOCH_LENGTH) as i64),
+    );
+    // Make sure that the boundary was crossed during the test rather than the
+    // chain starting at the new protocol version.
+    assert!(num_epochs_before_upgrade > 0);
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
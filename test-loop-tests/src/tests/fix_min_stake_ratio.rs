@@ -5,8 +5,10 @@ use crate::utils::validators::get_epoch_all_validators;
 use itertools::Itertools;
 use near_async::messaging::SendAsync;
 use near_async::test_loop::data::TestLoopData;
+use near_async::test_loop::sender::TestLoopSender;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestGenesisBuilder, ValidatorsSpec};
+use near_client::TxRequestHandler;
 use near_network::client::ProcessTxRequest;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::epoch_manager::EpochConfigStore;
@@ -20,12 +22,13 @@ use near_primitives::version::ProtocolFeature;
 use std::string::ToString;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-/// Check that small validator is included in the validator set after
-/// enabling protocol feature `FixMinStakeRatio`.
-#[test]
-fn slow_test_fix_min_stake_ratio() {
-    init_test_logger();
+const EPOCH_LENGTH: u64 = 10;
 
+/// Sets up a chain at the protocol version right before `FixMinStakeRatio`,
+/// voting for the version enabling it right away. Returns the environment and
+/// the account of the small validator, which can only join the validator set
+/// after the fix.
+fn setup_env() -> (TestLoopEnv, AccountId) {
     // Take epoch configuration before the protocol upgrade, where minimum
     // stake ratio was 1/6250.
     let epoch_config_store = EpochConfigStore::for_chain_id("mainnet", None).unwrap();
@@ -39,7 +42,6 @@ fn slow_test_fix_min_stake_ratio() {
     let builder = TestLoopBuilder::new().protocol_upgrade_schedule(protocol_upgrade_schedule);
 
     let initial_balance = 1_000_000 * ONE_NEAR;
-    let epoch_length = 10;
     let accounts =
         (0..8).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
     let clients = accounts.iter().cloned().collect_vec();
@@ -73,25 +75,59 @@ fn slow_test_fix_min_stake_ratio() {
     let validators_spec = ValidatorsSpec::raw(validators, 1, 1, 2);
 
     // Create chain with version before FixMinStakeRatio was enabled.
-    // Check that the small validator is not included in the validator set.
     let genesis = TestGenesisBuilder::new()
         .genesis_time_from_clock(&builder.clock())
         .shard_layout(shard_layout)
         .protocol_version(genesis_protocol_version)
-        .epoch_length(epoch_length)
+        .epoch_length(EPOCH_LENGTH)
         .validators_spec(validators_spec)
         // Disable validator rewards.
         .max_inflation_rate(Rational32::new(0, 1))
         .add_user_accounts_simple(&accounts, initial_balance)
         .build();
 
-    let TestLoopEnv { mut test_loop, node_datas, shared_state } = builder
+    let env = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
         .clients(clients)
         .build()
         .warmup();
+    (env, small_validator)
+}
 
+/// Sends a stake transaction for the small validator, so that it gets included
+/// in the validator set as soon as its stake is enough.
+fn stake_small_validator(
+    tx_processor_sender: &TestLoopSender<TxRequestHandler>,
+    small_validator: &AccountId,
+    prev_block_hash: CryptoHash,
+    epoch_height: u64,
+) {
+    let tx = SignedTransaction::stake(
+        epoch_height,
+        small_validator.clone(),
+        &create_user_test_signer(small_validator).into(),
+        ONE_NEAR,
+        near_primitives::test_utils::create_test_signer(small_validator.as_str()).public_key(),
+        prev_block_hash,
+    );
+    let future = tx_processor_sender.send_async(ProcessTxRequest {
+        transaction: tx,
+        is_forwarded: false,
+        check_only: false,
+    });
+    drop(future);
+}
+
+/// Check that small validator is included in the validator set after
+/// enabling protocol feature `FixMinStakeRatio`.
+#[test]
+fn slow_test_fix_min_stake_ratio() {
+    init_test_logger();
+
+    let (TestLoopEnv { mut test_loop, node_datas, shared_state }, small_validator) = setup_env();
+
+    // Check that the small validator is not included in the validator set.
     let tx_processor_sender = node_datas[0].tx_processor_sender.clone();
     let client_handle = node_datas[0].client_sender.actor_handle();
     let initial_validators = get_epoch_all_validators(&test_loop.data.get(&client_handle).client);
@@ -108,21 +144,12 @@ fn slow_test_fix_min_stake_ratio() {
     let stake_if_new_epoch_started = |prev_block_hash: CryptoHash, epoch_height: u64| {
         if epoch_height > latest_epoch_height.load(Ordering::Relaxed) {
             latest_epoch_height.store(epoch_height, Ordering::Relaxed);
-            let sender = &accounts[2];
-            let tx = SignedTransaction::stake(
-                epoch_height,
-                sender.clone(),
-                &create_user_test_signer(sender).into(),
-                ONE_NEAR,
-                near_primitives::test_utils::create_test_signer(accounts[2].as_str()).public_key(),
+            stake_small_validator(
+                &tx_processor_sender,
+                &small_validator,
                 prev_block_hash,
+                epoch_height,
             );
-            let future = tx_processor_sender.send_async(ProcessTxRequest {
-                transaction: tx,
-                is_forwarded: false,
-                check_only: false,
-            });
-            drop(future);
         }
     };
 
@@ -154,8 +181,87 @@ fn slow_test_fix_min_stake_ratio() {
     test_loop.run_until(
         success_condition,
         // Timeout at producing 5 epochs, approximately.
-        Duration::seconds((5 * epoch_length) as i64),
+        Duration::seconds((5 * EPOCH_LENGTH) as i64),
+    );
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Check that the validator set changes exactly at the protocol upgrade
+/// enabling `FixMinStakeRatio`: the small validator stakes in every epoch, but
+/// must stay out of all epochs with an older protocol version and be in the
+/// first epoch with the new one. Catches the fix being activated at the wrong
+/// protocol version.
+#[test]
+fn slow_test_fix_min_stake_ratio_upgrade_boundary() {
+    init_test_logger();
+
+    let (TestLoopEnv { mut test_loop, node_datas, shared_state }, small_validator) = setup_env();
+    let target_protocol_version = ProtocolFeature::FixMinStakeRatio.protocol_version();
+
+    let tx_processor_sender = node_datas[0].tx_processor_sender.clone();
+    let client_handle = node_datas[0].client_sender.actor_handle();
+
+    let mut latest_epoch_height = 0;
+    let mut last_checked_epoch_id = None;
+    let mut num_epochs_before_upgrade = 0;
+    let upgrade_boundary_checked = |test_loop_data: &mut TestLoopData| -> bool {
+        let client = &test_loop_data.get(&client_handle).client;
+        let tip = client.chain.head().unwrap();
+        let epoch_height =
+            client.epoch_manager.get_epoch_height_from_prev_block(&tip.prev_block_hash).unwrap();
+        if epoch_height > latest_epoch_height {
+            latest_epoch_height = epoch_height;
+            stake_small_validator(
+                &tx_processor_sender,
+                &small_validator,
+                tip.prev_block_hash,
+                epoch_height,
+            );
+        }
+        assert!(epoch_height < 5);
+
+        if last_checked_epoch_id == Some(tip.epoch_id) {
+            return false;
+        }
+        last_checked_epoch_id = Some(tip.epoch_id);
+
+        let protocol_version =
+            client.epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap();
+        let epoch_config = client.epoch_manager.get_epoch_config(&tip.epoch_id).unwrap();
+        let validators = get_epoch_all_validators(client);
+        if protocol_version < target_protocol_version {
+            assert_eq!(epoch_config.minimum_stake_ratio, Rational32::new(1, 6_250));
+            assert!(
+                !validators.contains(&small_validator.to_string()),
+                "small validator selected in epoch {} with protocol version {}, before the fix",
+                epoch_height,
+                protocol_version
+            );
+            num_epochs_before_upgrade += 1;
+            return false;
+        }
+
+        assert_eq!(protocol_version, target_protocol_version);
+        assert_eq!(epoch_config.minimum_stake_ratio, Rational32::new(1, 62_500));
+        assert!(
+            validators.contains(&small_validator.to_string()),
+            "small validator not selected in epoch {}, the first one with protocol version {}",
+            epoch_height,
+            protocol_version
+        );
+        true
+    };
+
+    test_loop.run_until(
+        upgrade_boundary_checked,
+        // Timeout at producing 5 epochs, approximately.
+        Duration::seconds((5 * EPOCH_LENGTH) as i64),
     );
+    // Make sure that the boundary was crossed during the test rather than the
+    // chain starting at the new protocol version.
+    assert!(num_epochs_before_upgrade > 0);
 
     TestLoopEnv { test_loop, node_datas, shared_state }
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
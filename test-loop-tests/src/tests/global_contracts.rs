@@ -1,4 +1,7 @@
+use std::task::Poll;
+
 use assert_matches::assert_matches;
+use itertools::Itertools;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
 use near_client::Client;
@@ -22,8 +25,8 @@ use near_vm_runner::ContractCode;
 use crate::setup::builder::TestLoopBuilder;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::client_queries::ClientQueries;
-use crate::utils::transactions::{self};
-use crate::utils::{ONE_NEAR, TGAS};
+use crate::utils::transactions::{self, TransactionRunner};
+use crate::utils::{ONE_NEAR, TGAS, get_node_data};
 
 const GAS_PRICE: Balance = 1;
 
@@ -111,6 +114,69 @@ fn test_global_contract_update() {
     env.shutdown();
 }
 
+#[test]
+fn test_global_contract_upgrade_and_rollback() {
+    let mut env = GlobalContractsTestEnv::setup(1000 * ONE_NEAR);
+    let use_accounts = [env.account_shard_0.clone(), env.account_shard_1.clone()];
+
+    env.deploy_trivial_global_contract(GlobalContractDeployMode::AccountId);
+    for account in &use_accounts {
+        env.use_global_contract(
+            account,
+            GlobalContractIdentifier::AccountId(env.deploy_account.clone()),
+        );
+        let call_tx = env.call_global_contract_tx(account);
+        assert_method_not_found(&env.execute_tx(call_tx));
+    }
+
+    // Upgrade the contract and call it from every referencing account in the
+    // same block. The new code reaches the shards through distribution
+    // receipts which are only produced once the upgrade is applied, so calls
+    // executed in the same block must still resolve against the old code.
+    let mut txs = vec![env.deploy_global_contract_tx(GlobalContractDeployMode::AccountId)];
+    txs.extend(use_accounts.iter().map(|account| env.call_global_contract_tx(account)));
+    let outcomes = env.execute_txs(txs);
+    let (upgrade_outcome, call_outcomes) = outcomes.split_first().unwrap();
+    assert_matches!(upgrade_outcome.status, FinalExecutionStatus::SuccessValue(_));
+    let upgrade_block = upgrade_outcome.receipts_outcome[0].block_hash;
+    for call_outcome in call_outcomes {
+        assert_eq!(
+            call_outcome.receipts_outcome[0].block_hash, upgrade_block,
+            "call was expected to execute in the same block as the upgrade"
+        );
+        assert_method_not_found(call_outcome);
+    }
+
+    // All referencing accounts pick up the new code on subsequent calls.
+    for account in &use_accounts {
+        env.call_global_contract(account);
+    }
+
+    // Roll back to the previous version, the referencing accounts must go
+    // back to executing the old code.
+    env.deploy_trivial_global_contract(GlobalContractDeployMode::AccountId);
+    for account in &use_accounts {
+        let call_tx = env.call_global_contract_tx(account);
+        assert_method_not_found(&env.execute_tx(call_tx));
+    }
+
+    env.shutdown();
+}
+
+/// The trivial contract doesn't have any methods, so any function call to it
+/// fails with MethodNotFound.
+fn assert_method_not_found(outcome: &FinalExecutionOutcomeView) {
+    assert_matches!(
+        outcome.status,
+        FinalExecutionStatus::Failure(TxExecutionError::ActionError(ActionError {
+            kind: ActionErrorKind::FunctionCallError(FunctionCallError::MethodResolveError(
+                MethodResolveError::MethodNotFound
+            )),
+            index: _
+        }))
+    );
+}
+
 fn test_deploy_and_call_global_contract(deploy_mode: GlobalContractDeployMode) {
     const INITIAL_BALANCE: Balance = 1000 * ONE_NEAR;
     let mut env = GlobalContractsTestEnv::setup(INITIAL_BALANCE);
@@ -359,6 +425,33 @@ impl GlobalContractsTestEnv {
         .unwrap()
     }
 
+    /// Submits all the transactions at once and waits for all of them to be executed.
+    fn execute_txs(&mut self, txs: Vec<SignedTransaction>) -> Vec<FinalExecutionOutcomeView> {
+        let node_data = get_node_data(&self.env.node_datas, &self.rpc);
+        let client_sender = &node_data.client_sender;
+        let tx_processor_sender = &node_data.tx_processor_sender;
+        let future_spawner = self.env.test_loop.future_spawner("TransactionRunner");
+
+        let mut tx_runners =
+            txs.into_iter().map(|tx| TransactionRunner::new(tx, true)).collect_vec();
+        let mut results = vec![None; tx_runners.len()];
+        self.env.test_loop.run_until(
+            |tl_data| {
+                let client = &tl_data.get(&client_sender.actor_handle()).client;
+                for (runner, result) in tx_runners.iter_mut().zip(results.iter_mut()) {
+                    if let Poll::Ready(tx_res) =
+                        runner.poll(tx_processor_sender, client, &future_spawner)
+                    {
+                        *result = Some(tx_res.unwrap());
+                    }
+                }
+                results.iter().all(|result| result.is_some())
+            },
+            Duration::seconds(5),
+        );
+        results.into_iter().map(|result| result.unwrap()).collect()
+    }
+
     fn run_tx(&mut self, tx: SignedTransaction) {
         transactions::run_tx(
             &mut self.env.test_loop,
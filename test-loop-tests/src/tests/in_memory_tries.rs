@@ -1,4 +1,7 @@
+#[cfg(feature = "test_features")]
+use bytesize::ByteSize;
 use itertools::Itertools;
+use near_async::test_loop::data::TestLoopData;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
 use near_o11y::testonly::init_test_logger;
@@ -9,7 +12,10 @@ use crate::setup::builder::TestLoopBuilder;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::ONE_NEAR;
 use crate::utils::client_queries::ClientQueries;
+use crate::utils::sharding::{in_memory_trie_node_count, is_shard_loaded_in_memory};
 use crate::utils::transactions::execute_money_transfers;
+#[cfg(feature = "test_features")]
+use crate::utils::transactions::make_accounts;
 
 /// Runs chain with sequence of chunks with empty state changes, long enough to
 /// cover 5 epochs which is default GC period.
@@ -95,3 +101,146 @@ fn test_load_memtrie_after_empty_chunks() {
     TestLoopEnv { test_loop, node_datas, shared_state }
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
+
+/// Checks that every shard tracked by a node is backed by a memtrie, that
+/// untracked shards are not loaded, and that the number of nodes kept in
+/// memory stays bounded while the chain progresses.
+#[test]
+fn test_memtrie_loaded_for_tracked_shards() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let epoch_length = 5;
+    let shard_layout = ShardLayout::simple_v1(&["account3"]);
+    let accounts =
+        (0..6).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let client_accounts = accounts.iter().take(2).cloned().collect_vec();
+    let validators_spec = ValidatorsSpec::desired_roles(
+        &client_accounts.iter().map(|t| t.as_str()).collect_vec(),
+        &[],
+    );
+
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(epoch_length)
+        .shard_layout(shard_layout.clone())
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(client_accounts)
+        .build()
+        .warmup();
+
+    let get_node_counts = |test_loop_data: &TestLoopData| {
+        let mut node_counts = vec![];
+        for data in &node_datas {
+            let client = &test_loop_data.get(&data.client_sender.actor_handle()).client;
+            let tracked_shards = vec![client].tracked_shards_for_each_client().remove(0);
+            for shard_id in shard_layout.shard_ids() {
+                let is_tracked = tracked_shards.contains(&shard_id);
+                assert_eq!(is_shard_loaded_in_memory(client, shard_id), is_tracked);
+                if is_tracked {
+                    node_counts.push(in_memory_trie_node_count(client, shard_id));
+                }
+            }
+        }
+        node_counts
+    };
+
+    let initial_node_counts = get_node_counts(&test_loop.data);
+    assert!(!initial_node_counts.is_empty(), "some shards must be loaded in memory");
+    // Every tracked shard holds at least the genesis accounts and their keys.
+    assert!(initial_node_counts.iter().all(|&count| count > 0));
+
+    execute_money_transfers(&mut test_loop, &node_datas, &accounts).unwrap();
+
+    let node_counts = get_node_counts(&test_loop.data);
+    assert_eq!(node_counts.len(), initial_node_counts.len());
+    // Transfers between existing accounts only replace nodes on the updated
+    // paths, and roots below the final block are garbage collected, so the
+    // number of nodes must stay in the same ballpark.
+    for (count, initial_count) in node_counts.iter().zip(initial_node_counts.iter()) {
+        assert!(*count > 0);
+        assert!(*count <= 2 * initial_count, "{count} > 2 * {initial_count}");
+    }
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Checks that nodes whose memtries don't fit into the memtrie memory limit
+/// don't keep them in memory and read the state from disk instead, while the
+/// chain keeps producing blocks and the state stays correct.
+#[test]
+#[cfg(feature = "test_features")] // required for the memtrie memory limit
+fn test_memtrie_memory_limit_exceeded() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let epoch_length = 5;
+    let shard_layout = ShardLayout::simple_v1(&["account3"]);
+    // With this many accounts the state of each shard takes well over the limit.
+    let accounts = make_accounts(100);
+    let client_accounts = accounts.iter().take(2).cloned().collect_vec();
+    let validators_spec = ValidatorsSpec::desired_roles(
+        &client_accounts.iter().map(|t| t.as_str()).collect_vec(),
+        &[],
+    );
+
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(epoch_length)
+        .shard_layout(shard_layout.clone())
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(client_accounts)
+        .memtrie_memory_limit(ByteSize::kb(1))
+        .build()
+        .warmup();
+
+    let assert_no_memtries = |test_loop_data: &TestLoopData| {
+        for data in &node_datas {
+            let client = &test_loop_data.get(&data.client_sender.actor_handle()).client;
+            let tracked_shards = vec![client].tracked_shards_for_each_client().remove(0);
+            assert!(!tracked_shards.is_empty());
+            for shard_id in shard_layout.shard_ids() {
+                assert!(!is_shard_loaded_in_memory(client, shard_id));
+            }
+        }
+    };
+    assert_no_memtries(&test_loop.data);
+
+    // Balances are checked with state queries, which are served from disk.
+    execute_money_transfers(&mut test_loop, &node_datas, &accounts).unwrap();
+
+    // Run into the next epoch, checking that every block has all its chunks.
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    let start_height = test_loop.data.get(&client_handle).client.chain.head().unwrap().height;
+    let mut last_checked_height = start_height;
+    test_loop.run_until(
+        |test_loop_data| {
+            let chain = &test_loop_data.get(&client_handle).client.chain;
+            let head = chain.head().unwrap();
+            if head.height > last_checked_height {
+                let block = chain.get_block(&head.last_block_hash).unwrap();
+                for chunk in block.chunks().iter_raw() {
+                    assert!(chunk.is_new_chunk(head.height), "missing chunk at {}", head.height);
+                }
+                last_checked_height = head.height;
+            }
+            head.height > start_height + 2 * epoch_length
+        },
+        Duration::seconds(10),
+    );
+    assert_no_memtries(&test_loop.data);
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -2,25 +2,25 @@
 //! Test behaviors of the network when the chunk producer is malicious or misbehaving.
 
 use crate::setup::builder::TestLoopBuilder;
+use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
-use crate::utils::ONE_NEAR;
 use crate::utils::client_queries::ClientQueries;
-use crate::utils::transactions::get_anchor_hash;
+use crate::utils::transactions::{get_anchor_hash, make_accounts};
+use crate::utils::{ONE_NEAR, get_node_client, run_until_node_head_height};
 use near_async::messaging::CanSend as _;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
 use near_client::ProcessTxRequest;
 use near_client::client_actor::{AdvProduceChunksMode, NetworkAdversarialMessage};
 use near_primitives::shard_layout::ShardLayout;
+use near_primitives::stateless_validation::ChunkProductionKey;
 use near_primitives::test_utils::create_user_test_signer;
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::AccountId;
 use near_primitives::views::{QueryRequest, QueryResponseKind};
 
 #[test]
 fn test_producer_with_expired_transactions() {
-    let accounts =
-        (0..3).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let accounts = make_accounts(3);
     let chunk_producer = accounts[0].as_str();
     let validators: Vec<_> = accounts[1..].iter().map(|a| a.as_str()).collect();
     let validators_spec = ValidatorsSpec::desired_roles(&[chunk_producer], &validators);
@@ -146,3 +146,67 @@ fn test_producer_with_expired_transactions() {
 
     test_loop_env.shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
+
+/// The chunk producer silently withholds all of its chunks: it never
+/// distributes the chunk parts and does not respond to requests for them.
+/// The rest of the network should treat these chunks as missing and keep
+/// finalizing blocks without them.
+#[test]
+fn test_producer_withholding_chunks() {
+    let accounts = make_accounts(4);
+    let malicious_producer = accounts[0].clone();
+    let honest_node = accounts[1].clone();
+    let producers: Vec<_> = accounts.iter().map(|a| a.as_str()).collect();
+    let validators_spec = ValidatorsSpec::desired_roles(&producers, &[]);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(10)
+        .shard_layout(ShardLayout::multi_shard(2, 1))
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let genesis_height = genesis.config.genesis_height;
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(accounts.clone())
+        .build()
+        .warmup()
+        .drop(DropCondition::ChunksWithheldBy(malicious_producer.clone()));
+
+    let target_height = genesis_height + 30;
+    run_until_node_head_height(&mut env, &honest_node, target_height, Duration::seconds(60));
+
+    let client = get_node_client(&env, &honest_node);
+    let epoch_manager = client.chain.epoch_manager.clone();
+    let final_height = client.chain.final_head().unwrap().height;
+    assert!(final_height > genesis_height + 20, "chain should keep finalizing blocks");
+
+    // Chunks produced by the malicious producer can't be reconstructed by
+    // anyone else, so they must never be included in the canonical chain,
+    // and the chunk mask must show the corresponding shards as missing.
+    let mut num_withheld_chunks = 0;
+    // Skip the warmup blocks, whose chunks were distributed before the
+    // withholding kicked in.
+    for height in genesis_height + 6..=final_height {
+        let Ok(block) = client.chain.get_block_by_height(height) else {
+            continue;
+        };
+        let epoch_id = *block.header().epoch_id();
+        let shard_layout = epoch_manager.get_shard_layout(&epoch_id).unwrap();
+        let chunk_mask = block.header().chunk_mask();
+        for shard_id in shard_layout.shard_ids() {
+            let shard_index = shard_layout.get_shard_index(shard_id).unwrap();
+            let key = ChunkProductionKey { epoch_id, height_created: height, shard_id };
+            let chunk_producer = epoch_manager.get_chunk_producer_info(&key).unwrap();
+            if chunk_producer.account_id() != &malicious_producer {
+                continue;
+            }
+            assert!(!chunk_mask[shard_index], "withheld chunk included at height {height}");
+            num_withheld_chunks += 1;
+        }
+    }
+    assert!(num_withheld_chunks > 0, "malicious producer should have been assigned chunks");
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -1,5 +1,6 @@
 mod bandwidth_scheduler;
 mod bandwidth_scheduler_protocol_upgrade;
+mod checkpoint_sync;
 mod chunk_validator_kickout;
 mod congestion_control;
 mod congestion_control_genesis_bootstrap;
@@ -21,6 +22,11 @@ mod optimistic_block;
 mod protocol_upgrade;
 mod reject_outdated_blocks;
 mod resharding_v3;
+mod restart_all_nodes;
+mod restart_with_corrupted_db;
 mod state_sync;
 mod syncing;
+mod transaction_generator;
+mod tx_request_handler_flood;
 mod view_requests_to_archival_node;
+mod view_state_proof;
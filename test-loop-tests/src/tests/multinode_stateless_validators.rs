@@ -4,16 +4,16 @@ use itertools::Itertools;
 use near_async::messaging::Handler;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
-use near_client::{GetValidatorInfo, ViewClientActorInner};
+use near_client::{Client, GetValidatorInfo, ViewClientActorInner};
 use near_o11y::testonly::init_test_logger;
 use near_primitives::shard_layout::ShardLayout;
-use near_primitives::types::{AccountId, EpochId, EpochReference};
+use near_primitives::types::{AccountId, BlockHeight, EpochId, EpochReference};
 use near_primitives::views::{CurrentEpochValidatorInfo, EpochValidatorInfo};
 
 use crate::setup::builder::TestLoopBuilder;
 use crate::setup::env::TestLoopEnv;
-use crate::utils::ONE_NEAR;
 use crate::utils::transactions::execute_money_transfers;
+use crate::utils::{ONE_NEAR, get_node_client, run_until_node_head_height};
 
 const NUM_ACCOUNTS: usize = 20;
 const NUM_SHARDS: u64 = 4;
@@ -98,6 +98,165 @@ fn slow_test_stateless_validators_with_multi_test_loop() {
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
+/// Takes a subset of the chunk validators offline mid-run and checks that the
+/// chain keeps finalizing while the online validators hold enough stake to
+/// endorse every chunk. Then brings the validators back online and checks that
+/// they catch up and resume endorsing chunks.
+#[test]
+fn slow_test_stateless_validators_with_partial_offline_validator_set() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let accounts = (0..NUM_ACCOUNTS)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
+    let block_and_chunk_producers =
+        (0..NUM_BLOCK_AND_CHUNK_PRODUCERS).map(|idx| accounts[idx].as_str()).collect_vec();
+    let chunk_validators_only = (NUM_BLOCK_AND_CHUNK_PRODUCERS..NUM_VALIDATORS)
+        .map(|idx| accounts[idx].as_str())
+        .collect_vec();
+    let clients = accounts.iter().take(NUM_VALIDATORS).cloned().collect_vec();
+
+    // Take the two chunk validators with the lowest stake offline. They are
+    // chunk validators only, so block and chunk production is not affected
+    // and only the endorsement threshold is exercised.
+    let offline_validators = accounts[NUM_VALIDATORS - 2..NUM_VALIDATORS].to_vec();
+    let observer = accounts[0].clone();
+
+    let shard_layout = ShardLayout::simple_v1(&["account3", "account5", "account7"]);
+    let validators_spec =
+        ValidatorsSpec::desired_roles(&block_and_chunk_producers, &chunk_validators_only);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .shard_layout(shard_layout)
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(clients)
+        .build()
+        .warmup();
+
+    env.test_loop.run_for(Duration::seconds(EPOCH_LENGTH as i64));
+
+    // Take the validators offline.
+    let offline_height = get_node_client(&env, &observer).chain.head().unwrap().height;
+    let offline_node_states = offline_validators
+        .iter()
+        .map(|account_id| env.kill_node(account_id.as_str()))
+        .collect_vec();
+    run_until_node_head_height(
+        &mut env,
+        &observer,
+        offline_height + EPOCH_LENGTH,
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    // The chain must keep finalizing and every included chunk must be
+    // endorsed by the online validators only. Skip a couple of blocks whose
+    // endorsements may have been sent before the validators went offline.
+    let client = get_node_client(&env, &observer);
+    let online_height = client.chain.head().unwrap().height;
+    assert!(
+        client.chain.final_head().unwrap().height > offline_height + EPOCH_LENGTH / 2,
+        "chain should keep finalizing with a partially offline validator set"
+    );
+    let endorsements = assert_chunk_endorsements(client, offline_height + 2..=online_height);
+    for account_id in &offline_validators {
+        assert_eq!(endorsements.get(account_id), None, "{account_id} endorsed while offline");
+    }
+
+    // Bring the validators back online and let them catch up.
+    let restarted_identifiers =
+        offline_validators.iter().map(|account_id| format!("{account_id}-restart")).collect_vec();
+    for (identifier, node_state) in restarted_identifiers.iter().zip(offline_node_states) {
+        env.restart_node(identifier, node_state);
+    }
+    run_until_node_head_height(
+        &mut env,
+        &observer,
+        online_height + 2 * EPOCH_LENGTH,
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    let client = get_node_client(&env, &observer);
+    let head_height = client.chain.head().unwrap().height;
+    for identifier in &restarted_identifiers {
+        let node_data = env.node_datas.iter().find(|data| &data.identifier == identifier).unwrap();
+        let restarted_client =
+            &env.test_loop.data.get(&node_data.client_sender.actor_handle()).client;
+        let restarted_height = restarted_client.chain.head().unwrap().height;
+        assert!(
+            restarted_height + 1 >= head_height,
+            "{identifier} did not catch up: {restarted_height} vs {head_height}"
+        );
+    }
+
+    // Once caught up, the validators must resume endorsing chunks.
+    let endorsements = assert_chunk_endorsements(client, head_height - EPOCH_LENGTH..=head_height);
+    for account_id in &offline_validators {
+        assert!(
+            endorsements.get(account_id).copied().unwrap_or_default() > 0,
+            "{account_id} did not resume endorsing chunks"
+        );
+    }
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Checks that every new chunk included in the blocks at the given heights is
+/// endorsed by more than 2/3 of the stake of its chunk validators, as required
+/// for the chunk to be included. Returns the number of endorsements included
+/// in these blocks per validator.
+fn assert_chunk_endorsements(
+    client: &Client,
+    heights: std::ops::RangeInclusive<BlockHeight>,
+) -> HashMap<AccountId, usize> {
+    let epoch_manager = &client.epoch_manager;
+    let mut endorsements = HashMap::new();
+    for height in heights {
+        let Ok(block) = client.chain.get_block_by_height(height) else {
+            continue;
+        };
+        for (shard_index, chunk) in block.chunks().iter_raw().enumerate() {
+            if !chunk.is_new_chunk(height) {
+                continue;
+            }
+            let epoch_id =
+                epoch_manager.get_epoch_id_from_prev_block(chunk.prev_block_hash()).unwrap();
+            let assignments = epoch_manager
+                .get_chunk_validator_assignments(
+                    &epoch_id,
+                    chunk.shard_id(),
+                    chunk.height_created(),
+                )
+                .unwrap();
+            let signatures = &block.chunk_endorsements()[shard_index];
+            assert_eq!(signatures.len(), assignments.len());
+
+            let mut total_stake = 0;
+            let mut endorsed_stake = 0;
+            for ((account_id, stake), signature) in assignments.assignments().iter().zip(signatures)
+            {
+                total_stake += stake;
+                if signature.is_some() {
+                    endorsed_stake += stake;
+                    *endorsements.entry(account_id.clone()).or_default() += 1;
+                }
+            }
+            assert!(
+                endorsed_stake > total_stake * 2 / 3,
+                "chunk at height {height} for shard {} included with {endorsed_stake} out of {total_stake} stake endorsed",
+                chunk.shard_id(),
+            );
+        }
+    }
+    endorsements
+}
+
 /// Returns the CurrentEpochValidatorInfo for each validator account for the given epoch id.
 fn get_validator_info(
     view_client: &mut ViewClientActorInner,
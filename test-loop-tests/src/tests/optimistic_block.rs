@@ -1,36 +1,60 @@
+use std::collections::HashSet;
 #[cfg(feature = "test_features")]
 use std::sync::Arc;
 
+use assert_matches::assert_matches;
 use itertools::Itertools;
+use near_async::messaging::CanSend;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
-#[cfg(feature = "test_features")]
+use near_client::Client;
+use near_epoch_manager::shard_assignment::{account_id_to_shard_id, shard_id_to_uid};
+use near_network::client::OptimisticBlockMessage;
 use near_network::types::NetworkRequests;
 use near_o11y::testonly::init_test_logger;
+use near_primitives::network::PeerId;
 #[cfg(feature = "test_features")]
 use near_primitives::optimistic_block::{OptimisticBlock, OptimisticBlockAdvType};
 use near_primitives::shard_layout::ShardLayout;
+use near_primitives::sharding::MaybeNew;
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::test_utils::create_user_test_signer;
+use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{AccountId, BlockHeight};
 #[cfg(feature = "test_features")]
 use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::views::{FinalExecutionStatus, QueryRequest, QueryResponseKind};
 
 use crate::setup::builder::TestLoopBuilder;
 use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
-use crate::utils::ONE_NEAR;
+use crate::utils::client_queries::ClientQueries;
+use crate::utils::transactions::{
+    call_contract, do_deploy_contract, get_anchor_hash, get_next_nonce, submit_tx,
+};
+use crate::utils::{ONE_NEAR, TGAS, get_node_client, run_until_node_head_height};
+
+const NUM_VALIDATORS: usize = 4;
 
 fn get_builder(num_shards: usize) -> TestLoopBuilder {
+    get_builder_with_rpcs(num_shards, 0)
+}
+
+/// Like `get_builder`, with `num_rpcs` additional non-validator nodes, whose
+/// accounts come after the ones of the validators.
+fn get_builder_with_rpcs(num_shards: usize, num_rpcs: usize) -> TestLoopBuilder {
     init_test_logger();
     let builder = TestLoopBuilder::new();
 
     let epoch_length = 100;
     // Keep it above 3 to prevent missing blocks from stalling the network.
-    let accounts =
-        (0..4).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let accounts = (0..NUM_VALIDATORS + num_rpcs)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
     let clients = accounts.iter().cloned().collect_vec();
     let validators_spec = ValidatorsSpec::desired_roles(
-        &accounts.iter().map(|account_id| account_id.as_str()).collect_vec(),
+        &accounts[..NUM_VALIDATORS].iter().map(|account_id| account_id.as_str()).collect_vec(),
         &[],
     );
 
@@ -354,3 +378,335 @@ fn test_optimistic_block_with_invalidated_outcome() {
 
     env.shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
+
+#[test]
+/// Test that the speculative results of an optimistic block are discarded
+/// when the block at its height never makes it to the canonical chain.
+/// The block at the skipped height is not broadcast, so only its producer has
+/// it, while the other nodes have applied the chunks of that height on top of
+/// the optimistic block. The next producer builds on the previous block, which
+/// orphans both. A transfer is placed in one of the orphaned chunks to check
+/// that it takes effect once, through the canonical chain.
+fn test_optimistic_block_orphaned_by_fork() {
+    let num_shards = 3;
+    let mut env: TestLoopEnv = get_builder(num_shards).track_all_shards().build().warmup();
+
+    env.test_loop.run_for(Duration::seconds(10));
+
+    let (height_to_skip, producer, next_producer) = get_height_to_skip_and_producers(&env);
+    tracing::info!(target: "test", ?height_to_skip, ?producer, "Orphaning block at height");
+    env = env.drop(DropCondition::BlocksByHeight([height_to_skip].into_iter().collect()));
+
+    let sender: AccountId = "account0".parse().unwrap();
+    let receiver: AccountId = "account1".parse().unwrap();
+    let receiver_balance_before = get_clients(&env).query_balance(&receiver);
+
+    // The chunks of the skipped height are produced once the block before it is
+    // processed. Submit the transfer to the producer of the sender's chunk
+    // just before that, so the transfer lands in a chunk on the orphaned fork.
+    run_until_node_head_height(
+        &mut env,
+        producer.account_id(),
+        height_to_skip - 2,
+        Duration::seconds(10),
+    );
+    let chunk_producer = {
+        let client = get_node_client(&env, producer.account_id());
+        let epoch_manager = client.epoch_manager.as_ref();
+        let epoch_id = client.chain.head().unwrap().epoch_id;
+        let shard_id = account_id_to_shard_id(epoch_manager, &sender, &epoch_id).unwrap();
+        let key = ChunkProductionKey { epoch_id, height_created: height_to_skip, shard_id };
+        epoch_manager.get_chunk_producer_info(&key).unwrap().take_account_id()
+    };
+    let tx = {
+        let clients = get_clients(&env);
+        let signer = create_user_test_signer(&sender);
+        let response = clients.runtime_query(
+            &sender,
+            QueryRequest::ViewAccessKey {
+                account_id: sender.clone(),
+                public_key: signer.public_key(),
+            },
+        );
+        let QueryResponseKind::AccessKey(access_key) = response.kind else {
+            panic!("Expected AccessKey response");
+        };
+        SignedTransaction::send_money(
+            access_key.nonce + 1,
+            sender.clone(),
+            receiver.clone(),
+            &signer,
+            ONE_NEAR,
+            get_anchor_hash(&clients),
+        )
+    };
+    let tx_hash = tx.get_hash();
+    submit_tx(&env.node_datas, &chunk_producer, tx);
+
+    let client_handle = env
+        .get_node_data_by_account_id(next_producer.account_id())
+        .unwrap()
+        .client_sender
+        .actor_handle();
+    let wait_blocks_after_skip = 5;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height
+                > (height_to_skip + wait_blocks_after_skip)
+        },
+        Duration::seconds(20),
+    );
+
+    let clients = get_clients(&env);
+    let producer_client = get_node_client(&env, producer.account_id());
+
+    // Only the producer knows about a block at the skipped height, and the
+    // canonical chain goes straight from the previous block to the next height.
+    let orphaned_blocks = producer_client
+        .chain
+        .chain_store
+        .get_all_block_hashes_by_height(height_to_skip)
+        .unwrap()
+        .values()
+        .flatten()
+        .copied()
+        .collect::<HashSet<_>>();
+    assert!(!orphaned_blocks.is_empty(), "the producer should have the orphaned block");
+    let fork_base = producer_client.chain.get_block_hash_by_height(height_to_skip - 1).unwrap();
+    let next_block = producer_client.chain.chain_store.get_next_block_hash(&fork_base).unwrap();
+    assert!(producer_client.chain.get_block_header(&next_block).unwrap().height() > height_to_skip);
+
+    // The transfer was applied on the orphaned fork, but its outcome must
+    // come from a canonical block, and it must not be applied twice.
+    let outcome = vec![producer_client].tx_outcome(tx_hash);
+    assert_matches!(outcome.status, FinalExecutionStatus::SuccessValue(_));
+    let outcome_block = outcome.transaction_outcome.block_hash;
+    assert!(!orphaned_blocks.contains(&outcome_block));
+    let outcome_height = producer_client.chain.get_block_header(&outcome_block).unwrap().height();
+    assert_eq!(
+        producer_client.chain.get_block_hash_by_height(outcome_height).unwrap(),
+        outcome_block
+    );
+    assert_eq!(clients.query_balance(&receiver), receiver_balance_before + ONE_NEAR);
+
+    // All nodes, including the producer which rolled back its own block, must
+    // agree on the canonical chain and on the state of every shard.
+    let head_height =
+        clients.iter().map(|client| client.chain.head().unwrap().height).min().unwrap();
+    for height in height_to_skip - 1..=head_height {
+        let Ok(block) = clients[0].chain.get_block_by_height(height) else {
+            continue;
+        };
+        let block_hash = *block.hash();
+        for client in &clients[1..] {
+            assert_eq!(client.chain.get_block_hash_by_height(height).unwrap(), block_hash);
+        }
+        for chunk in block.chunks().iter() {
+            let MaybeNew::New(chunk) = chunk else {
+                continue;
+            };
+            let epoch_id = block.header().epoch_id();
+            let shard_uid =
+                shard_id_to_uid(clients[0].epoch_manager.as_ref(), chunk.shard_id(), epoch_id)
+                    .unwrap();
+            let state_roots = clients
+                .iter()
+                .map(|client| {
+                    *client.chain.get_chunk_extra(&block_hash, &shard_uid).unwrap().state_root()
+                })
+                .collect::<HashSet<_>>();
+            assert_eq!(state_roots.len(), 1, "nodes disagree on state of {shard_uid} at {height}");
+        }
+    }
+
+    // No speculative leftovers: at most one optimistic block may be waiting for
+    // its chunks, like under normal block processing.
+    for client in &clients {
+        assert!(client.chain.optimistic_block_chunks.num_blocks() <= 1);
+    }
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Makes every node except `node_id` send its optimistic blocks to the other
+/// nodes except `node_id`, so that `node_id` only applies chunks once their
+/// block arrives, without any speculative results.
+fn withhold_optimistic_blocks_from(env: &mut TestLoopEnv, node_id: &AccountId) {
+    for data in &env.node_datas {
+        if &data.account_id == node_id {
+            continue;
+        }
+        let receivers = env
+            .node_datas
+            .iter()
+            .filter(|other| other.account_id != data.account_id && &other.account_id != node_id)
+            .map(|other| other.client_sender.clone())
+            .collect_vec();
+        let peer_actor = env.test_loop.data.get_mut(&data.peer_manager_sender.actor_handle());
+        peer_actor.register_override_handler(Box::new(move |request: NetworkRequests| {
+            let NetworkRequests::OptimisticBlock { optimistic_block } = &request else {
+                return Some(request);
+            };
+            for receiver in &receivers {
+                receiver.send(OptimisticBlockMessage {
+                    optimistic_block: optimistic_block.clone(),
+                    from_peer: PeerId::random(),
+                });
+            }
+            None
+        }));
+    }
+}
+
+#[test]
+/// Test that chunks applied on top of optimistic blocks while a shard is
+/// congested respect congestion control the same way as chunks applied once
+/// their block arrives. The contract shard is flooded with calls that burn
+/// much more gas than fits in a chunk, so receipts pile up in its delayed
+/// receipts queue. The validators use their speculative results all along,
+/// while a reference node never gets the optimistic blocks. Once the blocks
+/// are confirmed, the validators must agree with the reference node on the
+/// chunk extra of every chunk, including its congestion info.
+fn test_optimistic_block_with_congested_shard() {
+    let num_shards = 3;
+    let mut env: TestLoopEnv =
+        get_builder_with_rpcs(num_shards, 1).track_all_shards().build().warmup();
+    let reference_id = env.node_datas[NUM_VALIDATORS].account_id.clone();
+    let validator_ids =
+        env.node_datas[..NUM_VALIDATORS].iter().map(|data| data.account_id.clone()).collect_vec();
+    withhold_optimistic_blocks_from(&mut env, &reference_id);
+
+    env.test_loop.run_for(Duration::seconds(10));
+
+    let contract_id = validator_ids[0].clone();
+    do_deploy_contract(
+        &mut env,
+        &reference_id,
+        &contract_id,
+        near_test_contracts::rs_contract().into(),
+    );
+    let shard_uid = {
+        let client = get_node_client(&env, &reference_id);
+        let epoch_manager = client.epoch_manager.as_ref();
+        let epoch_id = client.chain.head().unwrap().epoch_id;
+        let shard_id = account_id_to_shard_id(epoch_manager, &contract_id, &epoch_id).unwrap();
+        shard_id_to_uid(epoch_manager, shard_id, &epoch_id).unwrap()
+    };
+
+    let validator_hits_before = validator_ids
+        .iter()
+        .map(|account_id| get_node_client(&env, account_id).chain.apply_chunk_results_cache.hits())
+        .collect_vec();
+    let start_height = get_node_client(&env, &reference_id).chain.head().unwrap().height;
+
+    // Each call burns a quarter of the chunk gas limit, so the calls take
+    // many chunks to get through.
+    let num_calls_per_account = 25;
+    let args = (250 * TGAS).to_le_bytes().to_vec();
+    for sender_id in &validator_ids {
+        let nonce = get_next_nonce(&env.test_loop.data, &env.node_datas, sender_id);
+        for i in 0..num_calls_per_account {
+            call_contract(
+                &mut env.test_loop,
+                &env.node_datas,
+                &reference_id,
+                sender_id,
+                &contract_id,
+                "burn_gas_raw".to_string(),
+                args.clone(),
+                nonce + i,
+            );
+        }
+    }
+
+    // Run until the shard got congested and its delayed receipts were all
+    // processed.
+    let reference_handle =
+        env.get_node_data_by_account_id(&reference_id).unwrap().client_sender.actor_handle();
+    let mut max_delayed_receipts_gas = 0;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let chain = &test_loop_data.get(&reference_handle).client.chain;
+            let head = chain.head().unwrap();
+            let congestion_info = chain
+                .get_chunk_extra(&head.last_block_hash, &shard_uid)
+                .unwrap()
+                .congestion_info()
+                .unwrap();
+            max_delayed_receipts_gas =
+                max_delayed_receipts_gas.max(congestion_info.delayed_receipts_gas());
+            max_delayed_receipts_gas > 0 && congestion_info.delayed_receipts_gas() == 0
+        },
+        Duration::seconds(60),
+    );
+
+    let clients = get_clients(&env);
+    let reference_client = get_node_client(&env, &reference_id);
+    assert_eq!(
+        reference_client.chain.apply_chunk_results_cache.hits(),
+        0,
+        "the reference node must not use optimistic results"
+    );
+
+    // The validators kept applying chunks speculatively while the shard was
+    // congested.
+    let end_height =
+        clients.iter().map(|client| client.chain.head().unwrap().height).min().unwrap();
+    for (account_id, hits_before) in validator_ids.iter().zip(validator_hits_before) {
+        let hits = get_node_client(&env, account_id).chain.apply_chunk_results_cache.hits();
+        assert!(
+            hits - hits_before >= (end_height - start_height) as usize,
+            "{account_id} must use optimistic results at every height"
+        );
+    }
+
+    // Every chunk applied speculatively must have the same outcome as when it
+    // is applied without an optimistic block, including the congestion info
+    // the next chunk of the shard is built on.
+    let mut num_congested_chunks = 0;
+    for height in start_height..=end_height {
+        let Ok(block) = reference_client.chain.get_block_by_height(height) else {
+            continue;
+        };
+        let block_hash = *block.hash();
+        for chunk in block.chunks().iter() {
+            let MaybeNew::New(chunk) = chunk else {
+                continue;
+            };
+            let epoch_id = block.header().epoch_id();
+            let chunk_shard_uid = shard_id_to_uid(
+                reference_client.epoch_manager.as_ref(),
+                chunk.shard_id(),
+                epoch_id,
+            )
+            .unwrap();
+            let reference_extra =
+                reference_client.chain.get_chunk_extra(&block_hash, &chunk_shard_uid).unwrap();
+            for account_id in &validator_ids {
+                let extra = get_node_client(&env, account_id)
+                    .chain
+                    .get_chunk_extra(&block_hash, &chunk_shard_uid)
+                    .unwrap();
+                assert_eq!(
+                    extra, reference_extra,
+                    "{account_id} disagrees on chunk extra of {chunk_shard_uid} at {height}"
+                );
+            }
+            if chunk_shard_uid == shard_uid
+                && reference_extra.congestion_info().unwrap().delayed_receipts_gas() > 0
+            {
+                num_congested_chunks += 1;
+            }
+        }
+    }
+    assert!(num_congested_chunks > 1, "the contract shard must have been congested");
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+fn get_clients(env: &TestLoopEnv) -> Vec<&Client> {
+    env.node_datas
+        .iter()
+        .map(|data| &env.test_loop.data.get(&data.client_sender.actor_handle()).client)
+        .collect_vec()
+}
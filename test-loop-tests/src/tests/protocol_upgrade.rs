@@ -1,9 +1,12 @@
 use itertools::Itertools;
+use near_async::messaging::CanSend;
 use near_async::test_loop::data::TestLoopData;
 use near_async::time::Duration;
 use near_chain_configs::test_genesis::{
     TestEpochConfigBuilder, TestGenesisBuilder, ValidatorsSpec,
 };
+use near_client::BlockResponse;
+use near_network::types::NetworkRequests;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::epoch_manager::{EpochConfig, EpochConfigStore};
 use near_primitives::shard_layout::ShardLayout;
@@ -15,6 +18,7 @@ use near_vm_runner::logic::ProtocolVersion;
 use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::Deref;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use crate::setup::builder::TestLoopBuilder;
@@ -22,23 +26,27 @@ use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
 use crate::utils::ONE_NEAR;
 
-/// Test upgrading the blockchain to another protocol version.
-/// Optionally make some chunks around epoch boundary missing.
-/// Uses a hardcoded shard layout, it doesn't change during the test.
-pub(crate) fn test_protocol_upgrade(
+pub(crate) const EPOCH_LENGTH: u64 = 10;
+
+/// Sets up a network starting at `old_protocol` and immediately voting for `new_protocol`,
+/// with 2 producers (account0, account1), 2 chunk validators (account2, account3) and
+/// 1 rpc node (account4).
+/// Drops the chunks in `chunk_ranges_to_drop` around the first block with the new version.
+/// The nodes in `not_upgraded` keep voting for `old_protocol` until they're restarted after
+/// removing them from `SharedState::upgrade_schedule_overrides`.
+pub(crate) fn setup_env(
     old_protocol: ProtocolVersion,
     new_protocol: ProtocolVersion,
-    chunk_ranges_to_drop: HashMap<ShardIndex, std::ops::Range<i64>>,
-) {
-    init_test_logger();
-
+    chunk_ranges_to_drop: &HashMap<ShardIndex, std::ops::Range<i64>>,
+    not_upgraded: &[AccountId],
+) -> (TestLoopEnv, ShardLayout) {
     // 2 producers, 2 validators, 1 rpc node, 4 shards, 20 accounts (account{i}) with 10k NEAR each.
     // Taken from standard_setup_1()
     let num_clients = 5;
     let num_producers = 2;
     let num_validators = 2;
     let num_rpc = 1;
-    let epoch_length = 10;
+    let epoch_length = EPOCH_LENGTH;
     let accounts =
         (0..20).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
     let initial_balance = 10000 * ONE_NEAR;
@@ -108,14 +116,36 @@ pub(crate) fn test_protocol_upgrade(
     // Immediately start voting for the new protocol version
     let protocol_upgrade_schedule = ProtocolUpgradeVotingSchedule::new_immediate(new_protocol);
 
-    let TestLoopEnv { mut test_loop, node_datas, shared_state } = builder
+    let mut builder = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
-        .protocol_upgrade_schedule(protocol_upgrade_schedule)
+        .protocol_upgrade_schedule(protocol_upgrade_schedule);
+    for account_id in not_upgraded {
+        builder = builder.protocol_upgrade_schedule_for_account(
+            account_id.clone(),
+            ProtocolUpgradeVotingSchedule::new_immediate(old_protocol),
+        );
+    }
+    let env = builder
         .clients(clients)
         .build()
         .drop(DropCondition::ProtocolUpgradeChunkRange(new_protocol, chunk_ranges_to_drop.clone()))
         .warmup();
+    (env, shard_layout)
+}
+
+/// Test upgrading the blockchain to another protocol version.
+/// Optionally make some chunks around epoch boundary missing.
+/// Uses a hardcoded shard layout, it doesn't change during the test.
+pub(crate) fn test_protocol_upgrade(
+    old_protocol: ProtocolVersion,
+    new_protocol: ProtocolVersion,
+    chunk_ranges_to_drop: HashMap<ShardIndex, std::ops::Range<i64>>,
+) {
+    init_test_logger();
+
+    let (env, shard_layout) = setup_env(old_protocol, new_protocol, &chunk_ranges_to_drop, &[]);
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = env;
 
     let client_handle = node_datas[0].client_sender.actor_handle();
     let epoch_ids_with_old_protocol = RefCell::new(BTreeSet::new());
@@ -176,7 +206,7 @@ pub(crate) fn test_protocol_upgrade(
             && epoch_ids_with_new_protocol.borrow().len() >= 2
     };
 
-    test_loop.run_until(success_condition, Duration::seconds((7 * epoch_length) as i64));
+    test_loop.run_until(success_condition, Duration::seconds((7 * EPOCH_LENGTH) as i64));
 
     // Validate that the correct chunks were missing
     let upgraded_epoch_start = first_new_protocol_height.get().unwrap();
@@ -197,6 +227,152 @@ pub(crate) fn test_protocol_upgrade(
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
+/// Makes the other nodes stop sending blocks above `height` to `node_id`, and `node_id` stop
+/// requesting blocks and headers from them, for as long as `cut_off` is set.
+fn cut_off_from_blocks_above(
+    env: &mut TestLoopEnv,
+    node_id: &AccountId,
+    height: BlockHeight,
+    cut_off: Rc<Cell<bool>>,
+) {
+    for data in &env.node_datas {
+        let peer_actor = env.test_loop.data.get_mut(&data.peer_manager_sender.actor_handle());
+        if &data.account_id == node_id {
+            let cut_off = cut_off.clone();
+            peer_actor.register_override_handler(Box::new(move |request: NetworkRequests| {
+                match &request {
+                    NetworkRequests::BlockRequest { .. }
+                    | NetworkRequests::BlockHeadersRequest { .. }
+                        if cut_off.get() =>
+                    {
+                        None
+                    }
+                    _ => Some(request),
+                }
+            }));
+            continue;
+        }
+        let receivers = env
+            .node_datas
+            .iter()
+            .filter(|other| other.account_id != data.account_id && &other.account_id != node_id)
+            .map(|other| other.client_sender.clone())
+            .collect_vec();
+        let peer_id = data.peer_id.clone();
+        let cut_off = cut_off.clone();
+        peer_actor.register_override_handler(Box::new(move |request: NetworkRequests| {
+            let NetworkRequests::Block { block } = &request else {
+                return Some(request);
+            };
+            if !cut_off.get() || block.header().height() <= height {
+                return Some(request);
+            }
+            for receiver in &receivers {
+                receiver.send(BlockResponse {
+                    block: block.clone(),
+                    peer_id: peer_id.clone(),
+                    was_requested: false,
+                });
+            }
+            None
+        }));
+    }
+}
+
+/// Test a validator that doesn't upgrade in time. The lagging node keeps voting for the old
+/// protocol version, and since a binary that doesn't support the new version can't process
+/// blocks with it, it's cut off from every block after the last one with the old version. It
+/// keeps running but stops following the chain while the rest of the network moves on, and
+/// once restarted, as if with an upgraded binary, it catches up with the network via sync.
+fn test_protocol_upgrade_with_lagging_node(
+    old_protocol: ProtocolVersion,
+    new_protocol: ProtocolVersion,
+) {
+    init_test_logger();
+
+    // A chunk validator only, so that the network keeps producing blocks without it.
+    let lagging_node: AccountId = "account3".parse().unwrap();
+    let (mut env, _) =
+        setup_env(old_protocol, new_protocol, &HashMap::new(), &[lagging_node.clone()]);
+    let lagging_handle =
+        env.get_node_data_by_account_id(&lagging_node).unwrap().client_sender.actor_handle();
+    let validator_handle = env.node_datas[0].client_sender.actor_handle();
+
+    // Run until the lagging node reaches the last block before the new version takes effect.
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let client = &test_loop_data.get(&lagging_handle).client;
+            let tip = client.chain.head().unwrap();
+            client.epoch_manager.is_next_block_epoch_start(&tip.last_block_hash).unwrap()
+                && client.epoch_manager.get_epoch_protocol_version(&tip.next_epoch_id).unwrap()
+                    == new_protocol
+        },
+        Duration::seconds((5 * EPOCH_LENGTH) as i64),
+    );
+    let stalled_tip = env.test_loop.data.get(&lagging_handle).client.chain.head().unwrap();
+    let cut_off = Rc::new(Cell::new(true));
+    cut_off_from_blocks_above(&mut env, &lagging_node, stalled_tip.height, cut_off.clone());
+    tracing::info!(target: "test", ?lagging_node, height = stalled_tip.height, "Cut off lagging node");
+
+    // The rest of the network moves on to the new version, while the lagging node keeps running
+    // at the last block with the old version.
+    let lagging_stuck = Cell::new(true);
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let lagging_head = test_loop_data.get(&lagging_handle).client.chain.head().unwrap();
+            lagging_stuck.set(lagging_stuck.get() && lagging_head == stalled_tip);
+            let head = test_loop_data.get(&validator_handle).client.chain.head().unwrap();
+            head.height > stalled_tip.height + 2 * EPOCH_LENGTH
+        },
+        Duration::seconds((3 * EPOCH_LENGTH) as i64),
+    );
+    assert!(lagging_stuck.get(), "lagging node followed the chain past the upgrade");
+    let client = &env.test_loop.data.get(&validator_handle).client;
+    let network_tip = client.chain.head().unwrap();
+    assert_eq!(
+        client.epoch_manager.get_epoch_protocol_version(&network_tip.epoch_id).unwrap(),
+        new_protocol
+    );
+    let lagging_client = &env.test_loop.data.get(&lagging_handle).client;
+    assert_eq!(lagging_client.chain.head().unwrap(), stalled_tip);
+    assert_eq!(
+        lagging_client.epoch_manager.get_epoch_protocol_version(&stalled_tip.epoch_id).unwrap(),
+        old_protocol
+    );
+
+    // Restart the lagging node, as if with an upgraded binary, and wait for it to catch up.
+    let lagging_identifier =
+        env.get_node_data_by_account_id(&lagging_node).unwrap().identifier.clone();
+    let node_state = env.kill_node(&lagging_identifier);
+    cut_off.set(false);
+    env.shared_state.upgrade_schedule_overrides.remove(&lagging_node);
+    env.restart_node(&format!("{}-upgraded", lagging_node), node_state);
+    let upgraded_handle = env.node_datas.last().unwrap().client_sender.actor_handle();
+    let target_height = network_tip.height + 2;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let head = test_loop_data.get(&upgraded_handle).client.chain.head().unwrap();
+            head.height >= target_height
+        },
+        Duration::seconds((3 * EPOCH_LENGTH) as i64),
+    );
+
+    let upgraded_client = &env.test_loop.data.get(&upgraded_handle).client;
+    let upgraded_tip = upgraded_client.chain.head().unwrap();
+    assert_eq!(
+        upgraded_client.epoch_manager.get_epoch_protocol_version(&upgraded_tip.epoch_id).unwrap(),
+        new_protocol
+    );
+    let client = &env.test_loop.data.get(&validator_handle).client;
+    assert_eq!(
+        upgraded_client.chain.get_block_hash_by_height(network_tip.height).unwrap(),
+        client.chain.get_block_hash_by_height(network_tip.height).unwrap(),
+        "upgraded node is on a different chain"
+    );
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
 #[test]
 fn slow_test_protocol_upgrade_no_missing_chunks() {
     test_protocol_upgrade(PROTOCOL_VERSION - 1, PROTOCOL_VERSION, HashMap::new());
@@ -227,3 +403,8 @@ fn slow_test_protocol_upgrade_with_missing_chunks_two() {
 fn slow_test_protocol_upgrade_not_latest() {
     test_protocol_upgrade(PROTOCOL_VERSION - 2, PROTOCOL_VERSION - 1, HashMap::new());
 }
+
+#[test]
+fn slow_test_protocol_upgrade_with_lagging_node() {
+    test_protocol_upgrade_with_lagging_node(PROTOCOL_VERSION - 1, PROTOCOL_VERSION);
+}
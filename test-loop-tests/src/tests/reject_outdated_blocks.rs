@@ -5,16 +5,21 @@ use itertools::Itertools;
 use near_async::test_loop::data::TestLoopData;
 use near_async::time::Duration;
 use near_chain::{Block, Error, Provenance};
+use near_chain_configs::test_genesis::TestEpochConfigBuilder;
 use near_chain_configs::test_genesis::TestGenesisBuilder;
 use near_chain_configs::test_genesis::ValidatorsSpec;
 use near_crypto::InMemorySigner;
 use near_crypto::KeyType;
+use near_crypto::Signature;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::epoch_manager::EpochConfigStore;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::test_utils::create_test_signer;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::AccountId;
 use near_primitives::types::AccountInfo;
+use near_primitives::types::EpochId;
 use near_primitives::upgrade_schedule::ProtocolUpgradeVotingSchedule;
 use near_primitives::version::ProtocolFeature;
 use near_primitives_core::num_rational::Rational32;
@@ -170,3 +175,83 @@ fn slow_test_reject_blocks_with_outdated_protocol_version_protocol_upgrade() {
     TestLoopEnv { test_loop, node_datas, shared_state }
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
+
+/// A block from many epochs ago, below the tail of the node's chain, must be
+/// dropped by the block intake path before any validation is attempted, so that
+/// peers can't make the node do expensive work or look up long gone epoch info
+/// by sending it old blocks.
+#[test]
+fn test_reject_block_outdated_by_many_epochs() {
+    init_test_logger();
+    let epoch_length = 10;
+    let accounts =
+        (0..4).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let clients = accounts.iter().cloned().collect_vec();
+    let validators_spec =
+        ValidatorsSpec::desired_roles(&clients.iter().map(|a| a.as_str()).collect_vec(), &[]);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(epoch_length)
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store =
+        TestEpochConfigBuilder::from_genesis(&genesis).build_store_for_genesis_protocol_version();
+    let TestLoopEnv { mut test_loop, node_datas, shared_state } = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(clients)
+        .gc_num_epochs_to_keep(3)
+        .build()
+        .warmup();
+
+    let handle = node_datas[0].client_sender.actor_handle();
+    let old_block = {
+        let client = &test_loop.data.get(&handle).client;
+        let head = client.chain.head().unwrap();
+        client.chain.get_block(&head.last_block_hash).unwrap()
+    };
+    let old_height = old_block.header().height();
+
+    // Run until the old block is many epochs behind and garbage collected.
+    test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            let client = &test_loop_data.get(&handle).client;
+            client.chain.head().unwrap().height > old_height + 6 * epoch_length
+                && client.chain.tail().unwrap() > old_height
+        },
+        Duration::seconds(10 * epoch_length as i64),
+    );
+
+    // The same block with an epoch the node never heard of and a bogus signature.
+    // If the node tried to validate it, this would fail with EpochOutOfBounds or
+    // InvalidSignature instead of being dropped.
+    let mut bogus_block = old_block.clone();
+    bogus_block.mut_header().set_epoch_id(EpochId(CryptoHash::hash_bytes(b"unknown epoch")));
+    bogus_block.mut_header().set_signature(Signature::empty(KeyType::ED25519));
+
+    let client = &mut test_loop.data.get_mut(&handle).client;
+    let head_before = client.chain.head().unwrap();
+    let signer = client.validator_signer.get();
+    for (block, was_requested) in
+        [(old_block.clone(), false), (old_block.clone(), true), (bogus_block.clone(), false)]
+    {
+        let hash = *block.hash();
+        let res = client.receive_block_impl(block, PeerId::random(), was_requested, None, &signer);
+        assert!(res.is_ok(), "outdated block was not dropped: {:?}", res);
+        assert!(!client.chain.block_exists(&hash).unwrap());
+        assert!(!client.chain.is_orphan(&hash));
+        assert_eq!(client.chain.head().unwrap(), head_before);
+    }
+
+    // The node keeps going as if nothing happened.
+    test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            test_loop_data.get(&handle).client.chain.head().unwrap().height
+                > head_before.height + epoch_length
+        },
+        Duration::seconds(2 * epoch_length as i64),
+    );
+
+    TestLoopEnv { test_loop, node_datas, shared_state }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -26,8 +26,9 @@ use crate::utils::receipts::{
 use crate::utils::resharding::fork_before_resharding_block;
 use crate::utils::resharding::{
     TrackedShardSchedule, call_burn_gas_contract, call_promise_yield, check_state_cleanup,
-    delayed_receipts_repro_missing_trie_value, execute_money_transfers, execute_storage_operations,
-    promise_yield_repro_missing_trie_value, send_large_cross_shard_receipts,
+    check_synced_child_state, delayed_receipts_repro_missing_trie_value, execute_money_transfers,
+    execute_storage_operations, promise_yield_repro_missing_trie_value,
+    send_large_cross_shard_receipts, send_max_size_receipts_towards_splitted_shard,
     temporary_account_during_resharding,
 };
 use crate::utils::setups::{derive_new_epoch_config_from_boundary, two_upgrades_voting_schedule};
@@ -951,6 +952,45 @@ fn slow_test_resharding_v3_sync_child() {
     );
 }
 
+// Sets up an extra node that tracks an unrelated shard before resharding and a child right after
+// it. The node has to state sync the parent in the last epoch before resharding, and the resharding
+// boundary is crossed while it is catching up. Checks that it ends up tracking the child in the new
+// shard layout, with the same state as the other nodes.
+#[test]
+fn slow_test_resharding_v3_sync_parent_during_resharding() {
+    let account_in_stable_shard: AccountId = "account0".parse().unwrap();
+    let split_boundary_account: AccountId = NEW_BOUNDARY_ACCOUNT.parse().unwrap();
+    let base_shard_layout = get_base_shard_layout(DEFAULT_SHARD_LAYOUT_VERSION);
+    let new_shard_layout =
+        ShardLayout::derive_shard_layout(&base_shard_layout, split_boundary_account.clone());
+    let child_shard_id = new_shard_layout.account_id_to_shard_id(&split_boundary_account);
+    let unrelated_shard_id = new_shard_layout.account_id_to_shard_id(&account_in_stable_shard);
+
+    let tracked_shard_sequence = vec![unrelated_shard_id, unrelated_shard_id, child_shard_id];
+    let num_clients = 8;
+    let num_epochs_to_wait = DEFAULT_TESTLOOP_NUM_EPOCHS_TO_WAIT;
+    let client_index = (num_clients - 1) as usize;
+    let tracked_shard_schedule = TrackedShardSchedule {
+        client_index,
+        schedule: shard_sequence_to_schedule(tracked_shard_sequence, num_epochs_to_wait),
+    };
+    test_resharding_v3_base(
+        TestReshardingParametersBuilder::default()
+            .num_clients(num_clients)
+            // Give the node enough time to sync the parent and reshard it within one epoch.
+            .epoch_length(INCREASED_EPOCH_LENGTH)
+            .tracked_shard_schedule(Some(tracked_shard_schedule.clone()))
+            .add_loop_action(check_synced_child_state(
+                client_index,
+                child_shard_id,
+                new_shard_layout,
+                2,
+            ))
+            .add_loop_action(check_state_cleanup(tracked_shard_schedule, num_epochs_to_wait, true))
+            .build(),
+    );
+}
+
 #[test]
 fn slow_test_resharding_v3_track_all_shards() {
     test_resharding_v3_base(
@@ -1352,6 +1392,39 @@ fn slow_test_resharding_v3_large_receipts_towards_splitted_shard_v2() {
     test_resharding_v3_large_receipts_towards_splitted_shard_base(2);
 }
 
+/// Receipts of nearly `max_receipt_size` are sent towards the shard being split right before the
+/// resharding boundary. Each receipt must reach the child shard owning its receiver intact and
+/// without going over the size limit.
+fn test_resharding_v3_max_size_receipts_towards_splitted_shard_base(
+    base_shard_layout_version: u64,
+) {
+    let account_in_left_child: AccountId = "account4".parse().unwrap();
+    let account_in_right_child: AccountId = "account6".parse().unwrap();
+    let account_in_stable_shard: AccountId = "account1".parse().unwrap();
+
+    let params = TestReshardingParametersBuilder::default()
+        .base_shard_layout_version(base_shard_layout_version)
+        .deploy_test_contract(account_in_left_child.clone())
+        .deploy_test_contract(account_in_right_child.clone())
+        .deploy_test_contract(account_in_stable_shard.clone())
+        .add_loop_action(send_max_size_receipts_towards_splitted_shard(
+            account_in_stable_shard,
+            vec![account_in_left_child, account_in_right_child],
+        ))
+        .build();
+    test_resharding_v3_base(params);
+}
+
+#[test]
+fn slow_test_resharding_v3_max_size_receipts_towards_splitted_shard_v1() {
+    test_resharding_v3_max_size_receipts_towards_splitted_shard_base(1);
+}
+
+#[test]
+fn slow_test_resharding_v3_max_size_receipts_towards_splitted_shard_v2() {
+    test_resharding_v3_max_size_receipts_towards_splitted_shard_base(2);
+}
+
 #[test]
 #[cfg_attr(not(feature = "test_features"), ignore)]
 fn slow_test_resharding_v3_outgoing_receipts_towards_splitted_shard() {
@@ -0,0 +1,137 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_client::SyncStatus;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, BlockHeight};
+
+use crate::setup::builder::TestLoopBuilder;
+use crate::utils::ONE_NEAR;
+use crate::utils::transactions::execute_money_transfers;
+
+const NUM_ACCOUNTS: usize = 20;
+const NUM_VALIDATORS: usize = 4;
+const EPOCH_LENGTH: u64 = 10;
+const GENESIS_HEIGHT: BlockHeight = 10000;
+
+/// Shuts down every node in the middle of an epoch and restarts all of them
+/// from their own stores, as in a network-wide restart. The chain must resume
+/// on top of the last final block the nodes had, all nodes must keep agreeing
+/// on it, and none of them may need state sync to get going again.
+#[test]
+fn test_restart_all_nodes_mid_epoch() {
+    init_test_logger();
+    let accounts = (0..NUM_ACCOUNTS)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
+    let clients = accounts.iter().take(NUM_VALIDATORS).cloned().collect_vec();
+
+    let validators_spec =
+        ValidatorsSpec::desired_roles(&clients.iter().map(|a| a.as_str()).collect_vec(), &[]);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .genesis_height(GENESIS_HEIGHT)
+        .transaction_validity_period(1000)
+        .build();
+    let epoch_config_store =
+        TestEpochConfigBuilder::from_genesis(&genesis).build_store_for_genesis_protocol_version();
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(clients)
+        .build()
+        .warmup();
+
+    execute_money_transfers(&mut env.test_loop, &env.node_datas, &accounts[NUM_VALIDATORS..])
+        .unwrap();
+
+    // Stop in the middle of an epoch, so that the nodes have to pick up an
+    // epoch they already started rather than begin a new one.
+    let handle = env.node_datas[0].client_sender.actor_handle();
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let head = test_loop_data.get(&handle).client.chain.head().unwrap();
+            (head.height - GENESIS_HEIGHT) % EPOCH_LENGTH == EPOCH_LENGTH / 2
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+
+    let old_node_datas = env.node_datas.clone();
+    let final_heads = old_node_datas
+        .iter()
+        .map(|data| {
+            let client = &env.test_loop.data.get(&data.client_sender.actor_handle()).client;
+            client.chain.final_head().unwrap()
+        })
+        .collect_vec();
+    let node_states =
+        old_node_datas.iter().map(|data| env.kill_node(&data.identifier)).collect_vec();
+
+    // Keep the whole network down for a while before bringing it back.
+    env.test_loop.run_for(Duration::seconds(5));
+
+    for (data, node_state) in old_node_datas.iter().zip_eq(node_states) {
+        env.restart_node(&format!("{}-restart", data.identifier), node_state);
+    }
+    let new_handles = env.node_datas[old_node_datas.len()..]
+        .iter()
+        .map(|data| data.client_sender.actor_handle())
+        .collect_vec();
+
+    let state_synced = Rc::new(Cell::new(false));
+    {
+        let state_synced = state_synced.clone();
+        let new_handles = new_handles.clone();
+        env.test_loop.set_every_event_callback(move |test_loop_data| {
+            for handle in &new_handles {
+                let sync_status = &test_loop_data.get(handle).client.sync_handler.sync_status;
+                if matches!(sync_status, SyncStatus::StateSync(_) | SyncStatus::StateSyncDone) {
+                    state_synced.set(true);
+                }
+            }
+        });
+    }
+
+    let restart_height = final_heads.iter().map(|tip| tip.height).max().unwrap();
+    env.test_loop.run_until(
+        |test_loop_data| {
+            new_handles.iter().all(|handle| {
+                let final_head = test_loop_data.get(handle).client.chain.final_head().unwrap();
+                final_head.height > restart_height + 2 * EPOCH_LENGTH
+            })
+        },
+        Duration::seconds(4 * EPOCH_LENGTH as i64),
+    );
+    assert!(!state_synced.get(), "a node needed state sync after restarting from its own store");
+
+    // Every node must have built on the final block it had before the restart,
+    // and all nodes must be on the same chain since then.
+    let new_clients =
+        new_handles.iter().map(|handle| &env.test_loop.data.get(handle).client).collect_vec();
+    for (client, old_final_head) in new_clients.iter().zip_eq(&final_heads) {
+        assert_eq!(
+            client.chain.get_block_hash_by_height(old_final_head.height).unwrap(),
+            old_final_head.last_block_hash,
+            "node lost its final block at height {} after the restart",
+            old_final_head.height
+        );
+    }
+    let min_final_height =
+        new_clients.iter().map(|client| client.chain.final_head().unwrap().height).min().unwrap();
+    let first_final_height = final_heads.iter().map(|tip| tip.height).min().unwrap();
+    for height in first_final_height..=min_final_height {
+        let hashes = new_clients
+            .iter()
+            .map(|client| client.chain.get_block_hash_by_height(height).ok())
+            .dedup()
+            .collect_vec();
+        assert_eq!(hashes.len(), 1, "nodes diverged at height {height}: {hashes:?}");
+    }
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,222 @@
+use std::io;
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_epoch_manager::shard_assignment::shard_id_to_uid;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::sharding::MaybeNew;
+use near_primitives::types::{AccountId, BlockHeight};
+use near_store::DBCol;
+
+use crate::setup::builder::TestLoopBuilder;
+use crate::setup::env::{ColumnCorruption, TestLoopEnv};
+use crate::setup::state::NodeExecutionData;
+use crate::utils::ONE_NEAR;
+use crate::utils::transactions::execute_money_transfers;
+use crate::utils::trie_sanity::assert_trie_state_equal;
+
+const NUM_ACCOUNTS: usize = 20;
+const NUM_VALIDATORS: usize = 4;
+const EPOCH_LENGTH: u64 = 10;
+const GENESIS_HEIGHT: BlockHeight = 10000;
+
+/// Starts a network of validators and a single RPC node, all tracking all shards.
+/// The RPC node is the last client.
+fn setup_env(accounts: &[AccountId]) -> TestLoopEnv {
+    let validators = accounts.iter().take(NUM_VALIDATORS).map(|a| a.as_str()).collect_vec();
+    let clients = accounts.iter().take(NUM_VALIDATORS + 1).cloned().collect_vec();
+
+    let validators_spec = ValidatorsSpec::desired_roles(&validators, &[]);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(accounts, 1_000_000 * ONE_NEAR)
+        .genesis_height(GENESIS_HEIGHT)
+        .transaction_validity_period(1000)
+        .build();
+    let epoch_config_store =
+        TestEpochConfigBuilder::from_genesis(&genesis).build_store_for_genesis_protocol_version();
+
+    TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(clients)
+        .track_all_shards()
+        .build()
+        .warmup()
+}
+
+/// Checks that `node` agrees with all the other running nodes on the block hash
+/// and on the state root of every shard at each height it has processed since
+/// `from_height`.
+fn assert_same_chain_and_state(
+    env: &TestLoopEnv,
+    node: &NodeExecutionData,
+    from_height: BlockHeight,
+) {
+    let client = &env.test_loop.data.get(&node.client_sender.actor_handle()).client;
+    let head_height = client.chain.head().unwrap().height;
+    let others = env
+        .node_datas
+        .iter()
+        .filter(|data| data.account_id != node.account_id)
+        .map(|data| &env.test_loop.data.get(&data.client_sender.actor_handle()).client)
+        .collect_vec();
+
+    for height in from_height..=head_height {
+        let Ok(block_hash) = client.chain.get_block_hash_by_height(height) else {
+            continue;
+        };
+        let block = client.chain.get_block(&block_hash).unwrap();
+        for other in &others {
+            assert_eq!(
+                other.chain.get_block_hash_by_height(height).unwrap(),
+                block_hash,
+                "restarted node is on a different chain at height {height}"
+            );
+            for chunk in block.chunks().iter() {
+                let MaybeNew::New(chunk) = chunk else {
+                    continue;
+                };
+                let shard_uid = shard_id_to_uid(
+                    client.epoch_manager.as_ref(),
+                    chunk.shard_id(),
+                    block.header().epoch_id(),
+                )
+                .unwrap();
+                let chunk_extra = client.chain.get_chunk_extra(&block_hash, &shard_uid).unwrap();
+                let other_chunk_extra =
+                    other.chain.get_chunk_extra(&block_hash, &shard_uid).unwrap();
+                assert_eq!(
+                    chunk_extra.state_root(),
+                    other_chunk_extra.state_root(),
+                    "restarted node has a different state root for {shard_uid} at height {height}"
+                );
+            }
+        }
+    }
+}
+
+/// How a node restarted with a damaged column is expected to behave.
+enum RestartOutcome {
+    /// The node must refuse to start, failing to deserialize the damaged data.
+    Refused,
+    /// The node must start, catch up with the network, either via block sync
+    /// or state sync, and agree with the other nodes on the chain and the state.
+    Recovered,
+}
+
+/// Kills the RPC node, lets the network run for a few epochs, damages `col` in
+/// the node's store and restarts it, checking that it behaves as `expected`.
+/// A node must never silently keep going with bad state.
+fn test_restart_with_corrupted_column(
+    col: DBCol,
+    corruption: ColumnCorruption,
+    expected: RestartOutcome,
+) {
+    init_test_logger();
+    let accounts = (0..NUM_ACCOUNTS)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
+    let mut env = setup_env(&accounts);
+    let rpc_id = accounts[NUM_VALIDATORS].clone();
+
+    execute_money_transfers(&mut env.test_loop, &env.node_datas, &accounts[NUM_VALIDATORS + 1..])
+        .unwrap();
+
+    let rpc_identifier =
+        env.node_datas.iter().find(|data| data.account_id == rpc_id).unwrap().identifier.clone();
+    let node_state = env.kill_node(&rpc_identifier);
+
+    // Run the network for a few epochs, so that the node is far enough behind to
+    // need more than just a few blocks to catch up.
+    let validator_handle = env.node_datas[0].client_sender.actor_handle();
+    let kill_height = env.test_loop.data.get(&validator_handle).client.chain.head().unwrap().height;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let head = test_loop_data.get(&validator_handle).client.chain.head().unwrap();
+            head.height > kill_height + 3 * EPOCH_LENGTH
+        },
+        Duration::seconds(3 * EPOCH_LENGTH as i64),
+    );
+
+    let new_identifier = format!("{}-restart", rpc_id);
+    let result =
+        env.restart_node_with_corrupted_column(&new_identifier, node_state, col, corruption);
+    match expected {
+        RestartOutcome::Refused => {
+            let err = result.err().unwrap_or_else(|| {
+                panic!("node started with {corruption:?} {col:?} instead of refusing to")
+            });
+            tracing::info!(target: "test", ?col, ?corruption, %err, "node refused to start");
+            assert!(
+                matches!(
+                    &err,
+                    near_client::Error::Chain(near_chain::Error::IOErr(io_err))
+                        if io_err.kind() == io::ErrorKind::InvalidData
+                ),
+                "node refused to start with an unexpected error: {err:?}"
+            );
+        }
+        RestartOutcome::Recovered => {
+            if let Err(err) = result {
+                panic!("node refused to start with {corruption:?} {col:?}: {err:?}");
+            }
+            tracing::info!(target: "test", ?col, ?corruption, "node started, waiting for it to catch up");
+            let restarted_handle = env.node_datas.last().unwrap().client_sender.actor_handle();
+            let target_height =
+                env.test_loop.data.get(&validator_handle).client.chain.head().unwrap().height + 2;
+            env.test_loop.run_until(
+                |test_loop_data| {
+                    let head = test_loop_data.get(&restarted_handle).client.chain.head().unwrap();
+                    head.height >= target_height
+                },
+                Duration::seconds(4 * EPOCH_LENGTH as i64),
+            );
+
+            let restarted_node = env.node_datas.last().unwrap();
+            assert_same_chain_and_state(&env, restarted_node, target_height - 2);
+            let validator = &env.test_loop.data.get(&validator_handle).client;
+            let restarted = &env.test_loop.data.get(&restarted_handle).client;
+            let shard_layout = validator
+                .epoch_manager
+                .get_shard_layout(&validator.chain.head().unwrap().epoch_id)
+                .unwrap();
+            for shard_id in shard_layout.shard_ids() {
+                assert_trie_state_equal(validator, restarted, shard_id);
+            }
+        }
+    }
+
+    // The rest of the network must keep going regardless of the damaged node.
+    let height = env.test_loop.data.get(&validator_handle).client.chain.head().unwrap().height;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&validator_handle).client.chain.head().unwrap().height > height + 3
+        },
+        Duration::seconds(5),
+    );
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Garbage in the block headers makes the node unable to load its head.
+#[test]
+fn slow_test_restart_with_corrupted_block_headers() {
+    test_restart_with_corrupted_column(
+        DBCol::BlockHeader,
+        ColumnCorruption::Corrupt,
+        RestartOutcome::Refused,
+    );
+}
+
+/// Losing the partial chunks only affects serving them to other nodes.
+#[test]
+fn slow_test_restart_with_truncated_partial_chunks() {
+    test_restart_with_corrupted_column(
+        DBCol::PartialChunks,
+        ColumnCorruption::Truncate,
+        RestartOutcome::Recovered,
+    );
+}
@@ -9,7 +9,7 @@ use near_network::client::{ProcessTxRequest, StateRequestHeader};
 use near_o11y::testonly::init_test_logger;
 use near_primitives::epoch_manager::EpochConfigStore;
 use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::ShardLayout;
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::test_utils::create_user_test_signer;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{
@@ -345,6 +345,13 @@ fn produce_chunks(
 }
 
 fn run_test(state: TestState) {
+    let mut env = run_epochs(state);
+    env.shutdown_and_drain_remaining_events(Duration::seconds(3));
+}
+
+/// Runs the chain the same way as `run_test()`, but returns the env instead of shutting it down,
+/// so the caller can check the nodes' state afterwards.
+fn run_epochs(state: TestState) -> TestLoopEnv {
     let TestState { mut env, mut accounts, skip_block_height } = state;
     let handle = env.node_datas[0].client_sender.actor_handle();
     let client = &env.test_loop.data.get(&handle).client;
@@ -367,7 +374,7 @@ fn run_test(state: TestState) {
     );
 
     produce_chunks(&mut env, accounts, skip_block_height);
-    env.shutdown_and_drain_remaining_events(Duration::seconds(3));
+    env
 }
 
 #[derive(Debug)]
@@ -527,6 +534,60 @@ fn slow_test_state_sync_untrack_then_track() {
     run_test(state);
 }
 
+// This adds an extra node that tracks no shards for the first few epochs, and then only
+// shard 2 out of 4. It has to state sync that one shard, and we check that it ends up with the
+// same state for it as the validators, and without any state for the other shards.
+#[test]
+fn slow_test_state_sync_single_tracked_shard() {
+    init_test_logger();
+
+    let num_shards = 4;
+    let tracked_shard = ShardId::new(2);
+    // The schedule is indexed by epoch height modulo its length, and it's long enough that
+    // we never wrap around to the epochs where we don't track anything.
+    let schedule = (0..10).map(|h| if h < 3 { vec![] } else { vec![tracked_shard] }).collect();
+    let state =
+        setup_initial_blockchain(5, 4, 4, num_shards, true, HashMap::new(), None, &Some(schedule));
+    let mut env = run_epochs(state);
+
+    let extra_node_handle = env.node_datas.last().unwrap().client_sender.actor_handle();
+    let extra_client = &env.test_loop.data.get(&extra_node_handle).client;
+    let tip = extra_client.chain.head().unwrap();
+    let epoch_height =
+        extra_client.epoch_manager.get_epoch_info(&tip.epoch_id).unwrap().epoch_height();
+    assert!(epoch_height > 3, "extra node never got to the epochs where it tracks shard 2");
+    let shard_layout = extra_client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+
+    for shard_id in shard_layout.shard_ids() {
+        let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+        let chunk_extra = extra_client.chain.get_chunk_extra(&tip.last_block_hash, &shard_uid);
+        if shard_id != tracked_shard {
+            assert!(
+                chunk_extra.is_err(),
+                "extra node has state for shard {} that it never tracked",
+                shard_id
+            );
+            continue;
+        }
+        let state_root = *chunk_extra.unwrap().state_root();
+        // Compare against the validators that applied the same chunk.
+        let validator_state_roots = env.node_datas[..env.node_datas.len() - 1]
+            .iter()
+            .filter_map(|data| {
+                let client = &env.test_loop.data.get(&data.client_sender.actor_handle()).client;
+                client.chain.get_chunk_extra(&tip.last_block_hash, &shard_uid).ok()
+            })
+            .map(|chunk_extra| *chunk_extra.state_root())
+            .collect_vec();
+        assert!(!validator_state_roots.is_empty(), "no validator has state for shard {}", shard_id);
+        for validator_state_root in validator_state_roots {
+            assert_eq!(state_root, validator_state_root);
+        }
+    }
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(3));
+}
+
 // Here we drop the block that's supposed to be the sync hash after the first full epoch,
 // which causes it to be produced but then skipped on the final chain. If the state sync code
 // is unaware of the possibility of forks, this will cause the producer of that block to
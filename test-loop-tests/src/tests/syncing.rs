@@ -2,6 +2,7 @@ use crate::setup::builder::{NodeStateBuilder, TestLoopBuilder};
 use crate::setup::env::TestLoopEnv;
 use crate::utils::ONE_NEAR;
 use crate::utils::client_queries::ClientQueries;
+use crate::utils::run_until;
 use crate::utils::transactions::execute_money_transfers;
 use itertools::Itertools;
 use near_async::time::Duration;
@@ -51,10 +52,10 @@ fn slow_test_sync_from_genesis() {
 
     // Make sure the chain progresses for several epochs.
     let client_handle = node_datas[0].client_sender.actor_handle();
-    test_loop.run_until(
-        |test_loop_data| {
-            test_loop_data.get(&client_handle).client.chain.head().unwrap().height > 10050
-        },
+    run_until(
+        &mut test_loop,
+        |test_loop_data| test_loop_data.get(&client_handle).client.chain.head().unwrap().height,
+        |height| *height > 10050,
         Duration::seconds(50),
     );
 
@@ -69,8 +70,10 @@ fn slow_test_sync_from_genesis() {
 
     // Check that the new node will reach a high height as well.
     let new_node = env.node_datas.last().unwrap().client_sender.actor_handle();
-    env.test_loop.run_until(
-        |test_loop_data| test_loop_data.get(&new_node).client.chain.head().unwrap().height > 10050,
+    run_until(
+        &mut env.test_loop,
+        |test_loop_data| test_loop_data.get(&new_node).client.chain.head().unwrap().height,
+        |height| *height > 10050,
         Duration::seconds(20),
     );
     env.shutdown_and_drain_remaining_events(Duration::seconds(20));
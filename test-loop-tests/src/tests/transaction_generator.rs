@@ -0,0 +1,88 @@
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_o11y::testonly::init_test_logger;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::AccountId;
+
+use crate::setup::builder::TestLoopBuilder;
+use crate::utils::transaction_generator::{FunctionCallSpec, TransactionGenerator};
+use crate::utils::transactions::{check_txs, deploy_contract, make_accounts};
+use crate::utils::{ONE_NEAR, TGAS};
+
+const NUM_USER_ACCOUNTS: usize = 10;
+
+fn burn_gas_call(contract_id: AccountId) -> FunctionCallSpec {
+    FunctionCallSpec {
+        contract_id,
+        method_name: "burn_gas_raw".to_string(),
+        args: TGAS.to_le_bytes().to_vec(),
+        gas: 10 * TGAS,
+    }
+}
+
+/// Generators with the same accounts and seed produce the same transactions.
+#[test]
+fn test_transaction_generator_is_deterministic() {
+    let accounts = make_accounts(NUM_USER_ACCOUNTS);
+    let contract_id: AccountId = "contract".parse().unwrap();
+    let generate = |seed: u64| {
+        let mut generator = TransactionGenerator::new(accounts.clone(), seed)
+            .txs_per_block(20)
+            .function_calls(burn_gas_call(contract_id.clone()), 0.5);
+        (0..3u8)
+            .flat_map(|i| generator.next_batch(CryptoHash::hash_bytes(&[i])))
+            .map(|tx| tx.get_hash())
+            .collect_vec()
+    };
+
+    let txs = generate(42);
+    assert_eq!(txs.len(), 60);
+    assert_eq!(txs, generate(42));
+    assert_ne!(txs, generate(43));
+}
+
+/// Runs a chain with a generator sending a mix of transfers and function
+/// calls for a number of blocks, and checks that all of them succeed.
+#[test]
+fn slow_test_transaction_generator_scenario() {
+    init_test_logger();
+
+    let clients: Vec<AccountId> =
+        ["validator0", "validator1", "rpc"].iter().map(|id| id.parse().unwrap()).collect();
+    let rpc_id = clients[2].clone();
+    let contract_id: AccountId = "contract".parse().unwrap();
+    let user_accounts = make_accounts(NUM_USER_ACCOUNTS);
+    let genesis_accounts =
+        clients.iter().chain(&user_accounts).chain([&contract_id]).cloned().collect_vec();
+
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(10)
+        .validators_spec(ValidatorsSpec::desired_roles(&["validator0", "validator1"], &[]))
+        .add_user_accounts_simple(&genesis_accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(clients)
+        .build()
+        .warmup();
+
+    let code = near_test_contracts::rs_contract().to_vec();
+    let tx = deploy_contract(&mut env.test_loop, &env.node_datas, &rpc_id, &contract_id, code, 1);
+    env.test_loop.run_for(Duration::seconds(5));
+    check_txs(&env.test_loop.data, &env.node_datas, &rpc_id, &[tx]);
+
+    let mut generator = TransactionGenerator::new(user_accounts, 0)
+        .txs_per_block(5)
+        .function_calls(burn_gas_call(contract_id), 0.3);
+    let submitted = generator.run_for_blocks(&mut env, &rpc_id, 10);
+    assert_eq!(submitted.len(), 50);
+
+    env.test_loop.run_for(Duration::seconds(10));
+    let tx_hashes = submitted.into_iter().map(|(tx_hash, _)| tx_hash).collect_vec();
+    check_txs(&env.test_loop.data, &env.node_datas, &rpc_id, &tx_hashes);
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
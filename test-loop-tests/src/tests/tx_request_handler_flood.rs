@@ -0,0 +1,247 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use near_async::futures::FutureSpawnerExt;
+use near_async::messaging::SendAsync;
+use near_async::test_loop::futures::TestLoopFutureSpawner;
+use near_async::test_loop::sender::TestLoopSender;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_client::{ProcessTxResponse, TxRequestHandler};
+use near_network::client::ProcessTxRequest;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::errors::{InvalidAccessKeyError, InvalidTxError};
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
+use near_primitives::test_utils::create_user_test_signer;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::AccountId;
+
+use crate::setup::builder::TestLoopBuilder;
+use crate::utils::ONE_NEAR;
+use crate::utils::transactions::make_accounts;
+
+const NUM_ACCOUNTS: usize = 20;
+const TXS_PER_ACCOUNT: u64 = 20;
+const EPOCH_LENGTH: u64 = 10;
+/// Room for a few dozen money transfers, far less than the flood.
+const POOL_SIZE_LIMIT: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxKind {
+    Valid,
+    Duplicate,
+    WrongKey,
+    Expired,
+}
+
+type Responses = Arc<Mutex<Vec<(TxKind, ProcessTxResponse)>>>;
+
+fn send_tx(
+    tx_processor_sender: &TestLoopSender<TxRequestHandler>,
+    future_spawner: &TestLoopFutureSpawner,
+    responses: &Responses,
+    kind: TxKind,
+    tx: SignedTransaction,
+) {
+    let future = tx_processor_sender.send_async(ProcessTxRequest {
+        transaction: tx,
+        is_forwarded: false,
+        check_only: false,
+    });
+    let responses = responses.clone();
+    future_spawner.spawn("send_tx", async move {
+        let response = future.await.unwrap();
+        responses.lock().unwrap().push((kind, response));
+    });
+}
+
+fn count(
+    responses: &Responses,
+    kind: TxKind,
+    is_expected: fn(&ProcessTxResponse) -> bool,
+) -> usize {
+    responses
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(tx_kind, response)| *tx_kind == kind && is_expected(response))
+        .count()
+}
+
+/// Floods the transaction request handler of the only validator with many more
+/// transactions than its pool can hold, mixed with duplicates and invalid
+/// transactions. The pool must stay within its size limit, only keep what it
+/// accepted until it is included, and the handler must keep accepting valid
+/// transactions once the flood is over.
+#[test]
+fn test_tx_request_handler_flood() {
+    init_test_logger();
+    let accounts = make_accounts(NUM_ACCOUNTS);
+    let validator: AccountId = accounts[0].clone();
+
+    let validators_spec = ValidatorsSpec::desired_roles(&[validator.as_str()], &[]);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .shard_layout(ShardLayout::single_shard())
+        .validators_spec(validators_spec)
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .transaction_validity_period(1000)
+        .build();
+    let epoch_config_store =
+        TestEpochConfigBuilder::from_genesis(&genesis).build_store_for_genesis_protocol_version();
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(vec![validator])
+        .config_modifier(|config, _| {
+            config.transaction_pool_size_limit = Some(POOL_SIZE_LIMIT);
+        })
+        .build()
+        .warmup();
+
+    let client_handle = env.node_datas[0].client_sender.actor_handle();
+    let tx_processor_sender = env.node_datas[0].tx_processor_sender.clone();
+    let future_spawner = env.test_loop.future_spawner("TxRequestHandlerFlood");
+    let shard_uid = ShardUId::single_shard();
+
+    let max_pool_size = Rc::new(Cell::new(0));
+    {
+        let max_pool_size = max_pool_size.clone();
+        let client_handle = client_handle.clone();
+        env.test_loop.set_every_event_callback(move |test_loop_data| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let (_, size) =
+                client.chunk_producer.sharded_tx_pool.lock().unwrap().pool_len_and_size(shard_uid);
+            assert!(size <= POOL_SIZE_LIMIT, "pool grew to {size} bytes");
+            max_pool_size.set(max_pool_size.get().max(size));
+        });
+    }
+
+    // Send the whole flood at once, so that it reaches the handler before any
+    // chunk can take transactions out of the pool.
+    let anchor_hash =
+        env.test_loop.data.get(&client_handle).client.chain.head().unwrap().last_block_hash;
+    let responses = Responses::default();
+    let mut valid_txs = vec![];
+    for (i, sender) in accounts.iter().enumerate() {
+        let receiver = &accounts[(i + 1) % NUM_ACCOUNTS];
+        let signer = create_user_test_signer(sender);
+        for nonce in 1..=TXS_PER_ACCOUNT {
+            let tx = SignedTransaction::send_money(
+                nonce,
+                sender.clone(),
+                receiver.clone(),
+                &signer,
+                1,
+                anchor_hash,
+            );
+            valid_txs.push(tx.get_hash());
+            send_tx(&tx_processor_sender, &future_spawner, &responses, TxKind::Valid, tx.clone());
+            send_tx(&tx_processor_sender, &future_spawner, &responses, TxKind::Duplicate, tx);
+        }
+        // Signed with the key of another account.
+        let tx = SignedTransaction::send_money(
+            TXS_PER_ACCOUNT + 1,
+            sender.clone(),
+            receiver.clone(),
+            &create_user_test_signer(receiver),
+            1,
+            anchor_hash,
+        );
+        send_tx(&tx_processor_sender, &future_spawner, &responses, TxKind::WrongKey, tx);
+        // Built on top of a block that doesn't exist.
+        let tx = SignedTransaction::send_money(
+            TXS_PER_ACCOUNT + 1,
+            sender.clone(),
+            receiver.clone(),
+            &signer,
+            1,
+            CryptoHash::default(),
+        );
+        send_tx(&tx_processor_sender, &future_spawner, &responses, TxKind::Expired, tx);
+    }
+    let num_sent = valid_txs.len() * 2 + NUM_ACCOUNTS * 2;
+
+    // Wait for all the responses and for the pool to be drained by the chunks.
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let (len, size) =
+                client.chunk_producer.sharded_tx_pool.lock().unwrap().pool_len_and_size(shard_uid);
+            responses.lock().unwrap().len() == num_sent && len == 0 && size == 0
+        },
+        Duration::seconds(4 * EPOCH_LENGTH as i64),
+    );
+
+    // Transactions that pass validation are reported as valid even when the
+    // pool has no space left for them, and duplicates are reported as valid too.
+    let num_valid = valid_txs.len();
+    assert_eq!(
+        count(&responses, TxKind::Valid, |r| matches!(r, ProcessTxResponse::ValidTx)),
+        num_valid
+    );
+    assert_eq!(
+        count(&responses, TxKind::Duplicate, |r| matches!(r, ProcessTxResponse::ValidTx)),
+        num_valid
+    );
+    assert_eq!(
+        count(&responses, TxKind::WrongKey, |r| matches!(
+            r,
+            ProcessTxResponse::InvalidTx(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::AccessKeyNotFound { .. }
+            ))
+        )),
+        NUM_ACCOUNTS
+    );
+    assert_eq!(
+        count(&responses, TxKind::Expired, |r| matches!(
+            r,
+            ProcessTxResponse::InvalidTx(InvalidTxError::Expired)
+        )),
+        NUM_ACCOUNTS
+    );
+
+    // The pool filled up and then rejected the rest of the flood, so only part
+    // of the valid transactions made it on chain.
+    let client = &env.test_loop.data.get(&client_handle).client;
+    let num_executed = valid_txs
+        .iter()
+        .filter(|tx_hash| client.chain.get_final_transaction_result(tx_hash).is_ok())
+        .count();
+    tracing::info!(target: "test", num_executed, num_valid, max_pool_size = max_pool_size.get(), "flood done");
+    assert!(max_pool_size.get() > POOL_SIZE_LIMIT / 2);
+    assert!(num_executed > 0);
+    assert!(num_executed < num_valid, "the pool accepted the whole flood");
+
+    // Once the flood is over, new transactions are accepted and executed.
+    let anchor_hash = client.chain.head().unwrap().last_block_hash;
+    let responses = Responses::default();
+    let mut new_txs = vec![];
+    for (i, sender) in accounts.iter().enumerate() {
+        let tx = SignedTransaction::send_money(
+            TXS_PER_ACCOUNT + 2,
+            sender.clone(),
+            accounts[(i + 1) % NUM_ACCOUNTS].clone(),
+            &create_user_test_signer(sender),
+            1,
+            anchor_hash,
+        );
+        new_txs.push(tx.get_hash());
+        send_tx(&tx_processor_sender, &future_spawner, &responses, TxKind::Valid, tx);
+    }
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let client = &test_loop_data.get(&client_handle).client;
+            new_txs.iter().all(|tx_hash| client.chain.get_final_transaction_result(tx_hash).is_ok())
+        },
+        Duration::seconds(2 * EPOCH_LENGTH as i64),
+    );
+    assert_eq!(
+        count(&responses, TxKind::Valid, |r| matches!(r, ProcessTxResponse::ValidTx)),
+        NUM_ACCOUNTS
+    );
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
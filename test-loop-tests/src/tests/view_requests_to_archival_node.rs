@@ -13,6 +13,7 @@ use near_client::{
 };
 use near_network::client::BlockHeadersRequest;
 use near_o11y::testonly::init_test_logger;
+use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardLayout;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
@@ -26,6 +27,7 @@ use near_primitives::views::{
 };
 
 use crate::setup::builder::TestLoopBuilder;
+use crate::setup::drop_condition::DropCondition;
 use crate::setup::env::TestLoopEnv;
 use crate::setup::state::NodeExecutionData;
 use crate::utils::ONE_NEAR;
@@ -38,6 +40,9 @@ const GENESIS_HEIGHT: u64 = 0;
 const GC_NUM_EPOCHS_TO_KEEP: u64 = 3;
 const ARCHIVAL_CLIENT: usize = 2;
 const NUM_SHARDS: usize = 4;
+/// Number of validators in the reorg test. Keep it above 3 so that the missing block
+/// does not stall the network.
+const NUM_REORG_VALIDATORS: usize = 4;
 
 /// Tests view client functionality of an archival node.
 /// For this, it runs a network with 2 validators and a non-validator archival node.
@@ -102,6 +107,106 @@ fn slow_test_view_requests_to_archival_node() {
         .shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
+/// Tests view client functionality of an archival node around a height whose block was reorged away.
+/// The block producer at that height produces its block, but the block is never delivered to
+/// the other nodes, so the next block producer builds on top of its parent and the block ends up
+/// orphaned on the producer. The archival node is then expected to answer requests for that height
+/// with an error rather than with stale data, and requests for the neighbouring heights with blocks
+/// from the canonical chain.
+#[test]
+fn slow_test_view_requests_to_archival_node_with_reorg() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let accounts = (0..NUM_ACCOUNTS)
+        .map(|i| format!("account{}", i).parse().unwrap())
+        .collect::<Vec<AccountId>>();
+    let validators =
+        accounts.iter().take(NUM_REORG_VALIDATORS).map(|account| account.as_str()).collect_vec();
+
+    // Contains the accounts of the validators and the non-validator archival node.
+    let all_clients: Vec<AccountId> =
+        accounts.iter().take(NUM_REORG_VALIDATORS + 1).cloned().collect_vec();
+    let archival_client = NUM_REORG_VALIDATORS;
+    let archival_clients: HashSet<AccountId> =
+        vec![all_clients[archival_client].clone()].into_iter().collect();
+    let shard_layout = ShardLayout::simple_v1(&["account3", "account5", "account7"]);
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .shard_layout(shard_layout.clone())
+        .validators_spec(ValidatorsSpec::desired_roles(&validators, &[]))
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .genesis_height(GENESIS_HEIGHT)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(all_clients)
+        .archival_clients(archival_clients)
+        .build()
+        .warmup();
+
+    let (orphaned_height, producer) = get_height_to_orphan(&env);
+    tracing::info!(target: "test", ?orphaned_height, ?producer, "Dropping block at height");
+    env = env.drop(DropCondition::BlocksByHeight([orphaned_height].into_iter().collect()));
+
+    // Run until the chain is final past the orphaned height, so the fork is resolved.
+    let client_handle = env.node_datas[archival_client].client_sender.actor_handle();
+    env.test_loop.run_until(
+        |test_loop_data| {
+            let chain = &test_loop_data.get(&client_handle).client.chain;
+            chain.final_head().unwrap().height > orphaned_height + 1
+        },
+        Duration::seconds(20),
+    );
+
+    // Sanity check: the producer did produce a block at the orphaned height, but it is not
+    // on the canonical chain.
+    let producer_handle =
+        env.get_node_data_by_account_id(&producer).unwrap().client_sender.actor_handle();
+    let producer_chain = &env.test_loop.data.get(&producer_handle).client.chain;
+    let orphaned_hashes =
+        producer_chain.chain_store().get_all_block_hashes_by_height(orphaned_height).unwrap();
+    let orphaned_hashes = orphaned_hashes.values().flatten().copied().collect_vec();
+    assert_eq!(orphaned_hashes.len(), 1);
+    let orphaned_hash = orphaned_hashes[0];
+    assert!(producer_chain.get_block_by_height(orphaned_height).is_err());
+
+    let mut view_client_tester = ViewClientTester::new(&mut env.test_loop, &env.node_datas);
+    view_client_tester.check_reorged_height(
+        archival_client,
+        orphaned_height,
+        orphaned_hash,
+        &shard_layout,
+    );
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Returns a height in the current epoch whose block producer is different from the block
+/// producer at the next height, along with the block producer at that height. Dropping the
+/// block at that height makes the next block producer build on top of its parent instead.
+fn get_height_to_orphan(env: &TestLoopEnv) -> (BlockHeight, AccountId) {
+    let client = &env.test_loop.data.get(&env.node_datas[0].client_sender.actor_handle()).client;
+    let head = client.chain.head().unwrap();
+    let epoch_manager = &client.epoch_manager;
+    let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+    let epoch_end_height = epoch_manager
+        .get_estimated_next_epoch_start(
+            &epoch_manager.get_block_info(&head.last_block_hash).unwrap(),
+        )
+        .unwrap();
+    for height in (head.height + 3)..(epoch_end_height - 1) {
+        let producer = epoch_manager.get_block_producer(&epoch_id, height).unwrap();
+        let next_producer = epoch_manager.get_block_producer(&epoch_id, height + 1).unwrap();
+        if producer != next_producer {
+            return (height, producer);
+        }
+    }
+    panic!("No block producer change found in the current epoch.");
+}
+
 struct ViewClientTester<'a> {
     test_loop: &'a mut TestLoopV2,
     /// List of data handles to the view client senders for sending the requests.
@@ -202,6 +307,52 @@ impl<'a> ViewClientTester<'a> {
         get_and_check_block(block_by_sync_earliest);
     }
 
+    /// Issues requests around `orphaned_height`, whose block `orphaned_hash` was reorged away,
+    /// to the view client at position `idx`, and checks they are answered from the canonical chain.
+    fn check_reorged_height(
+        &mut self,
+        idx: usize,
+        orphaned_height: BlockHeight,
+        orphaned_hash: CryptoHash,
+        shard_layout: &ShardLayout,
+    ) {
+        let shard_id = shard_layout.shard_ids().next().unwrap();
+
+        // There is no canonical block at the orphaned height, so the requests must fail.
+        let block_by_height = GetBlock(BlockReference::BlockId(BlockId::Height(orphaned_height)));
+        let err = self.send(block_by_height, idx).unwrap_err();
+        assert!(err.to_string().contains("never been observed"), "unexpected error: {err}");
+
+        let chunk_by_height = GetChunk::Height(orphaned_height, shard_id);
+        let err = self.send(chunk_by_height, idx).unwrap_err();
+        assert!(err.to_string().contains("never been observed"), "unexpected error: {err}");
+
+        // The orphaned block was never delivered to this node, so it is unknown by hash as well.
+        let block_by_hash = GetBlock(BlockReference::BlockId(BlockId::Hash(orphaned_hash)));
+        let err = self.send(block_by_hash, idx).unwrap_err();
+        assert!(err.to_string().contains("never been observed"), "unexpected error: {err}");
+
+        // The canonical chain skips the orphaned height.
+        let prev_block = self
+            .send(GetBlock(BlockReference::BlockId(BlockId::Height(orphaned_height - 1))), idx)
+            .unwrap();
+        let next_block = ((orphaned_height + 1)..)
+            .find_map(|height| {
+                self.send(GetBlock(BlockReference::BlockId(BlockId::Height(height))), idx).ok()
+            })
+            .unwrap();
+        assert_eq!(next_block.header.prev_hash, prev_block.header.hash);
+
+        let next_block_by_hash = self
+            .send(GetBlock(BlockReference::BlockId(BlockId::Hash(next_block.header.hash))), idx)
+            .unwrap();
+        assert_eq!(next_block_by_hash.header.height, next_block.header.height);
+
+        let chunk_by_block_hash = GetChunk::BlockHash(next_block.header.hash, shard_id);
+        let chunk = self.send(chunk_by_block_hash, idx).unwrap();
+        assert_ne!(chunk.header.prev_block_hash, orphaned_hash);
+    }
+
     /// Generates variations of the [`BlockHeadersRequest`] request and issues them to the view client of the archival node.
     fn check_get_block_headers(&mut self) {
         let block = self.get_block_at_height(6);
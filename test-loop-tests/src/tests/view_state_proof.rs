@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use itertools::Itertools;
+use near_async::messaging::Handler;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::{TestEpochConfigBuilder, ValidatorsSpec};
+use near_client::Query;
+use near_epoch_manager::shard_assignment::{account_id_to_shard_id, shard_id_to_uid};
+use near_o11y::testonly::init_test_logger;
+use near_primitives::hash::hash;
+use near_primitives::state::PartialState;
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{AccountId, BlockId, BlockReference, StateRoot};
+use near_primitives::views::{QueryRequest, QueryResponseKind};
+use near_store::{KeyLookupMode, PartialStorage, Trie};
+
+use crate::setup::builder::TestLoopBuilder;
+use crate::setup::env::TestLoopEnv;
+use crate::utils::ONE_NEAR;
+use crate::utils::transactions::{do_call_contract, do_deploy_contract};
+
+const NUM_VALIDATORS: usize = 2;
+const EPOCH_LENGTH: u64 = 10;
+
+/// Checks that `value` is stored under the contract data `key` of `account_id`
+/// in the state with root `state_root` the way a light client would, using
+/// nothing but the proof nodes.
+fn verify_proof(
+    proof: &[Arc<[u8]>],
+    state_root: StateRoot,
+    account_id: &AccountId,
+    key: &[u8],
+    value: &[u8],
+) -> bool {
+    let trie = Trie::from_recorded_storage(
+        PartialStorage { nodes: PartialState::TrieValues(proof.to_vec()) },
+        state_root,
+        false,
+    );
+    let trie_key = TrieKey::ContractData { account_id: account_id.clone(), key: key.to_vec() };
+    match trie.get_optimized_ref(&trie_key.to_vec(), KeyLookupMode::MemOrTrie) {
+        Ok(Some(value_ref)) => value_ref.value_hash() == hash(value),
+        Ok(None) | Err(_) => false,
+    }
+}
+
+/// Writes a value to contract storage, requests it through the view client of
+/// an RPC node along with its inclusion proof, and verifies the proof against
+/// the state root of the block the query was answered at. A proof with a
+/// tampered byte must not verify.
+#[test]
+fn test_view_state_proof() {
+    init_test_logger();
+
+    let accounts =
+        (0..NUM_VALIDATORS + 1).map(|i| format!("account{}", i).parse().unwrap()).collect_vec();
+    let validators = accounts.iter().take(NUM_VALIDATORS).map(|a| a.as_str()).collect_vec();
+    let rpc_id: AccountId = accounts[NUM_VALIDATORS].clone();
+    let contract_id: AccountId = accounts[0].clone();
+
+    let genesis = TestLoopBuilder::new_genesis_builder()
+        .epoch_length(EPOCH_LENGTH)
+        .validators_spec(ValidatorsSpec::desired_roles(&validators, &[]))
+        .add_user_accounts_simple(&accounts, 1_000_000 * ONE_NEAR)
+        .build();
+    let epoch_config_store = TestEpochConfigBuilder::build_store_from_genesis(&genesis);
+    let mut env = TestLoopBuilder::new()
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .clients(accounts.clone())
+        .track_all_shards()
+        .build()
+        .warmup();
+
+    do_deploy_contract(&mut env, &rpc_id, &contract_id, near_test_contracts::rs_contract().into());
+    let key = b"light_client_key".to_vec();
+    let value = 42u64.to_le_bytes();
+    let args = [key.as_slice(), &value].concat();
+    do_call_contract(
+        &mut env,
+        &rpc_id,
+        &contract_id,
+        &contract_id,
+        "write_key_value".to_string(),
+        args,
+    );
+
+    let rpc_data = env.node_datas.iter().find(|data| data.account_id == rpc_id).unwrap();
+    let head_height = {
+        let client = &env.test_loop.data.get(&rpc_data.client_sender.actor_handle()).client;
+        client.chain.head().unwrap().height
+    };
+    let query = Query::new(
+        BlockReference::BlockId(BlockId::Height(head_height)),
+        QueryRequest::ViewState {
+            account_id: contract_id.clone(),
+            prefix: key.clone().into(),
+            include_proof: true,
+        },
+    );
+    let view_client = env.test_loop.data.get_mut(&rpc_data.view_client_sender.actor_handle());
+    let response = view_client.handle(query).unwrap();
+    let QueryResponseKind::ViewState(result) = response.kind else {
+        panic!("Expected ViewState response, got {:?}", response.kind);
+    };
+    assert_eq!(result.values.len(), 1);
+    assert_eq!(result.values[0].key.as_slice(), key.as_slice());
+    assert_eq!(result.values[0].value.as_slice(), value.as_slice());
+    assert!(!result.proof.is_empty());
+
+    // Get the state root independently of the view client, from the chunk
+    // extra of the block the query was answered at.
+    let client = &env.test_loop.data.get(&rpc_data.client_sender.actor_handle()).client;
+    let block = client.chain.get_block(&response.block_hash).unwrap();
+    let epoch_id = block.header().epoch_id();
+    let shard_id =
+        account_id_to_shard_id(client.epoch_manager.as_ref(), &contract_id, epoch_id).unwrap();
+    let shard_uid = shard_id_to_uid(client.epoch_manager.as_ref(), shard_id, epoch_id).unwrap();
+    let state_root =
+        *client.chain.get_chunk_extra(&response.block_hash, &shard_uid).unwrap().state_root();
+
+    assert!(verify_proof(&result.proof, state_root, &contract_id, &key, &value));
+    assert!(!verify_proof(&result.proof, state_root, &contract_id, &key, &43u64.to_le_bytes()));
+    assert!(!verify_proof(&result.proof, state_root, &rpc_id, &key, &value));
+
+    // The first node of the proof is the root, so changing any of its bytes
+    // changes its hash and the proof can't be linked to the state root anymore.
+    let mut tampered_proof = result.proof.clone();
+    let mut root_node = tampered_proof[0].to_vec();
+    *root_node.last_mut().unwrap() ^= 1;
+    tampered_proof[0] = root_node.into();
+    assert!(!verify_proof(&tampered_proof, state_root, &contract_id, &key, &value));
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use near_async::test_loop::TestLoopV2;
+use near_o11y::metrics::IntCounterVec;
+use near_o11y::metrics::prometheus::core::Collector;
+
+/// Counters that can be captured per node with `NodeMetrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum NodeCounter {
+    /// `near_transaction_processed_total`
+    TransactionsProcessed,
+    /// `near_delayed_receipt_processed_total`, summed over all shards.
+    DelayedReceiptsProcessed,
+}
+
+impl NodeCounter {
+    const ALL: [NodeCounter; 2] =
+        [NodeCounter::TransactionsProcessed, NodeCounter::DelayedReceiptsProcessed];
+
+    fn read(self) -> u64 {
+        match self {
+            NodeCounter::TransactionsProcessed => {
+                node_runtime::metrics::TRANSACTION_PROCESSED_TOTAL.get()
+            }
+            NodeCounter::DelayedReceiptsProcessed => {
+                sum_over_labels(&node_runtime::metrics::DELAYED_RECEIPT_PROCESSED_TOTAL)
+            }
+        }
+    }
+}
+
+fn sum_over_labels(counter: &IntCounterVec) -> u64 {
+    counter
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_counter().get_value() as u64)
+        .sum()
+}
+
+/// Increases of the `NodeCounter`s, split by the node that caused them.
+///
+/// Metrics are process-wide statics shared by all the nodes of a test loop.
+/// To tell the nodes apart, the counters are read after every test loop event
+/// and the increase is attributed to the identifier of the event, which is the
+/// identifier of the node that handled it. Only increases made after
+/// `NodeMetrics::capture` are counted.
+///
+/// Tests running in parallel in the same process may still add to the
+/// counters, so scenarios should only assert on lower bounds.
+#[derive(Clone)]
+pub(crate) struct NodeMetrics {
+    increases: Rc<RefCell<HashMap<String, HashMap<NodeCounter, u64>>>>,
+}
+
+impl NodeMetrics {
+    /// Starts capturing the counters of all the nodes of the test loop.
+    /// Replaces any previously set after event callback.
+    pub fn capture(test_loop: &mut TestLoopV2) -> Self {
+        let increases = Rc::new(RefCell::new(HashMap::new()));
+        let mut last_values = NodeCounter::ALL.map(NodeCounter::read);
+        let captured_increases = increases.clone();
+        test_loop.set_after_event_callback(move |identifier, _| {
+            let values = NodeCounter::ALL.map(NodeCounter::read);
+            let mut increases = captured_increases.borrow_mut();
+            for ((counter, value), last_value) in
+                NodeCounter::ALL.iter().zip(values).zip(last_values)
+            {
+                if value > last_value {
+                    *increases
+                        .entry(identifier.to_string())
+                        .or_insert_with(HashMap::new)
+                        .entry(*counter)
+                        .or_default() += value - last_value;
+                }
+            }
+            last_values = values;
+        });
+        Self { increases }
+    }
+
+    /// Returns how much `counter` increased in the events handled by the node
+    /// with the given identifier.
+    pub fn get(&self, identifier: &str, counter: NodeCounter) -> u64 {
+        self.increases
+            .borrow()
+            .get(identifier)
+            .and_then(|counters| counters.get(&counter))
+            .copied()
+            .unwrap_or_default()
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt::Debug;
+
+use near_async::test_loop::TestLoopV2;
 use near_async::test_loop::data::TestLoopData;
 use near_async::time::Duration;
 use near_client::Client;
@@ -10,12 +13,14 @@ use crate::setup::state::NodeExecutionData;
 pub(crate) mod client_queries;
 pub(crate) mod contract_distribution;
 pub(crate) mod loop_action;
+pub(crate) mod metrics;
 pub(crate) mod network;
 pub(crate) mod peer_manager_actor;
 pub(crate) mod receipts;
 pub(crate) mod resharding;
 pub(crate) mod setups;
 pub(crate) mod sharding;
+pub(crate) mod transaction_generator;
 pub(crate) mod transactions;
 pub(crate) mod trie_sanity;
 pub(crate) mod validators;
@@ -39,6 +44,36 @@ pub(crate) fn get_node_head_height(
     get_node_client(env, client_account_id).chain.head().unwrap().height
 }
 
+/// Runs the test loop until `predicate` holds for the state returned by
+/// `observe`, and returns that state. The state is observed every time before
+/// the clock would advance.
+///
+/// Panics with the last observed state if the predicate doesn't hold within
+/// `maximum_duration`.
+pub(crate) fn run_until<S: Debug>(
+    test_loop: &mut TestLoopV2,
+    mut observe: impl FnMut(&mut TestLoopData) -> S,
+    predicate: impl Fn(&S) -> bool,
+    maximum_duration: Duration,
+) -> S {
+    let mut last_state = None;
+    let fulfilled = test_loop.try_run_until(
+        |test_loop_data| {
+            let state = observe(test_loop_data);
+            let fulfilled = predicate(&state);
+            last_state = Some(state);
+            fulfilled
+        },
+        maximum_duration,
+    );
+    let last_state = last_state.unwrap();
+    assert!(
+        fulfilled,
+        "condition not fulfilled within {maximum_duration}, last observed state: {last_state:#?}"
+    );
+    last_state
+}
+
 #[allow(dead_code)]
 pub(crate) fn run_until_node_head_height(
     env: &mut TestLoopEnv,
@@ -46,12 +81,14 @@ pub(crate) fn run_until_node_head_height(
     height: BlockHeight,
     maximum_duration: Duration,
 ) {
-    env.test_loop.run_until(
+    run_until(
+        &mut env.test_loop,
         |test_loop_data| {
             let client_actor =
                 retrieve_client_actor(&env.node_datas, test_loop_data, client_account_id);
-            client_actor.client.chain.head().unwrap().height >= height
+            client_actor.client.chain.head().unwrap().height
         },
+        |head_height| *head_height >= height,
         maximum_duration,
     );
 }
@@ -1,11 +1,17 @@
+use near_async::messaging::CanSend;
+use near_async::test_loop::sender::TestLoopSender;
+use near_async::time::Duration;
 use near_epoch_manager::EpochManagerAdapter;
-use near_network::types::NetworkRequests;
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
+use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::ShardChunkHeader;
-use near_primitives::types::{AccountId, BlockHeight};
+use near_primitives::stateless_validation::ChunkProductionKey;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
 use crate::setup::drop_condition::TestLoopChunksStorage;
+use crate::utils::peer_manager_actor::TestLoopPeerManagerActor;
 
 type DropChunkCondition = Box<dyn Fn(ShardChunkHeader) -> bool>;
 
@@ -71,6 +77,192 @@ pub fn chunk_endorsement_dropper(
     })
 }
 
+/// Handler to delay network messages containing chunk endorsements sent from
+/// a given chunk-validator account for chunks at every `every_nth_height`-th
+/// height. Must be registered on the validator's own peer manager: a delayed
+/// endorsement is sent back to it after `delay` and then passes through to the
+/// remaining handlers.
+pub fn chunk_endorsement_delayer(
+    validator: AccountId,
+    delay: Duration,
+    every_nth_height: BlockHeight,
+    peer_manager_sender: TestLoopSender<TestLoopPeerManagerActor>,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    let peer_manager_sender = peer_manager_sender.with_delay(delay);
+    // Endorsements which were already delayed once, by target and chunk hash.
+    let delayed = Mutex::new(HashSet::new());
+    Box::new(move |request| {
+        let NetworkRequests::ChunkEndorsement(target, endorsement) = &request else {
+            return Some(request);
+        };
+        if endorsement.validator_account() != &validator
+            || endorsement.chunk_production_key().height_created % every_nth_height != 0
+        {
+            return Some(request);
+        }
+        let key = (target.clone(), endorsement.chunk_hash());
+        if delayed.lock().unwrap().remove(&key) {
+            return Some(request);
+        }
+        delayed.lock().unwrap().insert(key);
+        peer_manager_sender.send(PeerManagerMessageRequest::NetworkRequests(request));
+        None
+    })
+}
+
+/// Handler to delay the contract distribution messages sent to or from
+/// `validator` by `delay`, modeling a validator that is slow to process them.
+/// Must be registered on the peer manager of every node, with `sender` being
+/// that node's account: a delayed message is sent back to the same peer
+/// manager after `delay` and then passes through to the remaining handlers.
+/// Messages sent to several validators at once are split, so that only the
+/// copy for `validator` is delayed.
+pub fn contract_distribution_delayer(
+    sender: AccountId,
+    validator: AccountId,
+    delay: Duration,
+    peer_manager_sender: TestLoopSender<TestLoopPeerManagerActor>,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    let peer_manager_sender = peer_manager_sender.with_delay(delay);
+    let is_delayed = move |target: &AccountId| sender == validator || target == &validator;
+    // Messages which were already delayed once.
+    let delayed = Mutex::new(HashSet::new());
+    Box::new(move |request| {
+        let Some(key) = contract_distribution_message_key(&request) else {
+            return Some(request);
+        };
+        if delayed.lock().unwrap().remove(&key) {
+            return Some(request);
+        }
+
+        let (to_delay, to_send) = match request {
+            NetworkRequests::ChunkContractAccesses(targets, accesses) => {
+                let (delayed_targets, targets) =
+                    targets.into_iter().partition(|target| is_delayed(target));
+                (
+                    Some(NetworkRequests::ChunkContractAccesses(delayed_targets, accesses.clone())),
+                    Some(NetworkRequests::ChunkContractAccesses(targets, accesses)),
+                )
+            }
+            NetworkRequests::PartialEncodedContractDeploys(targets, deploys) => {
+                let (delayed_targets, targets) =
+                    targets.into_iter().partition(|target| is_delayed(target));
+                (
+                    Some(NetworkRequests::PartialEncodedContractDeploys(
+                        delayed_targets,
+                        deploys.clone(),
+                    )),
+                    Some(NetworkRequests::PartialEncodedContractDeploys(targets, deploys)),
+                )
+            }
+            NetworkRequests::ContractCodeRequest(target, request) if is_delayed(&target) => {
+                (Some(NetworkRequests::ContractCodeRequest(target, request)), None)
+            }
+            NetworkRequests::ContractCodeResponse(target, response) if is_delayed(&target) => {
+                (Some(NetworkRequests::ContractCodeResponse(target, response)), None)
+            }
+            request => (None, Some(request)),
+        };
+        if let Some(to_delay) = to_delay.filter(has_targets) {
+            delayed.lock().unwrap().insert(contract_distribution_message_key(&to_delay).unwrap());
+            peer_manager_sender.send(PeerManagerMessageRequest::NetworkRequests(to_delay));
+        }
+        to_send.filter(has_targets)
+    })
+}
+
+/// Identifies a contract distribution message along with its targets, so that
+/// `contract_distribution_delayer` can recognize the messages it delayed.
+fn contract_distribution_message_key(request: &NetworkRequests) -> Option<CryptoHash> {
+    match request {
+        NetworkRequests::ChunkContractAccesses(targets, accesses) => {
+            Some(CryptoHash::hash_borsh((targets, accesses)))
+        }
+        NetworkRequests::ContractCodeRequest(target, request) => {
+            Some(CryptoHash::hash_borsh((target, request)))
+        }
+        NetworkRequests::ContractCodeResponse(target, response) => {
+            Some(CryptoHash::hash_borsh((target, response)))
+        }
+        NetworkRequests::PartialEncodedContractDeploys(targets, deploys) => {
+            Some(CryptoHash::hash_borsh((targets, deploys)))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a message split by `contract_distribution_delayer` is still sent anywhere.
+fn has_targets(request: &NetworkRequests) -> bool {
+    match request {
+        NetworkRequests::ChunkContractAccesses(targets, _)
+        | NetworkRequests::PartialEncodedContractDeploys(targets, _) => !targets.is_empty(),
+        _ => true,
+    }
+}
+
+/// Handler to withhold all chunks produced by `producer`. Must be registered
+/// on the producer's own peer manager. Drops the initial distribution of the
+/// partial encoded chunks, forwards of their parts and responses to requests
+/// for them, so that no other node is able to reconstruct the chunk.
+pub fn chunk_withholding_dropper(
+    chunks_storage: Arc<Mutex<TestLoopChunksStorage>>,
+    epoch_manager_adapter: Arc<dyn EpochManagerAdapter>,
+    producer: AccountId,
+) -> Box<dyn Fn(NetworkRequests) -> Option<NetworkRequests>> {
+    Box::new(move |request| {
+        let chunk_key = match &request {
+            NetworkRequests::PartialEncodedChunkMessage { partial_encoded_chunk, .. } => {
+                let header = &partial_encoded_chunk.header;
+                Some((*header.prev_block_hash(), header.shard_id(), header.height_created()))
+            }
+            NetworkRequests::PartialEncodedChunkForward { forward, .. } => {
+                Some((forward.prev_block_hash, forward.shard_id, forward.height_created))
+            }
+            NetworkRequests::PartialEncodedChunkResponse { response, .. } => {
+                let chunks_storage = chunks_storage.lock().unwrap();
+                chunks_storage.get(&response.chunk_hash).map(|header| {
+                    (*header.prev_block_hash(), header.shard_id(), header.height_created())
+                })
+            }
+            _ => None,
+        };
+
+        let Some((prev_block_hash, shard_id, height_created)) = chunk_key else {
+            return Some(request);
+        };
+
+        if is_chunk_produced_by(
+            epoch_manager_adapter.as_ref(),
+            &prev_block_hash,
+            shard_id,
+            height_created,
+            &producer,
+        ) {
+            return None;
+        }
+
+        Some(request)
+    })
+}
+
+/// Checks whether the chunk identified by the given parameters is produced by
+/// `producer`. Returns false if the epoch of the chunk cannot be determined.
+fn is_chunk_produced_by(
+    epoch_manager_adapter: &dyn EpochManagerAdapter,
+    prev_block_hash: &CryptoHash,
+    shard_id: ShardId,
+    height_created: BlockHeight,
+    producer: &AccountId,
+) -> bool {
+    let Ok(epoch_id) = epoch_manager_adapter.get_epoch_id_from_prev_block(prev_block_hash) else {
+        return false;
+    };
+    let key = ChunkProductionKey { epoch_id, height_created, shard_id };
+    epoch_manager_adapter
+        .get_chunk_producer_info(&key)
+        .is_ok_and(|chunk_producer| chunk_producer.account_id() == producer)
+}
+
 /// Handler to drop all block broadcasts at certain heights.
 /// A few things to note:
 /// - This will not fully prevent the blocks from being distributed if they are explicitly requested with
@@ -15,11 +15,11 @@ use near_epoch_manager::shard_assignment::shard_id_to_uid;
 use near_primitives::action::{Action, FunctionCallAction};
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{
-    DelayedReceiptIndices, PromiseYieldIndices, ReceiptOrStateStoredReceipt,
+    DelayedReceiptIndices, PromiseYieldIndices, ReceiptEnum, ReceiptOrStateStoredReceipt,
 };
 use near_primitives::test_utils::create_user_test_signer;
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::{AccountId, BlockId, BlockReference, Gas, ShardId};
+use near_primitives::types::{AccountId, BlockId, BlockReference, EpochId, Gas, ShardId};
 use near_primitives::views::{
     FinalExecutionStatus, QueryRequest, QueryResponse, QueryResponseKind,
 };
@@ -35,7 +35,9 @@ use rand_chacha::ChaCha20Rng;
 use super::sharding::{next_epoch_has_new_shard_layout, this_block_has_new_shard_layout};
 use crate::setup::state::NodeExecutionData;
 use crate::utils::loop_action::LoopAction;
-use crate::utils::sharding::{get_memtrie_for_shard, next_block_has_new_shard_layout};
+use crate::utils::sharding::{
+    get_memtrie_for_shard, get_tracked_shards, next_block_has_new_shard_layout,
+};
 use crate::utils::transactions::{
     check_txs, check_txs_remove_successful, delete_account, get_anchor_hash, get_next_nonce,
     store_and_submit_tx, submit_tx,
@@ -483,6 +485,227 @@ pub(crate) fn send_large_cross_shard_receipts(
     LoopAction::new(action_fn, succeeded)
 }
 
+/// Send receipts of nearly `max_receipt_size` from `signer_id` to each of `receiver_ids`, just
+/// before the resharding boundary. The receivers are expected to live in the shard that is being
+/// split.
+///
+/// Once a transaction finishes, the receipt delivered to its receiver is checked to be intact and
+/// within the size limit, and to have been executed on the shard that owns the receiver at that
+/// time. The action succeeds only if at least one receipt crossed the resharding boundary and was
+/// executed on one of the child shards.
+pub(crate) fn send_max_size_receipts_towards_splitted_shard(
+    signer_id: AccountId,
+    receiver_ids: Vec<AccountId>,
+) -> LoopAction {
+    // Height of the last block with the old shard layout
+    let resharding_height = Cell::new(None);
+    let nonce = Cell::new(102);
+    let txs = Cell::new(vec![]);
+    let latest_height = Cell::new(0);
+    let receipts_delivered_to_child = Cell::new(0);
+    let (action_success_setter, succeeded) = LoopAction::shared_success_flag();
+
+    let action_fn = Box::new(
+        move |node_datas: &[NodeExecutionData],
+              test_loop_data: &mut TestLoopData,
+              client_account_id: AccountId| {
+            let client_actor =
+                retrieve_client_actor(node_datas, test_loop_data, &client_account_id);
+            let client = &client_actor.client;
+            let tip = client.chain.head().unwrap();
+            let epoch_manager = &client.epoch_manager;
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            // Set resharding height once known
+            if resharding_height.get().is_none()
+                && next_block_has_new_shard_layout(epoch_manager.as_ref(), &tip)
+            {
+                tracing::debug!(target: "test", height=tip.height, "resharding height set");
+                resharding_height.set(Some(tip.height));
+            }
+
+            let is_epoch_before_resharding =
+                next_epoch_has_new_shard_layout(epoch_manager.as_ref(), &tip);
+
+            // Estimate the resharding boundary to know when to start sending transactions.
+            let estimated_resharding_height = match resharding_height.get() {
+                Some(h) => h, // Resharding boundary known, use it.
+                None if is_epoch_before_resharding => {
+                    // Resharding boundary unknown, estimate it.
+                    let cur_epoch_start =
+                        epoch_manager.get_epoch_start_height(&tip.last_block_hash).unwrap();
+                    let cur_epoch_length =
+                        epoch_manager.get_epoch_config(&tip.epoch_id).unwrap().epoch_length;
+                    cur_epoch_start + cur_epoch_length - 1
+                }
+                _ => tip.height + 99999999999999, // Not in the next epoch, set to infinity into the future
+            };
+
+            // Send the receipts a moment before the resharding happens. The ones sent last are
+            // created with the old shard layout and delivered with the new one.
+            if tip.height + 4 >= estimated_resharding_height
+                && tip.height <= estimated_resharding_height - 2
+            {
+                let args_size = max_size_receipt_args_size(client, &tip.epoch_id);
+                for receiver_id in &receiver_ids {
+                    let signer: Signer = create_user_test_signer(&signer_id).into();
+                    nonce.set(nonce.get() + 1);
+                    let tx = SignedTransaction::call(
+                        nonce.get(),
+                        signer_id.clone(),
+                        signer_id.clone(),
+                        &signer,
+                        1,
+                        "generate_large_receipt".into(),
+                        format!(
+                            "{{\"account_id\": \"{}\", \"method_name\": \"noop\", \"total_args_size\": {}}}",
+                            receiver_id, args_size
+                        )
+                        .into(),
+                        300 * TGAS,
+                        tip.last_block_hash,
+                    );
+                    tracing::info!(
+                        target: "test",
+                        "Sending max size receipt from {} to {}. tx_hash: {:?}",
+                        signer_id,
+                        receiver_id,
+                        tx.get_hash()
+                    );
+                    store_and_submit_tx(
+                        &node_datas,
+                        &client_account_id,
+                        &txs,
+                        &signer_id,
+                        receiver_id,
+                        tip.height,
+                        tx,
+                    );
+                }
+            }
+
+            // Check status of transactions and verify the delivery of the finished ones.
+            let pending_txs = txs.take();
+            txs.set(pending_txs.clone());
+            check_txs_remove_successful(&txs, client);
+            let unfinished_txs = txs.take();
+            for (tx_hash, _) in &pending_txs {
+                if unfinished_txs.iter().any(|(hash, _)| hash == tx_hash) {
+                    continue;
+                }
+                if check_max_size_receipt_delivery(client, tx_hash) {
+                    receipts_delivered_to_child.set(receipts_delivered_to_child.get() + 1);
+                }
+            }
+            let all_finished = unfinished_txs.is_empty();
+            txs.set(unfinished_txs);
+
+            // If the chain is past the resharding boundary, all transactions finished
+            // successfully and at least one receipt was delivered to a child shard, declare the
+            // action as successful.
+            if let Some(height) = resharding_height.get() {
+                if tip.height > height + 2 && all_finished {
+                    assert!(
+                        receipts_delivered_to_child.get() > 0,
+                        "no max size receipt was delivered to a child shard"
+                    );
+                    action_success_setter.set(true);
+                }
+            }
+        },
+    );
+    LoopAction::new(action_fn, succeeded)
+}
+
+/// Size of the arguments passed to `generate_large_receipt` so that the resulting receipt is as
+/// close to `max_receipt_size` as possible. The margin covers the receipt and actions overhead.
+fn max_size_receipt_args_size(client: &Client, epoch_id: &EpochId) -> u64 {
+    let protocol_version = client.epoch_manager.get_epoch_protocol_version(epoch_id).unwrap();
+    let runtime_config = client.runtime_adapter.get_runtime_config(protocol_version);
+    runtime_config.wasm_config.limit_config.max_receipt_size - 4096
+}
+
+/// Checks the receipt generated by a finished `generate_large_receipt` transaction. The receipt
+/// must be within the size limit, carry all of the arguments and be executed on the shard owning
+/// the receiver in the epoch it was executed in.
+///
+/// Returns true if the receipt was executed on a child of the shard the receiver belonged to when
+/// the transaction was included.
+fn check_max_size_receipt_delivery(client: &Client, tx_hash: &CryptoHash) -> bool {
+    let outcome = client.chain.get_final_transaction_result(tx_hash).unwrap();
+    let signer_id = &outcome.transaction.signer_id;
+    // The large receipt is the only one executed by an account other than the signer.
+    let receipt_outcome = outcome
+        .receipts_outcome
+        .iter()
+        .find(|o| &o.outcome.executor_id != signer_id)
+        .expect("large receipt outcome not found");
+    let receiver_id = &receipt_outcome.outcome.executor_id;
+
+    let receipt = client
+        .chain
+        .chain_store()
+        .get_receipt(&receipt_outcome.id)
+        .unwrap()
+        .expect("large receipt not found");
+    assert_eq!(receipt.receiver_id(), receiver_id);
+
+    let epoch_manager = client.epoch_manager.as_ref();
+    let tx_epoch_id = epoch_manager.get_epoch_id(&outcome.transaction_outcome.block_hash).unwrap();
+    let receipt_epoch_id = epoch_manager.get_epoch_id(&receipt_outcome.block_hash).unwrap();
+
+    let max_receipt_size = client
+        .runtime_adapter
+        .get_runtime_config(epoch_manager.get_epoch_protocol_version(&receipt_epoch_id).unwrap())
+        .wasm_config
+        .limit_config
+        .max_receipt_size;
+    let receipt_size = borsh::object_length(&receipt).unwrap() as u64;
+    assert!(
+        receipt_size <= max_receipt_size,
+        "receipt size {receipt_size} is above the limit {max_receipt_size}"
+    );
+
+    let ReceiptEnum::Action(action_receipt) = receipt.receipt() else {
+        panic!("expected an action receipt, got {:?}", receipt.receipt());
+    };
+    let args_size: usize = action_receipt
+        .actions
+        .iter()
+        .map(|action| match action {
+            Action::FunctionCall(function_call) => function_call.args.len(),
+            _ => panic!("unexpected action {:?}", action),
+        })
+        .sum();
+    assert_eq!(args_size as u64, max_size_receipt_args_size(client, &tx_epoch_id));
+
+    let tx_shard_layout = epoch_manager.get_shard_layout(&tx_epoch_id).unwrap();
+    let receipt_shard_layout = epoch_manager.get_shard_layout(&receipt_epoch_id).unwrap();
+    let tx_shard_id = tx_shard_layout.account_id_to_shard_id(receiver_id);
+    let receipt_shard_id = receipt_shard_layout.account_id_to_shard_id(receiver_id);
+    tracing::info!(
+        target: "test",
+        ?tx_hash,
+        ?receiver_id,
+        receipt_size,
+        ?tx_shard_id,
+        ?receipt_shard_id,
+        "max size receipt delivered"
+    );
+    if tx_shard_layout == receipt_shard_layout {
+        return false;
+    }
+    let parent_shard_id = receipt_shard_layout.get_parent_shard_id(receipt_shard_id).unwrap();
+    assert_eq!(parent_shard_id, tx_shard_id);
+    assert_ne!(receipt_shard_id, tx_shard_id, "receiver's shard was not split");
+    true
+}
+
 /// Sends a promise-yield transaction before resharding. Then, if `call_resume` is `true` also sends
 /// a yield-resume transaction after resharding, otherwise it lets the promise-yield go into timeout.
 ///
@@ -860,6 +1083,81 @@ pub(crate) fn check_state_cleanup(
     LoopAction::new(action_fn, succeeded)
 }
 
+/// Loop action checking the state of a child shard which the client at `client_index`
+/// obtained by state syncing its parent right before resharding.
+/// Once the client is in an epoch with `shard_layout_after_resharding`, at every height it
+/// checks that the client tracks `child_shard_id` in that layout, and that its state root for
+/// the child matches the one of every other client which has the same chunk applied.
+/// It succeeds after `num_epochs_to_check` post-resharding epochs have been checked.
+pub(crate) fn check_synced_child_state(
+    client_index: usize,
+    child_shard_id: ShardId,
+    shard_layout_after_resharding: ShardLayout,
+    num_epochs_to_check: u64,
+) -> LoopAction {
+    let child_shard_uid =
+        ShardUId::from_shard_id_and_layout(child_shard_id, &shard_layout_after_resharding);
+    let latest_height = Cell::new(0);
+    let first_epoch_height = Cell::new(None);
+
+    let (done, succeeded) = LoopAction::shared_success_flag();
+    let action_fn = Box::new(
+        move |node_datas: &[NodeExecutionData], test_loop_data: &mut TestLoopData, _: AccountId| {
+            if done.get() {
+                return;
+            }
+
+            let client_handle = node_datas[client_index].client_sender.actor_handle();
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            if shard_layout != shard_layout_after_resharding {
+                return;
+            }
+            let tracked_shards = get_tracked_shards(client, &tip.last_block_hash);
+            assert_eq!(tracked_shards, [child_shard_uid], "the synced node tracks wrong shards");
+
+            let chunk_extra = client
+                .chain
+                .get_chunk_extra(&tip.last_block_hash, &child_shard_uid)
+                .unwrap_or_else(|_| {
+                    panic!("no state for child {child_shard_uid} at #{}", tip.height)
+                });
+            let other_state_roots = node_datas
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != client_index)
+                .filter_map(|(_, data)| {
+                    let client = &test_loop_data.get(&data.client_sender.actor_handle()).client;
+                    client.chain.get_chunk_extra(&tip.last_block_hash, &child_shard_uid).ok()
+                })
+                .map(|chunk_extra| *chunk_extra.state_root())
+                .collect_vec();
+            for other_state_root in &other_state_roots {
+                assert_eq!(chunk_extra.state_root(), other_state_root);
+            }
+
+            let epoch_height = client
+                .epoch_manager
+                .get_epoch_height_from_prev_block(&tip.prev_block_hash)
+                .unwrap();
+            let first = first_epoch_height.get().unwrap_or(epoch_height);
+            first_epoch_height.set(Some(first));
+            if !other_state_roots.is_empty() && epoch_height >= first + num_epochs_to_check {
+                done.set(true);
+            }
+        },
+    );
+    LoopAction::new(action_fn, succeeded)
+}
+
 /// Repro case for the issue of 'Missing TrieValue' after GC period for refcounted trie nodes
 /// that are duplicated to both children during resharding. This particular scenario tests
 /// promise yield indices.
@@ -1,11 +1,13 @@
 use near_chain::types::Tip;
 use near_client::Client;
 use near_epoch_manager::EpochManagerAdapter;
+use near_epoch_manager::shard_assignment::shard_id_to_uid;
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardLayout;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::ShardId;
 use near_store::{ShardUId, Trie};
+use std::panic::AssertUnwindSafe;
 
 /// Returns `true` if `client` is tracking the shard having the given `shard_id`.
 pub fn client_tracking_shard(client: &Client, shard_id: ShardId, parent_hash: &CryptoHash) -> bool {
@@ -14,6 +16,34 @@ pub fn client_tracking_shard(client: &Client, shard_id: ShardId, parent_hash: &C
     client.shard_tracker.cares_about_shard(account_id, parent_hash, shard_id, true)
 }
 
+/// Returns the `ShardUId` of the shard having the given `shard_id` in the
+/// epoch of the client's current head.
+fn shard_uid_at_head(client: &Client, shard_id: ShardId) -> ShardUId {
+    let tip = client.chain.head().unwrap();
+    shard_id_to_uid(client.epoch_manager.as_ref(), shard_id, &tip.epoch_id).unwrap()
+}
+
+/// Returns `true` if `client` has the shard having the given `shard_id` loaded
+/// in memory, i.e. the shard is backed by a memtrie.
+pub fn is_shard_loaded_in_memory(client: &Client, shard_id: ShardId) -> bool {
+    let shard_uid = shard_uid_at_head(client, shard_id);
+    client.runtime_adapter.get_tries().get_memtries(shard_uid).is_some()
+}
+
+/// Returns the number of in-memory trie nodes `client` holds for the shard
+/// having the given `shard_id`, across all of its roots. Panics if the shard
+/// is not loaded in memory.
+pub fn in_memory_trie_node_count(client: &Client, shard_id: ShardId) -> usize {
+    let shard_uid = shard_uid_at_head(client, shard_id);
+    let memtries = client
+        .runtime_adapter
+        .get_tries()
+        .get_memtries(shard_uid)
+        .unwrap_or_else(|| panic!("memtrie for shard {shard_uid} is not loaded"));
+    let memtries = memtries.read().unwrap();
+    memtries.num_nodes()
+}
+
 // Finds the client who tracks the shard with `shard_id` among the list of `clients`.
 pub fn get_client_tracking_shard<'a>(
     clients: &'a [&Client],
@@ -165,3 +195,26 @@ pub fn get_shards_will_care_about(client: &Client, block_hash: &CryptoHash) -> V
     }
     shards_needs_for_next_epoch
 }
+
+/// Runs `scenario` once for each of the given shard layouts, so that a single
+/// test body covers several shard counts. All layouts are run even if some of
+/// them fail, and the test then panics listing the layouts that failed.
+pub fn run_with_shard_layouts(shard_layouts: Vec<ShardLayout>, scenario: impl Fn(ShardLayout)) {
+    let mut failures = Vec::new();
+    for shard_layout in shard_layouts {
+        let num_shards = shard_layout.num_shards();
+        let boundary_accounts = shard_layout.boundary_accounts().clone();
+        tracing::info!(target: "test", num_shards, ?boundary_accounts, "running scenario");
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| scenario(shard_layout)));
+        if let Err(payload) = result {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            tracing::error!(target: "test", num_shards, ?boundary_accounts, %message, "scenario failed");
+            failures.push(format!("{} shards {:?}: {}", num_shards, boundary_accounts, message));
+        }
+    }
+    assert!(failures.is_empty(), "scenario failed for shard layouts:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use near_async::time::Duration;
+use near_primitives::hash::CryptoHash;
+use near_primitives::test_utils::create_user_test_signer;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Balance, BlockHeight, Gas, Nonce};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::setup::env::TestLoopEnv;
+
+use super::transactions::{get_shared_block_hash, submit_tx};
+use super::{ONE_NEAR, get_node_client, get_node_data};
+
+/// A function call that a `TransactionGenerator` sends instead of a transfer
+/// for some of the transactions it produces.
+#[derive(Clone, Debug)]
+pub(crate) struct FunctionCallSpec {
+    pub contract_id: AccountId,
+    pub method_name: String,
+    pub args: Vec<u8>,
+    pub gas: Gas,
+}
+
+/// Produces a stream of transfers and function calls between a fixed set of
+/// accounts, which only depends on the accounts and the seed. Two generators
+/// created with the same parameters produce the same transactions given the
+/// same block hashes, so that a failure found with some seed reproduces exactly.
+///
+/// The accounts must be created with a user test signer key, e.g. with
+/// `add_user_accounts_simple`, and must not have sent any transactions before,
+/// since the nonces start at 1.
+pub(crate) struct TransactionGenerator {
+    rng: ChaCha20Rng,
+    accounts: Vec<AccountId>,
+    nonces: HashMap<AccountId, Nonce>,
+    txs_per_block: usize,
+    function_call: Option<(FunctionCallSpec, f64)>,
+    max_transfer_amount: Balance,
+}
+
+impl TransactionGenerator {
+    /// By default the generator produces 10 transfers per block.
+    pub fn new(accounts: Vec<AccountId>, seed: u64) -> Self {
+        assert!(accounts.len() >= 2, "need at least two accounts to send transactions between");
+        Self {
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            accounts,
+            nonces: HashMap::new(),
+            txs_per_block: 10,
+            function_call: None,
+            max_transfer_amount: ONE_NEAR,
+        }
+    }
+
+    /// Sets how many transactions `next_batch()` returns.
+    pub fn txs_per_block(mut self, txs_per_block: usize) -> Self {
+        self.txs_per_block = txs_per_block;
+        self
+    }
+
+    /// Sends `function_call` instead of a transfer with the given probability.
+    pub fn function_calls(mut self, function_call: FunctionCallSpec, probability: f64) -> Self {
+        assert!((0.0..=1.0).contains(&probability));
+        self.function_call = Some((function_call, probability));
+        self
+    }
+
+    /// Transfers send a random amount between 1 yoctoNEAR and this, 1 NEAR by default.
+    pub fn max_transfer_amount(mut self, max_transfer_amount: Balance) -> Self {
+        self.max_transfer_amount = max_transfer_amount;
+        self
+    }
+
+    /// Returns the next `txs_per_block` transactions, all using `block_hash`
+    /// as their reference block.
+    pub fn next_batch(&mut self, block_hash: CryptoHash) -> Vec<SignedTransaction> {
+        (0..self.txs_per_block).map(|_| self.next_tx(block_hash)).collect()
+    }
+
+    fn next_tx(&mut self, block_hash: CryptoHash) -> SignedTransaction {
+        let sender_id = self.accounts[self.rng.gen_range(0..self.accounts.len())].clone();
+        let nonce = self.nonces.entry(sender_id.clone()).or_insert(0);
+        *nonce += 1;
+        let nonce = *nonce;
+        let signer = create_user_test_signer(&sender_id);
+
+        if let Some((function_call, probability)) = &self.function_call {
+            if self.rng.gen_bool(*probability) {
+                return SignedTransaction::call(
+                    nonce,
+                    sender_id,
+                    function_call.contract_id.clone(),
+                    &signer,
+                    0,
+                    function_call.method_name.clone(),
+                    function_call.args.clone(),
+                    function_call.gas,
+                    block_hash,
+                );
+            }
+        }
+
+        let receiver_id = loop {
+            let receiver_id = &self.accounts[self.rng.gen_range(0..self.accounts.len())];
+            if receiver_id != &sender_id {
+                break receiver_id.clone();
+            }
+        };
+        let amount = self.rng.gen_range(1..=self.max_transfer_amount);
+        SignedTransaction::send_money(nonce, sender_id, receiver_id, &signer, amount, block_hash)
+    }
+
+    /// Submits a batch of transactions to `rpc_id` every time its head moves,
+    /// until it has done so for `num_blocks` blocks. Returns the hashes of all
+    /// the submitted transactions, along with the height they were submitted at.
+    pub fn run_for_blocks(
+        &mut self,
+        env: &mut TestLoopEnv,
+        rpc_id: &AccountId,
+        num_blocks: u64,
+    ) -> Vec<(CryptoHash, BlockHeight)> {
+        let mut submitted = Vec::new();
+        for _ in 0..num_blocks {
+            let height = get_node_client(env, rpc_id).chain.head().unwrap().height;
+            let block_hash = get_shared_block_hash(&env.node_datas, &env.test_loop.data);
+            for tx in self.next_batch(block_hash) {
+                submitted.push((tx.get_hash(), height));
+                submit_tx(&env.node_datas, rpc_id, tx);
+            }
+
+            let rpc_handle = get_node_data(&env.node_datas, rpc_id).client_sender.actor_handle();
+            env.test_loop.run_until(
+                |test_loop_data| {
+                    test_loop_data.get(&rpc_handle).client.chain.head().unwrap().height > height
+                },
+                Duration::seconds(5),
+            );
+        }
+        submitted
+    }
+}
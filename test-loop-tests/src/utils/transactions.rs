@@ -561,7 +561,22 @@ pub fn make_accounts(num_accounts: usize) -> Vec<AccountId> {
 
 /// Creates an account id to be contained at the given index.
 pub fn make_account(index: usize) -> AccountId {
-    format!("account{}", index).parse().unwrap()
+    deterministic_account(index as u64)
+}
+
+/// Returns the account id with the given index. The same index always gives
+/// the same account and different indices never give the same one, so account
+/// sets built from ranges of indices are reproducible across runs and don't
+/// collide within a scenario.
+pub fn deterministic_account(i: u64) -> AccountId {
+    format!("account{}", i).parse().unwrap()
+}
+
+/// Returns the signer of `deterministic_account(i)`. Its key is derived from
+/// the account id, so it's the same key `add_user_accounts_simple` puts into
+/// genesis for that account.
+pub fn deterministic_signer(i: u64) -> Signer {
+    create_user_test_signer(&deterministic_account(i))
 }
 
 /// Runs a transaction until completion.
@@ -4,14 +4,15 @@ use crate::utils::sharding::{
     get_tracked_shards_from_prev_block,
 };
 use borsh::BorshDeserialize;
-use itertools::Itertools;
+use itertools::{EitherOrBoth, Itertools};
 use near_chain::ChainStoreAccess;
 use near_chain::types::Tip;
 use near_client::Client;
+use near_epoch_manager::shard_assignment::shard_id_to_uid;
 use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardLayout;
 use near_primitives::state::FlatStateValue;
-use near_primitives::types::{AccountId, EpochId, NumShards};
+use near_primitives::types::{AccountId, EpochId, NumShards, ShardId};
 use near_primitives::version::PROTOCOL_VERSION;
 use near_store::adapter::StoreAdapter;
 use near_store::adapter::trie_store::get_shard_uid_mapping;
@@ -316,6 +317,63 @@ fn assert_state_equal(
     assert!(!has_diff, "{} state mismatch!", cmp_msg);
 }
 
+/// Asserts that the two clients have byte-identical state for `shard_id` after
+/// the last block final on both of them, which must be on the same chain. The
+/// state is read from the disk tries, so the shard must be tracked by both
+/// clients, but doesn't need to be loaded in memory. Panics with the first key
+/// whose value differs, in trie order.
+pub fn assert_trie_state_equal(client_a: &Client, client_b: &Client, shard_id: ShardId) {
+    let final_head_a = client_a.chain.final_head().unwrap();
+    let final_head_b = client_b.chain.final_head().unwrap();
+    let final_head =
+        if final_head_a.height <= final_head_b.height { final_head_a } else { final_head_b };
+    let block_hash = final_head.last_block_hash;
+    for client in [client_a, client_b] {
+        assert_eq!(
+            client.chain.get_block_hash_by_height(final_head.height).unwrap(),
+            block_hash,
+            "clients are on different chains at height {}",
+            final_head.height
+        );
+    }
+    let shard_uid =
+        shard_id_to_uid(client_a.epoch_manager.as_ref(), shard_id, &final_head.epoch_id).unwrap();
+
+    let get_trie = |client: &Client| {
+        let state_root =
+            *client.chain.get_chunk_extra(&block_hash, &shard_uid).unwrap().state_root();
+        // get_view_trie_for_shard() never uses memtries.
+        client.runtime_adapter.get_view_trie_for_shard(shard_id, &block_hash, state_root).unwrap()
+    };
+    let trie_a = get_trie(client_a);
+    let trie_b = get_trie(client_b);
+    let lock_a = trie_a.lock_for_iter();
+    let lock_b = trie_b.lock_for_iter();
+    let iter_a = lock_a.iter().unwrap().map(Result::unwrap);
+    let iter_b = lock_b.iter().unwrap().map(Result::unwrap);
+    for (idx, item) in iter_a.zip_longest(iter_b).enumerate() {
+        let (key, value_a, value_b) = match item {
+            EitherOrBoth::Both((key_a, value_a), (key_b, value_b)) => {
+                if key_a == key_b && value_a == value_b {
+                    continue;
+                }
+                if key_a <= key_b {
+                    let value_b = (key_a == key_b).then_some(value_b);
+                    (key_a, Some(value_a), value_b)
+                } else {
+                    (key_b, None, Some(value_b))
+                }
+            }
+            EitherOrBoth::Left((key, value)) => (key, Some(value), None),
+            EitherOrBoth::Right((key, value)) => (key, None, Some(value)),
+        };
+        panic!(
+            "state of {} differs at block {} height {}, item {}: key {:?} has value {:?} on the first client and {:?} on the second",
+            shard_uid, block_hash, final_head.height, idx, key, value_a, value_b
+        );
+    }
+}
+
 fn should_assert_state_sanity(
     load_memtries_for_tracked_shards: bool,
     is_resharded: bool,
@@ -718,7 +718,7 @@ impl ForkNetworkCommand {
         let mut storage_mutator: StorageMutator = make_storage_mutator(update_state.clone())?;
 
         // TODO: allow mutating the state with a secret, so this can be used to prepare a public test network
-        let default_key = near_mirror::key_mapping::default_extra_key(None).public_key();
+        let default_key = near_mirror::key_mapping::default_extra_key(None, None).public_key();
         // Keeps track of accounts that have a full access key.
         let mut has_full_key = HashSet::new();
         // Lets us lookup large values in the `State` columns.
@@ -751,7 +751,7 @@ impl ForkNetworkCommand {
                             has_full_key.insert(account_id.clone());
                         }
                         let new_account_id = map_account(&account_id, None);
-                        let replacement = map_key(&public_key, None);
+                        let replacement = map_key(&public_key, None, None);
                         let new_shard_id =
                             target_shard_layout.account_id_to_shard_id(&new_account_id);
                         let new_shard_idx =
@@ -780,7 +780,7 @@ impl ForkNetworkCommand {
                             receipt.receiver_id().clone(),
                             *receipt.receipt_id(),
                         )?;
-                        near_mirror::genesis::map_receipt(&mut receipt, None, &default_key);
+                        near_mirror::genesis::map_receipt(&mut receipt, None, None, &default_key);
 
                         let new_shard_id =
                             target_shard_layout.account_id_to_shard_id(receipt.receiver_id());
@@ -967,7 +967,7 @@ impl ForkNetworkCommand {
             &update_state,
         )?;
 
-        let default_key = near_mirror::key_mapping::default_extra_key(None).public_key();
+        let default_key = near_mirror::key_mapping::default_extra_key(None, None).public_key();
         Self::update_source_state_roots(
             &mut source_state_roots,
             &target_shard_layout,
@@ -87,7 +87,7 @@ fn set_target_delayed_receipt(
     mut receipt: Receipt,
     default_key: &PublicKey,
 ) {
-    near_mirror::genesis::map_receipt(&mut receipt, None, default_key);
+    near_mirror::genesis::map_receipt(&mut receipt, None, None, default_key);
 
     let value = ReceiptOrStateStoredReceipt::Receipt(Cow::Owned(receipt));
     let value = borsh::to_vec(&value).unwrap();
@@ -0,0 +1,233 @@
+use near_primitives::types::AccountId;
+use near_primitives::views::AccessKeyPermissionView;
+use near_primitives_core::account::id::AccountType;
+use near_primitives_core::account::{AccessKey, AccessKeyPermission, FunctionCallPermission};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Renames of source chain accounts, as given with --account-map. An account in the map is
+/// sent to the target chain under the ID it maps to, instead of the one given by
+/// `crate::key_mapping::map_account()`, everywhere a transaction or genesis record refers to
+/// it. Only named accounts can be renamed, since the IDs of implicit accounts are derived from
+/// their keys. Sub-accounts are not renamed along with their parent account, so they should be
+/// listed as well if their parent is renamed.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(transparent)]
+pub(crate) struct AccountMap {
+    renames: BTreeMap<AccountId, AccountId>,
+}
+
+impl AccountMap {
+    /// Reads a JSON map of source chain account IDs to the target chain account IDs they
+    /// should be renamed to.
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::new(serde_json::from_str(&s)?)
+    }
+
+    pub(crate) fn new(renames: BTreeMap<AccountId, AccountId>) -> anyhow::Result<Self> {
+        let mut renamed_to = HashMap::new();
+        for (source_account_id, target_account_id) in renames.iter() {
+            for account_id in [source_account_id, target_account_id] {
+                if account_id.get_account_type() != AccountType::NamedAccount {
+                    anyhow::bail!(
+                        "can't rename {} to {}: {} is not a named account",
+                        source_account_id,
+                        target_account_id,
+                        account_id
+                    );
+                }
+            }
+            if let Some(other) = renamed_to.insert(target_account_id, source_account_id) {
+                anyhow::bail!(
+                    "both {} and {} are renamed to {}",
+                    other,
+                    source_account_id,
+                    target_account_id
+                );
+            }
+        }
+        Ok(Self { renames })
+    }
+
+    /// Returns the target chain account ID `account_id` is renamed to, if any.
+    pub(crate) fn get(&self, account_id: &AccountId) -> Option<&AccountId> {
+        self.renames.get(account_id)
+    }
+
+    /// Returns the target chain account ID of the source chain account `account_id`: the one
+    /// it is renamed to if it is in the map, and otherwise the one given by
+    /// `crate::key_mapping::map_account()`.
+    pub(crate) fn map_account(
+        &self,
+        account_id: &AccountId,
+        secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    ) -> AccountId {
+        match self.get(account_id) {
+            Some(target_account_id) => target_account_id.clone(),
+            None => crate::key_mapping::map_account(account_id, secret),
+        }
+    }
+
+    // Same as get(), for an account ID that hasn't been parsed yet
+    fn get_str(&self, account_id: &str) -> Option<&AccountId> {
+        account_id.parse::<AccountId>().ok().and_then(|account_id| self.get(&account_id))
+    }
+
+    /// Returns `access_key` with the receiver its function call permission allows calling
+    /// renamed, if it is in the map.
+    pub(crate) fn map_access_key(&self, access_key: &AccessKey) -> AccessKey {
+        let AccessKeyPermission::FunctionCall(permission) = &access_key.permission else {
+            return access_key.clone();
+        };
+        let Some(receiver_id) = self.get_str(&permission.receiver_id) else {
+            return access_key.clone();
+        };
+        AccessKey {
+            nonce: access_key.nonce,
+            permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                receiver_id: receiver_id.to_string(),
+                ..permission.clone()
+            }),
+        }
+    }
+
+    /// Same as `map_access_key()`, for the permission of an access key as returned by RPC
+    /// queries.
+    pub(crate) fn map_permission_view(
+        &self,
+        permission: &AccessKeyPermissionView,
+    ) -> AccessKeyPermissionView {
+        let AccessKeyPermissionView::FunctionCall { allowance, receiver_id, method_names } =
+            permission
+        else {
+            return permission.clone();
+        };
+        match self.get_str(receiver_id) {
+            Some(receiver_id) => AccessKeyPermissionView::FunctionCall {
+                allowance: *allowance,
+                receiver_id: receiver_id.to_string(),
+                method_names: method_names.clone(),
+            },
+            None => permission.clone(),
+        }
+    }
+
+    /// Returns the function call arguments `args` with the accounts they refer to renamed, or
+    /// None if they don't refer to any account in the map. Only JSON arguments are looked into,
+    /// and only string values that are exactly a renamed account ID are replaced, since there's
+    /// no telling what other encodings mean. Arguments that change are serialized again, so
+    /// their formatting might not be kept.
+    pub(crate) fn map_args(&self, args: &[u8]) -> Option<Vec<u8>> {
+        if self.renames.is_empty() {
+            return None;
+        }
+        let mut args = serde_json::from_slice::<serde_json::Value>(args).ok()?;
+        if !self.rename_json_strings(&mut args) {
+            return None;
+        }
+        Some(serde_json::to_vec(&args).unwrap())
+    }
+
+    // Replaces the strings in `value` that are renamed account IDs, and returns whether there
+    // were any.
+    fn rename_json_strings(&self, value: &mut serde_json::Value) -> bool {
+        match value {
+            serde_json::Value::String(s) => match self.get_str(s) {
+                Some(target_account_id) => {
+                    *s = target_account_id.to_string();
+                    true
+                }
+                None => false,
+            },
+            serde_json::Value::Array(values) => {
+                values.iter_mut().fold(false, |renamed, v| self.rename_json_strings(v) || renamed)
+            }
+            serde_json::Value::Object(values) => {
+                values.values_mut().fold(false, |renamed, v| self.rename_json_strings(v) || renamed)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AccountMap;
+    use near_primitives::types::AccountId;
+    use near_primitives_core::account::{AccessKey, AccessKeyPermission, FunctionCallPermission};
+    use std::collections::BTreeMap;
+
+    fn account_map(renames: &[(&str, &str)]) -> anyhow::Result<AccountMap> {
+        AccountMap::new(
+            renames
+                .iter()
+                .map(|(source, target)| (source.parse().unwrap(), target.parse().unwrap()))
+                .collect::<BTreeMap<AccountId, AccountId>>(),
+        )
+    }
+
+    fn function_call_key(receiver_id: &str) -> AccessKey {
+        AccessKey {
+            nonce: 7,
+            permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                allowance: Some(100),
+                receiver_id: receiver_id.to_string(),
+                method_names: vec!["foo".to_string()],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_account_map() {
+        let map =
+            account_map(&[("alice.near", "alice.testnet"), ("bob.near", "carol.testnet")]).unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        assert_eq!(map.get(&alice).unwrap().as_str(), "alice.testnet");
+        assert_eq!(map.get(&dave), None);
+        assert_eq!(map.map_account(&alice, None).as_str(), "alice.testnet");
+        assert_eq!(map.map_account(&dave, None), dave);
+        let implicit_account: AccountId = "a".repeat(64).parse().unwrap();
+        assert_eq!(
+            map.map_account(&implicit_account, None),
+            crate::key_mapping::map_account(&implicit_account, None)
+        );
+
+        assert_eq!(
+            map.map_access_key(&function_call_key("bob.near")),
+            function_call_key("carol.testnet")
+        );
+        assert_eq!(
+            map.map_access_key(&function_call_key("dave.near")),
+            function_call_key("dave.near")
+        );
+        assert_eq!(map.map_access_key(&AccessKey::full_access()), AccessKey::full_access());
+
+        account_map(&[("alice.near", "x.testnet"), ("bob.near", "x.testnet")]).unwrap_err();
+        let implicit_account = "a".repeat(64);
+        account_map(&[(implicit_account.as_str(), "alice.testnet")]).unwrap_err();
+        account_map(&[("alice.near", implicit_account.as_str())]).unwrap_err();
+    }
+
+    #[test]
+    fn test_map_args() {
+        let map = account_map(&[("bob.near", "carol.testnet")]).unwrap();
+        let args = br#"{"receiver_id": "bob.near", "amount": "10", "memo": "bob.near is great", "route": [{"pool": "bob.near"}, "dave.near"]}"#;
+        let mapped = map.map_args(args).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&mapped).unwrap(),
+            serde_json::json!({
+                "receiver_id": "carol.testnet",
+                "amount": "10",
+                "memo": "bob.near is great",
+                "route": [{"pool": "carol.testnet"}, "dave.near"],
+            })
+        );
+
+        // Arguments that don't refer to renamed accounts, or aren't JSON, are kept as they are.
+        assert_eq!(map.map_args(br#"{"receiver_id": "dave.near"}"#), None);
+        assert_eq!(map.map_args(b"bob.near"), None);
+        assert_eq!(AccountMap::default().map_args(args), None);
+    }
+}
@@ -0,0 +1,118 @@
+use near_primitives::transaction::Action;
+use std::collections::BTreeSet;
+
+/// Kinds of actions, as given to --action-types
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ActionType {
+    CreateAccount,
+    DeployContract,
+    FunctionCall,
+    Transfer,
+    Stake,
+    AddKey,
+    DeleteKey,
+    DeleteAccount,
+    Delegate,
+    DeployGlobalContract,
+    UseGlobalContract,
+}
+
+impl ActionType {
+    fn of(action: &Action) -> Self {
+        match action {
+            Action::CreateAccount(_) => Self::CreateAccount,
+            Action::DeployContract(_) => Self::DeployContract,
+            Action::FunctionCall(_) => Self::FunctionCall,
+            Action::Transfer(_) => Self::Transfer,
+            Action::Stake(_) => Self::Stake,
+            Action::AddKey(_) => Self::AddKey,
+            Action::DeleteKey(_) => Self::DeleteKey,
+            Action::DeleteAccount(_) => Self::DeleteAccount,
+            Action::Delegate(_) => Self::Delegate,
+            Action::DeployGlobalContract(_) => Self::DeployGlobalContract,
+            Action::UseGlobalContract(_) => Self::UseGlobalContract,
+        }
+    }
+}
+
+/// What to do with source chain transactions that contain actions not given in --action-types
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ActionFilterMode {
+    /// Send the transaction with only the allowed actions
+    #[default]
+    DropActions,
+    /// Don't send the transaction at all
+    SkipTx,
+}
+
+/// Restricts the source chain transactions we mirror to the ones containing some given kinds
+/// of actions. Transactions without any allowed action are never sent, and the ones that mix
+/// allowed and disallowed actions are handled according to the `ActionFilterMode`.
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct ActionFilter {
+    allowed: BTreeSet<ActionType>,
+    mode: ActionFilterMode,
+}
+
+impl ActionFilter {
+    pub(crate) fn new(
+        allowed: impl IntoIterator<Item = ActionType>,
+        mode: ActionFilterMode,
+    ) -> Self {
+        Self { allowed: allowed.into_iter().collect(), mode }
+    }
+
+    /// Returns whether a transaction with these actions should be mirrored at all.
+    pub(crate) fn allows_tx(&self, actions: &[Action]) -> bool {
+        match self.mode {
+            ActionFilterMode::DropActions => actions.iter().any(|a| self.allows_action(a)),
+            ActionFilterMode::SkipTx => {
+                !actions.is_empty() && actions.iter().all(|a| self.allows_action(a))
+            }
+        }
+    }
+
+    /// Returns whether this action should be kept in a mirrored transaction.
+    pub(crate) fn allows_action(&self, action: &Action) -> bool {
+        self.allowed.contains(&ActionType::of(action))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActionFilter, ActionFilterMode, ActionType};
+    use near_primitives::transaction::{Action, FunctionCallAction, TransferAction};
+
+    fn function_call() -> Action {
+        Action::FunctionCall(Box::new(FunctionCallAction {
+            method_name: "foo".to_string(),
+            args: Vec::new(),
+            gas: 100,
+            deposit: 0,
+        }))
+    }
+
+    fn transfer() -> Action {
+        Action::Transfer(TransferAction { deposit: 1 })
+    }
+
+    #[test]
+    fn test_action_filter() {
+        let drop_actions =
+            ActionFilter::new([ActionType::FunctionCall], ActionFilterMode::DropActions);
+        assert!(drop_actions.allows_tx(&[function_call()]));
+        assert!(drop_actions.allows_tx(&[transfer(), function_call()]));
+        assert!(!drop_actions.allows_tx(&[transfer()]));
+        assert!(!drop_actions.allows_tx(&[]));
+        assert!(drop_actions.allows_action(&function_call()));
+        assert!(!drop_actions.allows_action(&transfer()));
+
+        let skip_tx = ActionFilter::new([ActionType::FunctionCall], ActionFilterMode::SkipTx);
+        assert!(skip_tx.allows_tx(&[function_call()]));
+        assert!(!skip_tx.allows_tx(&[transfer(), function_call()]));
+        assert!(!skip_tx.allows_tx(&[transfer()]));
+        assert!(!skip_tx.allows_tx(&[]));
+    }
+}
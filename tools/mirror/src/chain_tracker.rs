@@ -9,7 +9,7 @@ use near_crypto::{PublicKey, SecretKey};
 use near_indexer::StreamerMessage;
 use near_indexer_primitives::{IndexerExecutionOutcomeWithReceipt, IndexerTransactionWithOutcome};
 use near_primitives::hash::CryptoHash;
-use near_primitives::transaction::Transaction;
+use near_primitives::transaction::{SignedTransaction, Transaction};
 use near_primitives::types::{AccountId, BlockHeight};
 use near_primitives::views::{ActionView, ExecutionStatusView, ReceiptEnumView};
 use near_primitives_core::types::{Gas, Nonce};
@@ -22,6 +22,10 @@ use std::fmt::Write;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+// Number of target chain blocks after which we stop counting a sent transaction that
+// hasn't appeared on chain as in flight
+const IN_FLIGHT_TARGET_BLOCKS: BlockHeight = 100;
+
 // Information related to a single transaction that we sent in the past.
 // We could just forget it and not save any of this, but keeping this info
 // makes it easy to print out human-friendly info later on when we find this
@@ -146,6 +150,8 @@ pub(crate) struct TargetBlockInfo {
 // that clear and doesn't make that much sense. Should refactor
 pub(crate) struct TxTracker {
     sent_txs: HashMap<CryptoHash, TxSendInfo>,
+    // maps the hashes of the relayer transactions we sent to the mapped transactions they wrap
+    relayed_txs: HashMap<CryptoHash, SignedTransaction>,
     txs_by_signer: HashMap<(AccountId, PublicKey), BTreeSet<TxId>>,
     // for each updater (a tx or receipt hash, or a queued transaction we haven't sent yet), keeps
     // a set of access keys who might be updated by it
@@ -190,6 +196,7 @@ impl TxTracker {
             stop_height,
             tx_batch_interval,
             sent_txs: HashMap::new(),
+            relayed_txs: HashMap::new(),
             txs_by_signer: HashMap::new(),
             updater_to_keys: HashMap::new(),
             nonces: HashMap::new(),
@@ -230,6 +237,11 @@ impl TxTracker {
         Ok((next_height, create_account_height))
     }
 
+    // Takes effect when computing the delay after the next batch of transactions is sent.
+    pub(crate) fn set_tx_batch_interval(&mut self, tx_batch_interval: Option<Duration>) {
+        self.tx_batch_interval = tx_batch_interval;
+    }
+
     pub(crate) fn finished(&self) -> bool {
         match self.stop_height {
             Some(_) => {
@@ -240,6 +252,21 @@ impl TxTracker {
         }
     }
 
+    // number of transactions we've sent that we haven't yet seen in a target chain block
+    pub(crate) fn num_pending_txs(&self) -> usize {
+        self.sent_txs.len()
+    }
+
+    // number of pending transactions sent recently enough that we still expect to see them in a
+    // target chain block. Older ones were most likely lost, so we don't count them, otherwise
+    // they would keep the number of transactions in flight above --max-in-flight forever
+    pub(crate) fn num_in_flight_txs(&self, target_height: BlockHeight) -> usize {
+        self.sent_txs
+            .values()
+            .filter(|info| info.sent_at_target_height + IN_FLIGHT_TARGET_BLOCKS > target_height)
+            .count()
+    }
+
     // Makes sure that there's something written in the DB for this access key.
     // This function is called before calling initialize_target_nonce(), which sets
     // in-memory data associated with this nonce. It would make sense to do this part at the same time,
@@ -751,8 +778,17 @@ impl TxTracker {
         &mut self,
         tx_block_queue: &Mutex<VecDeque<MappedBlock>>,
         db: &DB,
-        tx: IndexerTransactionWithOutcome,
+        mut tx: IndexerTransactionWithOutcome,
     ) -> anyhow::Result<()> {
+        // Everything else only knows about the mapped transaction, so we treat the relayer's
+        // transaction as if it was that one. Its outcome is the one of the delegate action,
+        // whose receipt leads to the receipt executing the mapped transaction's actions.
+        if let Some(relayed_tx) = self.relayed_txs.remove(&tx.transaction.hash) {
+            tx.transaction.hash = relayed_tx.get_hash();
+            tx.transaction.signer_id = relayed_tx.transaction.signer_id().clone();
+            tx.transaction.public_key = relayed_tx.transaction.public_key().clone();
+            tx.transaction.nonce = relayed_tx.transaction.nonce();
+        }
         if let Some(info) = self.sent_txs.remove(&tx.transaction.hash) {
             crate::metrics::TRANSACTIONS_INCLUDED.inc();
             self.remove_tx(&tx);
@@ -895,6 +931,9 @@ impl TxTracker {
         // TODO: don't keep adding txs if we're not ever finding them on chain, since we'll OOM eventually
         // if that happens.
         self.sent_txs.insert(hash, TxSendInfo::new(&tx, source_height, target_height, now));
+        if let Some(relayed_tx_hash) = tx.relayed_tx_hash {
+            self.relayed_txs.insert(relayed_tx_hash, tx.target_tx.clone());
+        }
         let txs = self.txs_by_signer.entry(access_key.clone()).or_default();
 
         if let Some(highest_nonce) = txs.iter().next_back() {
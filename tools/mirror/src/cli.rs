@@ -1,8 +1,11 @@
 use anyhow::Context;
 use std::cell::Cell;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use near_primitives::types::BlockHeight;
+use near_crypto::{KeyType, PublicKey};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight};
 use near_primitives::views::AccessKeyPermissionView;
 
 #[derive(clap::Parser)]
@@ -15,16 +18,35 @@ pub struct MirrorCommand {
 enum SubCommand {
     Prepare(PrepareCmd),
     Run(RunCmd),
+    ShowConfig(ShowConfigCmd),
     ShowKeys(ShowKeysCmd),
+    MaterializeKeys(MaterializeKeysCmd),
+    Status(StatusCmd),
+    Verify(VerifyCmd),
 }
 
 /// initialize a target chain with genesis records from the source chain, and
 /// then try to mirror transactions from the source chain to the target chain.
+/// On SIGTERM or SIGINT, stops reading new source chain blocks and waits for the
+/// transactions already sent to appear on the target chain before exiting, so that
+/// a restart doesn't send them again. A second signal exits right away.
+/// On SIGHUP, reads --config-path again and applies the new options.
 #[derive(clap::Parser)]
 struct RunCmd {
-    /// source chain home dir
-    #[clap(long)]
-    source_home: PathBuf,
+    #[clap(flatten)]
+    args: RunArgs,
+    /// format of the log lines: "pretty" for the usual human-readable
+    /// lines, or "json" for one JSON object per line
+    #[clap(long, value_enum, default_value_t)]
+    log_format: near_o11y::LogFormat,
+}
+
+// Arguments shared by `run` and `show-config`
+#[derive(clap::Args)]
+struct RunArgs {
+    /// source chain home dir. Must be given unless --source-rpc is
+    #[clap(long, required_unless_present = "source_rpc")]
+    source_home: Option<PathBuf>,
     /// target chain home dir
     #[clap(long)]
     target_home: PathBuf,
@@ -45,22 +67,190 @@ struct RunCmd {
     /// whatever's currently stored in --source-home
     #[clap(long)]
     online_source: bool,
+    /// Read the source chain blocks from both the hot and the cold storage of
+    /// the archival node at --source-home, so that historical blocks that have
+    /// been garbage collected from its hot storage can be mirrored. Not
+    /// supported with --online-source
+    #[clap(long, conflicts_with = "online_source")]
+    source_cold_storage: bool,
+    /// Read the source chain from the RPC node at this URL instead of from
+    /// --source-home, e.g. a public endpoint. This is much slower than reading
+    /// a local DB, since every chunk, receipt and execution outcome takes a
+    /// separate request, so it might not keep up with a busy source chain.
+    /// The node must track all shards, and must be an archival node if the
+    /// mirror starts from blocks that have been garbage collected elsewhere
+    #[clap(long, conflicts_with_all = ["source_home", "online_source", "source_cold_storage"])]
+    source_rpc: Option<String>,
+    /// PEM encoded client certificate to present to a --source-rpc endpoint that requires mutual TLS
+    #[clap(long, requires = "source_rpc", requires = "source_rpc_client_key")]
+    source_rpc_client_cert: Option<PathBuf>,
+    /// PEM encoded private key for --source-rpc-client-cert
+    #[clap(long, requires = "source_rpc_client_cert")]
+    source_rpc_client_key: Option<PathBuf>,
     /// If provided, we will stop after sending transactions coming from
     /// this height in the source chain
     #[clap(long)]
     stop_height: Option<BlockHeight>,
     #[clap(long)]
     config_path: Option<PathBuf>,
+    /// JSON file containing a map from target chain account IDs to public keys.
+    /// When mirroring a transaction that creates one of these accounts without
+    /// adding a full access key, the given key is added instead of the default
+    /// extra key. Accounts not listed keep getting the default extra key
+    #[clap(long)]
+    extra_key_overrides: Option<PathBuf>,
+    /// JSON file containing a list of rules of the form
+    /// {"receiver_id": "...", "old_method": "...", "new_method": "..."}.
+    /// FunctionCall actions calling `old_method` on the target chain account
+    /// `receiver_id` will call `new_method` instead. Calls not matching any
+    /// rule are sent unchanged
+    #[clap(long)]
+    method_rename: Option<PathBuf>,
+    /// JSON file containing a map from source chain account IDs to the target
+    /// chain account IDs they should be renamed to. Signers, receivers, and
+    /// accounts referred to in actions, like delete account beneficiaries,
+    /// function call access key receivers, delegate action senders and
+    /// account IDs passed as JSON function call arguments, are renamed
+    /// accordingly. The same map should be given to `prepare`. Accounts
+    /// not in the map keep their usual target chain ID. Only named accounts
+    /// can be renamed, and sub-accounts are not renamed along with their parent
+    #[clap(long)]
+    account_map: Option<PathBuf>,
+    /// Exit with an error on source chain transactions signed by a key that
+    /// can't be mapped to a target chain key. By default such transactions
+    /// are skipped with a warning
+    #[clap(long)]
+    strict_keys: bool,
+    /// Scale down the gas attached to mirrored FunctionCall actions by the ratio
+    /// of the source chain's gas price to the target chain's current gas price,
+    /// when the target chain's is higher, so that their prepaid cost doesn't
+    /// exceed what it was on the source chain. By default the gas attached in
+    /// the source chain is kept as is
+    #[clap(long)]
+    adjust_gas: bool,
+    /// Mirror Stake actions, with their validator keys mapped to target chain
+    /// keys, and let the stakes resulting from staking pool function calls
+    /// stand. By default Stake actions are skipped, and stakes made by staking
+    /// pool contracts are reversed, so that the set of validators in the target
+    /// chain doesn't change
+    #[clap(long)]
+    mirror_stakes: bool,
+    /// Send source chain transactions again even if the mirror DB records
+    /// that they were already sent, e.g. because a previous run was stopped
+    /// in the middle of sending a block's worth of transactions. By default
+    /// these are skipped, so that going over the same source chain heights
+    /// again doesn't send conflicting transactions to the target chain. With
+    /// --mirror-db-retention-blocks, only transactions sent within the
+    /// retention window are remembered, so older ones are sent again anyway
+    #[clap(long)]
+    force_resubmit: bool,
+    /// Comma separated list of the kinds of actions to mirror, e.g.
+    /// `function-call,transfer`. Source chain transactions with none of
+    /// these are not sent. By default all actions are mirrored
+    #[clap(long, value_enum, value_delimiter = ',')]
+    action_types: Option<Vec<crate::action_filter::ActionType>>,
+    /// What to do with source chain transactions that contain both actions
+    /// given in --action-types and other ones
+    #[clap(long, value_enum, default_value_t, requires = "action_types")]
+    action_filter_mode: crate::action_filter::ActionFilterMode,
+    /// What to do if the source chain reorgs blocks whose transactions we already sent.
+    /// Only expected with --online-source or --source-rpc
+    #[clap(long, value_enum, default_value_t)]
+    on_reorg: crate::reorg::OnReorg,
+    /// Append a CSV row to this file for every transaction successfully sent
+    /// to the target chain, with the source chain transaction hash (empty for
+    /// extra transactions added by the mirror), the target chain signer,
+    /// receiver and nonce, the source chain height and the time it was sent
+    /// in milliseconds since the unix epoch. Rows from previous runs are kept
+    #[clap(long)]
+    log_sent_txs: Option<PathBuf>,
+    /// Serve health checks at this address: `/healthz` succeeds as long as the
+    /// process is up, and `/readyz` once the source chain is initialized and as
+    /// long as the target chain height keeps advancing
+    #[clap(long)]
+    health_addr: Option<std::net::SocketAddr>,
+    /// Serve the mirror's metrics in the Prometheus text format at `/metrics`
+    /// on this address
+    #[clap(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+    /// Prefix of the names of the metrics served with --metrics-addr, in place
+    /// of `near_mirror_`. Set a different one for each mirror process sharing
+    /// a host to keep their metrics apart
+    #[clap(long, default_value = crate::metrics::DEFAULT_PROMETHEUS_PREFIX)]
+    prometheus_prefix: String,
+    /// Refuse to start if the genesis hash of the chain at --target-home is not
+    /// this one. Regardless of this, the mirror refuses to start if the target
+    /// chain is not the one previous runs with the same mirror DB sent
+    /// transactions to
+    #[clap(long)]
+    expected_target_genesis: Option<CryptoHash>,
+    /// If given, the mirror DB is periodically pruned of bookkeeping entries
+    /// for receipts and transactions added more than this many source chain
+    /// blocks ago, and compacted, to bound its size on long running forks
+    #[clap(long)]
+    mirror_db_retention_blocks: Option<BlockHeight>,
+    /// If given, log the last source chain nonce seen and the last target chain
+    /// nonce sent for the access keys that sent the most transactions, every
+    /// this many seconds. Useful to tell whether transactions are being rejected
+    /// because the target chain nonces drifted from the expected ones
+    #[clap(long)]
+    nonce_report_interval: Option<u64>,
+    /// If given, send each source chain block's transactions at the time the
+    /// block was produced relative to the first one sent, with the time between
+    /// blocks divided by this factor: 1.0 reproduces the source chain's block
+    /// cadence and 2.0 replays it twice as fast. Takes precedence over
+    /// `tx_batch_interval` in --config-path
+    #[clap(long, value_parser = parse_replay_speed)]
+    replay_speed: Option<f64>,
+    /// If given, stop reading and sending new source chain blocks while this
+    /// many sent transactions have not yet appeared on the target chain, and
+    /// resume once enough of them have. Transactions sent more than 100 target
+    /// chain blocks ago are assumed lost and don't count towards the limit.
+    /// Must be positive
+    #[clap(long, value_parser = parse_max_in_flight)]
+    max_in_flight: Option<usize>,
+    /// Send mapped source chain transactions wrapped in delegate actions, in
+    /// transactions signed by this target chain account instead of by the
+    /// mapped signers. Transactions added by the mirror itself, and ones that
+    /// add access keys whose nonces the mirror needs, are still sent directly
+    #[clap(long, requires = "relayer_key")]
+    relayer: Option<AccountId>,
+    /// Key file with a full access key of the --relayer account
+    #[clap(long, requires = "relayer")]
+    relayer_key: Option<PathBuf>,
 }
 
-impl RunCmd {
-    fn run(self) -> anyhow::Result<()> {
-        openssl_probe::init_ssl_cert_env_vars();
+fn parse_replay_speed(s: &str) -> anyhow::Result<f64> {
+    let speed: f64 = s.parse()?;
+    if !(speed.is_finite() && speed > 0.0) {
+        anyhow::bail!("replay speed must be a positive number");
+    }
+    Ok(speed)
+}
+
+fn load_account_map(path: Option<&Path>) -> anyhow::Result<crate::account_map::AccountMap> {
+    let account_map = match path {
+        Some(path) => crate::account_map::AccountMap::load(path)
+            .with_context(|| format!("Failed to load account map from {}", path.display()))?,
+        None => crate::account_map::AccountMap::default(),
+    };
+    Ok(account_map)
+}
 
+fn parse_max_in_flight(s: &str) -> anyhow::Result<usize> {
+    let max_in_flight: usize = s.parse()?;
+    if max_in_flight == 0 {
+        anyhow::bail!("max in flight must be a positive number");
+    }
+    Ok(max_in_flight)
+}
+
+impl RunArgs {
+    fn load_secret(&self) -> anyhow::Result<crate::secret::MirrorSecret> {
         let secret = if let Some(secret_file) = &self.secret_file {
             let secret = crate::secret::load(secret_file)
                 .with_context(|| format!("Failed to load secret from {:?}", secret_file))?;
-            if secret.is_some() && self.no_secret {
+            if secret.secret.is_some() && self.no_secret {
                 anyhow::bail!(
                     "--no-secret given with --secret-file indicating that a secret should be used"
                 );
@@ -70,19 +260,169 @@ impl RunCmd {
             if !self.no_secret {
                 anyhow::bail!("Please give either --secret-file or --no-secret");
             }
-            None
+            crate::secret::MirrorSecret::default()
         };
+        Ok(secret)
+    }
 
-        run_async(crate::run(
-            self.source_home,
-            self.target_home,
-            self.mirror_db_path,
-            secret,
-            self.stop_height,
-            self.online_source,
-            self.config_path,
-        ))
+    fn load_extra_key_overrides(&self) -> anyhow::Result<HashMap<AccountId, PublicKey>> {
+        let extra_key_overrides = match &self.extra_key_overrides {
+            Some(path) => crate::load_extra_key_overrides(path).with_context(|| {
+                format!("Failed to load extra key overrides from {}", path.display())
+            })?,
+            None => HashMap::new(),
+        };
+        Ok(extra_key_overrides)
+    }
+
+    fn load_method_renames(&self) -> anyhow::Result<HashMap<(AccountId, String), String>> {
+        let method_renames = match &self.method_rename {
+            Some(path) => crate::load_method_renames(path).with_context(|| {
+                format!("Failed to load method rename rules from {}", path.display())
+            })?,
+            None => HashMap::new(),
+        };
+        Ok(method_renames)
     }
+
+    fn source_rpc_client_cert(&self) -> Option<crate::key_util::RpcClientCert> {
+        match (&self.source_rpc_client_cert, &self.source_rpc_client_key) {
+            (Some(cert_path), Some(key_path)) => Some(crate::key_util::RpcClientCert {
+                cert_path: cert_path.clone(),
+                key_path: key_path.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn action_filter(&self) -> Option<crate::action_filter::ActionFilter> {
+        self.action_types.as_ref().map(|action_types| {
+            crate::action_filter::ActionFilter::new(
+                action_types.iter().copied(),
+                self.action_filter_mode,
+            )
+        })
+    }
+
+    fn into_options(self) -> anyhow::Result<crate::MirrorOptions> {
+        let secret = self.load_secret()?;
+        Ok(crate::MirrorOptions {
+            secret: secret.secret,
+            target_key_type: secret.target_key_type,
+            extra_key_overrides: self.load_extra_key_overrides()?,
+            method_renames: self.load_method_renames()?,
+            account_map: load_account_map(self.account_map.as_deref())?,
+            action_filter: self.action_filter(),
+            source_rpc_client_cert: self.source_rpc_client_cert(),
+            source_home: self.source_home,
+            target_home: self.target_home,
+            mirror_db_path: self.mirror_db_path,
+            stop_height: self.stop_height,
+            online_source: self.online_source,
+            source_cold_storage: self.source_cold_storage,
+            source_rpc: self.source_rpc,
+            config_path: self.config_path,
+            strict_keys: self.strict_keys,
+            adjust_gas: self.adjust_gas,
+            mirror_stakes: self.mirror_stakes,
+            force_resubmit: self.force_resubmit,
+            on_reorg: self.on_reorg,
+            log_sent_txs: self.log_sent_txs,
+            health_addr: self.health_addr,
+            metrics_addr: self.metrics_addr,
+            prometheus_prefix: self.prometheus_prefix,
+            expected_target_genesis: self.expected_target_genesis,
+            mirror_db_retention_blocks: self.mirror_db_retention_blocks,
+            nonce_report_interval: self.nonce_report_interval.map(std::time::Duration::from_secs),
+            replay_speed: self.replay_speed,
+            max_in_flight: self.max_in_flight,
+            relayer: self.relayer,
+            relayer_key: self.relayer_key,
+        })
+    }
+}
+
+impl RunCmd {
+    fn run(self) -> anyhow::Result<()> {
+        openssl_probe::init_ssl_cert_env_vars();
+
+        let options = self.args.into_options()?;
+
+        run_async(self.log_format, async move {
+            let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+            actix::spawn(handle_shutdown_signals(shutdown_tx));
+            let (reload_tx, reload_rx) = tokio::sync::watch::channel(());
+            actix::spawn(handle_reload_signals(reload_tx));
+            crate::run(options, reload_rx, shutdown_rx).await
+        })
+    }
+}
+
+/// Print the settings `run` would use if given the same arguments, with the options
+/// from --config-path and their defaults filled in, and the secret redacted
+#[derive(clap::Parser)]
+struct ShowConfigCmd {
+    #[clap(flatten)]
+    args: RunArgs,
+}
+
+impl ShowConfigCmd {
+    fn run(self) -> anyhow::Result<()> {
+        let config = crate::effective_config(self.args.into_options()?)?;
+        println!("{}", serde_json::to_string_pretty(&config)?);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut sigint = signal(SignalKind::interrupt()).unwrap();
+    let mut sigterm = signal(SignalKind::terminate()).unwrap();
+
+    tokio::select! {
+         _ = sigint.recv()  => "SIGINT",
+         _ = sigterm.recv() => "SIGTERM",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> &'static str {
+    tokio::signal::ctrl_c().await.unwrap();
+    "Ctrl+C"
+}
+
+/// Sets `shutdown` on the first SIGINT or SIGTERM, so that we stop reading new source
+/// blocks and wait for the transactions already sent. A second signal exits right away.
+async fn handle_shutdown_signals(shutdown: tokio::sync::watch::Sender<bool>) {
+    let signal = wait_for_shutdown_signal().await;
+    tracing::info!(target: "mirror", "got {}, shutting down", signal);
+    // An error means the mirror already exited, so there's nothing to stop.
+    let _ = shutdown.send(true);
+
+    let signal = wait_for_shutdown_signal().await;
+    tracing::warn!(target: "mirror", "got {} again, exiting without waiting for sent transactions", signal);
+    std::process::exit(1);
+}
+
+/// Notifies `reload` on every SIGHUP, so that the options in --config-path are read again.
+#[cfg(unix)]
+async fn handle_reload_signals(reload: tokio::sync::watch::Sender<()>) {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut sighup = signal(SignalKind::hangup()).unwrap();
+    while sighup.recv().await.is_some() {
+        tracing::info!(target: "mirror", "got SIGHUP, reloading the mirror config");
+        // An error means the mirror already exited, so there's nothing to reload.
+        if reload.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+/// There's no SIGHUP here, so just keep `reload` open until the mirror exits.
+#[cfg(not(unix))]
+async fn handle_reload_signals(reload: tokio::sync::watch::Sender<()>) {
+    reload.closed().await;
 }
 
 /// Write a new genesis records file where the public keys have been
@@ -91,10 +431,13 @@ impl RunCmd {
 #[derive(clap::Parser)]
 struct PrepareCmd {
     /// A genesis records file as output by `neard view-state
-    /// dump-state --stream`
+    /// dump-state --stream`. It is decompressed on the fly if it
+    /// ends in `.gz` or `.zst`
     #[clap(long)]
     records_file_in: PathBuf,
-    /// Path to the new records file with updated public keys
+    /// Path to the new records file with updated public keys. If
+    /// this is "-", the records are written to stdout. The file is
+    /// compressed with gzip or zstd if it ends in `.gz` or `.zst`
     #[clap(long)]
     records_file_out: PathBuf,
     /// If this is provided, don't use a secret when mapping public
@@ -113,19 +456,123 @@ struct PrepareCmd {
     /// longer be able to mirror any traffic.
     #[clap(long)]
     secret_file_out: PathBuf,
+    /// If given, all keys on the target chain will be of this type
+    /// (ed25519 or secp256k1), with keys of the other type mapped
+    /// across deterministically from the source key and the secret.
+    /// This is recorded in the secret file, so later commands
+    /// loading it will map keys the same way.
+    #[clap(long)]
+    target_key_type: Option<KeyType>,
+    /// JSON file mapping source chain account IDs to the target chain
+    /// account IDs they should be renamed to, as given to `run`
+    #[clap(long)]
+    account_map: Option<PathBuf>,
 }
 
 impl PrepareCmd {
     fn run(self) -> anyhow::Result<()> {
+        let account_map = load_account_map(self.account_map.as_deref())?;
         crate::genesis::map_records(
             &self.records_file_in,
             &self.records_file_out,
             self.no_secret,
             &self.secret_file_out,
+            self.target_key_type,
+            &account_map,
         )
     }
 }
 
+/// Print the progress of a previous mirror run as recorded in the mirror DB.
+/// The DB is opened read-only, so this is safe to run alongside `mirror run`
+#[derive(clap::Parser)]
+struct StatusCmd {
+    /// mirror database dir
+    #[clap(long)]
+    mirror_db_path: PathBuf,
+}
+
+impl StatusCmd {
+    fn run(self) -> anyhow::Result<()> {
+        let status = crate::mirror_status(&self.mirror_db_path)?;
+        println!("{}", status);
+        Ok(())
+    }
+}
+
+/// Compare the balance, code hash and access keys of accounts on the source and
+/// target chains, taking the key mapping into account, and report any differences.
+/// Exits with an error if any are found. Run it after the mirror has caught up,
+/// since the accounts are compared at the final head of each chain
+#[derive(clap::Parser)]
+struct VerifyCmd {
+    /// RPC URL for a node running on the source chain
+    #[clap(long)]
+    source_rpc: String,
+    /// RPC URL for a node running on the target chain
+    #[clap(long)]
+    target_rpc: String,
+    /// source chain account ID to compare. Can be given several times
+    #[clap(long = "account", required = true)]
+    accounts: Vec<String>,
+    /// file containing an optional secret as generated by the
+    /// `prepare` command.
+    #[clap(long)]
+    secret_file: Option<PathBuf>,
+    /// JSON file mapping source chain account IDs to the target chain
+    /// account IDs they were renamed to, as given to `run`
+    #[clap(long)]
+    account_map: Option<PathBuf>,
+}
+
+impl VerifyCmd {
+    fn run(self) -> anyhow::Result<()> {
+        let secret = match &self.secret_file {
+            Some(secret_file) => crate::secret::load(secret_file)
+                .with_context(|| format!("Failed to load secret from {:?}", secret_file))?,
+            None => crate::secret::MirrorSecret::default(),
+        };
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|a| a.parse().with_context(|| format!("bad account ID {:?}", a)))
+            .collect::<anyhow::Result<Vec<AccountId>>>()?;
+        let account_map = load_account_map(self.account_map.as_deref())?;
+
+        let results = run_async(near_o11y::LogFormat::default(), async move {
+            crate::verify::verify_accounts(
+                &self.source_rpc,
+                &self.target_rpc,
+                &accounts,
+                &account_map,
+                secret.secret.as_ref(),
+                secret.target_key_type,
+            )
+            .await
+        })?;
+        let mut num_mismatched = 0;
+        for (target_account_id, discrepancies) in results.iter() {
+            if discrepancies.is_empty() {
+                println!("{}: OK", target_account_id);
+                continue;
+            }
+            num_mismatched += 1;
+            println!("{}:", target_account_id);
+            for d in discrepancies {
+                println!("  {}", d);
+            }
+        }
+        if num_mismatched > 0 {
+            anyhow::bail!(
+                "found discrepancies in {} of {} accounts",
+                num_mismatched,
+                results.len()
+            );
+        }
+        Ok(())
+    }
+}
+
 /// Given a source chain NEAR home dir, read and map access keys corresponding to
 /// a given account ID and optional block height.
 #[derive(clap::Parser)]
@@ -145,6 +592,12 @@ struct ShowKeysFromRPCCmd {
     /// RPC URL for a node running on the source chain. e.g. "https://rpc.mainnet.near.org"
     #[clap(long)]
     rpc_url: String,
+    /// PEM encoded client certificate to present to RPC endpoints that require mutual TLS.
+    #[clap(long, requires = "rpc_client_key")]
+    rpc_client_cert: Option<PathBuf>,
+    /// PEM encoded private key for `--rpc-client-cert`.
+    #[clap(long, requires = "rpc_client_cert")]
+    rpc_client_key: Option<PathBuf>,
     #[clap(long)]
     account_id: String,
     #[clap(long)]
@@ -158,6 +611,17 @@ struct ShowKeyFromKeyCmd {
     public_key: String,
 }
 
+/// Check that the given source chain public key is the one that maps to the given target chain
+/// public key. The mapping can't be inverted, so this is how to confirm where a key seen on the
+/// target chain came from. Exits with code 5 if the keys don't match
+#[derive(clap::Parser)]
+struct VerifyPubKeyCmd {
+    #[clap(long)]
+    source_public_key: String,
+    #[clap(long)]
+    target_public_key: String,
+}
+
 /// Show the default extra key. This key should exist for any account that does not have
 /// any full access keys in the source chain (e.g. validators with staking pools)
 #[derive(clap::Parser)]
@@ -168,6 +632,7 @@ enum ShowKeysSubCommand {
     FromSourceDB(ShowKeysFromSourceDBCmd),
     FromRPC(ShowKeysFromRPCCmd),
     FromPubKey(ShowKeyFromKeyCmd),
+    VerifyPubKey(VerifyPubKeyCmd),
     DefaultExtraKey(ShowDefaultExtraKeyCmd),
 }
 
@@ -178,6 +643,14 @@ struct ShowKeysCmd {
     /// `prepare` command.
     #[clap(long)]
     secret_file: Option<PathBuf>,
+    /// JSON file mapping source chain account IDs to the target chain
+    /// account IDs they are renamed to, as given to `run`
+    #[clap(long)]
+    account_map: Option<PathBuf>,
+    /// format of the log lines: "pretty" for the usual human-readable
+    /// lines, or "json" for one JSON object per line
+    #[clap(long, value_enum, default_value_t)]
+    log_format: near_o11y::LogFormat,
     #[clap(subcommand)]
     subcmd: ShowKeysSubCommand,
 }
@@ -189,16 +662,44 @@ impl ShowKeysCmd {
                 .with_context(|| format!("Failed to load secret from {:?}", secret_file))?;
             secret
         } else {
-            None
+            crate::secret::MirrorSecret::default()
         };
+        let account_map = load_account_map(self.account_map.as_deref())?;
+        if let Err(err) = Self::show_keys(
+            self.subcmd,
+            &account_map,
+            secret.secret.as_ref(),
+            secret.target_key_type,
+            self.log_format,
+        ) {
+            // Failures to look up or map keys have their own exit codes, so
+            // that scripts can tell them apart.
+            let exit_code = err.exit_code();
+            eprintln!("Error: {:?}", anyhow::Error::from(err));
+            std::process::exit(exit_code);
+        }
+        Ok(())
+    }
+
+    fn show_keys(
+        subcmd: ShowKeysSubCommand,
+        account_map: &crate::account_map::AccountMap,
+        secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+        target_key_type: Option<KeyType>,
+        log_format: near_o11y::LogFormat,
+    ) -> Result<(), crate::KeyMappingError> {
         let mut probably_extra_key = false;
-        let keys = match self.subcmd {
+        // The source chain account the keys were looked up for, if any
+        let mut source_account_id = None;
+        let keys = match subcmd {
             ShowKeysSubCommand::FromSourceDB(c) => {
+                source_account_id = Some(c.account_id.clone());
                 let keys = crate::key_util::keys_from_source_db(
                     &c.home,
                     &c.account_id,
                     c.block_height,
-                    secret.as_ref(),
+                    secret,
+                    target_key_type,
                 )?;
                 probably_extra_key = keys.iter().all(|key| {
                     key.permission
@@ -208,12 +709,23 @@ impl ShowKeysCmd {
                 keys
             }
             ShowKeysSubCommand::FromRPC(c) => {
-                let keys = run_async(async move {
+                source_account_id = Some(c.account_id.clone());
+                let client_cert = match (c.rpc_client_cert, c.rpc_client_key) {
+                    (Some(cert_path), Some(key_path)) => {
+                        Some(crate::key_util::RpcClientCert { cert_path, key_path })
+                    }
+                    _ => None,
+                };
+                // The future passed to run_async() must be 'static.
+                let secret = secret.copied();
+                let keys = run_async(log_format, async move {
                     crate::key_util::keys_from_rpc(
                         &c.rpc_url,
+                        client_cert.as_ref(),
                         &c.account_id,
                         c.block_height,
                         secret.as_ref(),
+                        target_key_type,
                     )
                     .await
                 })?;
@@ -225,12 +737,24 @@ impl ShowKeysCmd {
                 keys
             }
             ShowKeysSubCommand::FromPubKey(c) => {
-                vec![crate::key_util::map_pub_key(&c.public_key, secret.as_ref())?]
+                vec![crate::key_util::map_pub_key(&c.public_key, secret, target_key_type)?]
+            }
+            ShowKeysSubCommand::VerifyPubKey(c) => {
+                vec![crate::key_util::verify_mapped_pub_key(
+                    &c.source_public_key,
+                    &c.target_public_key,
+                    secret,
+                    target_key_type,
+                )?]
             }
             ShowKeysSubCommand::DefaultExtraKey(_c) => {
-                vec![crate::key_util::default_extra_key(secret.as_ref())]
+                vec![crate::key_util::default_extra_key(secret, target_key_type)]
             }
         };
+        if let Some(account_id) = source_account_id {
+            let account_id = crate::key_util::parse_account_id(&account_id)?;
+            println!("target account ID: {}", account_map.map_account(&account_id, secret));
+        }
         for key in keys.iter() {
             if let Some(k) = &key.original_key {
                 println!("original pub key: {}", k);
@@ -241,12 +765,12 @@ impl ShowKeysCmd {
                 key.mapped_key.public_key()
             );
             if let Some(a) = &key.permission {
-                println!("access: {:?}", a);
+                println!("access: {:?}", account_map.map_permission_view(a));
             }
             println!("------------")
         }
         if probably_extra_key {
-            let extra_key = crate::key_mapping::default_extra_key(secret.as_ref());
+            let extra_key = crate::key_mapping::default_extra_key(secret, target_key_type);
             println!(
                 "{} account probably has an extra full access key added:\nmapped secret key: {}\npublic key: {}",
                 if keys.is_empty() { "If it exists, this" } else { "This" },
@@ -258,6 +782,61 @@ impl ShowKeysCmd {
     }
 }
 
+/// Write the target chain full access keys of a set of source chain accounts to a JSON
+/// file, as a list of key files, e.g. to fund the accounts before a run
+#[derive(clap::Parser)]
+struct MaterializeKeysCmd {
+    /// file listing one source chain account ID per line, optionally followed
+    /// by the source chain full access public keys of that account. Accounts
+    /// listed without keys get the default extra key, or their mapped key
+    /// if they're NEAR-implicit. Empty lines and lines starting with '#' are
+    /// skipped
+    #[clap(long)]
+    accounts_file: PathBuf,
+    /// file containing an optional secret as generated by the
+    /// `prepare` command.
+    #[clap(long)]
+    secret_file: Option<PathBuf>,
+    /// JSON file mapping source chain account IDs to the target chain
+    /// account IDs they are renamed to, as given to `run`
+    #[clap(long)]
+    account_map: Option<PathBuf>,
+    /// where to write the key files
+    #[clap(long)]
+    out: PathBuf,
+}
+
+impl MaterializeKeysCmd {
+    fn run(self) -> anyhow::Result<()> {
+        let secret = match &self.secret_file {
+            Some(secret_file) => crate::secret::load(secret_file)
+                .with_context(|| format!("Failed to load secret from {:?}", secret_file))?,
+            None => crate::secret::MirrorSecret::default(),
+        };
+        let accounts = std::fs::read_to_string(&self.accounts_file)
+            .with_context(|| format!("Failed to read {:?}", &self.accounts_file))?;
+        let account_map = load_account_map(self.account_map.as_deref())?;
+        let keys = crate::key_util::materialize_keys(
+            &accounts,
+            &account_map,
+            secret.secret.as_ref(),
+            secret.target_key_type,
+        )?;
+
+        // the file holds secret keys, so only make it readable by the owner
+        let mut options = std::fs::File::options();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file =
+            options.open(&self.out).with_context(|| format!("Failed to create {:?}", &self.out))?;
+        std::io::Write::write_all(&mut file, &serde_json::to_vec_pretty(&keys)?)
+            .with_context(|| format!("Failed to write {:?}", &self.out))?;
+        println!("wrote {} keys to {}", keys.len(), self.out.display());
+        Ok(())
+    }
+}
+
 // copied from neard/src/cli.rs
 fn new_actix_system(runtime: tokio::runtime::Runtime) -> actix::SystemRunner {
     // `with_tokio_rt()` accepts an `Fn()->Runtime`, however we know that this function is called exactly once.
@@ -271,14 +850,17 @@ fn new_actix_system(runtime: tokio::runtime::Runtime) -> actix::SystemRunner {
     })
 }
 
-fn run_async<F: std::future::Future + 'static>(f: F) -> F::Output {
+fn run_async<F: std::future::Future + 'static>(
+    log_format: near_o11y::LogFormat,
+    f: F,
+) -> F::Output {
     let runtime = tokio::runtime::Runtime::new().unwrap();
     let system = new_actix_system(runtime);
     system
         .block_on(async move {
             let _subscriber_guard = near_o11y::default_subscriber(
                 near_o11y::EnvFilterBuilder::from_env().finish().unwrap(),
-                &near_o11y::Options::default(),
+                &near_o11y::Options::default().with_log_format(log_format),
             )
             .global();
             actix::spawn(f).await
@@ -293,7 +875,11 @@ impl MirrorCommand {
         match self.subcmd {
             SubCommand::Prepare(r) => r.run(),
             SubCommand::Run(r) => r.run(),
+            SubCommand::ShowConfig(r) => r.run(),
             SubCommand::ShowKeys(r) => r.run(),
+            SubCommand::MaterializeKeys(r) => r.run(),
+            SubCommand::Status(r) => r.run(),
+            SubCommand::Verify(r) => r.run(),
         }
     }
 }
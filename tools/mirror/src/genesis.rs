@@ -1,45 +1,141 @@
-use near_crypto::PublicKey;
+use crate::account_map::AccountMap;
+use near_crypto::{KeyType, PublicKey};
 use near_primitives::action::delegate::{DelegateAction, SignedDelegateAction};
 use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::state_record::StateRecord;
-use near_primitives::transaction::{Action, AddKeyAction, DeleteAccountAction, DeleteKeyAction};
+use near_primitives::transaction::{
+    Action, AddKeyAction, DeleteAccountAction, DeleteKeyAction, FunctionCallAction,
+};
+use near_primitives::types::AccountId;
 use near_primitives_core::account::id::AccountType;
 use near_primitives_core::account::{AccessKey, AccessKeyPermission};
 use serde::ser::{SerializeSeq, Serializer};
 use std::collections::HashSet;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
+/// Compression of a records file, going by its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+// Opens the records file at `path`, decompressing it as we read it if it's compressed.
+fn open_records_in(path: &Path) -> anyhow::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    Ok(match Compression::from_path(path) {
+        // MultiGzDecoder so that files made by concatenating several gzip streams are read whole
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        Compression::None => Box::new(file),
+    })
+}
+
+/// Where the mapped records are written, compressed the same way as the input if the output
+/// file's extension says so.
+enum RecordsOut {
+    Plain(Box<dyn Write>),
+    Gzip(flate2::write::GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl RecordsOut {
+    fn create(path: &Path) -> anyhow::Result<Self> {
+        if path == Path::new("-") {
+            return Ok(Self::Plain(Box::new(std::io::stdout().lock())));
+        }
+        let file = File::create(path)?;
+        Ok(match Compression::from_path(path) {
+            Compression::Gzip => {
+                Self::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Compression::Zstd => Self::Zstd(zstd::Encoder::new(file, 0)?),
+            Compression::None => Self::Plain(Box::new(file)),
+        })
+    }
+
+    // Writes out whatever the encoder still has buffered, along with the end of the stream.
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(mut w) => w.flush(),
+            Self::Gzip(e) => e.finish()?.flush(),
+            Self::Zstd(e) => e.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for RecordsOut {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(w) => w.write(buf),
+            Self::Gzip(e) => e.write(buf),
+            Self::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(w) => w.flush(),
+            Self::Gzip(e) => e.flush(),
+            Self::Zstd(e) => e.flush(),
+        }
+    }
+}
+
+// `receiver_id` is the source chain account the action is applied to
 fn map_action(
     action: &Action,
+    receiver_id: &AccountId,
+    account_map: &AccountMap,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
     default_key: &PublicKey,
     delegate_allowed: bool,
 ) -> Option<Action> {
+    let key_type = crate::key_mapping::account_key_type(receiver_id, target_key_type);
     match action {
         Action::AddKey(add_key) => {
-            let public_key = crate::key_mapping::map_key(&add_key.public_key, secret).public_key();
+            let public_key =
+                crate::key_mapping::map_key(&add_key.public_key, secret, key_type).public_key();
 
             Some(Action::AddKey(Box::new(AddKeyAction {
                 public_key,
-                access_key: add_key.access_key.clone(),
+                access_key: account_map.map_access_key(&add_key.access_key),
             })))
         }
         Action::DeleteKey(delete_key) => {
             let public_key =
-                crate::key_mapping::map_key(&delete_key.public_key, secret).public_key();
+                crate::key_mapping::map_key(&delete_key.public_key, secret, key_type).public_key();
 
             Some(Action::DeleteKey(Box::new(DeleteKeyAction { public_key })))
         }
         Action::DeleteAccount(delete_account) => {
-            let beneficiary_id =
-                crate::key_mapping::map_account(&delete_account.beneficiary_id, secret);
+            let beneficiary_id = account_map.map_account(&delete_account.beneficiary_id, secret);
             Some(Action::DeleteAccount(DeleteAccountAction { beneficiary_id }))
         }
+        Action::FunctionCall(call) => match account_map.map_args(&call.args) {
+            Some(args) => Some(Action::FunctionCall(Box::new(FunctionCallAction {
+                args,
+                ..(**call).clone()
+            }))),
+            None => Some(action.clone()),
+        },
         Action::Delegate(delegate) => {
             if delegate_allowed {
-                map_delegate_action(delegate, secret, default_key)
+                map_delegate_action(delegate, account_map, secret, target_key_type, default_key)
             } else {
                 // This should not happen, but we handle the case here defensively
                 tracing::warn!(target: "mirror", "a delegate action was contained inside another delegate action: {:?}", delegate);
@@ -52,9 +148,14 @@ fn map_action(
     }
 }
 
-fn map_delegate_action(
+/// Maps the account IDs and keys in the delegate action and the actions it contains, and signs
+/// it again with the mapped key. `default_key` is added to accounts it creates without a full
+/// access key.
+pub(crate) fn map_delegate_action(
     delegate: &SignedDelegateAction,
+    account_map: &AccountMap,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
     default_key: &PublicKey,
 ) -> Option<Action> {
     let source_actions = delegate.delegate_action.get_actions();
@@ -63,7 +164,15 @@ fn map_delegate_action(
     let mut account_created = false;
     let mut full_key_added = false;
     for action in source_actions.iter() {
-        if let Some(a) = map_action(action, secret, default_key, false) {
+        if let Some(a) = map_action(
+            action,
+            &delegate.delegate_action.receiver_id,
+            account_map,
+            secret,
+            target_key_type,
+            default_key,
+            false,
+        ) {
             match &a {
                 Action::AddKey(add_key) => {
                     if add_key.access_key.permission == AccessKeyPermission::FullAccess {
@@ -91,10 +200,14 @@ fn map_delegate_action(
             .unwrap(),
         );
     }
-    let mapped_key = crate::key_mapping::map_key(&delegate.delegate_action.public_key, secret);
+    let mapped_key = crate::key_mapping::map_key(
+        &delegate.delegate_action.public_key,
+        secret,
+        crate::key_mapping::account_key_type(&delegate.delegate_action.sender_id, target_key_type),
+    );
     let mapped_action = DelegateAction {
-        sender_id: crate::key_mapping::map_account(&delegate.delegate_action.sender_id, secret),
-        receiver_id: crate::key_mapping::map_account(&delegate.delegate_action.receiver_id, secret),
+        sender_id: account_map.map_account(&delegate.delegate_action.sender_id, secret),
+        receiver_id: account_map.map_account(&delegate.delegate_action.receiver_id, secret),
         actions,
         nonce: delegate.delegate_action.nonce,
         max_block_height: delegate.delegate_action.max_block_height,
@@ -111,21 +224,30 @@ fn map_delegate_action(
 // map all the account IDs and keys in this receipt and its actions, and skip any stake actions
 fn map_action_receipt(
     receipt: &mut ActionReceipt,
+    receiver_id: &AccountId,
+    account_map: &AccountMap,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
     default_key: &PublicKey,
 ) {
-    receipt.signer_id = crate::key_mapping::map_account(&receipt.signer_id, secret);
-    receipt.signer_public_key =
-        crate::key_mapping::map_key(&receipt.signer_public_key, secret).public_key();
+    receipt.signer_public_key = crate::key_mapping::map_key(
+        &receipt.signer_public_key,
+        secret,
+        crate::key_mapping::account_key_type(&receipt.signer_id, target_key_type),
+    )
+    .public_key();
+    receipt.signer_id = account_map.map_account(&receipt.signer_id, secret);
     for receiver in receipt.output_data_receivers.iter_mut() {
-        receiver.receiver_id = crate::key_mapping::map_account(&receiver.receiver_id, secret);
+        receiver.receiver_id = account_map.map_account(&receiver.receiver_id, secret);
     }
 
     let mut actions = Vec::with_capacity(receipt.actions.len());
     let mut account_created = false;
     let mut full_key_added = false;
     for action in receipt.actions.iter() {
-        if let Some(a) = map_action(action, secret, default_key, true) {
+        if let Some(a) =
+            map_action(action, receiver_id, account_map, secret, target_key_type, default_key, true)
+        {
             match &a {
                 Action::AddKey(add_key) => {
                     if add_key.access_key.permission == AccessKeyPermission::FullAccess {
@@ -153,50 +275,79 @@ fn map_action_receipt(
 pub fn map_receipt(
     receipt: &mut Receipt,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+    default_key: &PublicKey,
+) {
+    map_receipt_with_account_map(
+        receipt,
+        &AccountMap::default(),
+        secret,
+        target_key_type,
+        default_key,
+    );
+}
+
+// same as map_receipt(), renaming the accounts in `account_map`
+fn map_receipt_with_account_map(
+    receipt: &mut Receipt,
+    account_map: &AccountMap,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
     default_key: &PublicKey,
 ) {
-    receipt.set_predecessor_id(crate::key_mapping::map_account(receipt.predecessor_id(), secret));
-    receipt.set_receiver_id(crate::key_mapping::map_account(receipt.receiver_id(), secret));
+    let receiver_id = receipt.receiver_id().clone();
+    receipt.set_predecessor_id(account_map.map_account(receipt.predecessor_id(), secret));
+    receipt.set_receiver_id(account_map.map_account(&receiver_id, secret));
     match receipt.receipt_mut() {
         ReceiptEnum::Action(r) | ReceiptEnum::PromiseYield(r) => {
-            map_action_receipt(r, secret, default_key);
+            map_action_receipt(r, &receiver_id, account_map, secret, target_key_type, default_key);
         }
         _ => {}
     }
 }
 
 /// Reads records, makes changes to them and writes them to a new file.
-/// `records_file_in` must be different from `records_file_out`.
-/// Writes a secret to `secret_file_out`.
+/// `records_file_in` must be different from `records_file_out`. If
+/// `records_file_out` is "-", the records are streamed to stdout instead.
+/// Writes a secret to `secret_file_out`, along with `target_key_type` if given,
+/// which forces all mapped keys to that type. Accounts in `account_map` are
+/// renamed in all the records that refer to them.
 pub(crate) fn map_records<P: AsRef<Path>>(
     records_file_in: P,
     records_file_out: P,
     no_secret: bool,
     secret_file_out: P,
+    target_key_type: Option<KeyType>,
+    account_map: &AccountMap,
 ) -> anyhow::Result<()> {
     let secret = if no_secret {
-        crate::secret::write_empty(secret_file_out)?;
+        crate::secret::write_empty(secret_file_out, target_key_type)?;
         None
     } else {
-        Some(crate::secret::generate(secret_file_out)?)
+        Some(crate::secret::generate(secret_file_out, target_key_type)?)
     };
-    let reader = BufReader::new(File::open(records_file_in)?);
-    let records_out = BufWriter::new(File::create(records_file_out)?);
-    let mut records_ser = serde_json::Serializer::new(records_out);
+    let reader = BufReader::new(open_records_in(records_file_in.as_ref())?);
+    let records_out = RecordsOut::create(records_file_out.as_ref())?;
+    let mut records_ser = serde_json::Serializer::new(BufWriter::new(records_out));
     let mut records_seq = records_ser.serialize_seq(None).unwrap();
 
     let mut has_full_key = HashSet::new();
     let mut accounts = HashSet::new();
 
-    let default_key = crate::key_mapping::default_extra_key(secret.as_ref()).public_key();
+    let default_key =
+        crate::key_mapping::default_extra_key(secret.as_ref(), target_key_type).public_key();
     near_chain_configs::stream_records_from_file(reader, |mut r| {
         match &mut r {
             StateRecord::AccessKey { account_id, public_key, access_key } => {
-                let replacement = crate::key_mapping::map_key(&public_key, secret.as_ref());
+                let replacement = crate::key_mapping::map_key(
+                    &public_key,
+                    secret.as_ref(),
+                    crate::key_mapping::account_key_type(&account_id, target_key_type),
+                );
                 let new_record = StateRecord::AccessKey {
-                    account_id: crate::key_mapping::map_account(&account_id, secret.as_ref()),
+                    account_id: account_map.map_account(&account_id, secret.as_ref()),
                     public_key: replacement.public_key(),
-                    access_key: access_key.clone(),
+                    access_key: account_map.map_access_key(access_key),
                 };
                 // TODO(eth-implicit) Change back to is_implicit() when ETH-implicit accounts are supported.
                 if account_id.get_account_type() != AccountType::NearImplicitAccount
@@ -210,40 +361,42 @@ pub(crate) fn map_records<P: AsRef<Path>>(
             }
             StateRecord::Account { account_id, .. } => {
                 // TODO(eth-implicit) Change back to is_implicit() when ETH-implicit accounts are supported.
-                if account_id.get_account_type() == AccountType::NearImplicitAccount {
-                    *account_id = crate::key_mapping::map_account(&account_id, secret.as_ref());
-                } else {
+                if account_id.get_account_type() != AccountType::NearImplicitAccount {
                     accounts.insert(account_id.clone());
                 }
+                *account_id = account_map.map_account(&account_id, secret.as_ref());
                 records_seq.serialize_element(&r).unwrap();
             }
             StateRecord::Data { account_id, .. } => {
-                // TODO(eth-implicit) Change back to is_implicit() when ETH-implicit accounts are supported.
-                if account_id.get_account_type() == AccountType::NearImplicitAccount {
-                    *account_id = crate::key_mapping::map_account(&account_id, secret.as_ref());
-                }
+                *account_id = account_map.map_account(&account_id, secret.as_ref());
                 records_seq.serialize_element(&r).unwrap();
             }
             StateRecord::Contract { account_id, .. } => {
-                // TODO(eth-implicit) Change back to is_implicit() when ETH-implicit accounts are supported.
-                if account_id.get_account_type() == AccountType::NearImplicitAccount {
-                    *account_id = crate::key_mapping::map_account(&account_id, secret.as_ref());
-                }
+                *account_id = account_map.map_account(&account_id, secret.as_ref());
                 records_seq.serialize_element(&r).unwrap();
             }
             StateRecord::PostponedReceipt(receipt) => {
-                map_receipt(receipt, secret.as_ref(), &default_key);
+                map_receipt_with_account_map(
+                    receipt,
+                    account_map,
+                    secret.as_ref(),
+                    target_key_type,
+                    &default_key,
+                );
                 records_seq.serialize_element(&r).unwrap();
             }
             StateRecord::ReceivedData { account_id, .. } => {
-                // TODO(eth-implicit) Change back to is_implicit() when ETH-implicit accounts are supported.
-                if account_id.get_account_type() == AccountType::NearImplicitAccount {
-                    *account_id = crate::key_mapping::map_account(&account_id, secret.as_ref());
-                }
+                *account_id = account_map.map_account(&account_id, secret.as_ref());
                 records_seq.serialize_element(&r).unwrap();
             }
             StateRecord::DelayedReceipt(receipt) => {
-                map_receipt(&mut receipt.receipt, secret.as_ref(), &default_key);
+                map_receipt_with_account_map(
+                    &mut receipt.receipt,
+                    account_map,
+                    secret.as_ref(),
+                    target_key_type,
+                    &default_key,
+                );
                 records_seq.serialize_element(&r).unwrap();
             }
         };
@@ -252,29 +405,56 @@ pub(crate) fn map_records<P: AsRef<Path>>(
     for account_id in accounts {
         if !has_full_key.contains(&account_id) {
             records_seq.serialize_element(&StateRecord::AccessKey {
-                account_id,
+                account_id: account_map.map_account(&account_id, secret.as_ref()),
                 public_key: default_key.clone(),
                 access_key: AccessKey::full_access(),
             })?;
         }
     }
     records_seq.end()?;
+    records_ser.into_inner().into_inner().map_err(|e| e.into_error())?.finish()?;
     Ok(())
 }
 
 #[cfg(test)]
 mod test {
+    use crate::account_map::AccountMap;
     use near_crypto::{KeyType, SecretKey};
     use near_primitives::account::{AccessKeyPermission, FunctionCallPermission};
     use near_primitives::action::delegate::{DelegateAction, SignedDelegateAction};
     use near_primitives::hash::CryptoHash;
     use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum, ReceiptV0};
-    use near_primitives::transaction::{Action, AddKeyAction, CreateAccountAction};
-    use near_primitives_core::account::AccessKey;
+    use near_primitives::state_record::StateRecord;
+    use near_primitives::transaction::{
+        Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, FunctionCallAction,
+    };
+    use near_primitives::types::AccountId;
+    use near_primitives_core::account::{AccessKey, Account, AccountContract};
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_compressed_records_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let records = b"[{\"Account\": {}}]";
+        for name in ["records.json", "records.json.gz", "records.json.zst"] {
+            let path = dir.path().join(name);
+            let mut out = super::RecordsOut::create(&path).unwrap();
+            out.write_all(records).unwrap();
+            out.finish().unwrap();
+
+            let written = std::fs::read(&path).unwrap();
+            assert_eq!(written == records, name == "records.json", "{}", name);
+
+            let mut read = Vec::new();
+            super::open_records_in(&path).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(read, records, "{}", name);
+        }
+    }
 
     #[test]
     fn test_map_receipt() {
-        let default_key = crate::key_mapping::default_extra_key(None).public_key();
+        let default_key = crate::key_mapping::default_extra_key(None, None).public_key();
 
         let mut receipt0 = Receipt::V0(ReceiptV0 {
             predecessor_id: "foo.near".parse().unwrap(),
@@ -389,7 +569,7 @@ mod test {
             }),
         });
 
-        let mapped_secret_key = crate::key_mapping::map_key(&secret_key.public_key(), None);
+        let mapped_secret_key = crate::key_mapping::map_key(&secret_key.public_key(), None, None);
         let delegate_action = DelegateAction {
             sender_id: "799185fe8173d8adf46b0c088d57887b2550642c08aafdc20ccce67b5ad51976"
                 .parse()
@@ -436,9 +616,176 @@ mod test {
             }),
         });
 
-        crate::genesis::map_receipt(&mut receipt0, None, &default_key);
+        crate::genesis::map_receipt(&mut receipt0, None, None, &default_key);
         assert_eq!(receipt0, want_receipt0);
-        crate::genesis::map_receipt(&mut receipt1, None, &default_key);
+        crate::genesis::map_receipt(&mut receipt1, None, None, &default_key);
         assert_eq!(receipt1, want_receipt1);
     }
+
+    // Runs `prepare` on a few records referring to renamed accounts, and checks that they're
+    // renamed everywhere, including inside actions.
+    #[test]
+    fn test_map_records_with_account_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let account_map = AccountMap::new(BTreeMap::from([
+            (alice.clone(), "alice.testnet".parse().unwrap()),
+            (bob.clone(), "bob.testnet".parse().unwrap()),
+        ]))
+        .unwrap();
+
+        let fn_call_key = SecretKey::from_seed(KeyType::ED25519, "fn_call").public_key();
+        let full_key = SecretKey::from_seed(KeyType::ED25519, "full").public_key();
+        let delegate_key = SecretKey::from_seed(KeyType::ED25519, "delegate");
+        let function_call = |args: &str| {
+            Action::FunctionCall(Box::new(FunctionCallAction {
+                method_name: String::from("transfer"),
+                args: args.as_bytes().to_vec(),
+                gas: 1_000_000,
+                deposit: 0,
+            }))
+        };
+        let delegate_action = DelegateAction {
+            sender_id: bob.clone(),
+            receiver_id: alice.clone(),
+            actions: vec![function_call(r#"{"to":"bob.near"}"#).try_into().unwrap()],
+            nonce: 1,
+            max_block_height: 1234,
+            public_key: delegate_key.public_key(),
+        };
+        let signature = delegate_key.sign(delegate_action.get_nep461_hash().as_ref());
+        let records = vec![
+            StateRecord::Account {
+                account_id: alice.clone(),
+                account: Account::new(100, 0, AccountContract::None, 0),
+            },
+            StateRecord::AccessKey {
+                account_id: alice.clone(),
+                public_key: fn_call_key.clone(),
+                access_key: AccessKey {
+                    nonce: 0,
+                    permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                        allowance: None,
+                        receiver_id: bob.to_string(),
+                        method_names: vec![],
+                    }),
+                },
+            },
+            StateRecord::Account {
+                account_id: bob.clone(),
+                account: Account::new(100, 0, AccountContract::None, 0),
+            },
+            StateRecord::AccessKey {
+                account_id: bob.clone(),
+                public_key: full_key.clone(),
+                access_key: AccessKey::full_access(),
+            },
+            StateRecord::PostponedReceipt(Box::new(Receipt::V0(ReceiptV0 {
+                predecessor_id: bob.clone(),
+                receiver_id: alice.clone(),
+                receipt_id: CryptoHash::default(),
+                receipt: ReceiptEnum::Action(ActionReceipt {
+                    signer_id: bob.clone(),
+                    signer_public_key: full_key.clone(),
+                    gas_price: 100,
+                    output_data_receivers: vec![],
+                    input_data_ids: vec![],
+                    actions: vec![
+                        function_call(r#"{"memo":"bob.near","to":"bob.near"}"#),
+                        Action::Delegate(Box::new(SignedDelegateAction {
+                            delegate_action,
+                            signature,
+                        })),
+                        Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob.clone() }),
+                    ],
+                }),
+            }))),
+        ];
+        let records_in = dir.path().join("records_in.json");
+        let records_out = dir.path().join("records_out.json");
+        std::fs::write(&records_in, serde_json::to_vec(&records).unwrap()).unwrap();
+        crate::genesis::map_records(
+            &records_in,
+            &records_out,
+            true,
+            &dir.path().join("secret.json"),
+            None,
+            &account_map,
+        )
+        .unwrap();
+        let records: Vec<StateRecord> =
+            serde_json::from_slice(&std::fs::read(&records_out).unwrap()).unwrap();
+
+        let default_key = crate::key_mapping::default_extra_key(None, None).public_key();
+        let mapped_delegate_key =
+            crate::key_mapping::map_key(&delegate_key.public_key(), None, None);
+        let mapped_delegate_action = DelegateAction {
+            sender_id: "bob.testnet".parse().unwrap(),
+            receiver_id: "alice.testnet".parse().unwrap(),
+            actions: vec![function_call(r#"{"to":"bob.testnet"}"#).try_into().unwrap()],
+            nonce: 1,
+            max_block_height: 1234,
+            public_key: mapped_delegate_key.public_key(),
+        };
+        let mapped_signature =
+            mapped_delegate_key.sign(mapped_delegate_action.get_nep461_hash().as_ref());
+        let want_records = vec![
+            StateRecord::Account {
+                account_id: "alice.testnet".parse().unwrap(),
+                account: Account::new(100, 0, AccountContract::None, 0),
+            },
+            StateRecord::AccessKey {
+                account_id: "alice.testnet".parse().unwrap(),
+                public_key: crate::key_mapping::map_key(&fn_call_key, None, None).public_key(),
+                access_key: AccessKey {
+                    nonce: 0,
+                    permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                        allowance: None,
+                        receiver_id: String::from("bob.testnet"),
+                        method_names: vec![],
+                    }),
+                },
+            },
+            StateRecord::Account {
+                account_id: "bob.testnet".parse().unwrap(),
+                account: Account::new(100, 0, AccountContract::None, 0),
+            },
+            StateRecord::AccessKey {
+                account_id: "bob.testnet".parse().unwrap(),
+                public_key: crate::key_mapping::map_key(&full_key, None, None).public_key(),
+                access_key: AccessKey::full_access(),
+            },
+            StateRecord::PostponedReceipt(Box::new(Receipt::V0(ReceiptV0 {
+                predecessor_id: "bob.testnet".parse().unwrap(),
+                receiver_id: "alice.testnet".parse().unwrap(),
+                receipt_id: CryptoHash::default(),
+                receipt: ReceiptEnum::Action(ActionReceipt {
+                    signer_id: "bob.testnet".parse().unwrap(),
+                    signer_public_key: crate::key_mapping::map_key(&full_key, None, None)
+                        .public_key(),
+                    gas_price: 100,
+                    output_data_receivers: vec![],
+                    input_data_ids: vec![],
+                    actions: vec![
+                        function_call(r#"{"memo":"bob.near","to":"bob.testnet"}"#),
+                        Action::Delegate(Box::new(SignedDelegateAction {
+                            delegate_action: mapped_delegate_action,
+                            signature: mapped_signature,
+                        })),
+                        Action::DeleteAccount(DeleteAccountAction {
+                            beneficiary_id: "bob.testnet".parse().unwrap(),
+                        }),
+                    ],
+                }),
+            }))),
+            // alice.near has no full access key, so it gets the default extra key.
+            StateRecord::AccessKey {
+                account_id: "alice.testnet".parse().unwrap(),
+                public_key: default_key,
+                access_key: AccessKey::full_access(),
+            },
+        ];
+        assert_eq!(records, want_records);
+    }
 }
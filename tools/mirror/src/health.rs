@@ -0,0 +1,117 @@
+use actix_web::{App, HttpResponse, HttpServer, web};
+use near_primitives::types::BlockHeight;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Default for the longest time we consider the mirror ready without seeing the target chain
+// height advance, used if not set in the mirror config.
+pub(crate) const DEFAULT_READINESS_STALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Default)]
+struct Progress {
+    source_initialized: bool,
+    // last target chain height we saw, and when we first saw it
+    target_height: Option<(BlockHeight, Instant)>,
+}
+
+/// Progress of the mirror, as reported by the `/readyz` endpoint. The target chain
+/// height is also exported as the `near_mirror_target_height` metric. Also tells the
+/// transaction sending loop when to pause because the target chain stopped advancing.
+pub(crate) struct HealthState {
+    progress: Mutex<Progress>,
+    // longest time we consider the mirror ready without seeing the target chain height advance
+    stall_timeout: Mutex<Duration>,
+    // if set, longest time we keep sending transactions without seeing the target chain
+    // height advance
+    pause_timeout: Mutex<Option<Duration>>,
+}
+
+impl HealthState {
+    pub(crate) fn new(stall_timeout: Duration, pause_timeout: Option<Duration>) -> Self {
+        Self {
+            progress: Mutex::default(),
+            stall_timeout: Mutex::new(stall_timeout),
+            pause_timeout: Mutex::new(pause_timeout),
+        }
+    }
+
+    pub(crate) fn set_stall_timeout(&self, stall_timeout: Duration) {
+        *self.stall_timeout.lock().unwrap() = stall_timeout;
+    }
+
+    pub(crate) fn set_pause_timeout(&self, pause_timeout: Option<Duration>) {
+        *self.pause_timeout.lock().unwrap() = pause_timeout;
+    }
+
+    /// Returns the target chain height and how long it has been stuck there, if that's
+    /// longer than the pause timeout, meaning we should stop sending transactions.
+    pub(crate) fn target_stall(&self) -> Option<(BlockHeight, Duration)> {
+        let pause_timeout = (*self.pause_timeout.lock().unwrap())?;
+        let (height, updated_at) = self.progress.lock().unwrap().target_height?;
+        let stalled_for = updated_at.elapsed();
+        (stalled_for > pause_timeout).then_some((height, stalled_for))
+    }
+
+    pub(crate) fn on_source_initialized(&self) {
+        self.progress.lock().unwrap().source_initialized = true;
+    }
+
+    pub(crate) fn on_target_height(&self, height: BlockHeight) {
+        crate::metrics::TARGET_HEIGHT.set(height as i64);
+        let mut progress = self.progress.lock().unwrap();
+        match progress.target_height {
+            Some((h, _)) if h >= height => {}
+            _ => progress.target_height = Some((height, Instant::now())),
+        }
+    }
+
+    // Returns the reason we're not ready, or None if we are.
+    fn not_ready_reason(&self) -> Option<String> {
+        let stall_timeout = *self.stall_timeout.lock().unwrap();
+        let progress = self.progress.lock().unwrap();
+        if !progress.source_initialized {
+            return Some("source chain not initialized yet".to_string());
+        }
+        match progress.target_height {
+            None => Some("no target chain blocks seen yet".to_string()),
+            Some((height, updated_at)) if updated_at.elapsed() > stall_timeout => Some(format!(
+                "target chain height stuck at {} for {:?}",
+                height,
+                updated_at.elapsed()
+            )),
+            Some(_) => None,
+        }
+    }
+}
+
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().body("ok\n")
+}
+
+async fn readyz(state: web::Data<Arc<HealthState>>) -> HttpResponse {
+    match state.not_ready_reason() {
+        None => HttpResponse::Ok().body("ok\n"),
+        Some(reason) => HttpResponse::ServiceUnavailable().body(format!("{}\n", reason)),
+    }
+}
+
+/// Serves `/healthz`, which succeeds as long as the process is up, and `/readyz`, which
+/// succeeds once the source chain is initialized and as long as the target chain height
+/// has advanced within the last stall timeout the `HealthState` was given.
+pub(crate) fn spawn_server(addr: SocketAddr, state: Arc<HealthState>) -> anyhow::Result<()> {
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+    })
+    .bind(addr)?
+    .workers(1)
+    .shutdown_timeout(3)
+    .disable_signals()
+    .run();
+    tokio::spawn(server);
+    tracing::info!(target: "mirror", "serving health checks on {}", addr);
+    Ok(())
+}
@@ -1,6 +1,6 @@
 // cspell:words hkdf
 use hkdf::Hkdf;
-use near_crypto::{ED25519PublicKey, ED25519SecretKey, PublicKey, Secp256K1PublicKey, SecretKey};
+use near_crypto::{ED25519SecretKey, KeyType, PublicKey, SecretKey};
 use near_primitives::types::AccountId;
 use near_primitives::utils::derive_near_implicit_account_id;
 use near_primitives_core::account::id::AccountType;
@@ -17,36 +17,57 @@ const DEFAULT_EXTRA_KEY: SecretKey = SecretKey::ED25519(ED25519SecretKey([
     145,
 ]));
 
-pub fn default_extra_key(secret: Option<&[u8; crate::secret::SECRET_LEN]>) -> SecretKey {
-    match secret {
-        Some(s) => map_key(&DEFAULT_EXTRA_KEY.public_key(), Some(s)),
-        None => DEFAULT_EXTRA_KEY,
+// `target_key_type` below is the key type given to the `prepare` command, if any. All keys
+// are then mapped to keys of that type, regardless of the type of the source key. This has to
+// be the same for every command that maps keys for a given target chain, so it's recorded in
+// the secret file written by `prepare`, and read back from it along with the secret.
+
+pub fn default_extra_key(
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> SecretKey {
+    match (secret, target_key_type.unwrap_or(KeyType::ED25519)) {
+        (None, KeyType::ED25519) => DEFAULT_EXTRA_KEY,
+        _ => map_key(&DEFAULT_EXTRA_KEY.public_key(), secret, target_key_type),
     }
 }
 
-fn ed25519_map_secret(
-    buf: &mut [u8],
-    public: &ED25519PublicKey,
-    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
-) {
+// The type of key to map the access keys of `account_id` to. NEAR-implicit account IDs are
+// derived from ed25519 keys (see map_account()), and the target chain account created by a
+// transfer to one gets the key its ID was derived from, so if a target key type is set,
+// their keys are mapped to ed25519 keys regardless of it. Otherwise the keys we sign with
+// would not match the ones the target chain account actually has.
+pub fn account_key_type(
+    account_id: &AccountId,
+    target_key_type: Option<KeyType>,
+) -> Option<KeyType> {
+    match (account_id.get_account_type(), target_key_type) {
+        (AccountType::NearImplicitAccount, Some(_)) => Some(KeyType::ED25519),
+        _ => target_key_type,
+    }
+}
+
+// Fills `buf` with the bytes of the mapped secret key. `public` is the data of the source chain
+// public key, which may be of a different type than the key we're mapping it to.
+fn map_secret(buf: &mut [u8], public: &[u8], secret: Option<&[u8; crate::secret::SECRET_LEN]>) {
     match secret {
         Some(secret) => {
             let hk = Hkdf::<Sha256>::new(None, secret);
-            hk.expand(&public.0, buf).unwrap();
+            hk.expand(public, buf).unwrap();
         }
         None => {
-            buf.copy_from_slice(&public.0);
+            buf.copy_from_slice(&public[..buf.len()]);
         }
     };
 }
 
 fn map_ed25519(
-    public: &ED25519PublicKey,
+    public: &[u8],
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
 ) -> ED25519SecretKey {
     let mut buf = [0; ed25519_dalek::KEYPAIR_LENGTH];
 
-    ed25519_map_secret(&mut buf[..ed25519_dalek::SECRET_KEY_LENGTH], public, secret);
+    map_secret(&mut buf[..ed25519_dalek::SECRET_KEY_LENGTH], public, secret);
 
     let secret_key = ed25519_dalek::SigningKey::from_bytes(
         <&[u8; ed25519_dalek::SECRET_KEY_LENGTH]>::try_from(
@@ -60,35 +81,39 @@ fn map_ed25519(
     ED25519SecretKey(buf)
 }
 
-fn secp256k1_from_slice(buf: &mut [u8], public: &Secp256K1PublicKey) -> secp256k1::SecretKey {
+/// Returned when a source chain public key can't be mapped to a secret key we
+/// can sign target chain transactions with.
+#[derive(thiserror::Error, Debug)]
+pub enum UnmappableKeyError {
+    #[error("SECP256K1 key {0} does not map to a valid secret key")]
+    InvalidSecp256k1(PublicKey),
+}
+
+fn secp256k1_from_slice(
+    buf: &mut [u8],
+    public: &PublicKey,
+) -> Result<secp256k1::SecretKey, UnmappableKeyError> {
     match secp256k1::SecretKey::from_slice(buf) {
-        Ok(s) => s,
+        Ok(s) => Ok(s),
         Err(_) => {
             tracing::warn!(target: "mirror", "Something super unlikely occurred! SECP256K1 key mapped from {:?} is too large. Flipping most significant bit.", public);
             // If we got an error, it means that either `buf` is all zeros, or that when interpreted as a 256-bit
             // int, it is larger than the order of the secp256k1 curve. Since the order of the curve starts with 0xFF,
-            // in either case flipping the first bit should work, and we can unwrap() below.
+            // in either case flipping the first bit should work.
             buf[0] ^= 0x80;
-            secp256k1::SecretKey::from_slice(buf).unwrap()
+            secp256k1::SecretKey::from_slice(buf)
+                .map_err(|_| UnmappableKeyError::InvalidSecp256k1(public.clone()))
         }
     }
 }
 
 fn map_secp256k1(
-    public: &Secp256K1PublicKey,
+    public: &PublicKey,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
-) -> secp256k1::SecretKey {
+) -> Result<secp256k1::SecretKey, UnmappableKeyError> {
     let mut buf = [0; secp256k1::constants::SECRET_KEY_SIZE];
 
-    match secret {
-        Some(secret) => {
-            let hk = Hkdf::<Sha256>::new(None, secret);
-            hk.expand(public.as_ref(), &mut buf).unwrap();
-        }
-        None => {
-            buf.copy_from_slice(&public.as_ref()[..secp256k1::constants::SECRET_KEY_SIZE]);
-        }
-    };
+    map_secret(&mut buf, public.key_data(), secret);
 
     secp256k1_from_slice(&mut buf, public)
 }
@@ -96,18 +121,38 @@ fn map_secp256k1(
 // This maps the public key to a secret key so that we can sign
 // transactions on the target chain.  If secret is None, then we just
 // use the bytes of the public key directly, otherwise we feed the
-// public key to a key derivation function.
-pub fn map_key(key: &PublicKey, secret: Option<&[u8; crate::secret::SECRET_LEN]>) -> SecretKey {
-    match key {
-        PublicKey::ED25519(k) => SecretKey::ED25519(map_ed25519(k, secret)),
-        PublicKey::SECP256K1(k) => SecretKey::SECP256K1(map_secp256k1(k, secret)),
+// public key to a key derivation function. The mapped key has the
+// same type as `key` unless `target_key_type` is given, in which case
+// keys of the other type are mapped across, derived from the bytes of
+// the source key in the same way. Access keys of an account should be
+// mapped with the key type returned by account_key_type().
+pub fn try_map_key(
+    key: &PublicKey,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> Result<SecretKey, UnmappableKeyError> {
+    match target_key_type.unwrap_or_else(|| key.key_type()) {
+        KeyType::ED25519 => Ok(SecretKey::ED25519(map_ed25519(key.key_data(), secret))),
+        KeyType::SECP256K1 => Ok(SecretKey::SECP256K1(map_secp256k1(key, secret)?)),
     }
 }
 
+// Same as try_map_key(), but panics if the key can't be mapped.
+pub fn map_key(
+    key: &PublicKey,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> SecretKey {
+    try_map_key(key, secret, target_key_type).unwrap_or_else(|err| panic!("{}", err))
+}
+
 // If it's a NEAR-implicit account, interprets it as an ed25519 public key,
 // maps that and then returns the resulting implicit account. Otherwise does nothing.
 // We do this so that transactions creating an implicit account
 // by sending money will generate an account that we can control.
+// NEAR-implicit account IDs are always derived from ed25519 keys, so this
+// ignores the target key type, and account_key_type() maps the account's
+// access keys to ed25519 keys to match.
 // TODO: return a Cow<> since this usually just clones the account.
 pub fn map_account(
     account_id: &AccountId,
@@ -117,7 +162,7 @@ pub fn map_account(
         AccountType::NearImplicitAccount => {
             let public_key =
                 PublicKey::from_near_implicit_account(account_id).expect("must be implicit");
-            let mapped_key = map_key(&public_key, secret);
+            let mapped_key = SecretKey::ED25519(map_ed25519(public_key.key_data(), secret));
             derive_near_implicit_account_id(&mapped_key.public_key().unwrap_as_ed25519())
         }
         // TODO(eth-implicit) map to a new ETH address
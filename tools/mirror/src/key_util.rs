@@ -1,19 +1,105 @@
 use anyhow::Context;
 use near_epoch_manager::shard_assignment::{account_id_to_shard_id, shard_id_to_uid};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use near_chain::types::RuntimeAdapter;
 use near_chain::{ChainStore, ChainStoreAccess};
 use near_chain_configs::GenesisValidationMode;
-use near_crypto::{PublicKey, SecretKey};
+use near_chain_primitives::error::QueryError;
+use near_crypto::{KeyFile, KeyType, ParseKeyError, PublicKey, SecretKey};
 use near_epoch_manager::EpochManager;
+use near_jsonrpc_client_internal::JsonRpcClient;
+use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind};
+use near_jsonrpc_primitives::message::{Message, from_slice};
 use near_jsonrpc_primitives::types::query::{
-    QueryResponseKind as RpcQueryResponseKind, RpcQueryRequest,
+    QueryResponseKind as RpcQueryResponseKind, RpcQueryRequest, RpcQueryResponse,
 };
+use near_primitives::account::id::ParseAccountError;
 use near_primitives::types::{AccountId, BlockHeight, BlockId, BlockReference, Finality};
 use near_primitives::views::{AccessKeyPermissionView, QueryRequest, QueryResponseKind};
 use nearcore::{NightshadeRuntime, NightshadeRuntimeExt};
 
+/// Errors returned when looking up and mapping source chain access keys.
+#[derive(thiserror::Error, Debug)]
+pub enum KeyMappingError {
+    #[error("bad account ID {account_id:?}")]
+    BadAccountId {
+        account_id: String,
+        #[source]
+        source: ParseAccountError,
+    },
+    #[error("could not parse public key {public_key:?}")]
+    BadPublicKey {
+        public_key: String,
+        #[source]
+        source: ParseKeyError,
+    },
+    #[error("account {0} does not exist on the source chain")]
+    AccountNotFound(AccountId),
+    #[error("could not reach RPC node at {rpc_url}: {message}")]
+    RpcUnreachable { rpc_url: String, message: String },
+    #[error("RPC request to {rpc_url} failed: {message}")]
+    Rpc { rpc_url: String, message: String },
+    #[error("failed setting up RPC client certificate: {0:#}")]
+    ClientCert(anyhow::Error),
+    #[error("failed reading source chain DB: {0:#}")]
+    SourceDB(anyhow::Error),
+    #[error("source chain key {source_key} maps to {mapped_key}, not to {target_key}")]
+    KeyMismatch { source_key: PublicKey, target_key: PublicKey, mapped_key: PublicKey },
+}
+
+impl KeyMappingError {
+    /// Process exit code to use when a command fails with this error, so that
+    /// scripts can tell apart the failures they might want to handle.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::AccountNotFound(_) => 2,
+            Self::RpcUnreachable { .. } => 3,
+            Self::BadAccountId { .. } | Self::BadPublicKey { .. } => 4,
+            Self::KeyMismatch { .. } => 5,
+            Self::Rpc { .. } | Self::ClientCert(_) | Self::SourceDB(_) => 1,
+        }
+    }
+
+    fn rpc(rpc_url: &str, err: RpcQueryError) -> Self {
+        let rpc_url = rpc_url.to_string();
+        match err {
+            RpcQueryError::Send(err) => Self::RpcUnreachable { rpc_url, message: err.to_string() },
+            RpcQueryError::Rpc(err) => Self::Rpc { rpc_url, message: format!("{:?}", err) },
+        }
+    }
+}
+
+/// Error returned by `rpc_query()`.
+#[derive(Debug)]
+enum RpcQueryError {
+    /// The request never got a response from the node.
+    Send(awc::error::SendRequestError),
+    /// The node returned an error, or a response we couldn't parse.
+    Rpc(RpcError),
+}
+
+impl From<RpcError> for RpcQueryError {
+    fn from(err: RpcError) -> Self {
+        Self::Rpc(err)
+    }
+}
+
+pub(crate) fn parse_account_id(account_id: &str) -> Result<AccountId, KeyMappingError> {
+    account_id.parse().map_err(|source| KeyMappingError::BadAccountId {
+        account_id: account_id.to_string(),
+        source,
+    })
+}
+
+fn parse_public_key(public_key: &str) -> Result<PublicKey, KeyMappingError> {
+    public_key.parse().map_err(|source| KeyMappingError::BadPublicKey {
+        public_key: public_key.to_string(),
+        source,
+    })
+}
+
 pub(crate) struct SecretAccessKey {
     pub(crate) original_key: Option<PublicKey>,
     pub(crate) mapped_key: SecretKey,
@@ -22,10 +108,11 @@ pub(crate) struct SecretAccessKey {
 
 pub(crate) fn default_extra_key(
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
 ) -> SecretAccessKey {
     SecretAccessKey {
         original_key: None,
-        mapped_key: crate::key_mapping::default_extra_key(secret),
+        mapped_key: crate::key_mapping::default_extra_key(secret, target_key_type),
         permission: None,
     }
 }
@@ -33,29 +120,99 @@ pub(crate) fn default_extra_key(
 pub(crate) fn map_pub_key(
     public_key: &str,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
-) -> anyhow::Result<SecretAccessKey> {
-    let public_key: PublicKey = public_key.parse().context("Could not parse public key")?;
+    target_key_type: Option<KeyType>,
+) -> Result<SecretAccessKey, KeyMappingError> {
+    let public_key = parse_public_key(public_key)?;
     // we say original_key is None here because the user provided it on the command line in this case, so no need to print it again.
     Ok(SecretAccessKey {
         original_key: None,
-        mapped_key: crate::key_mapping::map_key(&public_key, secret),
+        mapped_key: crate::key_mapping::map_key(&public_key, secret, target_key_type),
         permission: None,
     })
 }
 
+/// Checks that `source_public_key` is the source chain key that maps to `target_public_key`.
+/// The mapping goes through a key derivation function, so there's no way to recover the source
+/// key from the target one, but this is enough to confirm a guess when looking into where a
+/// target chain transaction came from.
+pub(crate) fn verify_mapped_pub_key(
+    source_public_key: &str,
+    target_public_key: &str,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> Result<SecretAccessKey, KeyMappingError> {
+    let source_key = parse_public_key(source_public_key)?;
+    let target_key = parse_public_key(target_public_key)?;
+    let mapped_key = crate::key_mapping::map_key(&source_key, secret, target_key_type);
+    if mapped_key.public_key() != target_key {
+        return Err(KeyMappingError::KeyMismatch {
+            source_key,
+            target_key,
+            mapped_key: mapped_key.public_key(),
+        });
+    }
+    Ok(SecretAccessKey { original_key: Some(source_key), mapped_key, permission: None })
+}
+
+/// Returns the target chain full access keys of the accounts in `accounts`, which lists
+/// one source chain account ID per line, optionally followed by the source chain full access
+/// public keys of that account. Empty lines and lines starting with '#' are skipped.
+///
+/// The listed keys are mapped like `show-keys from-pub-key` does. A NEAR-implicit account
+/// listed without keys gets the mapping of the key it is derived from, and any other account
+/// listed without keys gets the default extra key, which is the full access key the mirror
+/// adds to accounts that don't have one. Account IDs are mapped to their target chain ones,
+/// renaming those in `account_map`.
+pub(crate) fn materialize_keys(
+    accounts: &str,
+    account_map: &crate::account_map::AccountMap,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> Result<Vec<KeyFile>, KeyMappingError> {
+    let mut key_files = Vec::new();
+    for line in accounts.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let source_account_id = parse_account_id(fields.next().unwrap())?;
+        let key_type = crate::key_mapping::account_key_type(&source_account_id, target_key_type);
+        let mut mapped_keys = fields
+            .map(|k| map_pub_key(k, secret, key_type).map(|k| k.mapped_key))
+            .collect::<Result<Vec<_>, _>>()?;
+        if mapped_keys.is_empty() {
+            let key = match PublicKey::from_near_implicit_account(&source_account_id) {
+                Ok(public_key) => crate::key_mapping::map_key(&public_key, secret, key_type),
+                Err(_) => crate::key_mapping::default_extra_key(secret, target_key_type),
+            };
+            mapped_keys.push(key);
+        }
+        let account_id = account_map.map_account(&source_account_id, secret);
+        key_files.extend(mapped_keys.into_iter().map(|secret_key| KeyFile {
+            account_id: account_id.clone(),
+            public_key: secret_key.public_key(),
+            secret_key,
+        }));
+    }
+    Ok(key_files)
+}
+
 pub(crate) fn keys_from_source_db(
     home: &Path,
     account_id: &str,
     block_height: Option<BlockHeight>,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
-) -> anyhow::Result<Vec<SecretAccessKey>> {
-    let account_id: AccountId = account_id.parse().context("bad account ID")?;
+    target_key_type: Option<KeyType>,
+) -> Result<Vec<SecretAccessKey>, KeyMappingError> {
+    let account_id = parse_account_id(account_id)?;
 
     let mut config =
         nearcore::config::load_config(home.as_ref(), GenesisValidationMode::UnsafeFast)
-            .with_context(|| format!("Error loading config from {}", home.display()))?;
-    let node_storage =
-        nearcore::open_storage(home.as_ref(), &mut config).context("failed opening storage")?;
+            .with_context(|| format!("Error loading config from {}", home.display()))
+            .map_err(KeyMappingError::SourceDB)?;
+    let node_storage = nearcore::open_storage(home.as_ref(), &mut config)
+        .context("failed opening storage")
+        .map_err(KeyMappingError::SourceDB)?;
     let store = node_storage.get_hot_store();
     let chain = ChainStore::new(
         store.clone(),
@@ -66,26 +223,35 @@ pub(crate) fn keys_from_source_db(
         EpochManager::new_arc_handle(store.clone(), &config.genesis.config, Some(home));
     let runtime =
         NightshadeRuntime::from_config(home.as_ref(), store, &config, epoch_manager.clone())
-            .context("could not create the transaction runtime")?;
+            .context("could not create the transaction runtime")
+            .map_err(KeyMappingError::SourceDB)?;
     let block_height = match block_height {
         Some(h) => h,
         None => {
-            let head = chain.head().context("failed getting chain head")?;
+            let head = chain
+                .head()
+                .context("failed getting chain head")
+                .map_err(KeyMappingError::SourceDB)?;
             head.height
         }
     };
 
     let header = chain
         .get_block_header_by_height(block_height)
-        .with_context(|| format!("failed getting block header #{}", block_height))?;
+        .with_context(|| format!("failed getting block header #{}", block_height))
+        .map_err(KeyMappingError::SourceDB)?;
     let shard_id = account_id_to_shard_id(epoch_manager.as_ref(), &account_id, header.epoch_id())
-        .with_context(|| format!("failed finding shard for {}", &account_id))?;
+        .with_context(|| format!("failed finding shard for {}", &account_id))
+        .map_err(KeyMappingError::SourceDB)?;
     let shard_uid = shard_id_to_uid(epoch_manager.as_ref(), shard_id, header.epoch_id())
-        .context("failed mapping ShardID to ShardUID")?;
-    let chunk_extra =
-        chain.get_chunk_extra(header.hash(), &shard_uid).context("failed getting chunk extra")?;
-    match runtime
-        .query(
+        .context("failed mapping ShardID to ShardUID")
+        .map_err(KeyMappingError::SourceDB)?;
+    let chunk_extra = chain
+        .get_chunk_extra(header.hash(), &shard_uid)
+        .context("failed getting chunk extra")
+        .map_err(KeyMappingError::SourceDB)?;
+    let query = |request: QueryRequest| {
+        runtime.query(
             shard_uid,
             chunk_extra.state_root(),
             header.height(),
@@ -93,60 +259,312 @@ pub(crate) fn keys_from_source_db(
             header.prev_hash(),
             header.hash(),
             header.epoch_id(),
-            &QueryRequest::ViewAccessKeyList { account_id: account_id.clone() },
+            &request,
         )
-        .with_context(|| format!("failed fetching access keys for {}", &account_id))?
+    };
+    let keys = match query(QueryRequest::ViewAccessKeyList { account_id: account_id.clone() })
+        .with_context(|| format!("failed fetching access keys for {}", &account_id))
+        .map_err(KeyMappingError::SourceDB)?
         .kind
     {
-        QueryResponseKind::AccessKeyList(l) => Ok(l
-            .keys
-            .into_iter()
-            .map(|k| SecretAccessKey {
-                mapped_key: crate::key_mapping::map_key(&k.public_key, secret),
-                original_key: Some(k.public_key),
-                permission: Some(k.access_key.permission),
-            })
-            .collect()),
+        QueryResponseKind::AccessKeyList(l) => l.keys,
         _ => unreachable!(),
+    };
+    // Listing the access keys of an account that doesn't exist just returns
+    // nothing, so check whether the account exists to tell the two cases apart.
+    if keys.is_empty() {
+        match query(QueryRequest::ViewAccount { account_id: account_id.clone() }) {
+            Ok(_) => {}
+            Err(QueryError::UnknownAccount { .. }) => {
+                return Err(KeyMappingError::AccountNotFound(account_id));
+            }
+            Err(e) => {
+                return Err(KeyMappingError::SourceDB(
+                    anyhow::Error::from(e)
+                        .context(format!("failed fetching account {}", &account_id)),
+                ));
+            }
+        }
     }
+    let key_type = crate::key_mapping::account_key_type(&account_id, target_key_type);
+    Ok(keys
+        .into_iter()
+        .map(|k| SecretAccessKey {
+            mapped_key: crate::key_mapping::map_key(&k.public_key, secret, key_type),
+            original_key: Some(k.public_key),
+            permission: Some(k.access_key.permission),
+        })
+        .collect())
+}
+
+/// PEM encoded client certificate and private key used to authenticate to
+/// source chain RPC endpoints that require mutual TLS.
+#[derive(serde::Serialize)]
+pub(crate) struct RpcClientCert {
+    pub(crate) cert_path: PathBuf,
+    pub(crate) key_path: PathBuf,
+}
+
+/// Timeout for establishing a connection to the RPC node. Same as the one used by
+/// `near_jsonrpc_client_internal::new_client()`.
+const RPC_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Max size of an RPC response body. Same as the one used by near_jsonrpc_client_internal.
+const RPC_PAYLOAD_LIMIT: usize = 100 * 1024 * 1024;
+
+pub(crate) fn new_rpc_client(
+    rpc_url: &str,
+    client_cert: Option<&RpcClientCert>,
+) -> Result<JsonRpcClient, KeyMappingError> {
+    if client_cert.is_none() {
+        return Ok(near_jsonrpc_client_internal::new_client(rpc_url));
+    }
+    Ok(JsonRpcClient::new(rpc_url, new_http_client(client_cert)?))
+}
+
+fn new_http_client(client_cert: Option<&RpcClientCert>) -> Result<awc::Client, KeyMappingError> {
+    let builder = awc::Client::builder().timeout(RPC_CONNECT_TIMEOUT);
+    let Some(client_cert) = client_cert else {
+        return Ok(builder.finish());
+    };
+    let ssl = client_cert_connector(client_cert).map_err(KeyMappingError::ClientCert)?;
+    Ok(builder.connector(awc::Connector::new().openssl(ssl)).finish())
+}
+
+/// Sends a `query` request to the RPC node. This does the same as
+/// `JsonRpcClient::query()`, except that it keeps the `awc` error when the
+/// request couldn't be sent, so that callers can tell an unreachable node apart
+/// from errors returned by the node.
+async fn rpc_query(
+    client: &awc::Client,
+    rpc_url: &str,
+    request: RpcQueryRequest,
+) -> Result<RpcQueryResponse, RpcQueryError> {
+    let request = Message::request("query".to_string(), serde_json::to_value(&request).unwrap());
+    let mut response = client
+        .post(rpc_url)
+        .insert_header(("Content-Type", "application/json"))
+        .send_json(&request)
+        .await
+        .map_err(RpcQueryError::Send)?;
+    let body =
+        response.body().limit(RPC_PAYLOAD_LIMIT).await.map_err(|err| {
+            RpcError::parse_error(format!("Failed to retrieve payload: {:?}", err))
+        })?;
+    let result = match from_slice(&body) {
+        Ok(Message::Response(response)) => response.result?,
+        Ok(_) => {
+            return Err(
+                RpcError::parse_error("Failed to parse JSON RPC response".to_string()).into()
+            );
+        }
+        Err(err) => {
+            return Err(RpcError::parse_error(format!("Error {:?} in {:?}", err, body)).into());
+        }
+    };
+    serde_json::from_value(result)
+        .map_err(|err| RpcError::parse_error(format!("Failed to parse: {:?}", err)).into())
+}
+
+fn client_cert_connector(
+    client_cert: &RpcClientCert,
+) -> anyhow::Result<openssl::ssl::SslConnector> {
+    let mut ssl = openssl::ssl::SslConnector::builder(openssl::ssl::SslMethod::tls())
+        .context("failed creating SSL connector")?;
+    ssl.set_certificate_chain_file(&client_cert.cert_path).with_context(|| {
+        format!("failed loading RPC client certificate from {}", client_cert.cert_path.display())
+    })?;
+    ssl.set_private_key_file(&client_cert.key_path, openssl::ssl::SslFiletype::PEM).with_context(
+        || format!("failed loading RPC client key from {}", client_cert.key_path.display()),
+    )?;
+    ssl.check_private_key().context("RPC client key does not match the certificate")?;
+    Ok(ssl.build())
 }
 
 pub(crate) async fn keys_from_rpc(
     rpc_url: &str,
+    client_cert: Option<&RpcClientCert>,
     account_id: &str,
     block_height: Option<BlockHeight>,
     secret: Option<&[u8; crate::secret::SECRET_LEN]>,
-) -> anyhow::Result<Vec<SecretAccessKey>> {
-    let account_id: AccountId = account_id.parse().context("bad account ID")?;
+    target_key_type: Option<KeyType>,
+) -> Result<Vec<SecretAccessKey>, KeyMappingError> {
+    let account_id = parse_account_id(account_id)?;
 
-    let rpc_client = near_jsonrpc_client_internal::new_client(rpc_url);
+    let http_client = new_http_client(client_cert)?;
 
     let block_reference = match block_height {
         Some(h) => BlockReference::BlockId(BlockId::Height(h)),
         None => BlockReference::Finality(Finality::None),
     };
     let request = RpcQueryRequest {
-        block_reference,
+        block_reference: block_reference.clone(),
         request: QueryRequest::ViewAccessKeyList { account_id: account_id.clone() },
     };
 
-    let response = match rpc_client.query(request).await {
-        Ok(r) => r,
-        Err(e) => anyhow::bail!("failed making RPC request: {:?}", e),
+    let response = rpc_query(&http_client, rpc_url, request)
+        .await
+        .map_err(|e| KeyMappingError::rpc(rpc_url, e))?;
+
+    let keys = match response.kind {
+        RpcQueryResponseKind::AccessKeyList(l) => l.keys,
+        k => {
+            return Err(KeyMappingError::Rpc {
+                rpc_url: rpc_url.to_string(),
+                message: format!("received unexpected response for access key query: {:?}", k),
+            });
+        }
     };
+    // See the comment in keys_from_source_db().
+    if keys.is_empty() {
+        let request = RpcQueryRequest {
+            block_reference,
+            request: QueryRequest::ViewAccount { account_id: account_id.clone() },
+        };
+        match rpc_query(&http_client, rpc_url, request).await {
+            Ok(_) => {}
+            Err(RpcQueryError::Rpc(e)) if is_unknown_account(&e) => {
+                return Err(KeyMappingError::AccountNotFound(account_id));
+            }
+            Err(e) => return Err(KeyMappingError::rpc(rpc_url, e)),
+        }
+    }
+    let key_type = crate::key_mapping::account_key_type(&account_id, target_key_type);
+    Ok(keys
+        .into_iter()
+        .map(|k| SecretAccessKey {
+            mapped_key: crate::key_mapping::map_key(&k.public_key, secret, key_type),
+            original_key: Some(k.public_key),
+            permission: Some(k.access_key.permission),
+        })
+        .collect())
+}
 
-    match response.kind {
-        RpcQueryResponseKind::AccessKeyList(l) => Ok(l
-            .keys
-            .into_iter()
-            .map(|k| SecretAccessKey {
-                mapped_key: crate::key_mapping::map_key(&k.public_key, secret),
-                original_key: Some(k.public_key),
-                permission: Some(k.access_key.permission),
+pub(crate) fn is_unknown_account(err: &RpcError) -> bool {
+    match &err.error_struct {
+        Some(RpcErrorKind::HandlerError(value)) => {
+            value.get("name").and_then(|name| name.as_str()) == Some("UNKNOWN_ACCOUNT")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KeyMappingError, RpcQueryError, materialize_keys};
+    use crate::account_map::AccountMap;
+    use near_crypto::{KeyType, SecretKey};
+    use near_jsonrpc_primitives::errors::RpcError;
+    use near_primitives::utils::derive_near_implicit_account_id;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_materialize_keys() {
+        let secret = [7; crate::secret::SECRET_LEN];
+        let source_key = SecretKey::from_seed(KeyType::ED25519, "alice").public_key();
+        let implicit_key = SecretKey::from_seed(KeyType::ED25519, "implicit").public_key();
+        let implicit_account = derive_near_implicit_account_id(implicit_key.unwrap_as_ed25519());
+        let accounts = format!(
+            "# accounts to fund\nalice.near {}\n\n  bob.near\n{}\n",
+            source_key, implicit_account
+        );
+
+        let keys =
+            materialize_keys(&accounts, &AccountMap::default(), Some(&secret), None).unwrap();
+        let keys = keys
+            .iter()
+            .map(|k| {
+                assert_eq!(k.secret_key.public_key(), k.public_key);
+                (k.account_id.to_string(), k.secret_key.clone())
             })
-            .collect()),
-        k => {
-            anyhow::bail!("received unexpected RPC response for access key query: {:?}", k);
+            .collect::<Vec<_>>();
+        let mapped_implicit_key = crate::key_mapping::map_key(&implicit_key, Some(&secret), None);
+        assert_eq!(
+            keys,
+            vec![
+                (
+                    String::from("alice.near"),
+                    crate::key_mapping::map_key(&source_key, Some(&secret), None)
+                ),
+                (
+                    String::from("bob.near"),
+                    crate::key_mapping::default_extra_key(Some(&secret), None)
+                ),
+                (
+                    derive_near_implicit_account_id(
+                        &mapped_implicit_key.public_key().unwrap_as_ed25519()
+                    )
+                    .to_string(),
+                    mapped_implicit_key
+                ),
+            ]
+        );
+
+        let account_map = AccountMap::new(BTreeMap::from([(
+            "alice.near".parse().unwrap(),
+            "alice.testnet".parse().unwrap(),
+        )]))
+        .unwrap();
+        let renamed_keys = materialize_keys(&accounts, &account_map, Some(&secret), None).unwrap();
+        assert_eq!(renamed_keys[0].account_id.as_str(), "alice.testnet");
+        assert_eq!(renamed_keys[0].secret_key, keys[0].1);
+        assert_eq!(renamed_keys[1].account_id.as_str(), "bob.near");
+
+        assert!(matches!(
+            materialize_keys("alice.near not-a-key", &AccountMap::default(), None, None),
+            Err(KeyMappingError::BadPublicKey { .. })
+        ));
+        assert!(matches!(
+            materialize_keys("Not An Account", &AccountMap::default(), None, None),
+            Err(KeyMappingError::BadAccountId { .. })
+        ));
+    }
+
+    #[test]
+    fn test_materialize_keys_target_key_type() {
+        let secret = [7; crate::secret::SECRET_LEN];
+        let target_key_type = Some(KeyType::SECP256K1);
+        let source_key = SecretKey::from_seed(KeyType::ED25519, "alice").public_key();
+        let implicit_key = SecretKey::from_seed(KeyType::ED25519, "implicit").public_key();
+        let implicit_account = derive_near_implicit_account_id(implicit_key.unwrap_as_ed25519());
+        let accounts = format!(
+            "alice.near {}\n{}\n{} {}\n",
+            source_key, implicit_account, implicit_account, implicit_key
+        );
+
+        let keys =
+            materialize_keys(&accounts, &AccountMap::default(), Some(&secret), target_key_type)
+                .unwrap();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0].secret_key.key_type(), KeyType::SECP256K1);
+        // The target chain implicit account is derived from the mapped ed25519 key, and
+        // that's the key it's created with, so that's the one we need to sign with.
+        for k in &keys[1..] {
+            assert_eq!(k.secret_key.key_type(), KeyType::ED25519);
+            assert_eq!(
+                k.account_id,
+                derive_near_implicit_account_id(k.public_key.unwrap_as_ed25519())
+            );
+            assert_eq!(
+                k.account_id,
+                crate::key_mapping::map_account(&implicit_account, Some(&secret))
+            );
+        }
+    }
+
+    #[test]
+    fn test_rpc_error_exit_code() {
+        let rpc_url = "http://localhost:3030";
+        let transport_error = RpcQueryError::Send(awc::error::SendRequestError::Timeout);
+        assert_eq!(KeyMappingError::rpc(rpc_url, transport_error).exit_code(), 3);
+
+        let node_errors = [
+            RpcError::new_internal_error(None, String::from("Storage error")),
+            RpcError::new_internal_error(None, String::from("Connect(Timeout)")),
+            RpcError::parse_error(String::from("Failed to parse")),
+        ];
+        for err in node_errors {
+            assert_eq!(KeyMappingError::rpc(rpc_url, err.into()).exit_code(), 1);
         }
     }
 }
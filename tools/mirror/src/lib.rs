@@ -10,17 +10,18 @@ use near_client_primitives::types::{
     GetBlock, GetBlockError, GetChunkError, GetExecutionOutcomeError, GetReceiptError, Query,
     QueryError, Status,
 };
-use near_crypto::{PublicKey, SecretKey};
+use near_crypto::{KeyType, PublicKey, SecretKey};
 use near_indexer::{Indexer, StreamerMessage};
 use near_o11y::WithSpanContextExt;
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{Receipt, ReceiptEnum};
 use near_primitives::transaction::{
     Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
-    SignedTransaction, StakeAction, Transaction,
+    FunctionCallAction, SignedTransaction, StakeAction, Transaction,
 };
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, Finality, TransactionOrReceiptId,
+    AccountId, Balance, BlockHeight, BlockReference, Finality, Gas, SyncCheckpoint,
+    TransactionOrReceiptId,
 };
 use near_primitives::views::{
     ExecutionOutcomeWithIdView, ExecutionStatusView, QueryRequest, QueryResponseKind,
@@ -29,25 +30,37 @@ use near_primitives_core::account::id::AccountType;
 use near_primitives_core::account::{AccessKey, AccessKeyPermission};
 use near_primitives_core::types::{Nonce, ShardId};
 use rocksdb::DB;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use strum::IntoEnumIterator;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
+use tracing::Instrument;
 
+mod account_map;
+mod action_filter;
 mod chain_tracker;
 pub mod cli;
 pub mod genesis;
+mod health;
 pub mod key_mapping;
 mod key_util;
 mod metrics;
+mod nonce_report;
 mod offline;
 mod online;
+mod relayer;
+mod reorg;
+mod rpc;
 pub mod secret;
+mod send_backoff;
+mod sent_txs_log;
+mod verify;
 
 pub use cli::MirrorCommand;
+pub use key_util::KeyMappingError;
 
 #[derive(strum::EnumIter)]
 enum DBCol {
@@ -59,6 +72,15 @@ enum DBCol {
     // state. Otherwise, we map tx nonces according to the values in this column.
     Nonces,
     AccessKeyOutcomes,
+    // Indexes the entries in the AccessKeyOutcomes column that don't affect any access keys
+    // by the last source height at the time they were added, so that old ones can be pruned.
+    PendingOutcomeHeights,
+    // Records the source chain height of every source chain transaction we successfully sent,
+    // keyed by its hash, so that we don't send it again if we go over the same heights twice.
+    SentSourceTxs,
+    // Indexes the entries in the SentSourceTxs column by the source height they were sent at,
+    // so that old ones can be pruned.
+    SentSourceTxHeights,
 }
 
 impl DBCol {
@@ -67,6 +89,9 @@ impl DBCol {
             Self::Misc => "miscellaneous",
             Self::Nonces => "nonces",
             Self::AccessKeyOutcomes => "access_key_outcomes",
+            Self::PendingOutcomeHeights => "pending_outcome_heights",
+            Self::SentSourceTxs => "sent_source_txs",
+            Self::SentSourceTxHeights => "sent_source_tx_heights",
         }
     }
 }
@@ -196,6 +221,14 @@ fn put_pending_outcome(
     access_keys: HashSet<(AccountId, PublicKey)>,
 ) -> anyhow::Result<()> {
     tracing::trace!(target: "mirror", "storing {:?} in DB for {:?}", &access_keys, &id);
+    if access_keys.is_empty() {
+        let height = get_last_source_height(db)?.unwrap_or(0);
+        db.put_cf(
+            db.cf_handle(DBCol::PendingOutcomeHeights.name()).unwrap(),
+            &pending_outcome_height_key(height, &id),
+            &[],
+        )?;
+    }
     Ok(db.put_cf(
         db.cf_handle(DBCol::AccessKeyOutcomes.name()).unwrap(),
         &borsh::to_vec(&id).unwrap(),
@@ -203,6 +236,112 @@ fn put_pending_outcome(
     )?)
 }
 
+// returns the key in the PendingOutcomeHeights or SentSourceTxHeights column for the given hash.
+// The height is big endian so that iterating over the column visits the oldest entries first,
+// and it's followed by the key of the entry in the AccessKeyOutcomes or SentSourceTxs column
+fn pending_outcome_height_key(height: BlockHeight, id: &CryptoHash) -> Vec<u8> {
+    let mut key = height.to_be_bytes().to_vec();
+    key.extend(borsh::to_vec(id).unwrap());
+    key
+}
+
+// Deletes the entries in `heights_col` with a height lower than `min_height`, along with the
+// entries in `col` they index, and returns the number of entries deleted. `heights_col` is keyed
+// as returned by pending_outcome_height_key(), so this stops at the first entry that is recent
+// enough.
+fn prune_height_index(
+    db: &DB,
+    batch: &mut rocksdb::WriteBatch,
+    heights_col: DBCol,
+    col: DBCol,
+    min_height: BlockHeight,
+) -> anyhow::Result<usize> {
+    let heights_cf = db.cf_handle(heights_col.name()).unwrap();
+    let cf = db.cf_handle(col.name()).unwrap();
+    let mut num_pruned = 0;
+    for item in db.iterator_cf(heights_cf, rocksdb::IteratorMode::Start) {
+        let (key, _) = item?;
+        let Some((height, entry_key)) = key.split_first_chunk::<8>() else {
+            anyhow::bail!("corrupted {} key: {:?}", heights_col.name(), key);
+        };
+        if BlockHeight::from_be_bytes(*height) >= min_height {
+            break;
+        }
+        // The entry might have been deleted already, in which case this is a no-op.
+        batch.delete_cf(cf, entry_key);
+        batch.delete_cf(heights_cf, &key);
+        num_pruned += 1;
+    }
+    Ok(num_pruned)
+}
+
+// Deletes the entries in the AccessKeyOutcomes column that don't affect any access keys and were
+// added more than `retention_blocks` source chain blocks before the last source height we sent
+// transactions for, and then compacts the DB to reclaim the space. These entries are only used to
+// reverse stake actions resulting from our transactions, and the ones that old are not expected to
+// appear on the target chain anymore. Entries affecting access keys are always kept because the
+// nonces we track depend on them. Entries in the SentSourceTxs column for source chain transactions
+// sent more than `retention_blocks` blocks ago are deleted too, since we don't expect to go over
+// those heights again. Both are found through their height index, so this only reads the entries
+// it deletes. Returns the number of entries deleted.
+fn prune_db(db: &DB, retention_blocks: BlockHeight) -> anyhow::Result<usize> {
+    let Some(last_height) = get_last_source_height(db)? else {
+        return Ok(0);
+    };
+    let min_height = last_height.saturating_sub(retention_blocks);
+    let mut batch = rocksdb::WriteBatch::default();
+    // Pending outcomes might have been deleted already if they appeared on chain.
+    let mut num_pruned = prune_height_index(
+        db,
+        &mut batch,
+        DBCol::PendingOutcomeHeights,
+        DBCol::AccessKeyOutcomes,
+        min_height,
+    )?;
+    num_pruned += prune_height_index(
+        db,
+        &mut batch,
+        DBCol::SentSourceTxHeights,
+        DBCol::SentSourceTxs,
+        min_height,
+    )?;
+    db.write(batch)?;
+    for col in DBCol::iter() {
+        db.compact_range_cf(db.cf_handle(col.name()).unwrap(), None::<&[u8]>, None::<&[u8]>);
+    }
+    Ok(num_pruned)
+}
+
+fn put_sent_source_tx(db: &DB, hash: &CryptoHash, height: BlockHeight) -> anyhow::Result<()> {
+    let mut batch = rocksdb::WriteBatch::default();
+    batch.put_cf(
+        db.cf_handle(DBCol::SentSourceTxHeights.name()).unwrap(),
+        &pending_outcome_height_key(height, hash),
+        &[],
+    );
+    batch.put_cf(
+        db.cf_handle(DBCol::SentSourceTxs.name()).unwrap(),
+        &borsh::to_vec(hash).unwrap(),
+        &borsh::to_vec(&height).unwrap(),
+    );
+    db.write(batch)?;
+    Ok(())
+}
+
+// returns the source chain height at which we sent the source chain transaction with this hash, if we did
+fn read_sent_source_tx(db: &DB, hash: &CryptoHash) -> anyhow::Result<Option<BlockHeight>> {
+    let Some(value) = db.get_cf(
+        db.cf_handle(DBCol::SentSourceTxs.name()).unwrap(),
+        &borsh::to_vec(hash).unwrap(),
+    )?
+    else {
+        return Ok(None);
+    };
+    let height = BlockHeight::try_from_slice(&value)
+        .with_context(|| format!("corrupted sent_source_txs entry for {}", hash))?;
+    Ok(Some(height))
+}
+
 fn delete_pending_outcome(db: &DB, id: &CryptoHash) -> anyhow::Result<()> {
     tracing::trace!(target: "mirror", "deleting {:?} from DB", &id);
     Ok(db.delete_cf(
@@ -227,20 +366,102 @@ fn set_last_source_height(db: &DB, height: BlockHeight) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Flushes all column families so that the progress recorded so far is on disk
+// before we exit.
+fn flush_db(db: &DB) -> anyhow::Result<()> {
+    for col in DBCol::iter() {
+        db.flush_cf(db.cf_handle(col.name()).unwrap())
+            .with_context(|| format!("failed flushing mirror DB column {}", col.name()))?;
+    }
+    Ok(())
+}
+
 fn get_last_source_height(db: &DB) -> anyhow::Result<Option<BlockHeight>> {
     Ok(db
         .get_cf(db.cf_handle(DBCol::Misc.name()).unwrap(), "last_source_height")?
         .map(|v| BlockHeight::try_from_slice(&v).unwrap()))
 }
 
+// Records the number of transactions sent in the last batch, and the time at which
+// it was sent, so that the `status` command can report on the progress of a run.
+fn record_txs_sent(db: &DB, num_txs: u64) -> anyhow::Result<()> {
+    let num_txs_sent = get_num_txs_sent(db)?.unwrap_or(0) + num_txs;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .context("system time is before the unix epoch")?
+        .as_secs();
+    let cf = db.cf_handle(DBCol::Misc.name()).unwrap();
+    db.put_cf(cf, "num_txs_sent", borsh::to_vec(&num_txs_sent).unwrap())?;
+    db.put_cf(cf, "last_update_time", borsh::to_vec(&now).unwrap())?;
+    Ok(())
+}
+
+fn get_num_txs_sent(db: &DB) -> anyhow::Result<Option<u64>> {
+    Ok(db
+        .get_cf(db.cf_handle(DBCol::Misc.name()).unwrap(), "num_txs_sent")?
+        .map(|v| u64::try_from_slice(&v).unwrap()))
+}
+
+fn set_target_genesis_hash(db: &DB, hash: &CryptoHash) -> anyhow::Result<()> {
+    db.put_cf(
+        db.cf_handle(DBCol::Misc.name()).unwrap(),
+        "target_genesis_hash",
+        borsh::to_vec(hash).unwrap(),
+    )?;
+    Ok(())
+}
+
+// returns the genesis hash of the target chain we sent transactions to in previous runs
+fn get_target_genesis_hash(db: &DB) -> anyhow::Result<Option<CryptoHash>> {
+    Ok(db
+        .get_cf(db.cf_handle(DBCol::Misc.name()).unwrap(), "target_genesis_hash")?
+        .map(|v| CryptoHash::try_from_slice(&v).unwrap()))
+}
+
+// returns the last update time as seconds since the unix epoch
+fn get_last_update_time(db: &DB) -> anyhow::Result<Option<u64>> {
+    Ok(db
+        .get_cf(db.cf_handle(DBCol::Misc.name()).unwrap(), "last_update_time")?
+        .map(|v| u64::try_from_slice(&v).unwrap()))
+}
+
+/// The parts of a source chain transaction we need in order to mirror it. We don't keep the
+/// `SignedTransaction` itself, since the RPC source only gets transaction views, which don't
+/// include the block hash the transaction was signed with, and so can't be turned back into one.
+struct SourceTransaction {
+    hash: CryptoHash,
+    signer_id: AccountId,
+    public_key: PublicKey,
+    nonce: Nonce,
+    receiver_id: AccountId,
+    actions: Vec<Action>,
+}
+
+impl From<SignedTransaction> for SourceTransaction {
+    fn from(tx: SignedTransaction) -> Self {
+        let hash = tx.get_hash();
+        let signer_id = tx.transaction.signer_id().clone();
+        let public_key = tx.transaction.public_key().clone();
+        let nonce = tx.transaction.nonce();
+        let receiver_id = tx.transaction.receiver_id().clone();
+        let actions = tx.transaction.take_actions();
+        Self { hash, signer_id, public_key, nonce, receiver_id, actions }
+    }
+}
+
 struct SourceChunk {
     shard_id: ShardId,
-    transactions: Vec<SignedTransaction>,
+    transactions: Vec<SourceTransaction>,
     receipts: Vec<Receipt>,
 }
 
 struct SourceBlock {
     hash: CryptoHash,
+    prev_hash: CryptoHash,
+    // the gas price set by this block, as reported in its header
+    gas_price: Balance,
+    // the block's timestamp in nanoseconds, as reported in its header
+    timestamp: u64,
     chunks: Vec<SourceChunk>,
 }
 
@@ -390,6 +611,61 @@ trait ChainAccess {
     ) -> Result<Vec<PublicKey>, ChainError>;
 }
 
+// Lets `run` pick the source chain access implementation at runtime
+#[async_trait(?Send)]
+impl ChainAccess for Box<dyn ChainAccess> {
+    async fn init(
+        &self,
+        last_height: BlockHeight,
+        num_initial_blocks: usize,
+    ) -> anyhow::Result<Vec<BlockHeight>> {
+        (**self).init(last_height, num_initial_blocks).await
+    }
+
+    async fn block_height_to_hash(&self, height: BlockHeight) -> Result<CryptoHash, ChainError> {
+        (**self).block_height_to_hash(height).await
+    }
+
+    async fn head_height(&self) -> Result<BlockHeight, ChainError> {
+        (**self).head_height().await
+    }
+
+    async fn get_txs(&self, height: BlockHeight) -> Result<SourceBlock, ChainError> {
+        (**self).get_txs(height).await
+    }
+
+    async fn get_next_block_height(&self, height: BlockHeight) -> Result<BlockHeight, ChainError> {
+        (**self).get_next_block_height(height).await
+    }
+
+    async fn get_outcome(
+        &self,
+        id: TransactionOrReceiptId,
+    ) -> Result<ExecutionOutcomeWithIdView, ChainError> {
+        (**self).get_outcome(id).await
+    }
+
+    async fn get_tx_receipt_id(
+        &self,
+        tx_hash: &CryptoHash,
+        signer_id: &AccountId,
+    ) -> Result<Option<CryptoHash>, ChainError> {
+        (**self).get_tx_receipt_id(tx_hash, signer_id).await
+    }
+
+    async fn get_receipt(&self, id: &CryptoHash) -> Result<Arc<Receipt>, ChainError> {
+        (**self).get_receipt(id).await
+    }
+
+    async fn get_full_access_keys(
+        &self,
+        account_id: &AccountId,
+        block_hash: &CryptoHash,
+    ) -> Result<Vec<PublicKey>, ChainError> {
+        (**self).get_full_access_keys(account_id, block_hash).await
+    }
+}
+
 fn execution_status_good(status: &ExecutionStatusView) -> bool {
     matches!(
         status,
@@ -405,10 +681,141 @@ struct MirrorConfig {
     /// wait this long before sending each mainnet block's worth of transactions.
     /// TODO: add an option to target a specific number of transactions per second
     tx_batch_interval: Option<Duration>,
+    /// Maximum time to wait before sending another transaction from an account
+    /// whose transactions were rejected by the target node. The wait grows
+    /// exponentially with each rejection up to this value, which defaults to 10 seconds.
+    tx_send_max_backoff: Option<Duration>,
+    /// Maximum time to wait on SIGTERM or SIGINT for the transactions already sent
+    /// to appear on the target chain before exiting. Defaults to 60 seconds.
+    shutdown_timeout: Option<Duration>,
+    /// If the target chain height hasn't advanced for this long, the `/readyz`
+    /// endpoint served with --health-addr reports the mirror as not ready.
+    /// Defaults to 120 seconds.
+    readiness_stall_timeout: Option<Duration>,
+    /// If the target chain height hasn't advanced for this long, stop sending
+    /// transactions until it does, so that a target chain that lost quorum
+    /// isn't buried under the backlog the moment it recovers. By default we
+    /// keep sending regardless.
+    target_stall_pause_timeout: Option<Duration>,
+}
+
+impl MirrorConfig {
+    fn load<P: AsRef<Path>>(config_path: Option<P>) -> anyhow::Result<Self> {
+        match config_path {
+            Some(p) => {
+                let c = std::fs::read_to_string(p.as_ref()).with_context(|| {
+                    format!("Could not read config from {}", p.as_ref().display())
+                })?;
+                serde_json::from_str(&c).with_context(|| {
+                    format!("Could not parse config from {}", p.as_ref().display())
+                })
+            }
+            None => Ok(Default::default()),
+        }
+    }
+
+    // Fills in the options that have a default value with that value.
+    fn with_defaults(self) -> Self {
+        Self {
+            tx_batch_interval: self.tx_batch_interval,
+            tx_send_max_backoff: Some(
+                self.tx_send_max_backoff.unwrap_or(crate::send_backoff::DEFAULT_MAX_BACKOFF),
+            ),
+            shutdown_timeout: Some(self.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT)),
+            readiness_stall_timeout: Some(
+                self.readiness_stall_timeout
+                    .unwrap_or(crate::health::DEFAULT_READINESS_STALL_TIMEOUT),
+            ),
+            target_stall_pause_timeout: self.target_stall_pause_timeout,
+        }
+    }
+
+    // Describes each option that has a different value in `self` than in `old`, with the
+    // defaults filled in.
+    fn changes_from(&self, old: &Self) -> Vec<String> {
+        let old = old.clone().with_defaults();
+        let new = self.clone().with_defaults();
+        let mut changes = Vec::new();
+        let mut check = |name: &str, old: &dyn std::fmt::Debug, new: &dyn std::fmt::Debug| {
+            let (old, new) = (format!("{:?}", old), format!("{:?}", new));
+            if old != new {
+                changes.push(format!("{}: {} -> {}", name, old, new));
+            }
+        };
+        check("tx_batch_interval", &old.tx_batch_interval, &new.tx_batch_interval);
+        check("tx_send_max_backoff", &old.tx_send_max_backoff, &new.tx_send_max_backoff);
+        check("shutdown_timeout", &old.shutdown_timeout, &new.shutdown_timeout);
+        check(
+            "readiness_stall_timeout",
+            &old.readiness_stall_timeout,
+            &new.readiness_stall_timeout,
+        );
+        check(
+            "target_stall_pause_timeout",
+            &old.target_stall_pause_timeout,
+            &new.target_stall_pause_timeout,
+        );
+        changes
+    }
+}
+
+// Staking pool contracts keep the key they stake with in their state, and it's set by
+// calling these methods with a `stake_public_key` argument.
+const STAKING_POOL_KEY_METHODS: &[&str] = &["new", "update_staking_key"];
+
+// Maps the `stake_public_key` argument of a call to one of the STAKING_POOL_KEY_METHODS,
+// so that the pool stakes with a key that's mapped the same way as in Stake actions.
+// Returns None if the args aren't a JSON object with a valid `stake_public_key`.
+fn map_staking_pool_args(
+    args: &[u8],
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> Option<Vec<u8>> {
+    let mut args: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(args).ok()?;
+    let public_key: PublicKey = args.get("stake_public_key")?.as_str()?.parse().ok()?;
+    let public_key = crate::key_mapping::map_key(&public_key, secret, target_key_type).public_key();
+    args.insert("stake_public_key".to_string(), public_key.to_string().into());
+    serde_json::to_vec(&args).ok()
+}
+
+/// Reads a JSON map of target chain account IDs to the public keys that should be
+/// added to them instead of the default extra key.
+fn load_extra_key_overrides(path: &Path) -> anyhow::Result<HashMap<AccountId, PublicKey>> {
+    let s = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+// A rule renaming the method called by FunctionCall actions sent to some contract
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MethodRenameRule {
+    receiver_id: AccountId,
+    old_method: String,
+    new_method: String,
+}
+
+/// Reads a JSON list of `{"receiver_id", "old_method", "new_method"}` rules and returns
+/// a map from (target chain receiver ID, old method name) to the new method name.
+fn load_method_renames(path: &Path) -> anyhow::Result<HashMap<(AccountId, String), String>> {
+    let s = std::fs::read_to_string(path)?;
+    let rules: Vec<MethodRenameRule> = serde_json::from_str(&s)?;
+    let mut method_renames = HashMap::new();
+    for rule in rules {
+        let key = (rule.receiver_id, rule.old_method);
+        if method_renames.contains_key(&key) {
+            anyhow::bail!("more than one rename rule given for method {} of {}", key.1, key.0);
+        }
+        method_renames.insert(key, rule.new_method);
+    }
+    Ok(method_renames)
 }
 
 const CREATE_ACCOUNT_DELTA: usize = 5;
 
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How often we prune and compact the mirror DB when --mirror-db-retention-blocks is given
+const DB_COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 // TODO: separate out the code that uses the target chain clients, and
 // make it an option to send the transactions to some RPC node.
 // that way it would be possible to run this code and send transactions with an
@@ -418,12 +825,55 @@ struct TxMirror<T: ChainAccess> {
     source_chain_access: T,
     db: Arc<DB>,
     target_genesis_height: BlockHeight,
+    target_chain_id: String,
     target_min_block_production_delay: Duration,
     secret: Option<[u8; crate::secret::SECRET_LEN]>,
+    // the key type recorded in the secret file, that all mapped keys should have
+    target_key_type: Option<KeyType>,
     default_extra_key: SecretKey,
+    // public keys to add instead of the default extra key for the given target chain accounts
+    extra_key_overrides: HashMap<AccountId, PublicKey>,
+    // new method names for FunctionCall actions, keyed by target chain receiver ID and old method name
+    method_renames: HashMap<(AccountId, String), String>,
+    // source chain accounts sent to the target chain under a different ID
+    account_map: crate::account_map::AccountMap,
+    // per-account backoff for transactions rejected by the target node
+    send_backoff: Arc<Mutex<crate::send_backoff::SendBackoff>>,
+    // progress reported by the health check endpoints
+    health: Arc<crate::health::HealthState>,
+    // if set, we append a row to this for every transaction we send
+    sent_txs_log: Option<Arc<Mutex<crate::sent_txs_log::SentTxsLog>>>,
+    // if set, we record the source and target chain nonces of the transactions we send
+    // in this, and periodically log them
+    nonce_report: Option<crate::nonce_report::NonceReport>,
+    // if set, mapped source chain transactions are sent as delegate actions signed by this account
+    relayer: Option<crate::relayer::Relayer>,
+    // if true, fail instead of skipping source chain transactions signed by keys we can't map
+    strict_keys: bool,
+    // if true, scale down the gas attached to FunctionCall actions when the target chain's
+    // gas price is higher than the source chain's
+    adjust_gas: bool,
+    // if true, mirror Stake actions with their keys mapped, and don't reverse the stakes
+    // resulting from our transactions
+    mirror_stakes: bool,
+    // if true, send source chain transactions even if the mirror DB says we already sent them
+    force_resubmit: bool,
+    // if set, we only mirror the actions it allows
+    action_filter: Option<crate::action_filter::ActionFilter>,
+    // remembers the source chain blocks we fetched so we notice if the source chain reorgs
+    reorg_detector: crate::reorg::ReorgDetector,
+    on_reorg: crate::reorg::OnReorg,
+    // where `config` was read from, so it can be read again on SIGHUP
+    config_path: Option<PathBuf>,
     config: MirrorConfig,
 }
 
+// Span covering the work done for a single source chain block, so that all the logs about
+// it can be correlated. `target_height` is the target chain head height at the time.
+fn mirror_block_span(source_height: BlockHeight, target_height: BlockHeight) -> tracing::Span {
+    tracing::info_span!(target: "mirror", "mirror_block", source_height, target_height)
+}
+
 fn open_db<P: AsRef<Path>>(home: P) -> anyhow::Result<DB> {
     let mut options = rocksdb::Options::default();
     options.create_missing_column_families(true);
@@ -434,9 +884,207 @@ fn open_db<P: AsRef<Path>>(home: P) -> anyhow::Result<DB> {
     Ok(DB::open_cf_descriptors(&options, home.as_ref(), cf_descriptors)?)
 }
 
+// Opens the mirror DB without taking the write lock, so that it is safe to
+// inspect it while another mirror process is running.
+fn open_db_read_only<P: AsRef<Path>>(home: P) -> anyhow::Result<DB> {
+    let options = rocksdb::Options::default();
+    // DBs created by older versions might not have all the columns, and we can't
+    // create them in read only mode.
+    let existing_cfs = DB::list_cf(&options, home.as_ref())?;
+    let cf_names =
+        DBCol::iter().map(|col| col.name()).filter(|name| existing_cfs.iter().any(|c| c == name));
+    Ok(DB::open_cf_for_read_only(&options, home.as_ref(), cf_names, false)?)
+}
+
+/// Progress of a mirror run, as recorded in the mirror DB.
+#[derive(Debug)]
+pub struct MirrorStatus {
+    /// Last source chain height we sent transactions for
+    pub last_source_height: Option<BlockHeight>,
+    /// Total number of transactions sent to the target chain
+    pub num_txs_sent: u64,
+    /// Number of sent transactions and generated receipts whose outcomes
+    /// have not yet been seen in the target chain
+    pub num_pending_outcomes: usize,
+    /// Number of access keys whose target chain nonces are being tracked
+    pub num_tracked_nonces: usize,
+    /// Time of the last batch of transactions sent, in seconds since the unix epoch
+    pub last_update_time: Option<u64>,
+}
+
+impl std::fmt::Display for MirrorStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.last_source_height {
+            Some(h) => writeln!(f, "last mirrored source height: {}", h)?,
+            None => writeln!(f, "last mirrored source height: none")?,
+        };
+        writeln!(f, "transactions sent: {}", self.num_txs_sent)?;
+        writeln!(f, "transactions/receipts pending on chain: {}", self.num_pending_outcomes)?;
+        writeln!(f, "tracked access key nonces: {}", self.num_tracked_nonces)?;
+        match self.last_update_time {
+            Some(t) => {
+                let time = near_primitives::utils::from_timestamp(t * 1_000_000_000);
+                write!(f, "last update: {}", time)
+            }
+            None => write!(f, "last update: never"),
+        }
+    }
+}
+
+fn count_col(db: &DB, col: DBCol) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for item in db.iterator_cf(db.cf_handle(col.name()).unwrap(), rocksdb::IteratorMode::Start) {
+        item?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Reads the progress of a previous (or currently running) mirror run from
+/// the mirror DB at `mirror_db_path`, without modifying it.
+pub fn mirror_status<P: AsRef<Path>>(mirror_db_path: P) -> anyhow::Result<MirrorStatus> {
+    let db = open_db_read_only(mirror_db_path.as_ref()).with_context(|| {
+        format!("failed to open mirror DB at {}", mirror_db_path.as_ref().display())
+    })?;
+    Ok(MirrorStatus {
+        last_source_height: get_last_source_height(&db)?,
+        num_txs_sent: get_num_txs_sent(&db)?.unwrap_or(0),
+        num_pending_outcomes: count_col(&db, DBCol::AccessKeyOutcomes)?,
+        num_tracked_nonces: count_col(&db, DBCol::Nonces)?,
+        last_update_time: get_last_update_time(&db)?,
+    })
+}
+
+/// The arguments to `mirror run`, with the secret, key overrides, method renames,
+/// account map and action filter they point to already loaded.
+pub(crate) struct MirrorOptions {
+    pub(crate) source_home: Option<PathBuf>,
+    pub(crate) target_home: PathBuf,
+    pub(crate) mirror_db_path: Option<PathBuf>,
+    pub(crate) secret: Option<[u8; crate::secret::SECRET_LEN]>,
+    pub(crate) target_key_type: Option<KeyType>,
+    pub(crate) stop_height: Option<BlockHeight>,
+    pub(crate) online_source: bool,
+    pub(crate) source_cold_storage: bool,
+    pub(crate) source_rpc: Option<String>,
+    pub(crate) source_rpc_client_cert: Option<crate::key_util::RpcClientCert>,
+    pub(crate) config_path: Option<PathBuf>,
+    pub(crate) extra_key_overrides: HashMap<AccountId, PublicKey>,
+    pub(crate) method_renames: HashMap<(AccountId, String), String>,
+    pub(crate) account_map: crate::account_map::AccountMap,
+    pub(crate) strict_keys: bool,
+    pub(crate) adjust_gas: bool,
+    pub(crate) mirror_stakes: bool,
+    pub(crate) force_resubmit: bool,
+    pub(crate) action_filter: Option<crate::action_filter::ActionFilter>,
+    pub(crate) on_reorg: crate::reorg::OnReorg,
+    pub(crate) log_sent_txs: Option<PathBuf>,
+    pub(crate) health_addr: Option<std::net::SocketAddr>,
+    pub(crate) metrics_addr: Option<std::net::SocketAddr>,
+    pub(crate) prometheus_prefix: String,
+    pub(crate) expected_target_genesis: Option<CryptoHash>,
+    pub(crate) mirror_db_retention_blocks: Option<BlockHeight>,
+    pub(crate) nonce_report_interval: Option<Duration>,
+    pub(crate) replay_speed: Option<f64>,
+    pub(crate) max_in_flight: Option<usize>,
+    pub(crate) relayer: Option<AccountId>,
+    pub(crate) relayer_key: Option<PathBuf>,
+}
+
+/// The settings `mirror run` would use if given the same arguments, as printed by
+/// `mirror show-config`. Mirror config options that have a default value are filled
+/// in with it, and the secret is redacted.
+#[derive(serde::Serialize)]
+pub(crate) struct EffectiveConfig {
+    source_home: Option<PathBuf>,
+    target_home: PathBuf,
+    /// None means the default location, in the target home's data dir
+    mirror_db_path: Option<PathBuf>,
+    secret: Option<&'static str>,
+    target_key_type: Option<KeyType>,
+    stop_height: Option<BlockHeight>,
+    online_source: bool,
+    source_cold_storage: bool,
+    source_rpc: Option<String>,
+    source_rpc_client_cert: Option<crate::key_util::RpcClientCert>,
+    config_path: Option<PathBuf>,
+    config: MirrorConfig,
+    extra_key_overrides: BTreeMap<AccountId, PublicKey>,
+    method_renames: Vec<MethodRenameRule>,
+    account_map: crate::account_map::AccountMap,
+    strict_keys: bool,
+    adjust_gas: bool,
+    mirror_stakes: bool,
+    force_resubmit: bool,
+    action_filter: Option<crate::action_filter::ActionFilter>,
+    on_reorg: crate::reorg::OnReorg,
+    log_sent_txs: Option<PathBuf>,
+    health_addr: Option<std::net::SocketAddr>,
+    metrics_addr: Option<std::net::SocketAddr>,
+    prometheus_prefix: String,
+    expected_target_genesis: Option<CryptoHash>,
+    mirror_db_retention_blocks: Option<BlockHeight>,
+    nonce_report_interval: Option<Duration>,
+    replay_speed: Option<f64>,
+    max_in_flight: Option<usize>,
+    relayer: Option<AccountId>,
+    relayer_key: Option<PathBuf>,
+}
+
+/// Loads the mirror config the same way `mirror run` does, and returns it along with
+/// the other options it would run with.
+pub(crate) fn effective_config(options: MirrorOptions) -> anyhow::Result<EffectiveConfig> {
+    let config = MirrorConfig::load(options.config_path.as_ref())?.with_defaults();
+    let mut method_renames = options
+        .method_renames
+        .into_iter()
+        .map(|((receiver_id, old_method), new_method)| MethodRenameRule {
+            receiver_id,
+            old_method,
+            new_method,
+        })
+        .collect::<Vec<_>>();
+    method_renames
+        .sort_by(|a, b| (&a.receiver_id, &a.old_method).cmp(&(&b.receiver_id, &b.old_method)));
+    Ok(EffectiveConfig {
+        source_home: options.source_home,
+        target_home: options.target_home,
+        mirror_db_path: options.mirror_db_path,
+        secret: options.secret.map(|_| "<redacted>"),
+        target_key_type: options.target_key_type,
+        stop_height: options.stop_height,
+        online_source: options.online_source,
+        source_cold_storage: options.source_cold_storage,
+        source_rpc: options.source_rpc,
+        source_rpc_client_cert: options.source_rpc_client_cert,
+        config_path: options.config_path,
+        config,
+        extra_key_overrides: options.extra_key_overrides.into_iter().collect(),
+        method_renames,
+        account_map: options.account_map,
+        strict_keys: options.strict_keys,
+        adjust_gas: options.adjust_gas,
+        mirror_stakes: options.mirror_stakes,
+        force_resubmit: options.force_resubmit,
+        action_filter: options.action_filter,
+        on_reorg: options.on_reorg,
+        log_sent_txs: options.log_sent_txs,
+        health_addr: options.health_addr,
+        metrics_addr: options.metrics_addr,
+        prometheus_prefix: options.prometheus_prefix,
+        expected_target_genesis: options.expected_target_genesis,
+        mirror_db_retention_blocks: options.mirror_db_retention_blocks,
+        nonce_report_interval: options.nonce_report_interval,
+        replay_speed: options.replay_speed,
+        max_in_flight: options.max_in_flight,
+        relayer: options.relayer,
+        relayer_key: options.relayer_key,
+    })
+}
+
 #[derive(Clone, Copy, Debug)]
 enum MappedTxProvenance {
-    MappedSourceTx(BlockHeight, ShardId, usize),
+    MappedSourceTx(BlockHeight, ShardId, usize, CryptoHash),
     TxAddKey(BlockHeight, ShardId, usize),
     ReceiptAddKey(BlockHeight, ShardId, usize),
     TxCreateAccount(BlockHeight, ShardId, usize),
@@ -463,12 +1111,30 @@ impl MappedTxProvenance {
     fn is_unstake(&self) -> bool {
         matches!(self, MappedTxProvenance::Unstake(_))
     }
+
+    fn source_height(&self) -> Option<BlockHeight> {
+        match self {
+            Self::MappedSourceTx(height, _, _, _)
+            | Self::TxAddKey(height, _, _)
+            | Self::ReceiptAddKey(height, _, _)
+            | Self::TxCreateAccount(height, _, _)
+            | Self::ReceiptCreateAccount(height, _, _) => Some(*height),
+            Self::Unstake(_) => None,
+        }
+    }
+
+    fn source_tx_hash(&self) -> Option<&CryptoHash> {
+        match self {
+            Self::MappedSourceTx(_, _, _, hash) => Some(hash),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for MappedTxProvenance {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::MappedSourceTx(height, shard_id, idx) => {
+            Self::MappedSourceTx(height, shard_id, idx, _) => {
                 write!(f, "source #{} shard {} tx #{}", height, shard_id, idx)
             }
             Self::TxAddKey(height, shard_id, idx) => {
@@ -553,9 +1219,12 @@ struct MappedTx {
     source_signer_id: AccountId,
     source_receiver_id: AccountId,
     provenance: MappedTxProvenance,
+    target_secret_key: SecretKey,
     target_tx: SignedTransaction,
     nonce_updates: HashSet<(AccountId, PublicKey)>,
     sent_successfully: bool,
+    // if we sent this through the relayer, the hash of the relayer's transaction wrapping it
+    relayed_tx_hash: Option<CryptoHash>,
 }
 
 impl MappedTx {
@@ -588,9 +1257,11 @@ impl MappedTx {
             source_signer_id,
             source_receiver_id,
             provenance,
+            target_secret_key: target_secret_key.clone(),
             target_tx,
             nonce_updates,
             sent_successfully: false,
+            relayed_tx_hash: None,
         }
     }
 
@@ -600,6 +1271,14 @@ impl MappedTx {
         self.target_tx =
             SignedTransaction::new(target_secret_key.sign(&tx.get_hash_and_size().0.as_ref()), tx);
     }
+
+    // Whether we can send this through the relayer. Transactions we added ourselves and ones
+    // that add keys we need the nonces of are sent as is, since we look for their effects
+    // one receipt after they're included, and a delegate action adds a receipt in between.
+    fn can_relay(&self) -> bool {
+        matches!(self.provenance, MappedTxProvenance::MappedSourceTx(..))
+            && self.nonce_updates.is_empty()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -621,9 +1300,11 @@ impl TargetChainTx {
                     source_signer_id: t.source_signer_id.clone(),
                     source_receiver_id: t.source_receiver_id.clone(),
                     provenance: t.provenance,
+                    target_secret_key: t.target_secret_key.clone(),
                     target_tx,
                     nonce_updates: t.nonce_updates.clone(),
                     sent_successfully: false,
+                    relayed_tx_hash: None,
                 });
             }
             Self::Ready(_) => unreachable!(),
@@ -732,6 +1413,8 @@ struct MappedChunk {
 struct MappedBlock {
     source_height: BlockHeight,
     source_hash: CryptoHash,
+    // None for the extra transactions we send that don't come from a source chain block
+    source_timestamp: Option<u64>,
     chunks: Vec<MappedChunk>,
 }
 
@@ -739,6 +1422,7 @@ struct MappedBlock {
 struct TxBatch {
     source_height: BlockHeight,
     source_hash: CryptoHash,
+    source_timestamp: Option<u64>,
     txs: Vec<(TxRef, TargetChainTx)>,
 }
 
@@ -747,6 +1431,7 @@ impl From<&MappedBlock> for TxBatch {
         Self {
             source_height: block.source_height,
             source_hash: block.source_hash,
+            source_timestamp: block.source_timestamp,
             txs: block
                 .chunks
                 .iter()
@@ -767,6 +1452,28 @@ impl From<&MappedBlock> for TxBatch {
     }
 }
 
+async fn target_gas_price(view_client: &Addr<ViewClientActor>) -> anyhow::Result<Balance> {
+    Ok(view_client
+        .send(GetBlock(BlockReference::Finality(Finality::None)).with_span_context())
+        .await
+        .unwrap()
+        .context("failed fetching target chain HEAD")?
+        .header
+        .gas_price)
+}
+
+// Returns the gas to attach to a FunctionCall on the target chain so that its prepaid cost
+// there is no more than what it was on the source chain. We only ever scale the gas down,
+// since attaching more gas than the source transaction did could make it fail with
+// insufficient balance or exceed the max prepaid gas.
+fn adjust_attached_gas(gas: Gas, source_gas_price: Balance, target_gas_price: Balance) -> Gas {
+    if target_gas_price <= source_gas_price {
+        return gas;
+    }
+    // gas * source_gas_price / target_gas_price < gas, so this fits in a Gas
+    (u128::from(gas) * source_gas_price / target_gas_price) as Gas
+}
+
 async fn account_exists(
     view_client: &Addr<ViewClientActor>,
     account_id: &AccountId,
@@ -829,15 +1536,18 @@ async fn fetch_access_key_nonce(
 impl<T: ChainAccess> TxMirror<T> {
     fn new(
         source_chain_access: T,
-        target_home: &Path,
-        mirror_db_path: Option<&Path>,
-        secret: Option<[u8; crate::secret::SECRET_LEN]>,
+        options: &MirrorOptions,
+        sent_txs_log: Option<crate::sent_txs_log::SentTxsLog>,
+        nonce_report: Option<crate::nonce_report::NonceReport>,
+        relayer: Option<crate::relayer::Relayer>,
+        health: Arc<crate::health::HealthState>,
         config: MirrorConfig,
     ) -> anyhow::Result<Self> {
+        let target_home = options.target_home.as_path();
         let target_config =
             nearcore::config::load_config(target_home, GenesisValidationMode::UnsafeFast)
                 .with_context(|| format!("Error loading target config from {:?}", target_home))?;
-        let db = match mirror_db_path {
+        let db = match &options.mirror_db_path {
             Some(mirror_db_path) => open_db(mirror_db_path),
             None => {
                 // keep backward compatibility
@@ -853,62 +1563,211 @@ impl<T: ChainAccess> TxMirror<T> {
         };
         let db = db.context("failed to open mirror DB")?;
         let db = Arc::new(db);
-        let default_extra_key = crate::key_mapping::default_extra_key(secret.as_ref());
+        let default_extra_key =
+            crate::key_mapping::default_extra_key(options.secret.as_ref(), options.target_key_type);
 
         Ok(Self {
             source_chain_access,
             db,
             target_genesis_height: target_config.genesis.config.genesis_height,
+            target_chain_id: target_config.genesis.config.chain_id.clone(),
             target_min_block_production_delay: target_config
                 .client_config
                 .min_block_production_delay
                 .unsigned_abs(),
-            secret,
+            secret: options.secret,
+            target_key_type: options.target_key_type,
             default_extra_key,
+            extra_key_overrides: options.extra_key_overrides.clone(),
+            method_renames: options.method_renames.clone(),
+            account_map: options.account_map.clone(),
+            send_backoff: Arc::new(Mutex::new(crate::send_backoff::SendBackoff::new(
+                config.tx_send_max_backoff,
+            ))),
+            health,
+            sent_txs_log: sent_txs_log.map(|log| Arc::new(Mutex::new(log))),
+            nonce_report,
+            relayer,
+            strict_keys: options.strict_keys,
+            adjust_gas: options.adjust_gas,
+            mirror_stakes: options.mirror_stakes,
+            force_resubmit: options.force_resubmit,
+            action_filter: options.action_filter.clone(),
+            reorg_detector: crate::reorg::ReorgDetector::new(),
+            on_reorg: options.on_reorg,
+            config_path: options.config_path.clone(),
             config,
         })
     }
 
+    // The target chain account ID of the source chain account `account_id`
+    fn map_account(&self, account_id: &AccountId) -> AccountId {
+        self.account_map.map_account(account_id, self.secret.as_ref())
+    }
+
+    // The type of key to map the access keys of source chain account `account_id` to
+    fn key_type(&self, account_id: &AccountId) -> Option<KeyType> {
+        crate::key_mapping::account_key_type(account_id, self.target_key_type)
+    }
+
+    // The full access key we add to target chain accounts that would otherwise be
+    // created without one
+    fn extra_key(&self, target_account_id: &AccountId) -> PublicKey {
+        match self.extra_key_overrides.get(target_account_id) {
+            Some(public_key) => public_key.clone(),
+            None => self.default_extra_key.public_key(),
+        }
+    }
+
+    // Sends the transaction and returns the reason it was rejected if that's something that
+    // might go away if we send it again later.
+    async fn send_transaction(
+        target_client: &Addr<TxRequestHandlerActor>,
+        sent_txs_log: Option<&Mutex<crate::sent_txs_log::SentTxsLog>>,
+        nonce_report: Option<&crate::nonce_report::NonceReport>,
+        relayer: Option<&crate::relayer::Relayer>,
+        tx: &mut MappedTx,
+    ) -> anyhow::Result<Option<&'static str>> {
+        let relayed_tx = match relayer {
+            Some(relayer) if tx.can_relay() => relayer.wrap(&tx.target_tx, &tx.target_secret_key),
+            _ => None,
+        };
+        tx.relayed_tx_hash = relayed_tx.as_ref().map(|t| t.get_hash());
+        match target_client
+            .send(
+                ProcessTxRequest {
+                    transaction: relayed_tx.unwrap_or_else(|| tx.target_tx.clone()),
+                    is_forwarded: false,
+                    check_only: false,
+                }
+                .with_span_context(),
+            )
+            .await?
+        {
+            ProcessTxResponse::RequestRouted => {
+                crate::metrics::TRANSACTIONS_SENT.with_label_values(&["ok"]).inc();
+                tx.sent_successfully = true;
+                if let Some(sent_txs_log) = sent_txs_log {
+                    sent_txs_log
+                        .lock()
+                        .unwrap()
+                        .record(
+                            tx.provenance.source_tx_hash(),
+                            tx.target_tx.transaction.signer_id(),
+                            tx.target_tx.transaction.receiver_id(),
+                            tx.target_tx.transaction.nonce(),
+                            tx.provenance.source_height(),
+                            std::time::SystemTime::now(),
+                        )
+                        .context("failed writing to the sent transactions log")?;
+                }
+                if let Some(nonce_report) = nonce_report {
+                    nonce_report.on_tx_sent(
+                        tx.target_tx.transaction.signer_id(),
+                        tx.target_tx.transaction.public_key(),
+                        tx.target_tx.transaction.nonce(),
+                    );
+                }
+            }
+            ProcessTxResponse::InvalidTx(e) => {
+                if let Some(reason) = crate::send_backoff::retryable_error(&e) {
+                    tracing::debug!(
+                        target: "mirror", "Target node rejected tx for ({}, {:?}) from {}: {:?}",
+                        tx.target_tx.transaction.signer_id(), tx.target_tx.transaction.public_key(), &tx.provenance, e
+                    );
+                    return Ok(Some(reason));
+                }
+                // TODO: here if we're getting an error because the tx was already included, it is possible
+                // that some other instance of this code ran and made progress already. For now we can assume
+                // only once instance of this code will run, but this is the place to detect if that's not the case.
+                tracing::error!(
+                    target: "mirror", "Tried to send an invalid tx for ({}, {:?}) from {}: {:?}",
+                    tx.target_tx.transaction.signer_id(), tx.target_tx.transaction.public_key(), &tx.provenance, e
+                );
+                crate::metrics::TRANSACTIONS_SENT.with_label_values(&["invalid"]).inc();
+            }
+            r => {
+                tracing::error!(
+                    target: "mirror", "Unexpected response sending tx from {}: {:?}. The transaction was not sent",
+                    &tx.provenance, r
+                );
+                crate::metrics::TRANSACTIONS_SENT.with_label_values(&["internal_error"]).inc();
+            }
+        }
+        Ok(None)
+    }
+
+    // Records a rejected submission of `tx`, and returns whether we should try sending it again.
+    fn on_send_failure(
+        send_backoff: &Mutex<crate::send_backoff::SendBackoff>,
+        tx: &MappedTx,
+        reason: &'static str,
+        attempts: u32,
+    ) -> bool {
+        let signer_id = tx.target_tx.transaction.signer_id();
+        let retry_at = send_backoff.lock().unwrap().on_failure(signer_id);
+        if attempts < crate::send_backoff::MAX_SEND_ATTEMPTS {
+            crate::metrics::TRANSACTION_SEND_RETRIES.with_label_values(&[reason]).inc();
+            tracing::debug!(
+                target: "mirror", "Will retry sending tx for ({}, {:?}) from {} in {:?}",
+                signer_id, tx.target_tx.transaction.public_key(), &tx.provenance,
+                retry_at.saturating_duration_since(tokio::time::Instant::now())
+            );
+            true
+        } else {
+            tracing::error!(
+                target: "mirror", "Giving up on sending tx for ({}, {:?}) from {} after {} attempts. Last error: {}",
+                signer_id, tx.target_tx.transaction.public_key(), &tx.provenance, attempts, reason
+            );
+            crate::metrics::TRANSACTIONS_SENT.with_label_values(&["invalid"]).inc();
+            false
+        }
+    }
+
     async fn send_transactions<'a, I: Iterator<Item = &'a mut TargetChainTx>>(
         target_client: &Addr<TxRequestHandlerActor>,
+        send_backoff: &Mutex<crate::send_backoff::SendBackoff>,
+        sent_txs_log: Option<&Mutex<crate::sent_txs_log::SentTxsLog>>,
+        nonce_report: Option<&crate::nonce_report::NonceReport>,
+        relayer: Option<&crate::relayer::Relayer>,
         txs: I,
     ) -> anyhow::Result<()> {
+        // Transactions we'll send after waiting for their signer's backoff window to end, along
+        // with the number of times we've tried sending them so far.
+        let mut retries: HashMap<AccountId, VecDeque<(&'a mut MappedTx, u32)>> = HashMap::new();
+
         for tx in txs {
             match tx {
                 TargetChainTx::Ready(tx) => {
-                    match target_client
-                        .send(
-                            ProcessTxRequest {
-                                transaction: tx.target_tx.clone(),
-                                is_forwarded: false,
-                                check_only: false,
-                            }
-                            .with_span_context(),
-                        )
-                        .await?
+                    let signer_id = tx.target_tx.transaction.signer_id().clone();
+                    // Keep the order of transactions from the same account, since sending a later
+                    // one first would make the nonce of the earlier one invalid.
+                    if let Some(queue) = retries.get_mut(&signer_id) {
+                        queue.push_back((tx, 0));
+                        continue;
+                    }
+                    if send_backoff.lock().unwrap().retry_at(&signer_id).is_some() {
+                        retries.entry(signer_id).or_default().push_back((tx, 0));
+                        continue;
+                    }
+                    match Self::send_transaction(
+                        target_client,
+                        sent_txs_log,
+                        nonce_report,
+                        relayer,
+                        tx,
+                    )
+                    .await?
                     {
-                        ProcessTxResponse::RequestRouted => {
-                            crate::metrics::TRANSACTIONS_SENT.with_label_values(&["ok"]).inc();
-                            tx.sent_successfully = true;
-                        }
-                        ProcessTxResponse::InvalidTx(e) => {
-                            // TODO: here if we're getting an error because the tx was already included, it is possible
-                            // that some other instance of this code ran and made progress already. For now we can assume
-                            // only once instance of this code will run, but this is the place to detect if that's not the case.
-                            tracing::error!(
-                                target: "mirror", "Tried to send an invalid tx for ({}, {:?}) from {}: {:?}",
-                                tx.target_tx.transaction.signer_id(), tx.target_tx.transaction.public_key(), &tx.provenance, e
-                            );
-                            crate::metrics::TRANSACTIONS_SENT.with_label_values(&["invalid"]).inc();
+                        Some(reason) => {
+                            if Self::on_send_failure(send_backoff, tx, reason, 1) {
+                                retries.entry(signer_id).or_default().push_back((tx, 1));
+                            }
                         }
-                        r => {
-                            tracing::error!(
-                                target: "mirror", "Unexpected response sending tx from {}: {:?}. The transaction was not sent",
-                                &tx.provenance, r
-                            );
-                            crate::metrics::TRANSACTIONS_SENT
-                                .with_label_values(&["internal_error"])
-                                .inc();
+                        None => {
+                            if tx.sent_successfully {
+                                send_backoff.lock().unwrap().on_success(&signer_id);
+                            }
                         }
                     }
                 }
@@ -921,56 +1780,104 @@ impl<T: ChainAccess> TxMirror<T> {
                 }
             }
         }
+
+        while !retries.is_empty() {
+            // Go on with the account whose backoff window ends first.
+            let (signer_id, retry_at) = {
+                let send_backoff = send_backoff.lock().unwrap();
+                retries
+                    .keys()
+                    .map(|account_id| (account_id.clone(), send_backoff.retry_at(account_id)))
+                    .min_by_key(|(_, retry_at)| *retry_at)
+                    .unwrap()
+            };
+            if let Some(retry_at) = retry_at {
+                tokio::time::sleep_until(retry_at).await;
+            }
+            let queue = retries.get_mut(&signer_id).unwrap();
+            let (tx, attempts) = queue.front_mut().unwrap();
+            match Self::send_transaction(target_client, sent_txs_log, nonce_report, relayer, tx)
+                .await?
+            {
+                Some(reason) => {
+                    *attempts += 1;
+                    if !Self::on_send_failure(send_backoff, tx, reason, *attempts) {
+                        queue.pop_front();
+                    }
+                }
+                None => {
+                    if tx.sent_successfully {
+                        send_backoff.lock().unwrap().on_success(&signer_id);
+                    }
+                    queue.pop_front();
+                }
+            }
+            if queue.is_empty() {
+                retries.remove(&signer_id);
+            }
+        }
+        if let Some(sent_txs_log) = sent_txs_log {
+            sent_txs_log
+                .lock()
+                .unwrap()
+                .flush()
+                .context("failed writing to the sent transactions log")?;
+        }
         Ok(())
     }
 
     async fn map_actions(
         &self,
         target_view_client: &Addr<ViewClientActor>,
-        tx: &SignedTransaction,
+        tx: &SourceTransaction,
+        gas_prices: Option<(Balance, Balance)>,
     ) -> anyhow::Result<(Vec<Action>, HashSet<(AccountId, PublicKey)>)> {
         let mut actions = Vec::new();
         let mut nonce_updates = HashSet::new();
 
         let mut account_created = false;
         let mut full_key_added = false;
-        for action in tx.transaction.actions().iter() {
+        for action in tx.actions.iter() {
+            if let Some(action_filter) = &self.action_filter {
+                if !action_filter.allows_action(action) {
+                    continue;
+                }
+            }
             match &action {
                 Action::AddKey(add_key) => {
                     if add_key.access_key.permission == AccessKeyPermission::FullAccess {
                         full_key_added = true;
                     }
-                    let public_key =
-                        crate::key_mapping::map_key(&add_key.public_key, self.secret.as_ref())
-                            .public_key();
-                    let receiver_id = crate::key_mapping::map_account(
-                        &tx.transaction.receiver_id(),
+                    let public_key = crate::key_mapping::map_key(
+                        &add_key.public_key,
                         self.secret.as_ref(),
-                    );
+                        self.key_type(&tx.receiver_id),
+                    )
+                    .public_key();
+                    let receiver_id = self.map_account(&tx.receiver_id);
 
                     nonce_updates.insert((receiver_id, public_key.clone()));
                     actions.push(Action::AddKey(Box::new(AddKeyAction {
                         public_key,
-                        access_key: add_key.access_key.clone(),
+                        access_key: self.account_map.map_access_key(&add_key.access_key),
                     })));
                 }
                 Action::DeleteKey(delete_key) => {
-                    let replacement =
-                        crate::key_mapping::map_key(&delete_key.public_key, self.secret.as_ref());
+                    let replacement = crate::key_mapping::map_key(
+                        &delete_key.public_key,
+                        self.secret.as_ref(),
+                        self.key_type(&tx.receiver_id),
+                    );
                     let public_key = replacement.public_key();
 
                     actions.push(Action::DeleteKey(Box::new(DeleteKeyAction { public_key })));
                 }
                 Action::Transfer(_) => {
                     // TODO(eth-implicit) Change back to is_implicit() when ETH-implicit accounts are supported.
-                    if tx.transaction.receiver_id().get_account_type()
-                        == AccountType::NearImplicitAccount
-                        && tx.transaction.actions().len() == 1
+                    if tx.receiver_id.get_account_type() == AccountType::NearImplicitAccount
+                        && tx.actions.len() == 1
                     {
-                        let target_account = crate::key_mapping::map_account(
-                            &tx.transaction.receiver_id(),
-                            self.secret.as_ref(),
-                        );
+                        let target_account = self.map_account(&tx.receiver_id);
                         if !account_exists(target_view_client, &target_account).await.with_context(
                             || format!("failed checking existence for account {}", &target_account),
                         )? {
@@ -985,27 +1892,83 @@ impl<T: ChainAccess> TxMirror<T> {
                     }
                     actions.push(action.clone());
                 }
-                // We don't want to mess with the set of validators in the target chain
-                Action::Stake(_) => {}
+                // Unless asked to, we don't want to mess with the set of validators in the target chain
+                Action::Stake(stake) => {
+                    if self.mirror_stakes {
+                        let public_key = crate::key_mapping::map_key(
+                            &stake.public_key,
+                            self.secret.as_ref(),
+                            self.target_key_type,
+                        )
+                        .public_key();
+                        actions.push(Action::Stake(Box::new(StakeAction {
+                            stake: stake.stake,
+                            public_key,
+                        })));
+                    }
+                }
                 Action::CreateAccount(_) => {
                     account_created = true;
                     actions.push(action.clone());
                 }
                 Action::DeleteAccount(d) => {
                     actions.push(Action::DeleteAccount(DeleteAccountAction {
-                        beneficiary_id: crate::key_mapping::map_account(
-                            &d.beneficiary_id,
-                            self.secret.as_ref(),
-                        ),
+                        beneficiary_id: self.map_account(&d.beneficiary_id),
                     }));
                 }
-                // TODO: handle delegate actions
+                Action::FunctionCall(call) => {
+                    let target_receiver_id = self.map_account(&tx.receiver_id);
+                    let method_name = self
+                        .method_renames
+                        .get(&(target_receiver_id, call.method_name.clone()))
+                        .unwrap_or(&call.method_name)
+                        .clone();
+                    let gas = match gas_prices {
+                        Some((source_gas_price, target_gas_price)) => {
+                            adjust_attached_gas(call.gas, source_gas_price, target_gas_price)
+                        }
+                        None => call.gas,
+                    };
+                    let args = if self.mirror_stakes
+                        && STAKING_POOL_KEY_METHODS.contains(&call.method_name.as_str())
+                    {
+                        map_staking_pool_args(
+                            &call.args,
+                            self.secret.as_ref(),
+                            self.target_key_type,
+                        )
+                        .unwrap_or_else(|| call.args.clone())
+                    } else {
+                        call.args.clone()
+                    };
+                    let args = self.account_map.map_args(&args).unwrap_or(args);
+                    actions.push(Action::FunctionCall(Box::new(FunctionCallAction {
+                        method_name,
+                        gas,
+                        args,
+                        ..(**call).clone()
+                    })));
+                }
+                Action::Delegate(delegate) => {
+                    let target_receiver_id =
+                        self.map_account(&delegate.delegate_action.receiver_id);
+                    if let Some(a) = crate::genesis::map_delegate_action(
+                        delegate,
+                        &self.account_map,
+                        self.secret.as_ref(),
+                        self.target_key_type,
+                        &self.extra_key(&target_receiver_id),
+                    ) {
+                        actions.push(a);
+                    }
+                }
                 _ => actions.push(action.clone()),
             };
         }
         if account_created && !full_key_added {
+            let target_receiver_id = self.map_account(&tx.receiver_id);
             actions.push(Action::AddKey(Box::new(AddKeyAction {
-                public_key: self.default_extra_key.public_key(),
+                public_key: self.extra_key(&target_receiver_id),
                 access_key: AccessKey::full_access(),
             })));
         }
@@ -1107,8 +2070,7 @@ impl<T: ChainAccess> TxMirror<T> {
         provenance: MappedTxProvenance,
         source_height: Option<BlockHeight>,
     ) -> anyhow::Result<()> {
-        let target_signer_id =
-            crate::key_mapping::map_account(&predecessor_id, self.secret.as_ref());
+        let target_signer_id = self.map_account(&predecessor_id);
 
         let target_secret_key = match self
             .source_chain_access
@@ -1119,7 +2081,11 @@ impl<T: ChainAccess> TxMirror<T> {
                 let mut key = None;
                 let mut first_key = None;
                 for k in keys.iter() {
-                    let target_secret_key = crate::key_mapping::map_key(k, self.secret.as_ref());
+                    let target_secret_key = crate::key_mapping::map_key(
+                        k,
+                        self.secret.as_ref(),
+                        self.key_type(&predecessor_id),
+                    );
                     if fetch_access_key_nonce(
                         target_view_client,
                         &target_signer_id,
@@ -1149,10 +2115,17 @@ impl<T: ChainAccess> TxMirror<T> {
                 match key {
                     Some(key) => key,
                     None => {
-                        tracing::debug!(
-                            target: "mirror", "trying to prepare a transaction with the default extra key for {} because no full access key for {} in the source chain is known at block {}",
-                            &provenance, &target_signer_id, &block_hash,
-                        );
+                        if self.extra_key_overrides.contains_key(&target_signer_id) {
+                            tracing::warn!(
+                                target: "mirror", "preparing a transaction for {} with the default extra key even though {} has an overridden extra key we can't sign with",
+                                &provenance, &target_signer_id,
+                            );
+                        } else {
+                            tracing::debug!(
+                                target: "mirror", "trying to prepare a transaction with the default extra key for {} because no full access key for {} in the source chain is known at block {}",
+                                &provenance, &target_signer_id, &block_hash,
+                            );
+                        }
                         self.default_extra_key.clone()
                     }
                 }
@@ -1164,8 +2137,7 @@ impl<T: ChainAccess> TxMirror<T> {
             }
         };
 
-        let target_receiver_id =
-            crate::key_mapping::map_account(&receiver_id, self.secret.as_ref());
+        let target_receiver_id = self.map_account(&receiver_id);
 
         let mut nonce_updates = HashSet::new();
         let mut target_actions = Vec::new();
@@ -1178,14 +2150,17 @@ impl<T: ChainAccess> TxMirror<T> {
                     if a.access_key.permission == AccessKeyPermission::FullAccess {
                         full_key_added = true;
                     }
-                    let target_public_key =
-                        crate::key_mapping::map_key(&a.public_key, self.secret.as_ref())
-                            .public_key();
+                    let target_public_key = crate::key_mapping::map_key(
+                        &a.public_key,
+                        self.secret.as_ref(),
+                        self.key_type(&receiver_id),
+                    )
+                    .public_key();
 
                     nonce_updates.insert((target_receiver_id.clone(), target_public_key.clone()));
                     target_actions.push(Action::AddKey(Box::new(AddKeyAction {
                         public_key: target_public_key,
-                        access_key: a.access_key.clone(),
+                        access_key: self.account_map.map_access_key(&a.access_key),
                     })));
                 }
                 Action::CreateAccount(_) => {
@@ -1207,7 +2182,7 @@ impl<T: ChainAccess> TxMirror<T> {
         }
         if account_created && !full_key_added {
             target_actions.push(Action::AddKey(Box::new(AddKeyAction {
-                public_key: self.default_extra_key.public_key(),
+                public_key: self.extra_key(&target_receiver_id),
                 access_key: AccessKey::full_access(),
             })));
         }
@@ -1344,7 +2319,7 @@ impl<T: ChainAccess> TxMirror<T> {
 
     async fn add_tx_function_call_keys(
         &self,
-        tx: &SignedTransaction,
+        tx: &SourceTransaction,
         provenance: MappedTxProvenance,
         source_height: BlockHeight,
         ref_hash: &CryptoHash,
@@ -1356,13 +2331,13 @@ impl<T: ChainAccess> TxMirror<T> {
         // if signer and receiver are the same then the resulting local receipt
         // is only logically included, and we won't see it in the receipts in any chunk,
         // so handle that case here
-        if tx.transaction.signer_id() == tx.transaction.receiver_id()
-            && tx.transaction.actions().iter().any(|a| matches!(a, Action::FunctionCall(_)))
+        if tx.signer_id == tx.receiver_id
+            && tx.actions.iter().any(|a| matches!(a, Action::FunctionCall(_)))
         {
-            let tx_hash = tx.get_hash();
+            let tx_hash = tx.hash;
             if let Some(receipt_id) = self
                 .source_chain_access
-                .get_tx_receipt_id(&tx_hash, &tx.transaction.signer_id())
+                .get_tx_receipt_id(&tx_hash, &tx.signer_id)
                 .await
                 .with_context(|| format!("failed fetching local receipt ID for tx {}", &tx_hash))?
             {
@@ -1372,7 +2347,7 @@ impl<T: ChainAccess> TxMirror<T> {
                     target_view_client,
                     txs,
                     &receipt_id,
-                    &tx.transaction.receiver_id(),
+                    &tx.receiver_id,
                     ref_hash,
                     provenance,
                     source_height,
@@ -1473,7 +2448,7 @@ impl<T: ChainAccess> TxMirror<T> {
     // set of transactions that should be valid in the target chain
     // from it.
     async fn fetch_txs(
-        &self,
+        &mut self,
         source_height: BlockHeight,
         create_account_height: Option<BlockHeight>,
         ref_hash: CryptoHash,
@@ -1485,46 +2460,98 @@ impl<T: ChainAccess> TxMirror<T> {
             self.source_chain_access.get_txs(source_height).await.with_context(|| {
                 format!("Failed fetching chunks for source chain #{}", source_height)
             })?;
+        self.check_source_reorg(source_height, &source_block).await?;
+
+        let gas_prices = if self.adjust_gas {
+            let target_gas_price = target_gas_price(target_view_client).await?;
+            Some((source_block.gas_price, target_gas_price))
+        } else {
+            None
+        };
 
         let mut chunks = Vec::new();
         for ch in source_block.chunks {
             let mut txs = Vec::new();
 
             for (idx, source_tx) in ch.transactions.into_iter().enumerate() {
+                if !self.force_resubmit {
+                    if let Some(sent_height) = read_sent_source_tx(&self.db, &source_tx.hash)? {
+                        tracing::info!(
+                            target: "mirror", "skipping source chain transaction {} at #{} because it was already sent at #{}",
+                            source_tx.hash, source_height, sent_height
+                        );
+                        crate::metrics::ALREADY_SENT_TXS.inc();
+                        continue;
+                    }
+                }
+                let target_private_key = match crate::key_mapping::try_map_key(
+                    &source_tx.public_key,
+                    self.secret.as_ref(),
+                    self.key_type(&source_tx.signer_id),
+                ) {
+                    Ok(k) => k,
+                    Err(e) => {
+                        if self.strict_keys {
+                            return Err(e).with_context(|| {
+                                format!(
+                                    "Failed mapping the key of source chain transaction {}",
+                                    source_tx.hash
+                                )
+                            });
+                        }
+                        tracing::warn!(
+                            target: "mirror", "skipping source chain transaction {} at #{}: {}",
+                            source_tx.hash, source_height, e
+                        );
+                        crate::metrics::UNMAPPABLE_TXS.inc();
+                        continue;
+                    }
+                };
+                if let Some(action_filter) = &self.action_filter {
+                    if !action_filter.allows_tx(&source_tx.actions) {
+                        tracing::debug!(
+                            target: "mirror", "skipping source chain transaction {} at #{} because of --action-types",
+                            source_tx.hash, source_height
+                        );
+                        continue;
+                    }
+                }
                 let (actions, nonce_updates) =
-                    self.map_actions(target_view_client, &source_tx).await?;
+                    self.map_actions(target_view_client, &source_tx, gas_prices).await?;
                 if actions.is_empty() {
                     // If this is a tx containing only stake actions, skip it.
                     continue;
                 }
-                let target_private_key = crate::key_mapping::map_key(
-                    &source_tx.transaction.public_key(),
-                    self.secret.as_ref(),
-                );
 
-                let target_signer_id = crate::key_mapping::map_account(
-                    &source_tx.transaction.signer_id(),
-                    self.secret.as_ref(),
-                );
-                let target_receiver_id = crate::key_mapping::map_account(
-                    &source_tx.transaction.receiver_id(),
-                    self.secret.as_ref(),
-                );
+                let target_signer_id = self.map_account(&source_tx.signer_id);
+                let target_receiver_id = self.map_account(&source_tx.receiver_id);
+                if let Some(nonce_report) = &self.nonce_report {
+                    nonce_report.on_source_tx(
+                        &target_signer_id,
+                        &target_private_key.public_key(),
+                        source_tx.nonce,
+                    );
+                }
 
                 let target_tx = self
                     .prepare_tx(
                         tracker,
                         tx_block_queue,
                         target_view_client,
-                        source_tx.transaction.signer_id().clone(),
-                        source_tx.transaction.receiver_id().clone(),
+                        source_tx.signer_id.clone(),
+                        source_tx.receiver_id.clone(),
                         target_signer_id,
                         target_receiver_id,
                         &target_private_key,
                         actions,
                         &ref_hash,
                         Some(source_height),
-                        MappedTxProvenance::MappedSourceTx(source_height, ch.shard_id, idx),
+                        MappedTxProvenance::MappedSourceTx(
+                            source_height,
+                            ch.shard_id,
+                            idx,
+                            source_tx.hash,
+                        ),
                         nonce_updates,
                     )
                     .await?;
@@ -1581,7 +2608,58 @@ impl<T: ChainAccess> TxMirror<T> {
                 );
             }
         }
-        Ok(MappedBlock { source_height, source_hash: source_block.hash, chunks })
+        Ok(MappedBlock {
+            source_height,
+            source_hash: source_block.hash,
+            source_timestamp: Some(source_block.timestamp),
+            chunks,
+        })
+    }
+
+    // Checks that the source chain block we just fetched builds on the last one we fetched. If
+    // it doesn't, the source chain has reorged, and the transactions we already sent for the
+    // blocks that are no longer on it mean the target chain has diverged.
+    async fn check_source_reorg(
+        &mut self,
+        source_height: BlockHeight,
+        source_block: &SourceBlock,
+    ) -> anyhow::Result<()> {
+        let Some(fetched_blocks) =
+            self.reorg_detector.on_block(source_height, source_block.hash, source_block.prev_hash)
+        else {
+            return Ok(());
+        };
+        let mut orphaned_heights = Vec::new();
+        for (height, hash) in fetched_blocks {
+            match self.source_chain_access.block_height_to_hash(height).await {
+                Ok(h) if h == hash => {}
+                Ok(_) | Err(ChainError::Unknown) => orphaned_heights.push(height),
+                Err(ChainError::Other(e)) => {
+                    return Err(e).with_context(|| {
+                        format!("failed checking source chain block #{} after a reorg", height)
+                    });
+                }
+            }
+        }
+        crate::metrics::SOURCE_REORGS.inc();
+        let affected = match (orphaned_heights.first(), orphaned_heights.last()) {
+            (Some(first), Some(last)) => format!("#{} to #{}", first, last),
+            // the reorg is deeper than the blocks we remember
+            _ => format!("before #{}", source_height),
+        };
+        tracing::error!(
+            target: "mirror",
+            "source chain reorg detected at #{}: block {} doesn't build on the last block we mirrored. \
+             Transactions already sent for source blocks {} may have diverged the target chain",
+            source_height, source_block.hash, affected,
+        );
+        match self.on_reorg {
+            crate::reorg::OnReorg::Warn => Ok(()),
+            crate::reorg::OnReorg::Halt => anyhow::bail!(
+                "source chain reorg affecting blocks {}, halting because of --on-reorg=halt",
+                affected
+            ),
+        }
     }
 
     // Up to a certain capacity, prepare and queue up batches of
@@ -1594,8 +2672,20 @@ impl<T: ChainAccess> TxMirror<T> {
         tx_block_queue: &Mutex<VecDeque<MappedBlock>>,
         target_view_client: &Addr<ViewClientActor>,
         ref_hash: CryptoHash,
+        target_height: BlockHeight,
         have_stop_height: bool,
+        max_in_flight: Option<usize>,
     ) -> anyhow::Result<()> {
+        if let Some(max_in_flight) = max_in_flight {
+            let num_in_flight = tracker.lock().unwrap().num_in_flight_txs(target_height);
+            if num_in_flight >= max_in_flight {
+                tracing::debug!(
+                    target: "mirror", "{} transactions in flight, not reading new source blocks until some of them appear on the target chain",
+                    num_in_flight
+                );
+                return Ok(());
+            }
+        }
         let mut num_blocks_queued = {
             let tx_block_queue = tx_block_queue.lock().unwrap();
             tx_block_queue.len()
@@ -1618,24 +2708,29 @@ impl<T: ChainAccess> TxMirror<T> {
             if !have_stop_height && create_account_height.is_none() {
                 return Ok(());
             }
-            let b = self
-                .fetch_txs(
-                    next_height,
-                    create_account_height,
-                    ref_hash,
+            let span = mirror_block_span(next_height, target_height);
+            async {
+                let b = self
+                    .fetch_txs(
+                        next_height,
+                        create_account_height,
+                        ref_hash,
+                        tracker,
+                        tx_block_queue,
+                        target_view_client,
+                    )
+                    .await
+                    .with_context(|| format!("Can't fetch source #{} transactions", next_height))?;
+                crate::chain_tracker::TxTracker::queue_block(
                     tracker,
-                    tx_block_queue,
+                    &tx_block_queue,
+                    b,
                     target_view_client,
+                    &self.db,
                 )
                 .await
-                .with_context(|| format!("Can't fetch source #{} transactions", next_height))?;
-            crate::chain_tracker::TxTracker::queue_block(
-                tracker,
-                &tx_block_queue,
-                b,
-                target_view_client,
-                &self.db,
-            )
+            }
+            .instrument(span)
             .await?;
 
             num_blocks_queued += 1;
@@ -1647,7 +2742,8 @@ impl<T: ChainAccess> TxMirror<T> {
 
     // send stake txs for zero stake for each of the stake actions we just saw in
     // the last block's processed receipts. These would have come from function calls
-    // rather than normal stake txs, since we skip sending those.
+    // rather than normal stake txs, since we skip sending those. With --mirror-stakes
+    // we leave them alone.
     // TODO: here we're just sending it and forgetting about it, but would be good to
     // retry later if the tx got lost for some reason
     async fn unstake(
@@ -1661,6 +2757,9 @@ impl<T: ChainAccess> TxMirror<T> {
         target_hash: &CryptoHash,
         target_height: BlockHeight,
     ) -> anyhow::Result<()> {
+        if self.mirror_stakes {
+            return Ok(());
+        }
         let mut txs = Vec::new();
         for ((receiver_id, public_key), predecessor_id) in stakes {
             self.push_extra_tx(
@@ -1679,7 +2778,15 @@ impl<T: ChainAccess> TxMirror<T> {
             .await?;
         }
         if !txs.is_empty() {
-            Self::send_transactions(target_client, txs.iter_mut()).await?;
+            Self::send_transactions(
+                target_client,
+                &self.send_backoff,
+                self.sent_txs_log.as_deref(),
+                self.nonce_report.as_ref(),
+                self.relayer.as_ref(),
+                txs.iter_mut(),
+            )
+            .await?;
             let mut tracker = tracker.lock().unwrap();
             tracker.on_txs_sent(
                 tx_block_queue,
@@ -1691,6 +2798,20 @@ impl<T: ChainAccess> TxMirror<T> {
         Ok(())
     }
 
+    // Periodically prunes and compacts the mirror DB so that its size stays bounded
+    // on long running forks.
+    async fn compact_db_loop(db: Arc<DB>, retention_blocks: BlockHeight) {
+        loop {
+            tokio::time::sleep(DB_COMPACTION_INTERVAL).await;
+            match prune_db(&db, retention_blocks) {
+                Ok(num_pruned) => {
+                    tracing::info!(target: "mirror", "pruned {} old entries from the mirror DB", num_pruned)
+                }
+                Err(e) => tracing::warn!(target: "mirror", "failed pruning the mirror DB: {:?}", e),
+            }
+        }
+    }
+
     async fn send_txs_loop(
         db: Arc<DB>,
         blocks_sent: mpsc::Sender<TxBatch>,
@@ -1698,12 +2819,64 @@ impl<T: ChainAccess> TxMirror<T> {
         mut send_time: Pin<Box<tokio::time::Sleep>>,
         send_delay: Arc<Mutex<Duration>>,
         target_client: Addr<TxRequestHandlerActor>,
+        send_backoff: Arc<Mutex<crate::send_backoff::SendBackoff>>,
+        sent_txs_log: Option<Arc<Mutex<crate::sent_txs_log::SentTxsLog>>>,
+        nonce_report: Option<crate::nonce_report::NonceReport>,
+        relayer: Option<crate::relayer::Relayer>,
+        target_height: Arc<RwLock<BlockHeight>>,
+        replay_speed: Option<f64>,
+        tracker: Arc<Mutex<crate::chain_tracker::TxTracker>>,
+        max_in_flight: Option<usize>,
+        health: Arc<crate::health::HealthState>,
+        shutdown: watch::Receiver<bool>,
     ) -> anyhow::Result<()> {
         let mut sent_source_height = None;
+        // whether we stopped sending because the target chain height stopped advancing
+        let mut paused_on_stall = false;
+        // with replay_speed set, the source chain timestamp of the first batch we send and
+        // when we sent it, which the times to send the next batches are relative to
+        let mut replay_start: Option<(u64, tokio::time::Instant)> = None;
 
         loop {
             (&mut send_time).await;
 
+            if *shutdown.borrow() {
+                tracing::info!(target: "mirror", "shutting down, not sending any more transactions");
+                // Dropping the sender lets queue_txs_loop() know that no batch is being sent anymore.
+                // We don't return here because that would be handled as this thread failing.
+                drop(blocks_sent);
+                return std::future::pending().await;
+            }
+
+            if let Some(max_in_flight) = max_in_flight {
+                let target_height = *target_height.read().unwrap();
+                let num_in_flight = tracker.lock().unwrap().num_in_flight_txs(target_height);
+                if num_in_flight >= max_in_flight {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    continue;
+                }
+            }
+
+            match health.target_stall() {
+                Some((height, stalled_for)) => {
+                    if !paused_on_stall {
+                        tracing::warn!(
+                            target: "mirror", "target chain height stuck at {} for {:?}, pausing sending transactions until it advances",
+                            height, stalled_for,
+                        );
+                        paused_on_stall = true;
+                    }
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    continue;
+                }
+                None => {
+                    if paused_on_stall {
+                        tracing::info!(target: "mirror", "target chain height advanced, resuming sending transactions");
+                        paused_on_stall = false;
+                    }
+                }
+            }
+
             let tx_batch = {
                 let tx_block_queue = tx_block_queue.lock().unwrap();
                 let b = match sent_source_height {
@@ -1733,20 +2906,59 @@ impl<T: ChainAccess> TxMirror<T> {
                 }
             };
 
+            if let Some(replay_speed) = replay_speed {
+                if let Some(source_timestamp) = tx_batch.source_timestamp {
+                    let (start_timestamp, start_time) = *replay_start
+                        .get_or_insert((source_timestamp, tokio::time::Instant::now()));
+                    let offset =
+                        Duration::from_nanos(source_timestamp.saturating_sub(start_timestamp))
+                            .div_f64(replay_speed);
+                    tokio::time::sleep_until(start_time + offset).await;
+                }
+            }
+
             let start_time = tokio::time::Instant::now();
 
-            tracing::debug!(target: "mirror", "Sending transactions for source block #{}", tx_batch.source_height);
-            Self::send_transactions(
-                &target_client,
-                tx_batch.txs.iter_mut().map(|(_tx_ref, tx)| tx),
-            )
+            let span = mirror_block_span(tx_batch.source_height, *target_height.read().unwrap());
+            async {
+                tracing::debug!(target: "mirror", "Sending transactions for source block #{}", tx_batch.source_height);
+                Self::send_transactions(
+                    &target_client,
+                    &send_backoff,
+                    sent_txs_log.as_deref(),
+                    nonce_report.as_ref(),
+                    relayer.as_ref(),
+                    tx_batch.txs.iter_mut().map(|(_tx_ref, tx)| tx),
+                )
+                .await?;
+                for (_, tx) in tx_batch.txs.iter() {
+                    let TargetChainTx::Ready(t) = tx else {
+                        continue;
+                    };
+                    if !t.sent_successfully {
+                        continue;
+                    }
+                    if let Some(hash) = t.provenance.source_tx_hash() {
+                        put_sent_source_tx(&db, hash, tx_batch.source_height)?;
+                    }
+                }
+                set_last_source_height(&db, tx_batch.source_height)?;
+                let num_sent = tx_batch
+                    .txs
+                    .iter()
+                    .filter(|(_, tx)| matches!(tx, TargetChainTx::Ready(t) if t.sent_successfully))
+                    .count();
+                record_txs_sent(&db, num_sent as u64)
+            }
+            .instrument(span)
             .await?;
-            set_last_source_height(&db, tx_batch.source_height)?;
             sent_source_height = Some(tx_batch.source_height);
 
             blocks_sent.send(tx_batch).await.unwrap();
 
-            let send_delay = *send_delay.lock().unwrap();
+            // when replaying at the source chain's pace, we wait before sending each batch instead
+            let send_delay =
+                if replay_speed.is_some() { Duration::ZERO } else { *send_delay.lock().unwrap() };
             tracing::debug!(target: "mirror", "Sleeping for {:?} until sending more transactions", &send_delay);
             let next_send_time = start_time + send_delay;
             send_time.as_mut().reset(next_send_time);
@@ -1766,6 +2978,7 @@ impl<T: ChainAccess> TxMirror<T> {
         accounts_to_unstake: mpsc::Sender<HashMap<(AccountId, PublicKey), AccountId>>,
         target_height: Arc<RwLock<BlockHeight>>,
         target_head: Arc<RwLock<CryptoHash>>,
+        health: Arc<crate::health::HealthState>,
     ) -> anyhow::Result<()> {
         let target_indexer = Indexer::new(near_indexer::IndexerConfig {
             home_dir,
@@ -1788,6 +3001,7 @@ impl<T: ChainAccess> TxMirror<T> {
         .await?;
         *target_height.write().unwrap() = first_target_height;
         *target_head.write().unwrap() = first_target_head;
+        health.on_target_height(first_target_height);
         clients_tx
             .send((target_client.clone(), target_view_client.clone(), tx_processor.clone()))
             .unwrap();
@@ -1796,6 +3010,7 @@ impl<T: ChainAccess> TxMirror<T> {
             let msg = target_stream.recv().await.unwrap();
             *target_head.write().unwrap() = msg.block.header.hash;
             *target_height.write().unwrap() = msg.block.header.height;
+            health.on_target_height(msg.block.header.height);
             let target_block_info = {
                 let mut tracker = tracker.lock().unwrap();
                 tracker.on_target_block(&tx_block_queue, db.as_ref(), msg)?
@@ -1816,6 +3031,40 @@ impl<T: ChainAccess> TxMirror<T> {
         }
     }
 
+    // Reads --config-path again after a SIGHUP and applies it. All of the options in the config
+    // file can be changed while running, and take effect the next time they're used. Options
+    // given on the command line like the source and target homes can't be changed this way.
+    fn reload_config(&mut self, tracker: &Mutex<crate::chain_tracker::TxTracker>) {
+        let Some(config_path) = &self.config_path else {
+            tracing::warn!(target: "mirror", "got SIGHUP, but there's no --config-path to reload");
+            return;
+        };
+        let config = match MirrorConfig::load(Some(config_path)) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!(target: "mirror", "keeping the current mirror config: {:#}", e);
+                return;
+            }
+        };
+        let changes = config.changes_from(&self.config);
+        if changes.is_empty() {
+            tracing::info!(target: "mirror", "reloaded {}, nothing changed", config_path.display());
+        }
+        for change in changes {
+            tracing::info!(target: "mirror", "reloaded {}, changed {}", config_path.display(), change);
+        }
+
+        tracker.lock().unwrap().set_tx_batch_interval(config.tx_batch_interval);
+        self.send_backoff.lock().unwrap().set_max_backoff(config.tx_send_max_backoff);
+        self.health.set_stall_timeout(
+            config
+                .readiness_stall_timeout
+                .unwrap_or(crate::health::DEFAULT_READINESS_STALL_TIMEOUT),
+        );
+        self.health.set_pause_timeout(config.target_stall_pause_timeout);
+        self.config = config;
+    }
+
     async fn queue_txs_loop(
         &mut self,
         tracker: Arc<Mutex<crate::chain_tracker::TxTracker>>,
@@ -1829,18 +3078,46 @@ impl<T: ChainAccess> TxMirror<T> {
         target_head: Arc<RwLock<CryptoHash>>,
         mut source_hash: CryptoHash,
         have_stop_height: bool,
+        max_in_flight: Option<usize>,
+        mut reload: watch::Receiver<()>,
+        mut shutdown: watch::Receiver<bool>,
     ) -> anyhow::Result<()> {
         let mut queue_txs_time = tokio::time::interval(Duration::from_millis(100));
+        // set once we get a shutdown signal, after which we stop reading new source blocks
+        let mut shutdown_deadline = None;
+        // set when send_txs_loop() has stopped sending transactions after a shutdown signal
+        let mut sending_stopped = false;
 
         loop {
             tokio::select! {
                 // time to send a batch of transactions
                 _ = queue_txs_time.tick() => {
-                    let target_head = *target_head.read().unwrap();
-                    self.queue_txs(&tracker, &tx_block_queue, &target_view_client, target_head, have_stop_height).await?;
+                    if shutdown_deadline.is_none() {
+                        let target_head = *target_head.read().unwrap();
+                        let target_height = *target_height.read().unwrap();
+                        self.queue_txs(&tracker, &tx_block_queue, &target_view_client, target_head, target_height, have_stop_height, max_in_flight).await?;
+                    }
                 }
-                tx_batch = blocks_sent.recv() => {
-                    let tx_batch = tx_batch.unwrap();
+                Ok(()) = reload.changed() => {
+                    self.reload_config(&tracker);
+                }
+                Ok(_) = shutdown.wait_for(|shutdown| *shutdown), if shutdown_deadline.is_none() => {
+                    let shutdown_timeout =
+                        self.config.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+                    tracing::info!(
+                        target: "mirror", "shutting down, waiting up to {:?} for sent transactions to appear on the target chain",
+                        shutdown_timeout
+                    );
+                    shutdown_deadline = Some(tokio::time::Instant::now() + shutdown_timeout);
+                }
+                tx_batch = blocks_sent.recv(), if !sending_stopped => {
+                    let Some(tx_batch) = tx_batch else {
+                        // send_txs_loop() only drops the sender after a shutdown signal
+                        sending_stopped = true;
+                        continue;
+                    };
+                    let target_height = *target_height.read().unwrap();
+                    let _span = mirror_block_span(tx_batch.source_height, target_height).entered();
                     source_hash = tx_batch.source_hash;
                     // lock the tracker before removing the block from the queue so that
                     // we don't call on_target_block() in the other thread between removing the block
@@ -1852,7 +3129,6 @@ impl<T: ChainAccess> TxMirror<T> {
                         let b = tx_block_queue.pop_front().unwrap();
                         assert!(b.source_height == tx_batch.source_height);
                     };
-                    let target_height = *target_height.read().unwrap();
                     let new_delay = tracker.on_txs_sent(
                         &tx_block_queue,
                         &self.db,
@@ -1876,11 +3152,33 @@ impl<T: ChainAccess> TxMirror<T> {
             // exactly when we've done the thing that makes finished() return true, usually after a call to on_target_block()
             {
                 let tracker = tracker.lock().unwrap();
+                let target_height = *target_height.read().unwrap();
+                crate::metrics::TRANSACTIONS_IN_FLIGHT
+                    .set(tracker.num_in_flight_txs(target_height) as i64);
                 if tracker.finished() {
                     tracing::info!(target: "mirror", "finished sending all transactions");
                     return Ok(());
                 }
             }
+            if let Some(shutdown_deadline) = shutdown_deadline {
+                let num_pending = tracker.lock().unwrap().num_pending_txs();
+                if sending_stopped && num_pending == 0 {
+                    tracing::info!(target: "mirror", "all sent transactions appeared on the target chain");
+                    return Ok(());
+                }
+                if tokio::time::Instant::now() >= shutdown_deadline {
+                    tracing::warn!(
+                        target: "mirror", "timed out waiting for {} sent transactions to appear on the target chain",
+                        num_pending
+                    );
+                    if !sending_stopped {
+                        tracing::warn!(
+                            target: "mirror", "exiting while sending a batch of transactions. Some of them will be sent again after a restart"
+                        );
+                    }
+                    return Ok(());
+                }
+            }
         }
     }
 
@@ -1942,11 +3240,65 @@ impl<T: ChainAccess> TxMirror<T> {
         }
     }
 
+    // Refuses to go on if the target chain isn't the one given by --expected-target-genesis,
+    // or isn't the one previous runs with this mirror DB sent transactions to. Otherwise,
+    // records its genesis hash in the mirror DB to check it on the next runs.
+    async fn check_target_genesis(
+        &self,
+        target_view_client: &Addr<ViewClientActor>,
+        expected_target_genesis: Option<CryptoHash>,
+    ) -> anyhow::Result<()> {
+        let genesis_hash = target_view_client
+            .send(
+                GetBlock(BlockReference::SyncCheckpoint(SyncCheckpoint::Genesis))
+                    .with_span_context(),
+            )
+            .await
+            .unwrap()
+            .context("failed fetching target chain genesis block")?
+            .header
+            .hash;
+        tracing::info!(
+            target: "mirror", "target chain {} has genesis hash {}", &self.target_chain_id, &genesis_hash
+        );
+        if let Some(expected) = expected_target_genesis {
+            if genesis_hash != expected {
+                anyhow::bail!(
+                    "target chain {} has genesis hash {}, but --expected-target-genesis is {}",
+                    &self.target_chain_id,
+                    &genesis_hash,
+                    &expected
+                );
+            }
+        }
+        match get_target_genesis_hash(&self.db)? {
+            Some(recorded) => {
+                if genesis_hash != recorded {
+                    anyhow::bail!(
+                        "target chain {} has genesis hash {}, but the mirror DB was used to send transactions to a chain with genesis hash {}",
+                        &self.target_chain_id,
+                        &genesis_hash,
+                        &recorded
+                    );
+                }
+            }
+            None => set_target_genesis_hash(&self.db, &genesis_hash)?,
+        }
+        Ok(())
+    }
+
     async fn run(
         mut self,
+        options: &MirrorOptions,
         stop_height: Option<BlockHeight>,
-        target_home: PathBuf,
+        reload: watch::Receiver<()>,
+        shutdown: watch::Receiver<bool>,
     ) -> anyhow::Result<()> {
+        let target_home = options.target_home.clone();
+        let expected_target_genesis = options.expected_target_genesis;
+        let mirror_db_retention_blocks = options.mirror_db_retention_blocks;
+        let replay_speed = options.replay_speed;
+        let max_in_flight = options.max_in_flight;
         let last_stored_height = get_last_source_height(&self.db)?;
         let last_height = last_stored_height.unwrap_or(self.target_genesis_height - 1);
 
@@ -1972,6 +3324,7 @@ impl<T: ChainAccess> TxMirror<T> {
             .with_context(|| format!("error fetching hash of block #{}", next_heights[0]))?;
 
         tracing::debug!(target: "mirror", "source chain initialized with first heights: {:?}", &next_heights);
+        self.health.on_source_initialized();
 
         let tracker = Arc::new(Mutex::new(crate::chain_tracker::TxTracker::new(
             self.target_min_block_production_delay,
@@ -1990,6 +3343,7 @@ impl<T: ChainAccess> TxMirror<T> {
         let target_height2 = target_height.clone();
         let target_head2 = target_head.clone();
         let tracker2 = tracker.clone();
+        let health = self.health.clone();
         let index_target_thread = actix::Arbiter::new();
 
         // TODO: Consider moving this back to the TxTracker struct. Separating these made certain things easier, but now it
@@ -2008,6 +3362,7 @@ impl<T: ChainAccess> TxMirror<T> {
                 unstake_tx,
                 target_height2,
                 target_head2,
+                health,
             )
             .await;
             target_indexer_done_tx.send(res).unwrap();
@@ -2015,6 +3370,11 @@ impl<T: ChainAccess> TxMirror<T> {
 
         // wait til we set the values in target_height and target_head after receiving a message from the indexer
         let (_target_client, target_view_client, tx_processor) = clients_rx.await.unwrap();
+        self.check_target_genesis(&target_view_client, expected_target_genesis).await?;
+        if let Some(relayer) = &self.relayer {
+            relayer.init_nonce(&target_view_client).await?;
+            tracing::info!(target: "mirror", "sending mapped transactions through relayer {}", relayer.account_id());
+        }
 
         // Wait at least 15 seconds before sending any transactions because for
         // a few seconds after the node starts, transaction routing requests
@@ -2033,6 +3393,7 @@ impl<T: ChainAccess> TxMirror<T> {
             let mut block = MappedBlock {
                 source_hash: CryptoHash::default(),
                 source_height: last_height,
+                source_timestamp: None,
                 chunks: vec![MappedChunk { shard_id: ShardId::new(0), txs: Vec::new() }],
             };
 
@@ -2062,8 +3423,15 @@ impl<T: ChainAccess> TxMirror<T> {
                     let mut tx_block_queue = tx_block_queue.lock().unwrap();
                     TxBatch::from(&tx_block_queue.pop_front().unwrap())
                 };
-                Self::send_transactions(&tx_processor, b.txs.iter_mut().map(|(_tx_ref, tx)| tx))
-                    .await?;
+                Self::send_transactions(
+                    &tx_processor,
+                    &self.send_backoff,
+                    self.sent_txs_log.as_deref(),
+                    self.nonce_report.as_ref(),
+                    self.relayer.as_ref(),
+                    b.txs.iter_mut().map(|(_tx_ref, tx)| tx),
+                )
+                .await?;
                 let mut tracker = tracker.lock().unwrap();
                 send_delay = tracker.on_txs_sent(
                     &tx_block_queue,
@@ -2073,12 +3441,15 @@ impl<T: ChainAccess> TxMirror<T> {
                 )?;
             }
         }
+        let initial_target_height = *target_height.read().unwrap();
         self.queue_txs(
             &tracker,
             &tx_block_queue,
             &target_view_client,
             initial_target_head,
+            initial_target_height,
             stop_height.is_some(),
+            max_in_flight,
         )
         .await?;
 
@@ -2087,6 +3458,14 @@ impl<T: ChainAccess> TxMirror<T> {
         let (blocks_sent_tx, blocks_sent_rx) = mpsc::channel(10);
         let tx_block_queue2 = tx_block_queue.clone();
         let tx_processor2 = tx_processor.clone();
+        let send_backoff = self.send_backoff.clone();
+        let sent_txs_log = self.sent_txs_log.clone();
+        let nonce_report = self.nonce_report.clone();
+        let relayer = self.relayer.clone();
+        let target_height2 = target_height.clone();
+        let shutdown2 = shutdown.clone();
+        let tracker2 = tracker.clone();
+        let health2 = self.health.clone();
         let db = self.db.clone();
         let send_txs_thread = actix::Arbiter::new();
         let (send_txs_done_tx, send_txs_done_rx) =
@@ -2099,15 +3478,34 @@ impl<T: ChainAccess> TxMirror<T> {
                 send_time,
                 send_delay2,
                 tx_processor2,
+                send_backoff,
+                sent_txs_log,
+                nonce_report,
+                relayer,
+                target_height2,
+                replay_speed,
+                tracker2,
+                max_in_flight,
+                health2,
+                shutdown2,
             )
             .await;
             send_txs_done_tx.send(res).unwrap();
         });
-        tokio::select! {
+        if let Some(retention_blocks) = mirror_db_retention_blocks {
+            let db = self.db.clone();
+            let compact_db_thread = actix::Arbiter::new();
+            compact_db_thread.spawn(Self::compact_db_loop(db, retention_blocks));
+        }
+        if let Some(nonce_report) = &self.nonce_report {
+            let nonce_report_thread = actix::Arbiter::new();
+            nonce_report_thread.spawn(nonce_report.clone().run());
+        }
+        let res = tokio::select! {
             res = self.queue_txs_loop(
                 tracker, tx_block_queue, tx_processor, target_view_client,
                 blocks_sent_rx, unstake_rx, send_delay, target_height, target_head,
-                source_hash, stop_height.is_some(),
+                source_hash, stop_height.is_some(), max_in_flight, reload, shutdown.clone(),
             ) => {
                 // TODO: cancel other threads
                 res
@@ -2122,51 +3520,92 @@ impl<T: ChainAccess> TxMirror<T> {
                 tracing::error!("transaction sending thread exited");
                 res.context("target indexer thread failure")
             }
+        };
+        if *shutdown.borrow() {
+            flush_db(&self.db)?;
+            tracing::info!(
+                target: "mirror", "shut down after sending transactions up to source block #{:?}",
+                get_last_source_height(&self.db)?
+            );
         }
+        res
     }
 }
 
-async fn run<P: AsRef<Path>>(
-    source_home: P,
-    target_home: P,
-    mirror_db_path: Option<PathBuf>,
-    secret: Option<[u8; crate::secret::SECRET_LEN]>,
-    stop_height: Option<BlockHeight>,
-    online_source: bool,
-    config_path: Option<P>,
+async fn run(
+    options: MirrorOptions,
+    reload: watch::Receiver<()>,
+    shutdown: watch::Receiver<bool>,
 ) -> anyhow::Result<()> {
-    let config: MirrorConfig = match config_path {
-        Some(p) => {
-            let c = std::fs::read_to_string(p.as_ref())
-                .with_context(|| format!("Could not read config from {}", p.as_ref().display()))?;
-            serde_json::from_str(&c)
-                .with_context(|| format!("Could not parse config from {}", p.as_ref().display()))?
-        }
-        None => Default::default(),
+    let config = MirrorConfig::load(options.config_path.as_ref())?;
+    let relayer = match (&options.relayer, &options.relayer_key) {
+        (Some(account_id), Some(key_path)) => {
+            Some(crate::relayer::Relayer::load(account_id.clone(), key_path)?)
+        }
+        _ => None,
     };
-    if !online_source {
-        let source_chain_access = crate::offline::ChainAccess::new(source_home)?;
-        let stop_height = stop_height.unwrap_or(
-            source_chain_access.head_height().await.context("could not fetch source chain head")?,
-        );
-        TxMirror::new(
-            source_chain_access,
-            target_home.as_ref(),
-            mirror_db_path.as_deref(),
-            secret,
-            config,
-        )?
-        .run(Some(stop_height), target_home.as_ref().to_path_buf())
-        .await
-    } else {
-        TxMirror::new(
-            crate::online::ChainAccess::new(source_home)?,
-            target_home.as_ref(),
-            mirror_db_path.as_deref(),
-            secret,
-            config,
-        )?
-        .run(stop_height, target_home.as_ref().to_path_buf())
-        .await
+    let sent_txs_log = match &options.log_sent_txs {
+        Some(path) => Some(
+            crate::sent_txs_log::SentTxsLog::open(path)
+                .context("failed opening the sent transactions log")?,
+        ),
+        None => None,
+    };
+    let nonce_report = options.nonce_report_interval.map(crate::nonce_report::NonceReport::new);
+    let health = Arc::new(crate::health::HealthState::new(
+        config.readiness_stall_timeout.unwrap_or(crate::health::DEFAULT_READINESS_STALL_TIMEOUT),
+        config.target_stall_pause_timeout,
+    ));
+    if let Some(health_addr) = options.health_addr {
+        crate::health::spawn_server(health_addr, health.clone())
+            .with_context(|| format!("failed starting health check server on {}", health_addr))?;
+    }
+    if let Some(metrics_addr) = options.metrics_addr {
+        crate::metrics::spawn_server(metrics_addr, options.prometheus_prefix.clone())
+            .with_context(|| format!("failed starting metrics server on {}", metrics_addr))?;
     }
+    let mut stop_height = options.stop_height;
+    let source_chain_access: Box<dyn ChainAccess> = match &options.source_rpc {
+        Some(source_rpc) => Box::new(crate::rpc::ChainAccess::new(
+            source_rpc.clone(),
+            options.source_rpc_client_cert.as_ref(),
+        )?),
+        None => {
+            let source_home = options
+                .source_home
+                .as_ref()
+                .context("--source-home or --source-rpc must be given")?;
+            if !options.online_source {
+                let source_chain_access =
+                    crate::offline::ChainAccess::new(source_home, options.source_cold_storage)?;
+                if stop_height.is_none() {
+                    stop_height = Some(
+                        source_chain_access
+                            .head_height()
+                            .await
+                            .context("could not fetch source chain head")?,
+                    );
+                }
+                Box::new(source_chain_access)
+            } else {
+                if options.source_cold_storage {
+                    anyhow::bail!(
+                        "reading from cold storage is not supported with an online source"
+                    );
+                }
+                Box::new(crate::online::ChainAccess::new(source_home)?)
+            }
+        }
+    };
+    TxMirror::new(
+        source_chain_access,
+        &options,
+        sent_txs_log,
+        nonce_report,
+        relayer,
+        health,
+        config,
+    )?
+    .run(&options, stop_height, reload, shutdown)
+    .await
 }
@@ -1,8 +1,17 @@
+use actix_web::{App, HttpResponse, HttpServer, web};
 use near_o11y::metrics::{
-    IntCounter, IntCounterVec, try_create_int_counter, try_create_int_counter_vec,
+    Encoder, IntCounter, IntCounterVec, IntGauge, TextEncoder, try_create_int_counter,
+    try_create_int_counter_vec, try_create_int_gauge,
 };
+use std::net::SocketAddr;
 use std::sync::LazyLock;
 
+// Prefix of the names of all the metrics defined here. In the metrics served with
+// --metrics-addr, it's replaced with the one given with --prometheus-prefix.
+const METRIC_NAME_PREFIX: &str = "near_mirror_";
+
+pub(crate) const DEFAULT_PROMETHEUS_PREFIX: &str = "mirror_";
+
 pub static TRANSACTIONS_SENT: LazyLock<IntCounterVec> = LazyLock::new(|| {
     try_create_int_counter_vec(
         "near_mirror_transactions_sent",
@@ -19,3 +28,137 @@ pub static TRANSACTIONS_INCLUDED: LazyLock<IntCounter> = LazyLock::new(|| {
     )
     .unwrap()
 });
+
+pub static TRANSACTION_SEND_RETRIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    try_create_int_counter_vec(
+        "near_mirror_transaction_send_retries",
+        "Total number of times a transaction rejected by the target node was scheduled to be sent again",
+        &["reason"],
+    )
+    .unwrap()
+});
+
+pub static TARGET_HEIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_mirror_target_height",
+        "Height of the latest target chain block seen by the mirror",
+    )
+    .unwrap()
+});
+
+pub static UNMAPPABLE_TXS: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_mirror_unmappable_txs",
+        "Total number of source chain transactions skipped because their signer key could not be mapped",
+    )
+    .unwrap()
+});
+
+pub static SOURCE_REORGS: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_mirror_source_reorgs",
+        "Total number of times a source chain block didn't build on the last one we mirrored",
+    )
+    .unwrap()
+});
+
+pub static ALREADY_SENT_TXS: LazyLock<IntCounter> = LazyLock::new(|| {
+    try_create_int_counter(
+        "near_mirror_already_sent_txs",
+        "Total number of source chain transactions skipped because the mirror DB says they were already sent",
+    )
+    .unwrap()
+});
+
+pub static TRANSACTIONS_IN_FLIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    try_create_int_gauge(
+        "near_mirror_transactions_in_flight",
+        "Number of transactions sent that have not yet appeared on the target chain",
+    )
+    .unwrap()
+});
+
+// Returns an error if metric names starting with `prefix` are not valid Prometheus metric names.
+fn check_prefix(prefix: &str) -> anyhow::Result<()> {
+    let valid = prefix.chars().enumerate().all(|(i, c)| {
+        c.is_ascii_alphabetic() || c == '_' || c == ':' || (i > 0 && c.is_ascii_digit())
+    });
+    if !valid {
+        anyhow::bail!("invalid Prometheus metric name prefix: {:?}", prefix);
+    }
+    Ok(())
+}
+
+/// Returns the metrics defined here in the Prometheus text format, with the
+/// `near_mirror_` prefix of their names replaced by `prefix`. The other metrics
+/// registered in this process, like the ones of the node we run, are left out.
+fn encode_metrics(prefix: &str) -> anyhow::Result<String> {
+    let families = near_o11y::metrics::gather()
+        .into_iter()
+        .filter_map(|mut family| {
+            let name = family.get_name().strip_prefix(METRIC_NAME_PREFIX)?.to_string();
+            family.set_name(format!("{}{}", prefix, name));
+            Some(family)
+        })
+        .collect::<Vec<_>>();
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&families, &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+async fn metrics(prefix: web::Data<String>) -> HttpResponse {
+    match encode_metrics(&prefix) {
+        Ok(text) => HttpResponse::Ok().content_type(TextEncoder::new().format_type()).body(text),
+        Err(err) => HttpResponse::InternalServerError().body(format!("{:#}\n", err)),
+    }
+}
+
+/// Serves `/metrics` with the metrics defined here, with their names prefixed with
+/// `prefix` instead of `near_mirror_`, so that several mirror processes on one host
+/// can be scraped without their metric names colliding.
+pub(crate) fn spawn_server(addr: SocketAddr, prefix: String) -> anyhow::Result<()> {
+    check_prefix(&prefix)?;
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(prefix.clone()))
+            .route("/metrics", web::get().to(metrics))
+    })
+    .bind(addr)?
+    .workers(1)
+    .shutdown_timeout(3)
+    .disable_signals()
+    .run();
+    tokio::spawn(server);
+    tracing::info!(target: "mirror", "serving metrics on {}", addr);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_metrics() {
+        TRANSACTIONS_INCLUDED.inc();
+        TARGET_HEIGHT.set(5);
+
+        let text = encode_metrics("mirror_test_").unwrap();
+        assert!(text.contains("mirror_test_transactions_included "), "{}", text);
+        assert!(text.contains("mirror_test_target_height 5"), "{}", text);
+        assert!(!text.contains(METRIC_NAME_PREFIX), "{}", text);
+        // Metrics not defined by the mirror are not exported.
+        assert!(text.lines().all(|line| line.is_empty()
+            || line.starts_with("mirror_test_")
+            || line.starts_with("# HELP mirror_test_")
+            || line.starts_with("# TYPE mirror_test_")));
+    }
+
+    #[test]
+    fn test_check_prefix() {
+        check_prefix(DEFAULT_PROMETHEUS_PREFIX).unwrap();
+        check_prefix("").unwrap();
+        check_prefix("fleet:mirror_2_").unwrap();
+        check_prefix("2mirror_").unwrap_err();
+        check_prefix("mirror-").unwrap_err();
+    }
+}
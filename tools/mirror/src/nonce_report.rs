@@ -0,0 +1,142 @@
+use near_crypto::PublicKey;
+use near_primitives::types::{AccountId, Nonce};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Number of access keys listed in each report
+const NUM_REPORTED_KEYS: usize = 20;
+
+#[derive(Debug, Default)]
+struct KeyNonces {
+    // nonce of the last source chain transaction we mapped to this key
+    source_nonce: Option<Nonce>,
+    // nonce of the last transaction we successfully sent with this key
+    target_nonce: Option<Nonce>,
+    // number of transactions sent with this key since the last report
+    num_sent: u64,
+}
+
+/// Keeps the last source chain nonce seen and the last target chain nonce sent for each
+/// target chain access key, and periodically logs them for the keys that sent the most
+/// transactions since the last report. Target chain nonces that don't keep up with the
+/// source chain ones usually mean transactions are being rejected for bad nonces.
+#[derive(Clone)]
+pub(crate) struct NonceReport {
+    interval: Duration,
+    keys: Arc<Mutex<HashMap<(AccountId, PublicKey), KeyNonces>>>,
+}
+
+impl NonceReport {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self { interval, keys: Arc::default() }
+    }
+
+    /// Called when a source chain transaction with nonce `source_nonce` is mapped to a
+    /// transaction signed by the target chain access key `(signer_id, public_key)`.
+    pub(crate) fn on_source_tx(
+        &self,
+        signer_id: &AccountId,
+        public_key: &PublicKey,
+        source_nonce: Nonce,
+    ) {
+        let mut keys = self.keys.lock().unwrap();
+        let nonces = keys.entry((signer_id.clone(), public_key.clone())).or_default();
+        nonces.source_nonce = Some(source_nonce);
+    }
+
+    /// Called when the target node accepts a transaction signed by `(signer_id, public_key)`.
+    pub(crate) fn on_tx_sent(&self, signer_id: &AccountId, public_key: &PublicKey, nonce: Nonce) {
+        let mut keys = self.keys.lock().unwrap();
+        let nonces = keys.entry((signer_id.clone(), public_key.clone())).or_default();
+        nonces.target_nonce = Some(nonce);
+        nonces.num_sent += 1;
+    }
+
+    // Returns the report for the keys that sent the most transactions since the last
+    // call, and resets the counts.
+    fn take_report(&self) -> String {
+        let mut keys = self.keys.lock().unwrap();
+        let mut active = keys.iter().filter(|(_, nonces)| nonces.num_sent > 0).collect::<Vec<_>>();
+        active.sort_by(|(key_a, a), (key_b, b)| {
+            b.num_sent.cmp(&a.num_sent).then_with(|| key_a.cmp(key_b))
+        });
+        let mut report = format!(
+            "{} access keys sent transactions in the last {:?}",
+            active.len(),
+            self.interval
+        );
+        for ((signer_id, public_key), nonces) in active.into_iter().take(NUM_REPORTED_KEYS) {
+            write!(
+                report,
+                "\n  ({}, {}): {} sent, last source nonce {}, last target nonce {}",
+                signer_id,
+                public_key,
+                nonces.num_sent,
+                format_nonce(nonces.source_nonce),
+                format_nonce(nonces.target_nonce),
+            )
+            .unwrap();
+        }
+        for nonces in keys.values_mut() {
+            nonces.num_sent = 0;
+        }
+        report
+    }
+
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        // the first tick completes immediately, when nothing has been sent yet
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            tracing::info!(target: "mirror", "nonce report: {}", self.take_report());
+        }
+    }
+}
+
+fn format_nonce(nonce: Option<Nonce>) -> String {
+    nonce.map(|n| n.to_string()).unwrap_or_else(|| String::from("-"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::NonceReport;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::types::AccountId;
+    use std::time::Duration;
+
+    #[test]
+    fn test_take_report() {
+        let report = NonceReport::new(Duration::from_secs(60));
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice_key = SecretKey::from_seed(KeyType::ED25519, "alice").public_key();
+        let bob_key = SecretKey::from_seed(KeyType::ED25519, "bob").public_key();
+
+        report.on_source_tx(&alice, &alice_key, 10);
+        report.on_tx_sent(&alice, &alice_key, 101);
+        report.on_source_tx(&alice, &alice_key, 11);
+        report.on_tx_sent(&alice, &alice_key, 102);
+        report.on_source_tx(&bob, &bob_key, 5);
+
+        assert_eq!(
+            report.take_report(),
+            format!(
+                "1 access keys sent transactions in the last 60s\n  (alice.near, {}): 2 sent, last source nonce 11, last target nonce 102",
+                alice_key
+            )
+        );
+
+        // bob's transaction was rejected and then sent later on
+        report.on_tx_sent(&bob, &bob_key, 50);
+        assert_eq!(
+            report.take_report(),
+            format!(
+                "1 access keys sent transactions in the last 60s\n  (bob.near, {}): 1 sent, last source nonce 5, last target nonce 50",
+                bob_key
+            )
+        );
+    }
+}
@@ -8,7 +8,7 @@ use near_chain_primitives::error::EpochErrorResultToChainError;
 use near_crypto::PublicKey;
 use near_epoch_manager::shard_assignment::{account_id_to_shard_id, shard_id_to_uid};
 use near_epoch_manager::{EpochManager, EpochManagerHandle};
-use near_primitives::block::BlockHeader;
+use near_primitives::block::{BlockHeader, Tip};
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::Receipt;
 use near_primitives::types::{AccountId, BlockHeight, TransactionOrReceiptId};
@@ -16,6 +16,7 @@ use near_primitives::views::{
     AccessKeyPermissionView, ExecutionOutcomeWithIdView, QueryRequest, QueryResponseKind,
 };
 use near_store::genesis::initialize_genesis_state;
+use near_store::{COLD_HEAD_KEY, DBCol, Store};
 use nearcore::{NightshadeRuntime, NightshadeRuntimeExt};
 use std::path::Path;
 use std::sync::Arc;
@@ -32,16 +33,27 @@ pub(crate) struct ChainAccess {
     chain: ChainStore,
     epoch_manager: Arc<EpochManagerHandle>,
     runtime: Arc<NightshadeRuntime>,
+    // set if we read from both the hot and the cold storage of an archival node
+    cold_store: Option<Store>,
 }
 
 impl ChainAccess {
-    pub(crate) fn new<P: AsRef<Path>>(home: P) -> anyhow::Result<Self> {
+    /// If `cold_storage` is true, blocks that have been garbage collected from
+    /// the hot storage of the node at `home` are read from its cold storage.
+    pub(crate) fn new<P: AsRef<Path>>(home: P, cold_storage: bool) -> anyhow::Result<Self> {
         let mut config =
             nearcore::config::load_config(home.as_ref(), GenesisValidationMode::UnsafeFast)
                 .with_context(|| format!("Error loading config from {:?}", home.as_ref()))?;
         let node_storage =
             nearcore::open_storage(home.as_ref(), &mut config).context("failed opening storage")?;
-        let store = node_storage.get_hot_store();
+        let (store, cold_store) = if cold_storage {
+            let store = node_storage.get_split_store().with_context(|| {
+                format!("the node at {:?} is not configured with cold storage", home.as_ref())
+            })?;
+            (store, node_storage.get_cold_store())
+        } else {
+            (node_storage.get_hot_store(), None)
+        };
         initialize_genesis_state(store.clone(), &config.genesis, Some(home.as_ref()));
         let chain = ChainStore::new(
             store.clone(),
@@ -56,7 +68,39 @@ impl ChainAccess {
         let runtime =
             NightshadeRuntime::from_config(home.as_ref(), store, &config, epoch_manager.clone())
                 .context("could not create the transaction runtime")?;
-        Ok(Self { chain, epoch_manager, runtime })
+        Ok(Self { chain, epoch_manager, runtime, cold_store })
+    }
+
+    // Returns an error if the block at `height` might have been garbage collected, since
+    // otherwise we'd treat it as a skipped height and silently not send its transactions.
+    fn check_height_available(&self, height: BlockHeight) -> anyhow::Result<()> {
+        let tail = self.chain.tail().context("failed fetching the source chain tail")?;
+        if height >= tail {
+            return Ok(());
+        }
+        let Some(cold_store) = &self.cold_store else {
+            anyhow::bail!(
+                "source chain block #{} is below the tail #{} of the source node's storage. \
+                 If the source node is an archival node with cold storage, pass --source-cold-storage \
+                 to read historical blocks from it",
+                height,
+                tail
+            );
+        };
+        let cold_head = cold_store
+            .get_ser::<Tip>(DBCol::BlockMisc, COLD_HEAD_KEY)
+            .context("failed reading the cold storage head")?
+            .map(|tip| tip.height);
+        match cold_head {
+            Some(cold_head) if height <= cold_head => Ok(()),
+            _ => anyhow::bail!(
+                "source chain block #{} is below the tail #{} of the source node's hot storage, \
+                 and not in its cold storage with head {:?}",
+                height,
+                tail,
+                cold_head
+            ),
+        }
     }
 }
 
@@ -69,6 +113,7 @@ impl crate::ChainAccess for ChainAccess {
     ) -> anyhow::Result<Vec<BlockHeight>> {
         let mut block_heights = Vec::with_capacity(num_initial_blocks);
         let head = self.head_height().await?;
+        self.check_height_available(last_height + 1)?;
 
         let mut height = last_height + 1;
         loop {
@@ -118,6 +163,7 @@ impl crate::ChainAccess for ChainAccess {
     }
 
     async fn get_txs(&self, height: BlockHeight) -> Result<SourceBlock, ChainError> {
+        self.check_height_available(height)?;
         let block_hash = self.chain.get_block_hash_by_height(height)?;
         let block = self
             .chain
@@ -140,11 +186,17 @@ impl crate::ChainAccess for ChainAccess {
             };
             chunks.push(SourceChunk {
                 shard_id: chunk.shard_id(),
-                transactions: chunk.transactions().to_vec(),
+                transactions: chunk.transactions().iter().cloned().map(Into::into).collect(),
                 receipts: chunk.prev_outgoing_receipts().to_vec(),
             })
         }
-        Ok(SourceBlock { hash: block_hash, chunks })
+        Ok(SourceBlock {
+            hash: block_hash,
+            prev_hash: *block.header().prev_hash(),
+            gas_price: block.header().next_gas_price(),
+            timestamp: block.header().raw_timestamp(),
+            chunks,
+        })
     }
 
     async fn get_next_block_height(&self, height: BlockHeight) -> Result<BlockHeight, ChainError> {
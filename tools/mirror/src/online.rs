@@ -143,13 +143,19 @@ impl crate::ChainAccess for ChainAccess {
             if chunk.height_included() == height {
                 chunks.push(SourceChunk {
                     shard_id: chunk.shard_id(),
-                    transactions: chunk.transactions().to_vec(),
+                    transactions: chunk.transactions().iter().cloned().map(Into::into).collect(),
                     receipts: chunk.prev_outgoing_receipts().to_vec(),
                 })
             }
         }
 
-        Ok(SourceBlock { hash: block.header.hash, chunks })
+        Ok(SourceBlock {
+            hash: block.header.hash,
+            prev_hash: block.header.prev_hash,
+            gas_price: block.header.gas_price,
+            timestamp: block.header.timestamp_nanosec,
+            chunks,
+        })
     }
 
     async fn get_next_block_height(
@@ -0,0 +1,111 @@
+use actix::Addr;
+use anyhow::Context;
+use near_client::ViewClientActor;
+use near_crypto::{KeyFile, SecretKey};
+use near_primitives::action::delegate::{DelegateAction, NonDelegateAction, SignedDelegateAction};
+use near_primitives::transaction::{Action, SignedTransaction, Transaction};
+use near_primitives::types::{AccountId, BlockHeight};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A target chain account that submits mapped transactions on behalf of their signers, by
+/// wrapping them in delegate actions (NEP-366 meta transactions).
+#[derive(Clone)]
+pub(crate) struct Relayer {
+    account_id: AccountId,
+    secret_key: SecretKey,
+    // nonce of the last transaction we signed with the relayer's key
+    nonce: Arc<AtomicU64>,
+}
+
+impl Relayer {
+    /// Reads the relayer's full access key from `key_path`, which should be a key file
+    /// for `account_id` like the ones written by `neard init`.
+    pub(crate) fn load(account_id: AccountId, key_path: &Path) -> anyhow::Result<Self> {
+        let key_file = KeyFile::from_file(key_path)
+            .with_context(|| format!("Failed reading relayer key from {}", key_path.display()))?;
+        if key_file.account_id != account_id {
+            anyhow::bail!(
+                "relayer key file {} is for account {}, not {}",
+                key_path.display(),
+                key_file.account_id,
+                account_id
+            );
+        }
+        Ok(Self { account_id, secret_key: key_file.secret_key, nonce: Arc::default() })
+    }
+
+    pub(crate) fn account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+
+    /// Sets the nonce to start from to that of the relayer's access key on the target chain.
+    pub(crate) async fn init_nonce(
+        &self,
+        target_view_client: &Addr<ViewClientActor>,
+    ) -> anyhow::Result<()> {
+        let nonce = crate::fetch_access_key_nonce(
+            target_view_client,
+            &self.account_id,
+            &self.secret_key.public_key(),
+        )
+        .await?
+        .with_context(|| {
+            format!(
+                "relayer access key {} not found for {} on the target chain",
+                self.secret_key.public_key(),
+                &self.account_id
+            )
+        })?;
+        self.nonce.store(nonce, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns a transaction signed by the relayer that carries out `tx` as a delegate action
+    /// signed with `secret_key`, the key `tx` is signed with. Returns None if `tx` itself
+    /// contains a delegate action, since those can't be nested.
+    pub(crate) fn wrap(
+        &self,
+        tx: &SignedTransaction,
+        secret_key: &SecretKey,
+    ) -> Option<SignedTransaction> {
+        let actions = tx
+            .transaction
+            .actions()
+            .iter()
+            .cloned()
+            .map(NonDelegateAction::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        let delegate_action = DelegateAction {
+            sender_id: tx.transaction.signer_id().clone(),
+            receiver_id: tx.transaction.receiver_id().clone(),
+            actions,
+            nonce: tx.transaction.nonce(),
+            // The relayer's transaction is only valid for as long as its block hash is, so there's
+            // no need to limit this further.
+            max_block_height: BlockHeight::MAX,
+            public_key: tx.transaction.public_key().clone(),
+        };
+        let signature = secret_key.sign(delegate_action.get_nep461_hash().as_ref());
+        let receiver_id = delegate_action.sender_id.clone();
+
+        // We take a new nonce every time, even when resending a transaction that was rejected,
+        // since gaps in the relayer's nonces don't matter.
+        let nonce = self.nonce.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut relayer_tx = Transaction::new_v0(
+            self.account_id.clone(),
+            self.secret_key.public_key(),
+            receiver_id,
+            nonce,
+            *tx.transaction.block_hash(),
+        );
+        *relayer_tx.actions_mut() =
+            vec![Action::Delegate(Box::new(SignedDelegateAction { delegate_action, signature }))];
+        Some(SignedTransaction::new(
+            self.secret_key.sign(relayer_tx.get_hash_and_size().0.as_ref()),
+            relayer_tx,
+        ))
+    }
+}
@@ -0,0 +1,81 @@
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+use std::collections::VecDeque;
+
+// How many of the most recently fetched source chain blocks we remember, which bounds how far
+// back we can tell which heights a reorg affected
+const HISTORY_LEN: usize = 100;
+
+/// What to do when the source chain reorgs blocks we've already mirrored, as given to --on-reorg
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OnReorg {
+    /// Log the affected heights and keep going
+    #[default]
+    Warn,
+    /// Log the affected heights and exit with an error
+    Halt,
+}
+
+/// Remembers the hashes of the last source chain blocks we fetched transactions from, so that
+/// we can tell when the next one doesn't build on top of them. Transactions from the blocks
+/// that were reorged out may already have been sent to the target chain, so in that case
+/// the target chain will have diverged from the source chain.
+pub(crate) struct ReorgDetector {
+    recent_blocks: VecDeque<(BlockHeight, CryptoHash)>,
+}
+
+impl ReorgDetector {
+    pub(crate) fn new() -> Self {
+        Self { recent_blocks: VecDeque::new() }
+    }
+
+    /// Records a newly fetched source chain block. If its `prev_hash` isn't the last block we
+    /// fetched, returns the blocks we remember fetching before it, which the caller can check
+    /// against the current source chain to find the ones that were orphaned. Only the new block
+    /// is remembered after that.
+    pub(crate) fn on_block(
+        &mut self,
+        height: BlockHeight,
+        hash: CryptoHash,
+        prev_hash: CryptoHash,
+    ) -> Option<Vec<(BlockHeight, CryptoHash)>> {
+        let reorged = match self.recent_blocks.back() {
+            Some((_, last_hash)) if *last_hash != prev_hash => {
+                Some(self.recent_blocks.drain(..).collect())
+            }
+            _ => None,
+        };
+        self.recent_blocks.push_back((height, hash));
+        if self.recent_blocks.len() > HISTORY_LEN {
+            self.recent_blocks.pop_front();
+        }
+        reorged
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReorgDetector;
+    use near_primitives::hash::CryptoHash;
+
+    #[test]
+    fn test_reorg_detector() {
+        let hash = |h: u64| CryptoHash::hash_bytes(&h.to_le_bytes());
+        let mut detector = ReorgDetector::new();
+
+        // the first block can't be checked against anything
+        assert_eq!(detector.on_block(10, hash(10), hash(9)), None);
+        assert_eq!(detector.on_block(11, hash(11), hash(10)), None);
+        // skipped heights are fine as long as the hashes link up
+        assert_eq!(detector.on_block(13, hash(13), hash(11)), None);
+
+        let fork_hash = CryptoHash::hash_bytes(b"fork");
+        assert_eq!(
+            detector.on_block(14, hash(14), fork_hash),
+            Some(vec![(10, hash(10)), (11, hash(11)), (13, hash(13))])
+        );
+        // we only compare against the new block after a reorg
+        assert_eq!(detector.on_block(15, hash(15), hash(14)), None);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{ChainError, SourceBlock, SourceChunk, SourceTransaction};
+use anyhow::Context;
+use async_trait::async_trait;
+use near_crypto::PublicKey;
+use near_jsonrpc_client_internal::{ChunkId, JsonRpcClient};
+use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind};
+use near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofRequest;
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryRequest};
+use near_jsonrpc_primitives::types::receipts::{ReceiptReference, RpcReceiptRequest};
+use near_primitives::action::Action;
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::Receipt;
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockId, BlockReference, Finality, TransactionOrReceiptId,
+};
+use near_primitives::views::{
+    AccessKeyPermissionView, BlockView, ExecutionOutcomeWithIdView, QueryRequest,
+    SignedTransactionView,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Handler errors returned by the RPC node when it doesn't have what we asked for (yet).
+const UNKNOWN_ERROR_NAMES: &[&str] = &[
+    "UNKNOWN_BLOCK",
+    "UNKNOWN_CHUNK",
+    "UNKNOWN_RECEIPT",
+    "UNKNOWN_TRANSACTION_OR_RECEIPT",
+    "NOT_CONFIRMED",
+    "UNAVAILABLE_SHARD",
+    "UNKNOWN_ACCOUNT",
+    "GARBAGE_COLLECTED_BLOCK",
+];
+
+fn chain_error(rpc_url: &str, err: RpcError) -> ChainError {
+    if let Some(RpcErrorKind::HandlerError(value)) = &err.error_struct {
+        let name = value.get("name").and_then(|name| name.as_str());
+        if name.is_some_and(|name| UNKNOWN_ERROR_NAMES.contains(&name)) {
+            return ChainError::Unknown;
+        }
+    }
+    ChainError::Other(anyhow::anyhow!("RPC request to {} failed: {}", rpc_url, err))
+}
+
+impl TryFrom<SignedTransactionView> for SourceTransaction {
+    type Error = anyhow::Error;
+
+    fn try_from(tx: SignedTransactionView) -> anyhow::Result<Self> {
+        let actions = tx
+            .actions
+            .into_iter()
+            .map(Action::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!(e))
+            .with_context(|| format!("failed converting the actions of transaction {}", tx.hash))?;
+        Ok(Self {
+            hash: tx.hash,
+            signer_id: tx.signer_id,
+            public_key: tx.public_key,
+            nonce: tx.nonce,
+            receiver_id: tx.receiver_id,
+            actions,
+        })
+    }
+}
+
+/// Reads the source chain from an RPC node instead of from a local home dir. Every block
+/// takes one request per chunk on top of the one for the block, and looking up receipts
+/// and execution outcomes takes a round trip each, so this is a lot slower than reading
+/// a local DB. The RPC node must track all shards, and if we're starting from a height
+/// that's already been garbage collected on regular nodes, it must be an archival node.
+pub(crate) struct ChainAccess {
+    rpc_url: String,
+    client: JsonRpcClient,
+}
+
+impl ChainAccess {
+    pub(crate) fn new(
+        rpc_url: String,
+        client_cert: Option<&crate::key_util::RpcClientCert>,
+    ) -> anyhow::Result<Self> {
+        let client = crate::key_util::new_rpc_client(&rpc_url, client_cert)?;
+        Ok(Self { rpc_url, client })
+    }
+
+    async fn block(&self, block_reference: BlockReference) -> Result<BlockView, ChainError> {
+        self.client.block(block_reference).await.map_err(|e| chain_error(&self.rpc_url, e))
+    }
+
+    async fn final_head(&self) -> Result<BlockView, ChainError> {
+        self.block(BlockReference::Finality(Finality::Final)).await
+    }
+}
+
+#[async_trait(?Send)]
+impl crate::ChainAccess for ChainAccess {
+    async fn init(
+        &self,
+        last_height: BlockHeight,
+        num_initial_blocks: usize,
+    ) -> anyhow::Result<Vec<BlockHeight>> {
+        let mut block_heights = Vec::with_capacity(num_initial_blocks);
+        let mut height = last_height;
+
+        loop {
+            // Like the online source, get_next_block_height() lets us pass a height that
+            // doesn't exist in the chain, and only returns final heights.
+            match self.get_next_block_height(height).await {
+                Ok(h) => {
+                    block_heights.push(h);
+                    height = h;
+                    if block_heights.len() >= num_initial_blocks {
+                        return Ok(block_heights);
+                    }
+                }
+                Err(ChainError::Unknown) => {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                Err(ChainError::Other(e)) => {
+                    return Err(e)
+                        .with_context(|| format!("failed fetching next block after #{}", height));
+                }
+            }
+        }
+    }
+
+    async fn block_height_to_hash(&self, height: BlockHeight) -> Result<CryptoHash, ChainError> {
+        Ok(self.block(BlockReference::BlockId(BlockId::Height(height))).await?.header.hash)
+    }
+
+    async fn head_height(&self) -> Result<BlockHeight, ChainError> {
+        Ok(self.final_head().await?.header.height)
+    }
+
+    async fn get_txs(&self, height: BlockHeight) -> Result<SourceBlock, ChainError> {
+        let block = self.block(BlockReference::BlockId(BlockId::Height(height))).await?;
+        let mut chunks = Vec::new();
+        for c in block.chunks {
+            if c.height_included != height {
+                continue;
+            }
+            let chunk = match self.client.chunk(ChunkId::Hash(c.chunk_hash)).await {
+                Ok(chunk) => chunk,
+                Err(e) => match chain_error(&self.rpc_url, e) {
+                    ChainError::Unknown => {
+                        tracing::error!(
+                            "Can't fetch source chain shard {} chunk {} at height {}. Does the RPC node track all shards?",
+                            c.shard_id,
+                            c.chunk_hash,
+                            height
+                        );
+                        continue;
+                    }
+                    e => return Err(e),
+                },
+            };
+            let transactions = chunk
+                .transactions
+                .into_iter()
+                .map(SourceTransaction::try_from)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let receipts = chunk
+                .receipts
+                .into_iter()
+                .map(|r| Receipt::try_from(r).map_err(|e| anyhow::anyhow!(e)))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .with_context(|| {
+                    format!("failed converting the receipts of chunk {}", c.chunk_hash)
+                })?;
+            chunks.push(SourceChunk { shard_id: c.shard_id, transactions, receipts });
+        }
+
+        Ok(SourceBlock {
+            hash: block.header.hash,
+            prev_hash: block.header.prev_hash,
+            gas_price: block.header.gas_price,
+            timestamp: block.header.timestamp_nanosec,
+            chunks,
+        })
+    }
+
+    async fn get_next_block_height(
+        &self,
+        mut height: BlockHeight,
+    ) -> Result<BlockHeight, ChainError> {
+        let head = self.head_height().await?;
+
+        if height >= head {
+            // let's only return finalized heights
+            Err(ChainError::Unknown)
+        } else if height + 1 == head {
+            Ok(head)
+        } else {
+            loop {
+                height += 1;
+                if height >= head {
+                    break Err(ChainError::Unknown);
+                }
+                match self.block(BlockReference::BlockId(BlockId::Height(height))).await {
+                    Ok(b) => break Ok(b.header.height),
+                    Err(ChainError::Unknown) => {}
+                    Err(e) => break Err(e),
+                }
+            }
+        }
+    }
+
+    async fn get_outcome(
+        &self,
+        id: TransactionOrReceiptId,
+    ) -> Result<ExecutionOutcomeWithIdView, ChainError> {
+        // There's no plain RPC method returning an execution outcome by ID, but the light
+        // client proof includes it.
+        let light_client_head = self.final_head().await?.header.hash;
+        Ok(self
+            .client
+            .light_client_proof(RpcLightClientExecutionProofRequest { id, light_client_head })
+            .await
+            .map_err(|e| chain_error(&self.rpc_url, e))?
+            .outcome_proof)
+    }
+
+    async fn get_receipt(&self, id: &CryptoHash) -> Result<Arc<Receipt>, ChainError> {
+        let receipt_view = self
+            .client
+            .EXPERIMENTAL_receipt(RpcReceiptRequest {
+                receipt_reference: ReceiptReference { receipt_id: *id },
+            })
+            .await
+            .map_err(|e| chain_error(&self.rpc_url, e))?
+            .receipt_view;
+        Ok(Arc::new(receipt_view.try_into().unwrap()))
+    }
+
+    async fn get_full_access_keys(
+        &self,
+        account_id: &AccountId,
+        block_hash: &CryptoHash,
+    ) -> Result<Vec<PublicKey>, ChainError> {
+        let mut ret = Vec::new();
+        match self
+            .client
+            .query(RpcQueryRequest {
+                block_reference: BlockReference::BlockId(BlockId::Hash(*block_hash)),
+                request: QueryRequest::ViewAccessKeyList { account_id: account_id.clone() },
+            })
+            .await
+            .map_err(|e| chain_error(&self.rpc_url, e))?
+            .kind
+        {
+            QueryResponseKind::AccessKeyList(l) => {
+                for k in l.keys {
+                    if k.access_key.permission == AccessKeyPermissionView::FullAccess {
+                        ret.push(k.public_key);
+                    }
+                }
+            }
+            k => {
+                return Err(ChainError::Other(anyhow::anyhow!(
+                    "received unexpected response for access key query: {:?}",
+                    k
+                )));
+            }
+        };
+        Ok(ret)
+    }
+}
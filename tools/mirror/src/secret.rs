@@ -1,3 +1,4 @@
+use near_crypto::KeyType;
 use rand_core::{OsRng, RngCore};
 use std::fs::File;
 use std::io::Write;
@@ -10,6 +11,9 @@ struct KeyMapSecret([u8; SECRET_LEN]);
 #[derive(serde::Serialize, serde::Deserialize)]
 struct MirrorSecretConfig {
     pub key_map_secret: Option<KeyMapSecret>,
+    /// If set, all keys are mapped to keys of this type. See `key_mapping::try_map_key()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_key_type: Option<KeyType>,
 }
 
 impl serde::Serialize for KeyMapSecret {
@@ -58,27 +62,45 @@ impl FromStr for KeyMapSecret {
     }
 }
 
-pub(crate) fn generate<P: AsRef<Path>>(secret_file_out: P) -> anyhow::Result<[u8; SECRET_LEN]> {
+pub(crate) fn generate<P: AsRef<Path>>(
+    secret_file_out: P,
+    target_key_type: Option<KeyType>,
+) -> anyhow::Result<[u8; SECRET_LEN]> {
     let mut secret = [0; SECRET_LEN];
     let mut out = File::create(secret_file_out)?;
 
     OsRng.fill_bytes(&mut secret);
-    let config = MirrorSecretConfig { key_map_secret: Some(KeyMapSecret(secret)) };
+    let config = MirrorSecretConfig { key_map_secret: Some(KeyMapSecret(secret)), target_key_type };
     let str = serde_json::to_string_pretty(&config)?;
     out.write_all(str.as_bytes())?;
     Ok(secret)
 }
 
-pub(crate) fn write_empty<P: AsRef<Path>>(secret_file_out: P) -> anyhow::Result<()> {
+pub(crate) fn write_empty<P: AsRef<Path>>(
+    secret_file_out: P,
+    target_key_type: Option<KeyType>,
+) -> anyhow::Result<()> {
     let mut out = File::create(secret_file_out)?;
-    let config = MirrorSecretConfig { key_map_secret: None };
+    let config = MirrorSecretConfig { key_map_secret: None, target_key_type };
     let str = serde_json::to_string_pretty(&config)?;
     out.write_all(str.as_bytes())?;
     Ok(())
 }
 
-pub fn load<P: AsRef<Path>>(secret_file: P) -> anyhow::Result<Option<[u8; SECRET_LEN]>> {
+/// The contents of a secret file written by the `prepare` command.
+#[derive(Default)]
+pub struct MirrorSecret {
+    pub secret: Option<[u8; SECRET_LEN]>,
+    /// The key type to pass to the `key_mapping` functions, so that keys are mapped
+    /// the same way they were when the file was written.
+    pub target_key_type: Option<KeyType>,
+}
+
+pub fn load<P: AsRef<Path>>(secret_file: P) -> anyhow::Result<MirrorSecret> {
     let s = std::fs::read_to_string(secret_file)?;
     let config: MirrorSecretConfig = serde_json::from_str(&s)?;
-    Ok(config.key_map_secret.map(|s| s.0))
+    Ok(MirrorSecret {
+        secret: config.key_map_secret.map(|s| s.0),
+        target_key_type: config.target_key_type,
+    })
 }
@@ -0,0 +1,134 @@
+use near_primitives::errors::InvalidTxError;
+use near_primitives::types::AccountId;
+use rand_core::{OsRng, RngCore};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+// Delay before the first retry of a transaction rejected by the target node.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+// Default cap for the delay between retries, used if not set in the mirror config.
+pub(crate) const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+// Number of times we try sending a transaction before giving up on it.
+pub(crate) const MAX_SEND_ATTEMPTS: u32 = 5;
+
+struct AccountBackoff {
+    // number of consecutive rejected submissions for this account
+    failures: u32,
+    // don't send any transactions from this account before this time
+    retry_at: Instant,
+}
+
+/// Keeps track of signer accounts whose transactions were recently rejected by the
+/// target node, so that we wait a bit before sending more transactions from them
+/// instead of flooding the node with submissions that are going to fail anyway.
+///
+/// The delay doubles with each consecutive failure, up to `max_backoff`, and is
+/// randomized so that many stuck accounts don't all retry at the same time.
+pub(crate) struct SendBackoff {
+    max_backoff: Duration,
+    accounts: HashMap<AccountId, AccountBackoff>,
+}
+
+impl SendBackoff {
+    pub(crate) fn new(max_backoff: Option<Duration>) -> Self {
+        Self { max_backoff: max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF), accounts: HashMap::new() }
+    }
+
+    /// Changes the longest delay used for the failures recorded from now on.
+    pub(crate) fn set_max_backoff(&mut self, max_backoff: Option<Duration>) {
+        self.max_backoff = max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF);
+    }
+
+    /// Returns the time before which we shouldn't send transactions signed by `account_id`,
+    /// or None if there's no reason to wait.
+    pub(crate) fn retry_at(&self, account_id: &AccountId) -> Option<Instant> {
+        let backoff = self.accounts.get(account_id)?;
+        if backoff.retry_at > Instant::now() { Some(backoff.retry_at) } else { None }
+    }
+
+    /// Records a rejected submission for `account_id` and returns the time after which
+    /// the transaction can be retried.
+    pub(crate) fn on_failure(&mut self, account_id: &AccountId) -> Instant {
+        let backoff = self
+            .accounts
+            .entry(account_id.clone())
+            .or_insert_with(|| AccountBackoff { failures: 0, retry_at: Instant::now() });
+        backoff.failures += 1;
+        backoff.retry_at = Instant::now() + backoff_delay(backoff.failures, self.max_backoff);
+        backoff.retry_at
+    }
+
+    pub(crate) fn on_success(&mut self, account_id: &AccountId) {
+        self.accounts.remove(account_id);
+    }
+}
+
+// Exponential backoff with "equal jitter": half of the delay is fixed and the other half random.
+fn backoff_delay(failures: u32, max_backoff: Duration) -> Duration {
+    let exp = INITIAL_BACKOFF.saturating_mul(1 << failures.saturating_sub(1).min(16));
+    let delay = exp.min(max_backoff);
+    let half = delay / 2;
+    let jitter_millis = half.as_millis() as u64;
+    let jitter = if jitter_millis == 0 {
+        Duration::ZERO
+    } else {
+        Duration::from_millis(OsRng.next_u64() % (jitter_millis + 1))
+    };
+    half + jitter
+}
+
+/// Returns a label for the metrics if the error means the transaction may be accepted
+/// if we send it again a bit later, or None if retrying won't help.
+pub(crate) fn retryable_error(err: &InvalidTxError) -> Option<&'static str> {
+    match err {
+        // This happens when the target chain nonce of the access key hasn't yet caught up with
+        // some transaction or receipt we sent before.
+        InvalidTxError::InvalidNonce { .. } => Some("invalid_nonce"),
+        // The target node rejects transactions instead of queueing them when the receiving shard
+        // is congested, which is what we get when its pools are full.
+        InvalidTxError::ShardCongested { .. } => Some("shard_congested"),
+        InvalidTxError::ShardStuck { .. } => Some("shard_stuck"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{INITIAL_BACKOFF, SendBackoff, backoff_delay};
+    use near_primitives::types::AccountId;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff_delay() {
+        let max_backoff = Duration::from_secs(2);
+        for failures in 1..10 {
+            let exp = INITIAL_BACKOFF * (1 << (failures - 1));
+            let expected_max = exp.min(max_backoff);
+            let delay = backoff_delay(failures, max_backoff);
+            assert!(delay >= expected_max / 2, "{:?} < {:?}", delay, expected_max / 2);
+            assert!(delay <= expected_max, "{:?} > {:?}", delay, expected_max);
+        }
+        // doesn't overflow with lots of failures
+        assert!(backoff_delay(u32::MAX, max_backoff) <= max_backoff);
+    }
+
+    #[test]
+    fn test_send_backoff() {
+        let account: AccountId = "test.near".parse().unwrap();
+        let other: AccountId = "other.near".parse().unwrap();
+        let mut backoff = SendBackoff::new(Some(Duration::from_secs(10)));
+
+        assert_eq!(backoff.retry_at(&account), None);
+        let retry_at = backoff.on_failure(&account);
+        assert_eq!(backoff.retry_at(&account), Some(retry_at));
+        assert_eq!(backoff.retry_at(&other), None);
+
+        // the delay keeps growing while the account keeps failing
+        let next_retry_at = backoff.on_failure(&account);
+        assert!(next_retry_at >= retry_at);
+
+        backoff.on_success(&account);
+        assert_eq!(backoff.retry_at(&account), None);
+    }
+}
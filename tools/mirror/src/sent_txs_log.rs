@@ -0,0 +1,116 @@
+use anyhow::Context;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, Nonce};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+const HEADER: &str =
+    "source_tx_hash,target_signer_id,target_receiver_id,nonce,source_height,sent_at_ms";
+
+/// Appends one CSV row per transaction successfully sent to the target chain, so that
+/// there's a record of what the mirror submitted that doesn't depend on the log output.
+///
+/// The file is opened in append mode, so rows from previous runs are kept across restarts.
+/// The header is only written if the file is empty. Rows are buffered, and written out
+/// whenever `flush()` is called after sending a batch of transactions.
+pub(crate) struct SentTxsLog {
+    writer: BufWriter<File>,
+}
+
+impl SentTxsLog {
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed opening {}", path.display()))?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_empty {
+            writeln!(writer, "{}", HEADER)?;
+            writer.flush()?;
+        }
+        Ok(Self { writer })
+    }
+
+    /// `source_tx_hash` is None for transactions that don't correspond to a single source
+    /// chain transaction, like the extra AddKey and CreateAccount ones. `source_height`
+    /// is None for unstake transactions, which are not sent because of a source chain block.
+    pub(crate) fn record(
+        &mut self,
+        source_tx_hash: Option<&CryptoHash>,
+        target_signer_id: &AccountId,
+        target_receiver_id: &AccountId,
+        nonce: Nonce,
+        source_height: Option<BlockHeight>,
+        sent_at: SystemTime,
+    ) -> anyhow::Result<()> {
+        writeln!(
+            self.writer,
+            "{}",
+            format_row(
+                source_tx_hash,
+                target_signer_id,
+                target_receiver_id,
+                nonce,
+                source_height,
+                sent_at
+            )
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn format_row(
+    source_tx_hash: Option<&CryptoHash>,
+    target_signer_id: &AccountId,
+    target_receiver_id: &AccountId,
+    nonce: Nonce,
+    source_height: Option<BlockHeight>,
+    sent_at: SystemTime,
+) -> String {
+    let sent_at_ms =
+        sent_at.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    // account IDs and base58 hashes never contain commas or quotes, so nothing needs escaping
+    format!(
+        "{},{},{},{},{},{}",
+        source_tx_hash.map(|h| h.to_string()).unwrap_or_default(),
+        target_signer_id,
+        target_receiver_id,
+        nonce,
+        source_height.map(|h| h.to_string()).unwrap_or_default(),
+        sent_at_ms,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::format_row;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::AccountId;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_format_row() {
+        let signer: AccountId = "alice.near".parse().unwrap();
+        let receiver: AccountId = "bob.near".parse().unwrap();
+        let sent_at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let hash = CryptoHash::hash_bytes(b"tx");
+
+        assert_eq!(
+            format_row(Some(&hash), &signer, &receiver, 5, Some(100), sent_at),
+            format!("{},alice.near,bob.near,5,100,1700000000123", hash)
+        );
+        assert_eq!(
+            format_row(None, &signer, &receiver, 5, None, sent_at),
+            ",alice.near,bob.near,5,,1700000000123"
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::Context;
+use near_crypto::{KeyType, PublicKey};
+use near_jsonrpc_client_internal::JsonRpcClient;
+use near_jsonrpc_primitives::types::query::{
+    QueryResponseKind as RpcQueryResponseKind, RpcQueryRequest,
+};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, Balance, BlockReference, Finality};
+use near_primitives::views::{
+    AccessKeyInfoView, AccessKeyPermissionView, AccountView, QueryRequest,
+};
+use std::collections::HashMap;
+
+/// State of an account fetched from one of the chains.
+pub(crate) struct AccountState {
+    pub(crate) account: AccountView,
+    pub(crate) keys: Vec<AccessKeyInfoView>,
+}
+
+/// A difference between an account on the source chain and the corresponding
+/// account on the target chain.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Discrepancy {
+    /// The account exists on the source chain, but not on the target chain
+    MissingAccount,
+    Balance {
+        source: Balance,
+        target: Balance,
+    },
+    Locked {
+        source: Balance,
+        target: Balance,
+    },
+    CodeHash {
+        source: CryptoHash,
+        target: CryptoHash,
+    },
+    /// The target chain has no key corresponding to this source chain key
+    MissingKey {
+        source_key: PublicKey,
+        target_key: PublicKey,
+    },
+    KeyPermission {
+        target_key: PublicKey,
+        source: AccessKeyPermissionView,
+        target: AccessKeyPermissionView,
+    },
+    /// A target chain key that doesn't correspond to any source chain key,
+    /// and isn't the default extra key
+    UnexpectedKey(PublicKey),
+}
+
+impl std::fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingAccount => write!(f, "account does not exist on the target chain"),
+            Self::Balance { source, target } => {
+                write!(f, "balance differs: source {}, target {}", source, target)
+            }
+            Self::Locked { source, target } => {
+                write!(f, "locked balance differs: source {}, target {}", source, target)
+            }
+            Self::CodeHash { source, target } => {
+                write!(f, "code hash differs: source {}, target {}", source, target)
+            }
+            Self::MissingKey { source_key, target_key } => {
+                write!(f, "key {} missing on the target chain (maps to {})", source_key, target_key)
+            }
+            Self::KeyPermission { target_key, source, target } => write!(
+                f,
+                "permission of key {} differs: source {:?}, target {:?}",
+                target_key, source, target
+            ),
+            Self::UnexpectedKey(key) => write!(f, "unexpected key {} on the target chain", key),
+        }
+    }
+}
+
+/// Compares source chain account `account_id` with the corresponding account on
+/// the target chain, mapping the source chain keys the same way `mirror run` does.
+pub(crate) fn compare_accounts(
+    account_id: &AccountId,
+    source: &AccountState,
+    target: Option<&AccountState>,
+    account_map: &crate::account_map::AccountMap,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> Vec<Discrepancy> {
+    let Some(target) = target else {
+        return vec![Discrepancy::MissingAccount];
+    };
+    let mut discrepancies = Vec::new();
+    if source.account.amount != target.account.amount {
+        discrepancies.push(Discrepancy::Balance {
+            source: source.account.amount,
+            target: target.account.amount,
+        });
+    }
+    if source.account.locked != target.account.locked {
+        discrepancies.push(Discrepancy::Locked {
+            source: source.account.locked,
+            target: target.account.locked,
+        });
+    }
+    if source.account.code_hash != target.account.code_hash {
+        discrepancies.push(Discrepancy::CodeHash {
+            source: source.account.code_hash,
+            target: target.account.code_hash,
+        });
+    }
+
+    let mut target_keys: HashMap<&PublicKey, &AccessKeyPermissionView> =
+        target.keys.iter().map(|k| (&k.public_key, &k.access_key.permission)).collect();
+    let key_type = crate::key_mapping::account_key_type(account_id, target_key_type);
+    for k in source.keys.iter() {
+        let target_key = crate::key_mapping::map_key(&k.public_key, secret, key_type).public_key();
+        // Renamed function call key receivers are expected to differ.
+        let source_permission = account_map.map_permission_view(&k.access_key.permission);
+        match target_keys.remove(&target_key) {
+            Some(permission) => {
+                if *permission != source_permission {
+                    discrepancies.push(Discrepancy::KeyPermission {
+                        target_key,
+                        source: source_permission,
+                        target: permission.clone(),
+                    });
+                }
+            }
+            None => discrepancies
+                .push(Discrepancy::MissingKey { source_key: k.public_key.clone(), target_key }),
+        }
+    }
+    // The default extra key is added to accounts without a full access key
+    // so that we can control them on the target chain.
+    let default_extra_key =
+        crate::key_mapping::default_extra_key(secret, target_key_type).public_key();
+    for (key, _) in target_keys {
+        if *key != default_extra_key {
+            discrepancies.push(Discrepancy::UnexpectedKey(key.clone()));
+        }
+    }
+    discrepancies
+}
+
+async fn fetch_account_state(
+    rpc_client: &JsonRpcClient,
+    rpc_url: &str,
+    account_id: &AccountId,
+) -> anyhow::Result<Option<AccountState>> {
+    let block_reference = BlockReference::Finality(Finality::Final);
+    let request = RpcQueryRequest {
+        block_reference: block_reference.clone(),
+        request: QueryRequest::ViewAccount { account_id: account_id.clone() },
+    };
+    let account = match rpc_client.query(request).await {
+        Ok(response) => match response.kind {
+            RpcQueryResponseKind::ViewAccount(account) => account,
+            k => anyhow::bail!("received unexpected response for account query: {:?}", k),
+        },
+        Err(e) if crate::key_util::is_unknown_account(&e) => return Ok(None),
+        Err(e) => {
+            return Err(anyhow::anyhow!("{:?}", e))
+                .with_context(|| format!("failed fetching {} from {}", account_id, rpc_url));
+        }
+    };
+    let request = RpcQueryRequest {
+        block_reference,
+        request: QueryRequest::ViewAccessKeyList { account_id: account_id.clone() },
+    };
+    let response =
+        rpc_client.query(request).await.map_err(|e| anyhow::anyhow!("{:?}", e)).with_context(
+            || format!("failed fetching access keys of {} from {}", account_id, rpc_url),
+        )?;
+    let keys = match response.kind {
+        RpcQueryResponseKind::AccessKeyList(l) => l.keys,
+        k => anyhow::bail!("received unexpected response for access key query: {:?}", k),
+    };
+    Ok(Some(AccountState { account, keys }))
+}
+
+/// Fetches the given source chain accounts and the corresponding target chain
+/// accounts, and returns the target chain account ID and the discrepancies found
+/// for each of them.
+pub(crate) async fn verify_accounts(
+    source_rpc: &str,
+    target_rpc: &str,
+    accounts: &[AccountId],
+    account_map: &crate::account_map::AccountMap,
+    secret: Option<&[u8; crate::secret::SECRET_LEN]>,
+    target_key_type: Option<KeyType>,
+) -> anyhow::Result<Vec<(AccountId, Vec<Discrepancy>)>> {
+    let source_client = near_jsonrpc_client_internal::new_client(source_rpc);
+    let target_client = near_jsonrpc_client_internal::new_client(target_rpc);
+
+    let mut results = Vec::new();
+    for account_id in accounts {
+        let Some(source) = fetch_account_state(&source_client, source_rpc, account_id).await?
+        else {
+            anyhow::bail!("account {} does not exist on the source chain", account_id);
+        };
+        let target_account_id = account_map.map_account(account_id, secret);
+        let target = fetch_account_state(&target_client, target_rpc, &target_account_id).await?;
+        let discrepancies = compare_accounts(
+            account_id,
+            &source,
+            target.as_ref(),
+            account_map,
+            secret,
+            target_key_type,
+        );
+        results.push((target_account_id, discrepancies));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AccountState, Discrepancy, compare_accounts};
+    use crate::account_map::AccountMap;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::AccountId;
+    use near_primitives::views::{
+        AccessKeyInfoView, AccessKeyPermissionView, AccessKeyView, AccountView,
+    };
+    use std::collections::BTreeMap;
+
+    fn account(amount: u128, keys: Vec<AccessKeyInfoView>) -> AccountState {
+        AccountState {
+            account: AccountView {
+                amount,
+                locked: 0,
+                code_hash: CryptoHash::default(),
+                storage_usage: 100,
+                storage_paid_at: 0,
+                global_contract_hash: None,
+                global_contract_account_id: None,
+            },
+            keys,
+        }
+    }
+
+    fn key(
+        public_key: near_crypto::PublicKey,
+        permission: AccessKeyPermissionView,
+    ) -> AccessKeyInfoView {
+        AccessKeyInfoView { public_key, access_key: AccessKeyView { nonce: 0, permission } }
+    }
+
+    #[test]
+    fn test_compare_accounts() {
+        let full_key = SecretKey::from_random(KeyType::ED25519).public_key();
+        let fn_call_key = SecretKey::from_random(KeyType::ED25519).public_key();
+        let fn_call_permission = AccessKeyPermissionView::FunctionCall {
+            allowance: None,
+            receiver_id: "foo.near".to_string(),
+            method_names: vec![],
+        };
+        let account_id: AccountId = "foo.near".parse().unwrap();
+        let mapped_full_key = crate::key_mapping::map_key(&full_key, None, None).public_key();
+        let mapped_fn_call_key = crate::key_mapping::map_key(&fn_call_key, None, None).public_key();
+        let source = account(
+            10,
+            vec![
+                key(full_key.clone(), AccessKeyPermissionView::FullAccess),
+                key(fn_call_key.clone(), fn_call_permission.clone()),
+            ],
+        );
+
+        let target = account(
+            10,
+            vec![
+                key(mapped_full_key.clone(), AccessKeyPermissionView::FullAccess),
+                key(mapped_fn_call_key.clone(), fn_call_permission.clone()),
+                key(
+                    crate::key_mapping::default_extra_key(None, None).public_key(),
+                    AccessKeyPermissionView::FullAccess,
+                ),
+            ],
+        );
+        assert_eq!(
+            compare_accounts(
+                &account_id,
+                &source,
+                Some(&target),
+                &AccountMap::default(),
+                None,
+                None
+            ),
+            vec![]
+        );
+        assert_eq!(
+            compare_accounts(&account_id, &source, None, &AccountMap::default(), None, None),
+            vec![Discrepancy::MissingAccount]
+        );
+
+        // Function call keys for a renamed receiver allow calling its new ID instead.
+        let account_map = AccountMap::new(BTreeMap::from([(
+            "foo.near".parse().unwrap(),
+            "foo.testnet".parse().unwrap(),
+        )]))
+        .unwrap();
+        let renamed_permission = AccessKeyPermissionView::FunctionCall {
+            allowance: None,
+            receiver_id: "foo.testnet".to_string(),
+            method_names: vec![],
+        };
+        let target = account(
+            10,
+            vec![
+                key(mapped_full_key.clone(), AccessKeyPermissionView::FullAccess),
+                key(mapped_fn_call_key.clone(), renamed_permission),
+            ],
+        );
+        assert_eq!(
+            compare_accounts(&account_id, &source, Some(&target), &account_map, None, None),
+            vec![]
+        );
+
+        // Source chain keys are not mapped.
+        let unexpected_key = full_key.clone();
+        let target = account(
+            11,
+            vec![
+                key(unexpected_key.clone(), AccessKeyPermissionView::FullAccess),
+                key(mapped_fn_call_key.clone(), AccessKeyPermissionView::FullAccess),
+            ],
+        );
+        assert_eq!(
+            compare_accounts(
+                &account_id,
+                &source,
+                Some(&target),
+                &AccountMap::default(),
+                None,
+                None
+            ),
+            vec![
+                Discrepancy::Balance { source: 10, target: 11 },
+                Discrepancy::MissingKey { source_key: full_key, target_key: mapped_full_key },
+                Discrepancy::KeyPermission {
+                    target_key: mapped_fn_call_key,
+                    source: fn_call_permission,
+                    target: AccessKeyPermissionView::FullAccess,
+                },
+                Discrepancy::UnexpectedKey(unexpected_key),
+            ]
+        );
+    }
+}
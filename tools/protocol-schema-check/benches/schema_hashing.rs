@@ -0,0 +1,65 @@
+//! Benchmarks computing the hashes of the protocol schema, which the tool does
+//! for all the registered structs on every run.
+//!
+//! Structs are only registered with the `enable_const_type_id` cfg, so run it with
+//! `RUSTFLAGS="--cfg enable_const_type_id" cargo +nightly bench -p protocol-schema-check`.
+#![cfg_attr(enable_const_type_id, feature(const_type_id))]
+#![allow(unused_imports)]
+
+// Needed because otherwise the benchmark doesn't notice `ProtocolSchemaInfo`s
+// from other crates, same as the tool itself.
+use near_chain::*;
+use near_crypto::*;
+use near_epoch_manager::*;
+use near_jsonrpc_primitives::errors::*;
+use near_network::*;
+use near_parameters::*;
+use near_primitives::*;
+use near_store::*;
+use near_vm_runner::*;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use near_schema_checker_lib::{ProtocolSchema, ProtocolSchemaInfo};
+use protocol_schema_check::{SchemaStats, compute_hash, compute_schema, structs_by_type_id};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashSet};
+use std::hint::black_box;
+
+fn registered_structs() -> BTreeMap<TypeId, &'static ProtocolSchemaInfo> {
+    #[cfg(enable_const_type_id)]
+    {
+        // Same workaround as in the tool, see `main`.
+        LatestWitnessesInfo::ensure_registration();
+        ServerError::ensure_registration();
+    }
+    let structs = structs_by_type_id(inventory::iter::<ProtocolSchemaInfo>).unwrap_or_else(|c| {
+        panic!("TypeId collision: {} and {} are registered with the same TypeId", c.first, c.second)
+    });
+    assert!(!structs.is_empty(), "no protocol structs registered, see the module docs");
+    structs
+}
+
+/// Times hashing all the registered structs, both through `compute_schema` as
+/// the tool does, and with plain `compute_hash` calls. Throughput is reported
+/// in structs per second.
+fn bench_schema_hashing(c: &mut Criterion) {
+    let structs = registered_structs();
+    let mut group = c.benchmark_group("protocol_schema");
+    group.throughput(Throughput::Elements(structs.len() as u64));
+    group.bench_function("compute_schema", |b| {
+        b.iter(|| compute_schema(structs.values().copied(), black_box(&structs)))
+    });
+    group.bench_function("compute_hash", |b| {
+        b.iter(|| {
+            for info in structs.values() {
+                let mut types_in_compute = HashSet::new();
+                let mut stats = SchemaStats::default();
+                black_box(compute_hash(info, &structs, &mut types_in_compute, &mut stats));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_schema_hashing);
+criterion_main!(benches);
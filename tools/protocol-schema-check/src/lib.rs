@@ -0,0 +1,1197 @@
+//! Computes hashes of the protocol structs registered by the `ProtocolSchema`
+//! macro. Used by the `protocol-schema-check` tool, and by anything else which
+//! needs the schema of the structs linked into its binary.
+#![cfg_attr(enable_const_type_id, feature(const_type_id))]
+
+use near_schema_checker_lib::{FieldName, FieldTypeInfo, ProtocolSchemaInfo};
+use near_stable_hasher::StableHasher;
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Size of the schema traversed while computing the hash of a struct.
+#[derive(Debug, Default, PartialEq)]
+pub struct SchemaStats {
+    /// Number of fields visited, including the fields of nested types.
+    pub num_fields: usize,
+    /// Maximum nesting depth of protocol types, 1 if there are no nested
+    /// protocol types.
+    pub max_depth: usize,
+}
+
+pub fn compute_hash(
+    info: &ProtocolSchemaInfo,
+    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+    types_in_compute: &mut HashSet<TypeId>,
+    stats: &mut SchemaStats,
+) -> u32 {
+    let type_id = info.type_id();
+    if types_in_compute.contains(&type_id) {
+        return 0;
+    }
+    types_in_compute.insert(type_id);
+    stats.max_depth = stats.max_depth.max(types_in_compute.len());
+
+    let mut hasher = StableHasher::new();
+    match info {
+        ProtocolSchemaInfo::Struct { name, type_id: _, fields } => {
+            "struct".hash(&mut hasher);
+            name.hash(&mut hasher);
+            compute_fields_hash(fields, structs, types_in_compute, stats, &mut hasher);
+        }
+        ProtocolSchemaInfo::Enum { name, type_id: _, variants } => {
+            "enum".hash(&mut hasher);
+            name.hash(&mut hasher);
+            for (variant_name, variant_fields) in *variants {
+                variant_name.hash(&mut hasher);
+                if let Some(fields) = variant_fields {
+                    compute_fields_hash(fields, structs, types_in_compute, stats, &mut hasher);
+                }
+            }
+        }
+    }
+
+    types_in_compute.remove(&type_id);
+
+    hasher.finish() as u32
+}
+
+/// Two registered structs with different type names but the same `TypeId`.
+#[derive(Debug, PartialEq)]
+pub struct TypeIdCollision {
+    pub first: &'static str,
+    pub second: &'static str,
+}
+
+/// Maps the `TypeId` of each registered struct to its info.
+///
+/// Fails if two registrations with different type names share a `TypeId`,
+/// because one of them would silently be missing from the schema otherwise.
+pub fn structs_by_type_id(
+    infos: impl IntoIterator<Item = &'static ProtocolSchemaInfo>,
+) -> Result<BTreeMap<TypeId, &'static ProtocolSchemaInfo>, TypeIdCollision> {
+    let mut structs: BTreeMap<TypeId, &'static ProtocolSchemaInfo> = BTreeMap::new();
+    for info in infos {
+        if let Some(existing) = structs.insert(info.type_id(), info) {
+            if existing.type_name() != info.type_name() {
+                return Err(TypeIdCollision {
+                    first: existing.type_name(),
+                    second: info.type_name(),
+                });
+            }
+        }
+    }
+    Ok(structs)
+}
+
+/// Computes the hash of an enum which doesn't depend on the order of its
+/// variants, or returns None if `info` is a struct.
+///
+/// Used to tell apart reordering of variants from changes of their content.
+/// Reordering still changes the result of `compute_hash`, because borsh
+/// discriminants are positional.
+pub fn compute_variant_set_hash(
+    info: &ProtocolSchemaInfo,
+    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+) -> Option<u32> {
+    let ProtocolSchemaInfo::Enum { name, type_id, variants } = info else {
+        return None;
+    };
+    let mut types_in_compute: HashSet<TypeId> = [*type_id].into_iter().collect();
+    let mut stats = SchemaStats::default();
+    let mut variant_hashes: Vec<u32> = variants
+        .iter()
+        .map(|(variant_name, variant_fields)| {
+            let mut hasher = StableHasher::new();
+            variant_name.hash(&mut hasher);
+            if let Some(fields) = variant_fields {
+                compute_fields_hash(
+                    fields,
+                    structs,
+                    &mut types_in_compute,
+                    &mut stats,
+                    &mut hasher,
+                );
+            }
+            hasher.finish() as u32
+        })
+        .collect();
+    variant_hashes.sort_unstable();
+
+    let mut hasher = StableHasher::new();
+    "enum".hash(&mut hasher);
+    name.hash(&mut hasher);
+    variant_hashes.hash(&mut hasher);
+    Some(hasher.finish() as u32)
+}
+
+fn compute_fields_hash(
+    fields: &'static [(FieldName, FieldTypeInfo)],
+    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+    types_in_compute: &mut HashSet<TypeId>,
+    stats: &mut SchemaStats,
+    hasher: &mut StableHasher,
+) {
+    stats.num_fields += fields.len();
+    for (field_name, (type_name, generic_params)) in fields {
+        field_name.hash(hasher);
+        type_name.hash(hasher);
+        for &param_type_id in generic_params.iter() {
+            compute_type_hash(param_type_id, structs, types_in_compute, stats, hasher);
+        }
+    }
+}
+
+fn compute_type_hash(
+    type_id: TypeId,
+    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+    types_in_compute: &mut HashSet<TypeId>,
+    stats: &mut SchemaStats,
+    hasher: &mut StableHasher,
+) {
+    if let Some(nested_info) = structs.get(&type_id) {
+        compute_hash(nested_info, structs, types_in_compute, stats).hash(hasher);
+    } else {
+        // Unsupported type. Always assume that hash is 0 because we cannot
+        // compute nontrivial deterministic hash in such cases.
+        0.hash(hasher);
+    }
+}
+
+/// Version of the way hashes are computed. Must be bumped on any change to
+/// the hashing logic, so that the resulting mass hash mismatch is not confused
+/// with changes to the structs.
+pub const SCHEMA_FORMAT_VERSION: u32 = 1;
+
+/// Format version of files written before the version was stored.
+fn unversioned_schema_format() -> u32 {
+    1
+}
+
+/// Contents of `protocol_schema.toml`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct StoredSchema {
+    /// Value of `SCHEMA_FORMAT_VERSION` the hashes were computed with.
+    #[serde(default = "unversioned_schema_format")]
+    pub schema_format_version: u32,
+    /// Hashes of all protocol structs and enums by type name.
+    #[serde(flatten)]
+    pub hashes: BTreeMap<String, u32>,
+    /// Hashes of enums computed by `compute_variant_set_hash`, used to report
+    /// reordered variants.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variant_sets: BTreeMap<String, u32>,
+}
+
+impl StoredSchema {
+    /// Removes the structs for which `is_excluded` returns true, and returns
+    /// them as a separate schema with the same format version.
+    pub fn split_off_excluded(&mut self, is_excluded: impl Fn(&str) -> bool) -> StoredSchema {
+        StoredSchema {
+            schema_format_version: self.schema_format_version,
+            hashes: split_off_matching(&mut self.hashes, &is_excluded),
+            variant_sets: split_off_matching(&mut self.variant_sets, &is_excluded),
+        }
+    }
+
+    /// Adds the structs of `other`, overwriting the ones with the same name.
+    pub fn extend(&mut self, other: StoredSchema) {
+        self.hashes.extend(other.hashes);
+        self.variant_sets.extend(other.variant_sets);
+    }
+
+    /// Hash of all the `(name, hash)` pairs, sorted by name. Two schemas with
+    /// the same fingerprint and format version have the same structs with the
+    /// same hashes, so comparing fingerprints is enough to tell whether a
+    /// detailed diff is needed.
+    pub fn fingerprint(&self) -> u32 {
+        let mut hasher = StableHasher::new();
+        for (name, hash) in &self.hashes {
+            name.hash(&mut hasher);
+            hash.hash(&mut hasher);
+        }
+        hasher.finish() as u32
+    }
+}
+
+fn split_off_matching(
+    map: &mut BTreeMap<String, u32>,
+    matches: impl Fn(&str) -> bool,
+) -> BTreeMap<String, u32> {
+    let (matching, rest) = std::mem::take(map).into_iter().partition(|(name, _)| matches(name));
+    *map = rest;
+    matching
+}
+
+/// Returns whether `name` matches `pattern`, where `*` in the pattern matches
+/// any sequence of characters, including an empty one, and `?` matches any
+/// single character.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position in the pattern right after the last `*`, and the position in
+    // the name that `*` has matched up to, to backtrack to on a mismatch.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            backtrack = Some((p, n));
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Default for StoredSchema {
+    fn default() -> Self {
+        Self {
+            schema_format_version: SCHEMA_FORMAT_VERSION,
+            hashes: Default::default(),
+            variant_sets: Default::default(),
+        }
+    }
+}
+
+/// A difference between two schemas, as returned by `check_schema`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// The hash of the struct changed. `variant_order_only` is set for enums
+    /// that still have the same variants, but in a different order, which
+    /// changes their Borsh encoding since it includes the variant index.
+    Changed {
+        name: String,
+        old_hash: u32,
+        new_hash: u32,
+        variant_order_only: bool,
+    },
+    Added {
+        name: String,
+        hash: u32,
+    },
+    Removed {
+        name: String,
+    },
+}
+
+/// Compares `new` against `old`, and returns the changed and added structs in
+/// name order, followed by the removed ones. Both schemas are expected to have
+/// the same format version, otherwise every hash is reported as changed.
+pub fn check_schema(old: &StoredSchema, new: &StoredSchema) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    for (name, &new_hash) in &new.hashes {
+        match old.hashes.get(name) {
+            Some(&old_hash) if old_hash != new_hash => {
+                let old_variant_set = old.variant_sets.get(name);
+                let variant_order_only =
+                    old_variant_set.is_some() && old_variant_set == new.variant_sets.get(name);
+                changes.push(SchemaChange::Changed {
+                    name: name.clone(),
+                    old_hash,
+                    new_hash,
+                    variant_order_only,
+                });
+            }
+            Some(_) => {}
+            None => changes.push(SchemaChange::Added { name: name.clone(), hash: new_hash }),
+        }
+    }
+    for name in old.hashes.keys() {
+        if !new.hashes.contains_key(name) {
+            changes.push(SchemaChange::Removed { name: name.clone() });
+        }
+    }
+    changes
+}
+
+/// Returns the names of the enums whose hash is the same in `old` and `new`,
+/// but whose variant set hash in `new` is missing from `old` or differs from
+/// it. `check_schema` can only report reordered variants for enums that have a
+/// variant set in `old`, so these should be written back to the stored schema
+/// with `--accept` even though nothing changed, e.g. for files written before
+/// variant sets were stored.
+pub fn stale_variant_sets(old: &StoredSchema, new: &StoredSchema) -> Vec<String> {
+    new.variant_sets
+        .iter()
+        .filter(|(name, hash)| {
+            old.hashes.get(*name) == new.hashes.get(*name)
+                && old.variant_sets.get(*name) != Some(*hash)
+        })
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Computes the schema of the given structs, along with the stats of each of
+/// them.
+pub fn compute_schema(
+    infos: impl IntoIterator<Item = &'static ProtocolSchemaInfo>,
+    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+) -> (StoredSchema, BTreeMap<String, SchemaStats>) {
+    let mut schema = StoredSchema::default();
+    let mut all_stats: BTreeMap<String, SchemaStats> = Default::default();
+    for info in infos {
+        let mut types_in_compute: HashSet<TypeId> = Default::default();
+        let mut stats = SchemaStats::default();
+        let hash = compute_hash(info, structs, &mut types_in_compute, &mut stats);
+        schema.hashes.insert(info.type_name().to_string(), hash);
+        all_stats.insert(info.type_name().to_string(), stats);
+        if let Some(variant_set_hash) = compute_variant_set_hash(info, structs) {
+            schema.variant_sets.insert(info.type_name().to_string(), variant_set_hash);
+        }
+    }
+    (schema, all_stats)
+}
+
+/// Computes the hash of the registered struct named `type_name`, the same way
+/// as for `protocol_schema.toml`, or returns None if no such struct is linked
+/// into the binary. Meant for tests that check the hash of a single struct
+/// without running the whole tool.
+pub fn hash_of(type_name: &str) -> Option<u32> {
+    let structs = structs_by_type_id(inventory::iter::<ProtocolSchemaInfo>).unwrap_or_else(|c| {
+        panic!("TypeId collision: {} and {} are registered with the same TypeId", c.first, c.second)
+    });
+    let info = structs.values().find(|info| info.type_name() == type_name)?;
+    let mut types_in_compute: HashSet<TypeId> = Default::default();
+    let mut stats = SchemaStats::default();
+    Some(compute_hash(info, &structs, &mut types_in_compute, &mut stats))
+}
+
+/// Whether a registered protocol type is a struct or an enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaKind {
+    Struct,
+    Enum,
+}
+
+/// Metadata of a single registered protocol type, as returned by
+/// `all_protocol_schemas`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ProtocolSchemaSummary {
+    pub name: String,
+    pub kind: SchemaKind,
+    /// Field names of a struct, or variant names of an enum, in declaration
+    /// order.
+    pub fields: Vec<String>,
+    /// Same hash as stored in `protocol_schema.toml`.
+    pub hash: u32,
+}
+
+impl ProtocolSchemaSummary {
+    fn new(info: &ProtocolSchemaInfo, hash: u32) -> Self {
+        let (kind, fields) = match info {
+            ProtocolSchemaInfo::Struct { fields, .. } => {
+                (SchemaKind::Struct, fields.iter().map(|(name, _)| name.to_string()).collect())
+            }
+            ProtocolSchemaInfo::Enum { variants, .. } => {
+                (SchemaKind::Enum, variants.iter().map(|(name, _)| name.to_string()).collect())
+            }
+        };
+        Self { name: info.type_name().to_string(), kind, fields, hash }
+    }
+}
+
+/// Returns the summaries of all the protocol structs and enums linked into
+/// the binary, sorted by name. Meant for tools which need a catalog of the
+/// protocol schema, such as docs generators.
+pub fn all_protocol_schemas() -> Vec<ProtocolSchemaSummary> {
+    let structs = structs_by_type_id(inventory::iter::<ProtocolSchemaInfo>).unwrap_or_else(|c| {
+        panic!("TypeId collision: {} and {} are registered with the same TypeId", c.first, c.second)
+    });
+    let mut summaries: Vec<ProtocolSchemaSummary> = structs
+        .values()
+        .map(|info| {
+            let mut types_in_compute: HashSet<TypeId> = Default::default();
+            let mut stats = SchemaStats::default();
+            let hash = compute_hash(info, &structs, &mut types_in_compute, &mut stats);
+            ProtocolSchemaSummary::new(info, hash)
+        })
+        .collect();
+    summaries.sort_by(|a, b| a.name.cmp(&b.name));
+    summaries
+}
+
+#[cfg(all(test, enable_const_type_id))]
+mod tests {
+    use super::*;
+    use near_schema_checker_lib::ProtocolSchema;
+    use std::collections::HashMap;
+
+    fn do_compute_type_hash(
+        ty: TypeId,
+        structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+    ) -> u32 {
+        let mut hasher = StableHasher::new();
+        let mut types_in_compute: HashSet<TypeId> = Default::default();
+        let mut stats = SchemaStats::default();
+        compute_type_hash(ty, structs, &mut types_in_compute, &mut stats, &mut hasher);
+        hasher.finish() as u32
+    }
+
+    fn check_types(
+        ty: TypeId,
+        other_ty: TypeId,
+        expect_equal: bool,
+        structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+    ) {
+        let hash = do_compute_type_hash(ty, structs);
+        let other_hash = do_compute_type_hash(other_ty, structs);
+        assert_eq!(hash == other_hash, expect_equal);
+    }
+
+    fn collect_structs() -> BTreeMap<TypeId, &'static ProtocolSchemaInfo> {
+        structs_by_type_id(inventory::iter::<ProtocolSchemaInfo>).unwrap()
+    }
+
+    /// Helper types for tests.
+    type TestU64 = u64;
+    #[derive(ProtocolSchema)]
+    #[allow(unused)]
+    struct TestStruct {
+        a: u64,
+        b: String,
+    }
+    use TestStruct as TestStruct2;
+
+    /// Checks that structs with same names and underlying structure have the
+    /// same hash, even if used with different aliases.
+    #[test]
+    fn test_identical() {
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct TestStruct {
+            a: u64,
+            b: String,
+        }
+
+        check_types(
+            TypeId::of::<TestStruct>(),
+            TypeId::of::<TestStruct2>(),
+            true,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that if identical structs have different field names, hashes are
+    /// different.
+    #[test]
+    fn test_different_field_names() {
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct TestStruct {
+            a: u64,
+            c: String,
+        }
+
+        check_types(
+            TypeId::of::<TestStruct>(),
+            TypeId::of::<TestStruct2>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that if identical structs have different type names, hashes are
+    /// different.
+    #[test]
+    fn test_different_type_names() {
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct TestStruct {
+            a: TestU64,
+            b: String,
+        }
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct TestStruct2 {
+            a: u64,
+            b: String,
+        }
+
+        check_types(
+            TypeId::of::<TestStruct>(),
+            TypeId::of::<TestStruct2>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that struct and enum have different hashes.
+    #[test]
+    fn test_different_struct_enum() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            pub struct Empty;
+        }
+        use inner::Empty as EmptyStruct;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        enum Empty {}
+
+        check_types(TypeId::of::<Empty>(), TypeId::of::<EmptyStruct>(), false, &collect_structs());
+    }
+
+    /// Checks that hashes can differentiate integers.
+    #[test]
+    fn test_different_integers() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub struct Unsigned {
+                a: u32,
+            }
+        }
+        use inner::Unsigned as ShortUnsigned;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Unsigned {
+            a: u64,
+        }
+
+        check_types(
+            TypeId::of::<Unsigned>(),
+            TypeId::of::<ShortUnsigned>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that hashes can differentiate containers.
+    #[test]
+    fn test_different_containers() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub struct Container {
+                a: Vec<u32>,
+            }
+        }
+        use inner::Container as VecContainer;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Container {
+            a: HashMap<u32, u32>,
+        }
+
+        check_types(
+            TypeId::of::<Container>(),
+            TypeId::of::<VecContainer>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that hashes can differentiate generics in containers.
+    #[test]
+    fn test_different_container_generics() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub struct Container {
+                a: Vec<Vec<Vec<u32>>>,
+            }
+        }
+        use inner::Container as VecContainer;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Container {
+            a: Vec<Vec<Vec<i32>>>,
+        }
+
+        check_types(
+            TypeId::of::<Container>(),
+            TypeId::of::<VecContainer>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that hashes can differentiate one of generics in containers.
+    #[test]
+    fn test_different_container_two_generics() {
+        mod inner {
+            use super::*;
+
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub struct Container {
+                a: HashMap<u32, u16>,
+            }
+        }
+        use inner::Container as MapContainer;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Container {
+            a: HashMap<u32, u32>,
+        }
+
+        check_types(
+            TypeId::of::<Container>(),
+            TypeId::of::<MapContainer>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that hashes can differentiate nested containers.
+    #[test]
+    fn test_nested_containers_different_types() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub struct Container {
+                a: Vec<Vec<u32>>,
+            }
+        }
+        use inner::Container as VecContainer;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Container {
+            a: Vec<Vec<i32>>,
+        }
+
+        check_types(
+            TypeId::of::<Container>(),
+            TypeId::of::<VecContainer>(),
+            false,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that if nested containers differ, this is not caught by hash
+    /// check.
+    /// Added to indicate limitations of implementation.
+    #[test]
+    fn test_nested_containers_different_containers_unsupported() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub struct Container {
+                a: Vec<Vec<u32>>,
+            }
+        }
+        use inner::Container as VecContainer;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Container {
+            a: Vec<HashSet<u32>>,
+        }
+
+        check_types(
+            TypeId::of::<Container>(),
+            TypeId::of::<VecContainer>(),
+            true,
+            &collect_structs(),
+        );
+    }
+
+    /// Checks that fields of nested protocol types are counted and that the
+    /// nesting depth is reported.
+    #[test]
+    fn test_stats() {
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Inner {
+            a: u64,
+            b: u32,
+        }
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct Outer {
+            inner: Vec<Inner>,
+            c: u8,
+        }
+
+        let structs = collect_structs();
+        let mut types_in_compute: HashSet<TypeId> = Default::default();
+
+        let mut stats = SchemaStats::default();
+        compute_hash(structs[&TypeId::of::<Inner>()], &structs, &mut types_in_compute, &mut stats);
+        assert_eq!(stats, SchemaStats { num_fields: 2, max_depth: 1 });
+
+        let mut stats = SchemaStats::default();
+        compute_hash(structs[&TypeId::of::<Outer>()], &structs, &mut types_in_compute, &mut stats);
+        assert_eq!(stats, SchemaStats { num_fields: 4, max_depth: 2 });
+    }
+
+    /// Checks that reordering enum variants changes the hash but keeps the
+    /// variant set hash, which is used to report the reorder.
+    #[test]
+    fn test_check_schema() {
+        let schema = |hashes: &[(&str, u32)], variant_sets: &[(&str, u32)]| StoredSchema {
+            hashes: hashes.iter().map(|(name, hash)| (name.to_string(), *hash)).collect(),
+            variant_sets: variant_sets
+                .iter()
+                .map(|(name, hash)| (name.to_string(), *hash))
+                .collect(),
+            ..StoredSchema::default()
+        };
+        let old = schema(&[("A", 1), ("B", 2), ("C", 3), ("E", 5)], &[("E", 50)]);
+        let new = schema(&[("A", 1), ("B", 20), ("D", 4), ("E", 6)], &[("E", 50)]);
+        assert_eq!(check_schema(&old, &old), vec![]);
+        assert_eq!(
+            check_schema(&old, &new),
+            vec![
+                SchemaChange::Changed {
+                    name: "B".to_string(),
+                    old_hash: 2,
+                    new_hash: 20,
+                    variant_order_only: false
+                },
+                SchemaChange::Added { name: "D".to_string(), hash: 4 },
+                SchemaChange::Changed {
+                    name: "E".to_string(),
+                    old_hash: 5,
+                    new_hash: 6,
+                    variant_order_only: true
+                },
+                SchemaChange::Removed { name: "C".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stale_variant_sets() {
+        let schema = |hashes: &[(&str, u32)], variant_sets: &[(&str, u32)]| StoredSchema {
+            hashes: hashes.iter().map(|(name, hash)| (name.to_string(), *hash)).collect(),
+            variant_sets: variant_sets
+                .iter()
+                .map(|(name, hash)| (name.to_string(), *hash))
+                .collect(),
+            ..StoredSchema::default()
+        };
+        let new =
+            schema(&[("A", 1), ("E", 5), ("F", 6), ("G", 7)], &[("E", 50), ("F", 60), ("G", 70)]);
+        // Variant sets are missing for unchanged enums, or differ from the
+        // current ones. Changed enums are left to `check_schema`.
+        let old = schema(&[("A", 1), ("E", 5), ("F", 6), ("G", 8)], &[("F", 61)]);
+        assert_eq!(stale_variant_sets(&old, &new), vec!["E".to_string(), "F".to_string()]);
+        assert_eq!(
+            check_schema(&old, &new),
+            vec![SchemaChange::Changed {
+                name: "G".to_string(),
+                old_hash: 8,
+                new_hash: 7,
+                variant_order_only: false
+            }]
+        );
+        assert_eq!(stale_variant_sets(&new, &new), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_reordered_enum_variants() {
+        mod inner {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub enum Enum {
+                A(u32),
+                B { b: u64 },
+                C,
+            }
+        }
+        use inner::Enum as OrderedEnum;
+
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        enum Enum {
+            C,
+            A(u32),
+            B { b: u64 },
+        }
+
+        mod changed {
+            #[derive(super::ProtocolSchema)]
+            #[allow(unused)]
+            pub enum Enum {
+                C,
+                A(u32),
+                B { b: u32 },
+            }
+        }
+        use changed::Enum as ChangedEnum;
+
+        let structs = collect_structs();
+        check_types(TypeId::of::<Enum>(), TypeId::of::<OrderedEnum>(), false, &structs);
+
+        let variant_set_hash = |ty| compute_variant_set_hash(structs[&ty], &structs).unwrap();
+        assert_eq!(
+            variant_set_hash(TypeId::of::<Enum>()),
+            variant_set_hash(TypeId::of::<OrderedEnum>())
+        );
+        assert_ne!(
+            variant_set_hash(TypeId::of::<Enum>()),
+            variant_set_hash(TypeId::of::<ChangedEnum>())
+        );
+        assert_eq!(compute_variant_set_hash(structs[&TypeId::of::<TestStruct>()], &structs), None);
+    }
+
+    /// Names used by the fuzzer. Variant names are disjoint from field and
+    /// type names because otherwise the hash can't tell apart, for example,
+    /// `enum E { A, B, C }` and `enum E { A { b: C } }`. This is a known
+    /// limitation of the implementation.
+    #[derive(Debug, PartialEq, arbitrary::Arbitrary)]
+    enum FuzzName {
+        A,
+        B,
+        C,
+    }
+
+    #[derive(Debug, PartialEq, arbitrary::Arbitrary)]
+    enum FuzzVariantName {
+        X,
+        Y,
+    }
+
+    impl FuzzName {
+        fn as_str(&self) -> &'static str {
+            match self {
+                FuzzName::A => "A",
+                FuzzName::B => "B",
+                FuzzName::C => "C",
+            }
+        }
+    }
+
+    impl FuzzVariantName {
+        fn as_str(&self) -> &'static str {
+            match self {
+                FuzzVariantName::X => "X",
+                FuzzVariantName::Y => "Y",
+            }
+        }
+    }
+
+    /// Tree resembling `ProtocolSchemaInfo`, generated by the fuzzer.
+    /// Generic parameters of fields are nested trees. Enum variants without
+    /// fields are unit variants.
+    #[derive(Debug, PartialEq, arbitrary::Arbitrary)]
+    enum FuzzSchema {
+        Struct { name: FuzzName, fields: Vec<FuzzField> },
+        Enum { name: FuzzName, variants: Vec<(FuzzVariantName, Vec<FuzzField>)> },
+    }
+
+    #[derive(Debug, PartialEq, arbitrary::Arbitrary)]
+    struct FuzzField {
+        name: FuzzName,
+        type_name: FuzzName,
+        generic_params: Vec<FuzzSchema>,
+    }
+
+    impl FuzzSchema {
+        fn num_nodes(&self) -> usize {
+            let fields: Box<dyn Iterator<Item = &FuzzField>> = match self {
+                FuzzSchema::Struct { fields, .. } => Box::new(fields.iter()),
+                FuzzSchema::Enum { variants, .. } => {
+                    Box::new(variants.iter().flat_map(|(_, fields)| fields.iter()))
+                }
+            };
+            1 + fields.flat_map(|f| f.generic_params.iter()).map(|s| s.num_nodes()).sum::<usize>()
+        }
+
+        /// Leaks the tree into `ProtocolSchemaInfo`s, assigning type ids from
+        /// `type_ids`, and returns the type id of the root.
+        fn register(
+            &self,
+            type_ids: &mut impl Iterator<Item = TypeId>,
+            structs: &mut BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+        ) -> TypeId {
+            let type_id = type_ids.next().unwrap();
+            let info = match self {
+                FuzzSchema::Struct { name, fields } => ProtocolSchemaInfo::Struct {
+                    name: name.as_str(),
+                    type_id,
+                    fields: register_fields(fields, type_ids, structs),
+                },
+                FuzzSchema::Enum { name, variants } => {
+                    let variants = variants
+                        .iter()
+                        .map(|(name, fields)| {
+                            let fields = if fields.is_empty() {
+                                None
+                            } else {
+                                Some(register_fields(fields, type_ids, structs))
+                            };
+                            (name.as_str(), fields)
+                        })
+                        .collect::<Vec<_>>();
+                    ProtocolSchemaInfo::Enum {
+                        name: name.as_str(),
+                        type_id,
+                        variants: Box::leak(variants.into_boxed_slice()),
+                    }
+                }
+            };
+            structs.insert(type_id, Box::leak(Box::new(info)));
+            type_id
+        }
+
+        fn hash(&self, type_ids: &mut impl Iterator<Item = TypeId>) -> u32 {
+            let mut structs = BTreeMap::new();
+            let type_id = self.register(type_ids, &mut structs);
+            let mut types_in_compute: HashSet<TypeId> = Default::default();
+            let mut stats = SchemaStats::default();
+            compute_hash(structs[&type_id], &structs, &mut types_in_compute, &mut stats)
+        }
+    }
+
+    fn register_fields(
+        fields: &[FuzzField],
+        type_ids: &mut impl Iterator<Item = TypeId>,
+        structs: &mut BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
+    ) -> &'static [(FieldName, FieldTypeInfo)] {
+        let fields = fields
+            .iter()
+            .map(|field| {
+                let generic_params = field
+                    .generic_params
+                    .iter()
+                    .map(|param| param.register(type_ids, structs))
+                    .collect::<Vec<_>>();
+                let generic_params: &'static [TypeId] =
+                    Box::leak(generic_params.into_boxed_slice());
+                (field.name.as_str(), (field.type_name.as_str(), generic_params))
+            })
+            .collect::<Vec<_>>();
+        Box::leak(fields.into_boxed_slice())
+    }
+
+    /// Distinct types used as type ids of the fuzzed trees. Type ids differ
+    /// between builds, so the hash must not depend on them.
+    struct Marker<const N: usize>;
+
+    macro_rules! marker_type_ids {
+        ($($n:literal)*) => { vec![$(TypeId::of::<Marker<$n>>()),*] };
+    }
+
+    /// Checks that the hash is deterministic and doesn't depend on type ids,
+    /// and that structurally distinct trees have distinct hashes.
+    #[test]
+    fn schema_hash_fuzzer() {
+        let type_ids = marker_type_ids!(
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+        );
+        bolero::check!().with_type().for_each(|(schema, other): &(FuzzSchema, FuzzSchema)| {
+            if schema.num_nodes() > type_ids.len() || other.num_nodes() > type_ids.len() {
+                return;
+            }
+            let hash = schema.hash(&mut type_ids.iter().copied());
+            assert_eq!(hash, schema.hash(&mut type_ids.iter().copied()));
+            assert_eq!(hash, schema.hash(&mut type_ids.iter().rev().copied()));
+
+            let other_hash = other.hash(&mut type_ids.iter().copied());
+            assert_eq!(hash == other_hash, schema == other, "{schema:?} vs {other:?}");
+        });
+    }
+
+    /// Checks that registrations with different type names sharing a `TypeId`
+    /// are reported, while duplicate registrations of the same type are not.
+    #[test]
+    fn test_type_id_collision() {
+        fn info(name: &'static str, type_id: TypeId) -> &'static ProtocolSchemaInfo {
+            Box::leak(Box::new(ProtocolSchemaInfo::Struct { name, type_id, fields: &[] }))
+        }
+
+        let a = info("A", TypeId::of::<u32>());
+        let b = info("B", TypeId::of::<u64>());
+        let structs = structs_by_type_id([a, b, a]).unwrap();
+        assert_eq!(structs.len(), 2);
+
+        let colliding = info("C", TypeId::of::<u32>());
+        assert_eq!(
+            structs_by_type_id([a, b, colliding]),
+            Err(TypeIdCollision { first: "A", second: "C" })
+        );
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("BlockHeader", "BlockHeader"));
+        assert!(!glob_matches("BlockHeader", "BlockHeaderV1"));
+        assert!(glob_matches("BlockHeader*", "BlockHeaderV1"));
+        assert!(glob_matches("BlockHeader*", "BlockHeader"));
+        assert!(glob_matches("*Test*", "near_primitives::TestStruct"));
+        assert!(glob_matches("BlockHeaderV?", "BlockHeaderV5"));
+        assert!(!glob_matches("BlockHeaderV?", "BlockHeaderV"));
+        assert!(glob_matches("*a*b", "aXbYab"));
+        assert!(!glob_matches("*a*b", "aXbYa"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn test_split_off_excluded() {
+        let mut schema: StoredSchema =
+            toml::from_str("TestA = 1\nTestB = 2\nOther = 3\n[variant_sets]\nTestA = 4\n").unwrap();
+        let excluded = schema.split_off_excluded(|name| glob_matches("Test*", name));
+        assert_eq!(schema.hashes.keys().collect::<Vec<_>>(), ["Other"]);
+        assert!(schema.variant_sets.is_empty());
+        assert_eq!(excluded.hashes.keys().collect::<Vec<_>>(), ["TestA", "TestB"]);
+        assert_eq!(excluded.variant_sets.get("TestA"), Some(&4));
+
+        schema.extend(excluded);
+        assert_eq!(schema.hashes.len(), 3);
+        assert_eq!(schema.variant_sets.len(), 1);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let schema: StoredSchema = toml::from_str("A = 1\nB = 2\n").unwrap();
+        let reordered: StoredSchema =
+            toml::from_str("B = 2\nA = 1\n[variant_sets]\nA = 3\n").unwrap();
+        assert_eq!(schema.fingerprint(), reordered.fingerprint());
+
+        let changed: StoredSchema = toml::from_str("A = 1\nB = 3\n").unwrap();
+        assert_ne!(schema.fingerprint(), changed.fingerprint());
+        let renamed: StoredSchema = toml::from_str("A = 1\nC = 2\n").unwrap();
+        assert_ne!(schema.fingerprint(), renamed.fingerprint());
+    }
+
+    #[test]
+    fn test_hash_of() {
+        let structs = collect_structs();
+        let (schema, _) = compute_schema(structs.values().copied(), &structs);
+        assert_eq!(hash_of("TestStruct"), schema.hashes.get("TestStruct").copied());
+        assert!(hash_of("TestStruct").is_some());
+        assert_eq!(hash_of("NoSuchStruct"), None);
+    }
+
+    /// Types covering the different kinds of items the hash is computed from:
+    /// named and unnamed fields, unit and data carrying variants, generic
+    /// containers, nested protocol types and recursion.
+    #[derive(ProtocolSchema)]
+    #[allow(unused)]
+    struct SnapshotInner(u32);
+
+    #[derive(ProtocolSchema)]
+    #[allow(unused)]
+    struct SnapshotStruct {
+        a: u64,
+        b: Vec<SnapshotInner>,
+        c: Option<[u8; 32]>,
+    }
+
+    #[derive(ProtocolSchema)]
+    #[allow(unused)]
+    enum SnapshotEnum {
+        Empty,
+        Tuple(SnapshotInner, u8),
+        Named { inner: SnapshotStruct, recursive: Box<SnapshotEnum> },
+    }
+
+    // Expected hashes of the types above. Any change to them means that the
+    // hashing algorithm changed, which must come with a bump of
+    // SCHEMA_FORMAT_VERSION. Run the test with UPDATE_SCHEMA_HASH_SNAPSHOT=1
+    // to rewrite them.
+    // BEGIN SNAPSHOT
+    const HASH_SNAPSHOT: &[(&str, u32)] = &[
+        ("SnapshotInner", 1758214243),
+        ("SnapshotStruct", 851266362),
+        ("SnapshotEnum", 1638672900),
+        ("SnapshotEnum variant set", 970681972),
+    ];
+    // END SNAPSHOT
+
+    fn snapshot_hashes() -> Vec<(String, u32)> {
+        let structs = collect_structs();
+        let type_ids = [
+            TypeId::of::<SnapshotInner>(),
+            TypeId::of::<SnapshotStruct>(),
+            TypeId::of::<SnapshotEnum>(),
+        ];
+        let (schema, _) = compute_schema(type_ids.iter().map(|type_id| structs[type_id]), &structs);
+        let mut hashes = vec![];
+        for type_id in type_ids {
+            let name = structs[&type_id].type_name();
+            hashes.push((name.to_string(), schema.hashes[name]));
+            if let Some(variant_set_hash) = schema.variant_sets.get(name) {
+                hashes.push((format!("{} variant set", name), *variant_set_hash));
+            }
+        }
+        hashes
+    }
+
+    /// Checks the hashing algorithm itself against a fixed set of types, as
+    /// opposed to the comparison against `protocol_schema.toml`, which depends
+    /// on the structs linked into the binary.
+    #[test]
+    fn test_hash_snapshot() {
+        let hashes = snapshot_hashes();
+        if std::env::var("UPDATE_SCHEMA_HASH_SNAPSHOT").is_ok_and(|v| v == "1") {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib.rs");
+            let source = std::fs::read_to_string(path).unwrap();
+            let begin = source.find("// BEGIN SNAPSHOT\n").unwrap() + "// BEGIN SNAPSHOT\n".len();
+            let end = source[begin..].find("    // END SNAPSHOT").unwrap() + begin;
+            let mut snapshot = String::from("    const HASH_SNAPSHOT: &[(&str, u32)] = &[\n");
+            for (name, hash) in &hashes {
+                snapshot.push_str(&format!("        ({:?}, {}),\n", name, hash));
+            }
+            snapshot.push_str("    ];\n");
+            std::fs::write(path, format!("{}{}{}", &source[..begin], snapshot, &source[end..]))
+                .unwrap();
+            return;
+        }
+
+        let expected: Vec<(String, u32)> =
+            HASH_SNAPSHOT.iter().map(|(name, hash)| (name.to_string(), *hash)).collect();
+        assert_eq!(
+            hashes, expected,
+            "schema hashes changed, rerun with UPDATE_SCHEMA_HASH_SNAPSHOT=1 if this is intended"
+        );
+    }
+
+    #[test]
+    fn test_all_protocol_schemas() {
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        struct TestSummaryStruct {
+            a: u64,
+            b: String,
+        }
+        #[derive(ProtocolSchema)]
+        #[allow(unused)]
+        enum TestSummaryEnum {
+            A,
+            B(u64),
+        }
+
+        let summaries = all_protocol_schemas();
+        assert!(summaries.windows(2).all(|w| w[0].name <= w[1].name));
+        let find = |name: &str| summaries.iter().find(|s| s.name == name).unwrap();
+        let summary = find("TestSummaryStruct");
+        assert_eq!(summary.kind, SchemaKind::Struct);
+        assert_eq!(summary.fields, vec!["a", "b"]);
+        assert_eq!(Some(summary.hash), hash_of("TestSummaryStruct"));
+        let summary = find("TestSummaryEnum");
+        assert_eq!(summary.kind, SchemaKind::Enum);
+        assert_eq!(summary.fields, vec!["A", "B"]);
+        assert_eq!(Some(summary.hash), hash_of("TestSummaryEnum"));
+    }
+
+    /// Checks that files written before the format version was stored are
+    /// read as the initial version, and that the version is written first.
+    #[test]
+    fn test_schema_format_version() {
+        let stored: StoredSchema = toml::from_str("A = 1\nB = 2\n").unwrap();
+        assert_eq!(stored.schema_format_version, unversioned_schema_format());
+        assert_eq!(stored.hashes.len(), 2);
+
+        let written = toml::to_string_pretty(&StoredSchema::default()).unwrap();
+        assert!(written.starts_with(&format!("schema_format_version = {}", SCHEMA_FORMAT_VERSION)));
+    }
+}
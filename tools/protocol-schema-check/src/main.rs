@@ -1,6 +1,12 @@
 //! Tool which is able to iterate over all structs and check their hashes.
 //! Iteration is done by `ProtocolSchemaInfo`s generated by `ProtocolSchema`
 //! macro.
+//!
+//! Exit codes:
+//! - 0: no changes, or the changes were accepted with `--accept`.
+//! - 1: the schema changed and `res/protocol_schema.toml` needs to be updated.
+//! - 2: the tool itself failed, e.g. it couldn't read or parse the stored
+//!   schema, or couldn't write a file.
 #![cfg_attr(enable_const_type_id, feature(const_type_id))]
 #![allow(unused_imports)]
 
@@ -16,82 +22,188 @@ use near_primitives::*;
 use near_store::*;
 use near_vm_runner::*;
 
-use near_schema_checker_lib::{FieldName, FieldTypeInfo, ProtocolSchema, ProtocolSchemaInfo};
-use near_stable_hasher::StableHasher;
-use std::any::TypeId;
-use std::collections::{BTreeMap, HashSet};
+use clap::Parser;
+use near_schema_checker_lib::{ProtocolSchema, ProtocolSchemaInfo};
+use protocol_schema_check::{
+    SCHEMA_FORMAT_VERSION, SchemaChange, StoredSchema, TypeIdCollision, check_schema,
+    compute_schema, glob_matches, stale_variant_sets, structs_by_type_id,
+};
+use std::collections::BTreeSet;
 use std::fs;
-use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-fn compute_hash(
-    info: &ProtocolSchemaInfo,
-    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
-    types_in_compute: &mut HashSet<TypeId>,
-) -> u32 {
-    let type_id = info.type_id();
-    if types_in_compute.contains(&type_id) {
-        return 0;
-    }
-    types_in_compute.insert(type_id);
+const PROTOCOL_SCHEMA_FILE: &str = "protocol_schema.toml";
 
-    let mut hasher = StableHasher::new();
-    match info {
-        ProtocolSchemaInfo::Struct { name, type_id: _, fields } => {
-            "struct".hash(&mut hasher);
-            name.hash(&mut hasher);
-            compute_fields_hash(fields, structs, types_in_compute, &mut hasher);
-        }
-        ProtocolSchemaInfo::Enum { name, type_id: _, variants } => {
-            "enum".hash(&mut hasher);
-            name.hash(&mut hasher);
-            for (variant_name, variant_fields) in *variants {
-                variant_name.hash(&mut hasher);
-                if let Some(fields) = variant_fields {
-                    compute_fields_hash(fields, structs, types_in_compute, &mut hasher);
+/// Exit code when the schema differs from the stored one.
+const EXIT_SCHEMA_CHANGED: i32 = 1;
+/// Exit code when the check couldn't be done at all.
+const EXIT_TOOL_ERROR: i32 = 2;
+
+/// Reports an error that prevents the check from running, and exits with
+/// `EXIT_TOOL_ERROR` so that CI can tell it apart from a schema change.
+fn tool_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(EXIT_TOOL_ERROR);
+}
+
+fn read_schema(path: &Path) -> StoredSchema {
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|err| tool_error(format!("failed to read {}: {}", path.display(), err)));
+    toml::from_str(&contents)
+        .unwrap_or_else(|err| tool_error(format!("failed to parse {}: {}", path.display(), err)))
+}
+
+fn write_schema(path: &Path, schema: &StoredSchema) {
+    let contents = toml::to_string_pretty(schema)
+        .unwrap_or_else(|err| tool_error(format!("failed to serialize the schema: {}", err)));
+    fs::write(path, contents)
+        .unwrap_or_else(|err| tool_error(format!("failed to write {}: {}", path.display(), err)));
+}
+
+#[derive(clap::Parser)]
+struct Cli {
+    /// Accept the detected changes: write the new hashes directly to
+    /// `res/protocol_schema.toml` and exit successfully. Only use this after
+    /// reviewing the changes reported by a run without this flag.
+    #[clap(long)]
+    accept: bool,
+    /// Print the number of fields and the nesting depth traversed for each
+    /// struct alongside its hash.
+    #[clap(long)]
+    stats: bool,
+    /// Only report structs whose hash changed, which are the ones that may
+    /// break backward compatibility. Added and removed structs are not printed
+    /// but still make the check fail.
+    #[clap(long)]
+    only_changed: bool,
+    /// Also write the computed schema to the given file, regardless of the
+    /// result of the check. Building neard with `NEARD_PROTOCOL_SCHEMA` set
+    /// to this file embeds it into the binary, see
+    /// `neard print-protocol-schema --embedded`.
+    #[clap(long)]
+    write_embedded: Option<std::path::PathBuf>,
+    /// Skip the structs whose type name matches this glob pattern, where `*`
+    /// matches any sequence of characters and `?` any single character. Can be
+    /// given multiple times. Excluded structs are not compared, and their
+    /// stored hashes are kept as is when writing a new TOML file.
+    #[clap(long)]
+    exclude: Vec<String>,
+    /// Run the full check and report the result through the exit code, but
+    /// never write any file, not even the new TOML file to `CARGO_TARGET_DIR`.
+    /// Meant for CI or read-only checkouts.
+    #[clap(long, conflicts_with_all = ["accept", "write_embedded"])]
+    verify: bool,
+    /// Print the schema format version, the schema fingerprint and the hashes
+    /// of all structs sorted by name as a JSON object, and exit without
+    /// comparing them to the stored schema.
+    #[clap(long, conflicts_with_all = ["accept", "verify", "write_embedded", "stats"])]
+    json: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Compare two previously generated TOML files, e.g. written with
+    /// `--write-embedded` on two branches, and report the changes from the
+    /// first one to the second one the same way as the regular check, without
+    /// looking at the structs linked into this binary. Exits with 1 if they
+    /// differ. Only `--only-changed` and `--exclude` apply in this mode.
+    Diff { old: PathBuf, new: PathBuf },
+}
+
+/// Prints the changes from the `old_label` schema to the `new_label` one.
+/// Added and removed structs are skipped if `only_changed` is set.
+fn report_changes(changes: &[SchemaChange], only_changed: bool, old_label: &str, new_label: &str) {
+    for change in changes {
+        match change {
+            SchemaChange::Changed { name, old_hash, new_hash, variant_order_only: true } => {
+                // Borsh encodes the variant index, so this changes the
+                // serialization even though the variants are the same.
+                println!(
+                    "Variant order changed for {}: {} {}, {} {}",
+                    name, old_label, old_hash, new_label, new_hash
+                );
+            }
+            SchemaChange::Changed { name, old_hash, new_hash, variant_order_only: false } => {
+                println!(
+                    "Hash mismatch for {}: {} {}, {} {}",
+                    name, old_label, old_hash, new_label, new_hash
+                );
+            }
+            SchemaChange::Added { name, hash } => {
+                if !only_changed {
+                    println!("New struct: {} with hash {}", name, hash);
+                }
+            }
+            SchemaChange::Removed { name } => {
+                if !only_changed {
+                    println!("Struct removed: {}", name);
                 }
             }
         }
     }
-
-    types_in_compute.remove(&type_id);
-
-    hasher.finish() as u32
 }
 
-fn compute_fields_hash(
-    fields: &'static [(FieldName, FieldTypeInfo)],
-    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
-    types_in_compute: &mut HashSet<TypeId>,
-    hasher: &mut StableHasher,
-) {
-    for (field_name, (type_name, generic_params)) in fields {
-        field_name.hash(hasher);
-        type_name.hash(hasher);
-        for &param_type_id in generic_params.iter() {
-            compute_type_hash(param_type_id, structs, types_in_compute, hasher);
-        }
+/// Splits off the structs matching any of the `exclude` patterns from both
+/// schemas, prints their names, and returns the ones split off from `old`.
+fn exclude_structs(
+    exclude: &[String],
+    old: &mut StoredSchema,
+    new: &mut StoredSchema,
+) -> StoredSchema {
+    let is_excluded = |name: &str| exclude.iter().any(|pattern| glob_matches(pattern, name));
+    let new_excluded = new.split_off_excluded(is_excluded);
+    let old_excluded = old.split_off_excluded(is_excluded);
+    let excluded_names: BTreeSet<&String> =
+        new_excluded.hashes.keys().chain(old_excluded.hashes.keys()).collect();
+    if !excluded_names.is_empty() {
+        println!(
+            "Excluded {} structs matching --exclude: {}",
+            excluded_names.len(),
+            excluded_names.into_iter().map(String::as_str).collect::<Vec<_>>().join(", ")
+        );
     }
+    old_excluded
 }
 
-fn compute_type_hash(
-    type_id: TypeId,
-    structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
-    types_in_compute: &mut HashSet<TypeId>,
-    hasher: &mut StableHasher,
-) {
-    if let Some(nested_info) = structs.get(&type_id) {
-        compute_hash(nested_info, structs, types_in_compute).hash(hasher);
+/// Runs `protocol-schema-check diff`.
+fn diff(cli: &Cli, old_path: &Path, new_path: &Path) {
+    let mut old = read_schema(old_path);
+    let mut new = read_schema(new_path);
+    if old.schema_format_version != new.schema_format_version {
+        tool_error(format!(
+            "{} has schema format version {} and {} has {}, so their hashes can't be compared",
+            old_path.display(),
+            old.schema_format_version,
+            new_path.display(),
+            new.schema_format_version
+        ));
+    }
+    println!("Schema fingerprint of {}: {}", old_path.display(), old.fingerprint());
+    println!("Schema fingerprint of {}: {}", new_path.display(), new.fingerprint());
+    exclude_structs(&cli.exclude, &mut old, &mut new);
+    let changes = check_schema(&old, &new);
+    report_changes(
+        &changes,
+        cli.only_changed,
+        &old_path.display().to_string(),
+        &new_path.display().to_string(),
+    );
+    if changes.is_empty() {
+        println!("No changes between {} and {}", old_path.display(), new_path.display());
     } else {
-        // Unsupported type. Always assume that hash is 0 because we cannot
-        // compute nontrivial deterministic hash in such cases.
-        0.hash(hasher);
+        std::process::exit(EXIT_SCHEMA_CHANGED);
     }
 }
 
-const PROTOCOL_SCHEMA_FILE: &str = "protocol_schema.toml";
-
 fn main() {
+    let cli = Cli::parse();
+    if let Some(Command::Diff { old, new }) = &cli.command {
+        diff(&cli, old, new);
+        return;
+    }
+
     #[cfg(enable_const_type_id)]
     {
         // For some reason, `LatestWitnessesInfo` structs is not picked up
@@ -110,344 +222,114 @@ fn main() {
         .unwrap_or_else(|_| std::path::PathBuf::from("./target"));
     let target_path = target_dir.join(PROTOCOL_SCHEMA_FILE);
 
-    let stored_hashes: BTreeMap<String, u32> = if source_path.exists() {
-        toml::from_str(&fs::read_to_string(&source_path).unwrap_or_else(|_| "".to_string()))
-            .unwrap()
-    } else {
-        BTreeMap::new()
-    };
-
-    let structs: BTreeMap<TypeId, &'static ProtocolSchemaInfo> =
-        inventory::iter::<ProtocolSchemaInfo>
-            .into_iter()
-            .map(|info| (info.type_id(), info))
-            .collect();
-
-    println!("Loaded {} structs", structs.len());
-
-    let mut current_hashes: BTreeMap<String, u32> = Default::default();
-    for info in inventory::iter::<ProtocolSchemaInfo> {
-        let mut types_in_compute: HashSet<TypeId> = Default::default();
-        let hash = compute_hash(info, &structs, &mut types_in_compute);
-        current_hashes.insert(info.type_name().to_string(), hash);
+    let mut stored: StoredSchema =
+        if source_path.exists() { read_schema(&source_path) } else { StoredSchema::default() };
+    let structs = structs_by_type_id(inventory::iter::<ProtocolSchemaInfo>).unwrap_or_else(
+        |TypeIdCollision { first, second }| {
+            tool_error(format!(
+                "TypeId collision: {} and {} are registered with the same TypeId",
+                first, second
+            ))
+        },
+    );
+
+    let (mut current, current_stats) =
+        compute_schema(inventory::iter::<ProtocolSchemaInfo>, &structs);
+
+    if cli.json {
+        let summary = serde_json::json!({
+            "schema_format_version": current.schema_format_version,
+            "fingerprint": current.fingerprint(),
+            "hashes": current.hashes,
+        });
+        let summary = serde_json::to_string_pretty(&summary)
+            .unwrap_or_else(|err| tool_error(format!("failed to serialize the schema: {}", err)));
+        println!("{}", summary);
+        return;
     }
 
-    let mut has_changes = false;
-    for (name, hash) in &current_hashes {
-        match stored_hashes.get(name) {
-            Some(stored_hash) if stored_hash != hash => {
-                println!("Hash mismatch for {}: stored {}, current {}", name, stored_hash, hash);
-                has_changes = true;
-            }
-            None => {
-                println!("New struct: {} with hash {}", name, hash);
-                has_changes = true;
-            }
-            _ => {}
+    println!("Loaded {} structs", structs.len());
+    println!("Schema fingerprint: {}", current.fingerprint());
+
+    if cli.stats {
+        for (name, hash) in &current.hashes {
+            let stats = &current_stats[name];
+            println!(
+                "{}: hash {}, fields {}, depth {}",
+                name, hash, stats.num_fields, stats.max_depth
+            );
         }
     }
 
-    let current_keys: HashSet<_> = current_hashes.keys().collect();
-    let stored_keys: HashSet<_> = stored_hashes.keys().collect();
-    for removed in stored_keys.difference(&current_keys) {
-        println!("Struct removed: {}", removed);
-        has_changes = true;
+    if let Some(path) = &cli.write_embedded {
+        write_schema(path, &current);
+        println!("Schema for embedding into neard written to: {}", path.display());
     }
-
-    if has_changes {
-        fs::write(&target_path, toml::to_string_pretty(&current_hashes).unwrap()).unwrap();
-        println!("New TOML file written to: {}", target_path.display());
+    if stored.schema_format_version != SCHEMA_FORMAT_VERSION {
         println!(
-            "Please review the changes and copy the file to {} if they are correct.",
-            PROTOCOL_SCHEMA_FILE
+            "Schema format version changed: stored {}, current {}. Hashes computed with \
+             different versions can't be compared, so no diff is reported.",
+            stored.schema_format_version, SCHEMA_FORMAT_VERSION
         );
-        std::process::exit(1);
-    } else {
-        println!("No changes detected in protocol structs");
-    }
-}
-
-#[cfg(all(test, enable_const_type_id))]
-mod tests {
-    use super::*;
-    use near_schema_checker_lib::ProtocolSchema;
-    use std::collections::HashMap;
-
-    fn do_compute_type_hash(
-        ty: TypeId,
-        structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
-    ) -> u32 {
-        let mut hasher = StableHasher::new();
-        let mut types_in_compute: HashSet<TypeId> = Default::default();
-        compute_type_hash(ty, structs, &mut types_in_compute, &mut hasher);
-        hasher.finish() as u32
-    }
-
-    fn check_types(
-        ty: TypeId,
-        other_ty: TypeId,
-        expect_equal: bool,
-        structs: &BTreeMap<TypeId, &'static ProtocolSchemaInfo>,
-    ) {
-        let hash = do_compute_type_hash(ty, structs);
-        let other_hash = do_compute_type_hash(other_ty, structs);
-        assert_eq!(hash == other_hash, expect_equal);
-    }
-
-    fn collect_structs() -> BTreeMap<TypeId, &'static ProtocolSchemaInfo> {
-        inventory::iter::<ProtocolSchemaInfo>
-            .into_iter()
-            .map(|info| (info.type_id(), info))
-            .collect()
-    }
-
-    /// Helper types for tests.
-    type TestU64 = u64;
-    #[derive(ProtocolSchema)]
-    #[allow(unused)]
-    struct TestStruct {
-        a: u64,
-        b: String,
-    }
-    use TestStruct as TestStruct2;
-
-    /// Checks that structs with same names and underlying structure have the
-    /// same hash, even if used with different aliases.
-    #[test]
-    fn test_identical() {
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct TestStruct {
-            a: u64,
-            b: String,
-        }
-
-        check_types(
-            TypeId::of::<TestStruct>(),
-            TypeId::of::<TestStruct2>(),
-            true,
-            &collect_structs(),
-        );
-    }
-
-    /// Checks that if identical structs have different field names, hashes are
-    /// different.
-    #[test]
-    fn test_different_field_names() {
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct TestStruct {
-            a: u64,
-            c: String,
-        }
-
-        check_types(
-            TypeId::of::<TestStruct>(),
-            TypeId::of::<TestStruct2>(),
-            false,
-            &collect_structs(),
-        );
-    }
-
-    /// Checks that if identical structs have different type names, hashes are
-    /// different.
-    #[test]
-    fn test_different_type_names() {
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct TestStruct {
-            a: TestU64,
-            b: String,
+        if cli.accept {
+            write_schema(&source_path, &current);
+            println!("Regenerated TOML file at: {}", source_path.display());
+            return;
         }
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct TestStruct2 {
-            a: u64,
-            b: String,
-        }
-
-        check_types(
-            TypeId::of::<TestStruct>(),
-            TypeId::of::<TestStruct2>(),
-            false,
-            &collect_structs(),
-        );
-    }
-
-    /// Checks that struct and enum have different hashes.
-    #[test]
-    fn test_different_struct_enum() {
-        mod inner {
-            #[derive(super::ProtocolSchema)]
-            pub struct Empty;
-        }
-        use inner::Empty as EmptyStruct;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        enum Empty {}
-
-        check_types(TypeId::of::<Empty>(), TypeId::of::<EmptyStruct>(), false, &collect_structs());
-    }
-
-    /// Checks that hashes can differentiate integers.
-    #[test]
-    fn test_different_integers() {
-        mod inner {
-            #[derive(super::ProtocolSchema)]
-            #[allow(unused)]
-            pub struct Unsigned {
-                a: u32,
-            }
-        }
-        use inner::Unsigned as ShortUnsigned;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct Unsigned {
-            a: u64,
-        }
-
-        check_types(
-            TypeId::of::<Unsigned>(),
-            TypeId::of::<ShortUnsigned>(),
-            false,
-            &collect_structs(),
-        );
-    }
-
-    /// Checks that hashes can differentiate containers.
-    #[test]
-    fn test_different_containers() {
-        mod inner {
-            #[derive(super::ProtocolSchema)]
-            #[allow(unused)]
-            pub struct Container {
-                a: Vec<u32>,
-            }
-        }
-        use inner::Container as VecContainer;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct Container {
-            a: HashMap<u32, u32>,
-        }
-
-        check_types(
-            TypeId::of::<Container>(),
-            TypeId::of::<VecContainer>(),
-            false,
-            &collect_structs(),
+        println!(
+            "If the hashing logic was changed intentionally, first make sure that no protocol \
+             structs changed, e.g. by running the previous version of the tool, and then rerun \
+             with --accept to regenerate {}.",
+            PROTOCOL_SCHEMA_FILE
         );
+        std::process::exit(EXIT_SCHEMA_CHANGED);
     }
 
-    /// Checks that hashes can differentiate generics in containers.
-    #[test]
-    fn test_different_container_generics() {
-        mod inner {
-            #[derive(super::ProtocolSchema)]
-            #[allow(unused)]
-            pub struct Container {
-                a: Vec<Vec<Vec<u32>>>,
-            }
-        }
-        use inner::Container as VecContainer;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct Container {
-            a: Vec<Vec<Vec<i32>>>,
-        }
-
-        check_types(
-            TypeId::of::<Container>(),
-            TypeId::of::<VecContainer>(),
-            false,
-            &collect_structs(),
+    let stored_excluded = exclude_structs(&cli.exclude, &mut stored, &mut current);
+    let changes = check_schema(&stored, &current);
+    report_changes(&changes, cli.only_changed, "stored", "current");
+    let has_changes = !changes.is_empty();
+    let stale_variant_sets = stale_variant_sets(&stored, &current);
+
+    // Keep the stored hashes of the excluded structs, so that excluding them
+    // doesn't remove them from the file.
+    current.extend(stored_excluded);
+    if has_changes && cli.accept {
+        write_schema(&source_path, &current);
+        println!("Accepted changes, TOML file updated at: {}", source_path.display());
+    } else if has_changes && cli.verify {
+        println!(
+            "Rerun without --verify to write the new TOML file, or with --accept to update {} \
+             in place after reviewing the changes.",
+            PROTOCOL_SCHEMA_FILE
         );
-    }
-
-    /// Checks that hashes can differentiate one of generics in containers.
-    #[test]
-    fn test_different_container_two_generics() {
-        mod inner {
-            use super::*;
-
-            #[derive(super::ProtocolSchema)]
-            #[allow(unused)]
-            pub struct Container {
-                a: HashMap<u32, u16>,
-            }
-        }
-        use inner::Container as MapContainer;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct Container {
-            a: HashMap<u32, u32>,
-        }
-
-        check_types(
-            TypeId::of::<Container>(),
-            TypeId::of::<MapContainer>(),
-            false,
-            &collect_structs(),
+        std::process::exit(EXIT_SCHEMA_CHANGED);
+    } else if has_changes {
+        write_schema(&target_path, &current);
+        println!("New TOML file written to: {}", target_path.display());
+        println!(
+            "Please review the changes and copy the file to {} if they are correct, \
+             or rerun with --accept to update it in place.",
+            PROTOCOL_SCHEMA_FILE
         );
-    }
-
-    /// Checks that hashes can differentiate nested containers.
-    #[test]
-    fn test_nested_containers_different_types() {
-        mod inner {
-            #[derive(super::ProtocolSchema)]
-            #[allow(unused)]
-            pub struct Container {
-                a: Vec<Vec<u32>>,
-            }
-        }
-        use inner::Container as VecContainer;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct Container {
-            a: Vec<Vec<i32>>,
-        }
-
-        check_types(
-            TypeId::of::<Container>(),
-            TypeId::of::<VecContainer>(),
-            false,
-            &collect_structs(),
+        std::process::exit(EXIT_SCHEMA_CHANGED);
+    } else if !stale_variant_sets.is_empty() && cli.accept {
+        write_schema(&source_path, &current);
+        println!(
+            "No changes detected in protocol structs, added the variant sets of {} enums to {}",
+            stale_variant_sets.len(),
+            source_path.display()
         );
-    }
-
-    /// Checks that if nested containers differ, this is not caught by hash
-    /// check.
-    /// Added to indicate limitations of implementation.
-    #[test]
-    fn test_nested_containers_different_containers_unsupported() {
-        mod inner {
-            #[derive(super::ProtocolSchema)]
-            #[allow(unused)]
-            pub struct Container {
-                a: Vec<Vec<u32>>,
-            }
-        }
-        use inner::Container as VecContainer;
-
-        #[derive(ProtocolSchema)]
-        #[allow(unused)]
-        struct Container {
-            a: Vec<HashSet<u32>>,
-        }
-
-        check_types(
-            TypeId::of::<Container>(),
-            TypeId::of::<VecContainer>(),
-            true,
-            &collect_structs(),
+    } else if !stale_variant_sets.is_empty() {
+        // Without the variant sets, reordered variants of these enums would be
+        // reported as plain hash mismatches.
+        println!(
+            "No changes detected in protocol structs, but {} is missing the variant sets of {} \
+             enums. Rerun with --accept to add them.",
+            PROTOCOL_SCHEMA_FILE,
+            stale_variant_sets.len()
         );
+    } else {
+        println!("No changes detected in protocol structs");
     }
 }